@@ -0,0 +1,165 @@
+// Searches in the background: infinite analysis for a gui exploring
+// positions, and pondering on the opponent's time. Every completed depth is
+// sent as an update over a channel, the final result last.
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::sync::Arc;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::engine::Engine;
+use crate::game::Game;
+use crate::moves::Action;
+use crate::search::{play, AnalysisLine, SearchLimits, SearchResult};
+
+// The state of the search after a completed depth
+#[derive(Debug, Clone)]
+pub struct AnalysisInfo {
+    pub depth: i32,
+    pub score: i32,
+    pub nodes: u64,
+    // nodes per second
+    pub nps: u64,
+    pub elapsed: Duration,
+    pub pv: Vec<Action>,
+    // every multi-pv line, best first
+    pub lines: Vec<AnalysisLine>,
+}
+
+#[derive(Debug, Clone)]
+pub enum AnalysisUpdate {
+    Info(AnalysisInfo),
+    // the search ended: stopped, out of its limits or after a ponderhit
+    Done(SearchResult),
+}
+
+// An engine searching on a background thread. Starting a search stops the
+// one running before, which still sends its `Done` to its own receiver.
+pub struct Analyzer {
+    engine: Arc<Engine>,
+    stop: Arc<AtomicBool>,
+    // set while pondering, the result waits until it is cleared
+    hold: Arc<AtomicBool>,
+    // the engine's, set while pondering so the time limits wait
+    ponder: Arc<AtomicBool>,
+    search: Option<JoinHandle<()>>,
+}
+
+impl Analyzer {
+    pub fn new(engine: Engine) -> Analyzer {
+        Analyzer {
+            stop: engine.stop_handle(),
+            ponder: engine.ponder_handle(),
+            engine: Arc::new(engine),
+            hold: Arc::new(AtomicBool::new(false)),
+            search: None,
+        }
+    }
+
+    // Searches `game` until stopped, or until a mate is proven or the
+    // deepest depth done
+    pub fn analyze(&mut self, game: &Game) -> Receiver<AnalysisUpdate> {
+        self.start(game, SearchLimits::default(), false)
+    }
+
+    // Searches `game` within `limits`
+    pub fn go(&mut self, game: &Game, limits: &SearchLimits) -> Receiver<AnalysisUpdate> {
+        self.start(game, limits.clone(), false)
+    }
+
+    // Searches the position after the opponent's expected `ponder_move`
+    // while the opponent thinks. The move time or clock of `limits` only
+    // starts counting once `ponderhit` tells the move was played, until
+    // then the result is held back.
+    pub fn ponder(
+        &mut self,
+        game: &Game,
+        ponder_move: &Action,
+        limits: &SearchLimits,
+    ) -> Result<Receiver<AnalysisUpdate>, String> {
+        let legal = game.clone().all_moves();
+        if !legal.iter().any(|action| action.key() == ponder_move.key()) {
+            return Err(format!("{} is not a legal move", ponder_move.to_uci()));
+        }
+        let position = play(game, ponder_move);
+        Ok(self.start(&position, limits.clone(), true))
+    }
+
+    // The opponent played the ponder move: the search goes on as if it had
+    // just started on the clock, with the time it searched as a head start
+    pub fn ponderhit(&mut self) {
+        self.ponder.store(false, Ordering::Relaxed);
+        self.hold.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_searching(&self) -> bool {
+        self.search
+            .as_ref()
+            .is_some_and(|search| !search.is_finished())
+    }
+
+    // Ends the search and waits until its result is sent
+    pub fn stop(&mut self) {
+        if self.search.is_some() {
+            self.hold.store(false, Ordering::Relaxed);
+            self.stop.store(true, Ordering::Relaxed);
+            self.wait();
+        }
+    }
+
+    // Waits until the search ends by itself
+    pub fn wait(&mut self) {
+        if let Some(search) = self.search.take() {
+            let _ = search.join();
+        }
+    }
+
+    // The engine, to change its options, once the search is stopped
+    pub fn engine_mut(&mut self) -> &mut Engine {
+        self.stop();
+        Arc::get_mut(&mut self.engine).expect("the search thread has ended")
+    }
+
+    fn start(&mut self, game: &Game, limits: SearchLimits, hold: bool) -> Receiver<AnalysisUpdate> {
+        self.stop();
+        self.stop.store(false, Ordering::Relaxed);
+        self.hold.store(hold, Ordering::Relaxed);
+        self.ponder.store(hold, Ordering::Relaxed);
+
+        let (sender, receiver) = mpsc::channel();
+        let engine = Arc::clone(&self.engine);
+        let (stop, hold) = (Arc::clone(&self.stop), Arc::clone(&self.hold));
+        let game = game.clone();
+        self.search = Some(thread::spawn(move || {
+            let start = Instant::now();
+            let result = engine.search_with_info(&game, &limits, |result| {
+                let elapsed = start.elapsed();
+                let info = AnalysisInfo {
+                    depth: result.depth,
+                    score: result.score,
+                    nodes: result.nodes,
+                    nps: result.nodes * 1000 / (elapsed.as_millis() as u64).max(1),
+                    elapsed,
+                    pv: result.pv.clone(),
+                    lines: result.lines.clone(),
+                };
+                // nobody listening is no reason to stop analysing
+                let _ = sender.send(AnalysisUpdate::Info(info));
+            });
+            while hold.load(Ordering::Relaxed) && !stop.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(1));
+            }
+            stop.store(false, Ordering::Relaxed);
+            let _ = sender.send(AnalysisUpdate::Done(result));
+        }));
+        receiver
+    }
+}
+
+impl Drop for Analyzer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
@@ -0,0 +1,57 @@
+// Builds a polyglot opening book out of pgn collections:
+// chess-book [--ply N] [--min-games N] [--min-rating N] -o book.bin games.pgn...
+use std::env;
+use std::process;
+
+use chess::opening_book::{BookBuilder, BuilderOptions};
+
+fn main() {
+    if let Err(message) = run(env::args().skip(1).collect()) {
+        eprintln!("{}", message);
+        eprintln!(
+            "usage: chess-book [--ply N] [--min-games N] [--min-rating N] -o book.bin games.pgn..."
+        );
+        process::exit(1);
+    }
+}
+
+fn run(args: Vec<String>) -> Result<(), String> {
+    let mut options = BuilderOptions::default();
+    let mut output: Option<String> = None;
+    let mut inputs: Vec<String> = vec![];
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "-o" | "--output" => output = Some(value()?),
+            "--ply" => options.max_ply = parse(&value()?)?,
+            "--min-games" => options.min_games = parse(&value()?)?,
+            "--min-rating" => options.min_rating = Some(parse(&value()?)?),
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}", arg)),
+            _ => inputs.push(arg),
+        }
+    }
+    let output = output.ok_or("no output file given")?;
+    if inputs.is_empty() {
+        return Err(String::from("no pgn files given"));
+    }
+
+    let mut builder = BookBuilder::new(options);
+    for input in inputs.iter() {
+        let added = builder.add_pgn_file(input)?;
+        println!("{}: {} games", input, added);
+    }
+    builder.write(&output)?;
+    println!(
+        "{} entries from {} games written to {}",
+        builder.entries().len(),
+        builder.games(),
+        output
+    );
+    Ok(())
+}
+
+fn parse<T: std::str::FromStr>(text: &str) -> Result<T, String> {
+    text.parse()
+        .map_err(|_| format!("{} is not a valid number", text))
+}
@@ -0,0 +1,115 @@
+// Writes training data for evaluation networks out of self-play games, one
+// "FEN | SCORE | RESULT" line per position:
+// chess-datagen [--games N] [--depth N] [--nodes N] [--random-plies N]
+//     [--max-moves N] [--concurrency N] [--option NAME=VALUE]... -o data.txt
+use std::env;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+use chess::datagen::{Datagen, DatagenOptions};
+use chess::random::random_u64;
+use chess::tournament::Player;
+
+fn main() {
+    if let Err(message) = run(env::args().skip(1).collect()) {
+        eprintln!("{}", message);
+        eprintln!(
+            "usage: chess-datagen [--games N] [--depth N] [--nodes N] [--random-plies N] \
+             [--max-moves N] [--concurrency N] [--option NAME=VALUE]... -o data.txt"
+        );
+        process::exit(1);
+    }
+}
+
+fn run(args: Vec<String>) -> Result<(), String> {
+    let mut player = Player {
+        name: String::from("datagen"),
+        ..Player::default()
+    };
+    player.limits.depth = Some(6);
+    let mut options = DatagenOptions::default();
+    options.game.max_moves = Some(200);
+    let mut games: usize = 100;
+    let mut concurrency: usize = 1;
+    let mut output: Option<String> = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--games" => games = parse(&value()?)?,
+            "--depth" => player.limits.depth = Some(parse(&value()?)?),
+            "--nodes" => player.limits.nodes = Some(parse(&value()?)?),
+            "--random-plies" => options.random_plies = parse(&value()?)?,
+            "--max-moves" => options.game.max_moves = Some(parse(&value()?)?),
+            "--concurrency" => concurrency = parse(&value()?)?,
+            "--option" => {
+                let text = value()?;
+                let (name, value) = text
+                    .split_once('=')
+                    .ok_or(format!("{} is not NAME=VALUE", text))?;
+                player.options.push((name.to_string(), value.to_string()));
+            }
+            "-o" | "--output" => output = Some(value()?),
+            _ => return Err(format!("unknown option {}", arg)),
+        }
+    }
+    let output = output.ok_or("no output file given")?;
+    let file = File::create(&output).map_err(|e| format!("could not write {}: {}", output, e))?;
+    let mut writer = BufWriter::new(file);
+
+    let next_game = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| -> Result<(), String> {
+        for _ in 0..concurrency.clamp(1, games.max(1)) {
+            let sender = sender.clone();
+            let (player, options, next_game) = (&player, &options, &next_game);
+            scope.spawn(move || {
+                let mut datagen = match Datagen::new(player, options.clone()) {
+                    Ok(datagen) => datagen,
+                    Err(message) => {
+                        let _ = sender.send(Err(message));
+                        return;
+                    }
+                };
+                while next_game.fetch_add(1, Ordering::Relaxed) < games {
+                    let records = datagen.play_game(random_u64());
+                    let failed = records.is_err();
+                    if sender.send(records).is_err() || failed {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+        let (mut played, mut positions) = (0, 0);
+        for records in receiver {
+            let records = records?;
+            for record in records.iter() {
+                writeln!(writer, "{}", record)
+                    .map_err(|e| format!("could not write {}: {}", output, e))?;
+            }
+            played += 1;
+            positions += records.len();
+            println!(
+                "game {}: {} positions, {} in all",
+                played,
+                records.len(),
+                positions
+            );
+        }
+        Ok(())
+    })?;
+    writer
+        .flush()
+        .map_err(|e| format!("could not write {}: {}", output, e))
+}
+
+fn parse<T: std::str::FromStr>(text: &str) -> Result<T, String> {
+    text.trim()
+        .parse()
+        .map_err(|_| format!("{} is not a valid number", text))
+}
@@ -0,0 +1,147 @@
+// Imports pgn files into a local game database and searches it:
+// chess-db import games.db games.pgn...
+// chess-db search games.db [--white NAME] [--black NAME] [--player NAME]
+//     [--event NAME] [--result R] [--moves "1. e4 c5"] [--fen FEN] [--limit N]
+//     [-o games.pgn]
+// chess-db stats games.db [--moves "1. e4 c5"] [--fen FEN]
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+use chess::database::{Database, Query};
+use chess::game::Game;
+use chess::pgn;
+
+fn main() {
+    if let Err(message) = run(env::args().skip(1).collect()) {
+        eprintln!("{}", message);
+        eprintln!(
+            "usage: chess-db import games.db games.pgn...\n       \
+             chess-db search games.db [--white NAME] [--black NAME] [--player NAME] \
+             [--event NAME] [--result R] [--moves MOVES] [--fen FEN] [--limit N] [-o games.pgn]\n       \
+             chess-db stats games.db [--moves MOVES] [--fen FEN]"
+        );
+        process::exit(1);
+    }
+}
+
+fn run(args: Vec<String>) -> Result<(), String> {
+    let mut args = args.into_iter();
+    let command = args.next().ok_or("no command given")?;
+    let path = args.next().ok_or("no database given")?;
+    match command.as_str() {
+        "import" => import(&path, args.collect()),
+        "search" => search(&path, args.collect()),
+        "stats" => stats(&path, args.collect()),
+        _ => Err(format!("unknown command {}", command)),
+    }
+}
+
+fn import(path: &str, inputs: Vec<String>) -> Result<(), String> {
+    if inputs.is_empty() {
+        return Err(String::from("no pgn files given"));
+    }
+    let mut database = if Path::new(path).exists() {
+        Database::open(path)?
+    } else {
+        Database::new()
+    };
+    for input in inputs.iter() {
+        let added = database.add_pgn_file(input)?;
+        println!("{}: {} games", input, added);
+    }
+    database.write(path)?;
+    println!(
+        "{} games with {} indexed positions written to {}",
+        database.len(),
+        database.positions(),
+        path
+    );
+    Ok(())
+}
+
+fn search(path: &str, args: Vec<String>) -> Result<(), String> {
+    let mut query = Query::default();
+    let mut position: Option<Game> = None;
+    let mut limit: Option<usize> = None;
+    let mut output: Option<String> = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--white" => query.white = Some(value()?),
+            "--black" => query.black = Some(value()?),
+            "--player" => query.player = Some(value()?),
+            "--event" => query.event = Some(value()?),
+            "--result" => query.result = Some(value()?),
+            "--moves" => position = Some(after_moves(&value()?)?),
+            "--fen" => position = Some(Game::from_fen(&value()?)?),
+            "--limit" => limit = Some(parse(&value()?)?),
+            "-o" | "--output" => output = Some(value()?),
+            _ => return Err(format!("unknown option {}", arg)),
+        }
+    }
+    query.position = position.map(|game| game.hash());
+
+    let database = Database::open(path)?;
+    let found = database.search(&query);
+    let mut games = String::new();
+    for &id in found.iter().take(limit.unwrap_or(usize::MAX)) {
+        let tag = |name: &str| database.tag(id, name).unwrap_or("?");
+        println!(
+            "{}: {} - {} {} {} {} {}",
+            id + 1,
+            tag("White"),
+            tag("Black"),
+            database.result(id).unwrap_or("*"),
+            tag("Event"),
+            tag("Date"),
+            tag("ECO")
+        );
+        if output.is_some() {
+            games.push_str(&database.game(id)?.to_pgn()?);
+        }
+    }
+    println!("{} of {} games", found.len(), database.len());
+    if let Some(path) = &output {
+        fs::write(path, games).map_err(|e| format!("could not write {}: {}", path, e))?;
+    }
+    Ok(())
+}
+
+fn stats(path: &str, args: Vec<String>) -> Result<(), String> {
+    let mut game = Game::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--moves" => game = after_moves(&value()?)?,
+            "--fen" => game = Game::from_fen(&value()?)?,
+            _ => return Err(format!("unknown option {}", arg)),
+        }
+    }
+
+    let database = Database::open(path)?;
+    let stats = database.stats(&game);
+    println!("{}", stats.results);
+    for (action, results) in stats.moves.iter() {
+        println!("{:8} {}", pgn::to_san(&game, action), results);
+    }
+    Ok(())
+}
+
+// The position after moves in standard algebraic notation from the start
+fn after_moves(moves: &str) -> Result<Game, String> {
+    let pgn_game = pgn::parse_games(moves).pop().ok_or("no moves given")??;
+    let mut game = Game::new();
+    for action in pgn_game.actions.iter() {
+        game.perform_action(*action);
+    }
+    Ok(game)
+}
+
+fn parse<T: std::str::FromStr>(text: &str) -> Result<T, String> {
+    text.parse()
+        .map_err(|_| format!("{} is not a valid number", text))
+}
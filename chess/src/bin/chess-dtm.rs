@@ -0,0 +1,49 @@
+// Generates distance to mate tables, together with the smaller tables they
+// need, and writes them as NAME.dtm files:
+// chess-dtm [-o directory] KQvK KRvK KPvK KBNvK...
+use std::env;
+use std::process;
+use std::time::Instant;
+
+use chess::dtm;
+use chess::dtm::Generator;
+
+fn main() {
+    if let Err(message) = run(env::args().skip(1).collect()) {
+        eprintln!("{}", message);
+        eprintln!("usage: chess-dtm [-o directory] MATERIAL...");
+        process::exit(1);
+    }
+}
+
+fn run(args: Vec<String>) -> Result<(), String> {
+    let mut directory = String::from(".");
+    let mut materials: Vec<String> = vec![];
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => directory = args.next().ok_or("-o needs a directory")?,
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}", arg)),
+            _ => materials.push(arg),
+        }
+    }
+    if materials.is_empty() {
+        return Err(String::from("no material given"));
+    }
+
+    let mut generator = Generator::new();
+    for material in materials.iter() {
+        let start = Instant::now();
+        let table = generator.generate(material)?;
+        println!(
+            "{}: longest mate {} moves, {:.1}s",
+            table.name(),
+            table.longest_mate(),
+            start.elapsed().as_secs_f64()
+        );
+    }
+    let tables = generator.into_tables();
+    dtm::write_tables(&tables, &directory)?;
+    println!("{} tables written to {}", tables.len(), directory);
+    Ok(())
+}
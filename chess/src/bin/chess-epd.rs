@@ -0,0 +1,106 @@
+// Runs epd test suites like WAC or STS through the engine and reports how
+// many positions it solves, optionally writing every position back with the
+// engine's analysis:
+// chess-epd [--depth N] [--nodes N] [--movetime MS] [--option NAME=VALUE]...
+//     [-o analysed.epd] suite.epd...
+use std::env;
+use std::fs;
+use std::process;
+use std::time::Duration;
+
+use chess::engine::Engine;
+use chess::epd;
+use chess::epd::Epd;
+use chess::search::SearchLimits;
+
+fn main() {
+    if let Err(message) = run(env::args().skip(1).collect()) {
+        eprintln!("{}", message);
+        eprintln!(
+            "usage: chess-epd [--depth N] [--nodes N] [--movetime MS] \
+             [--option NAME=VALUE]... [-o analysed.epd] suite.epd..."
+        );
+        process::exit(1);
+    }
+}
+
+fn run(args: Vec<String>) -> Result<(), String> {
+    let mut engine = Engine::default();
+    let mut limits = SearchLimits::default();
+    let mut output: Option<String> = None;
+    let mut inputs: Vec<String> = vec![];
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--depth" => limits.depth = Some(parse(&value()?)?),
+            "--nodes" => limits.nodes = Some(parse(&value()?)?),
+            "--movetime" => limits.movetime = Some(Duration::from_millis(parse(&value()?)?)),
+            "--option" => {
+                let text = value()?;
+                let (name, value) = text
+                    .split_once('=')
+                    .ok_or(format!("{} is not NAME=VALUE", text))?;
+                engine.set_option(name, value)?;
+            }
+            "-o" | "--output" => output = Some(value()?),
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}", arg)),
+            _ => inputs.push(arg),
+        }
+    }
+    if inputs.is_empty() {
+        return Err(String::from("no epd files given"));
+    }
+    if limits.depth.is_none() && limits.nodes.is_none() && limits.movetime.is_none() {
+        limits.movetime = Some(Duration::from_secs(1));
+    }
+
+    let mut suite: Vec<Epd> = vec![];
+    for input in inputs.iter() {
+        for record in epd::read_epd(input)? {
+            match record {
+                Ok(record) => suite.push(record),
+                Err(message) => eprintln!("{}: {}", input, message),
+            }
+        }
+    }
+
+    let mut analysed = String::new();
+    let mut next = 0;
+    let report = epd::run_suite(&mut engine, &suite, &limits, |position| {
+        let record = &suite[next];
+        next += 1;
+        let expected: Vec<String> = ["bm", "am", "c0"]
+            .iter()
+            .filter_map(|&opcode| {
+                let operands = record.operands(opcode)?;
+                Some(format!("{} {}", opcode, operands.join(" ")))
+            })
+            .collect();
+        println!(
+            "{}: {} ({}) {} depth {} score {}",
+            position.id,
+            position.san,
+            expected.join("; "),
+            if position.solved { "solved" } else { "failed" },
+            position.result.depth,
+            position.result.score
+        );
+    })?;
+    if let Some(path) = &output {
+        for (epd, position) in suite.iter().zip(report.results.iter()) {
+            let mut epd = epd.clone();
+            epd.set_analysis(&position.result, position.elapsed)?;
+            analysed.push_str(&epd.to_string());
+            analysed.push('\n');
+        }
+        fs::write(path, analysed).map_err(|e| format!("could not write {}: {}", path, e))?;
+    }
+    println!("{}", report);
+    Ok(())
+}
+
+fn parse<T: std::str::FromStr>(text: &str) -> Result<T, String> {
+    text.parse()
+        .map_err(|_| format!("{} is not a valid number", text))
+}
@@ -0,0 +1,163 @@
+// Plays engines against each other, the built-in one in any configuration or
+// external uci engines:
+// chess-match --engine [name=NAME] [cmd=PROGRAM] [arg=ARG]... [option.NAME=VALUE]...
+//         [depth=N] [nodes=N] [movetime=MS]
+//     --engine ... [--engine ...]... [--games N] [--openings FILE] [--concurrency N]
+//     [--tc TC] [--draw MOVE,SCORE,PLIES] [--resign SCORE,PLIES] [--max-moves N]
+//     [-o games.pgn]
+use std::env;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::process;
+use std::time::Duration;
+
+use chess::tournament::{
+    read_openings, run_match, DrawAdjudication, Player, ResignAdjudication, TimeControl,
+    TournamentOptions,
+};
+
+fn main() {
+    if let Err(message) = run(env::args().skip(1).collect()) {
+        eprintln!("{}", message);
+        eprintln!(
+            "usage: chess-match --engine [name=NAME] [cmd=PROGRAM] [arg=ARG]... \
+             [option.NAME=VALUE]... [depth=N] [nodes=N] [movetime=MS] --engine ... \
+             [--games N] [--openings FILE] [--concurrency N] [--tc TC] \
+             [--draw MOVE,SCORE,PLIES] [--resign SCORE,PLIES] [--max-moves N] [-o games.pgn]"
+        );
+        process::exit(1);
+    }
+}
+
+fn run(args: Vec<String>) -> Result<(), String> {
+    let mut players: Vec<Player> = vec![];
+    let mut options = TournamentOptions {
+        games: 2,
+        event: String::from("chess match"),
+        ..TournamentOptions::default()
+    };
+    let mut openings = vec![];
+    let mut concurrency = 1;
+    let mut output: Option<String> = None;
+    let mut args = args.into_iter().peekable();
+    while let Some(arg) = args.next() {
+        if arg == "--engine" {
+            let mut player = Player::default();
+            while let Some(field) = args.next_if(|field| !field.starts_with('-')) {
+                engine_field(&mut player, &field)?;
+            }
+            if player.name.is_empty() {
+                player.name = match player.command.first() {
+                    Some(program) => program.clone(),
+                    None => format!("engine{}", players.len() + 1),
+                };
+            }
+            players.push(player);
+            continue;
+        }
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--games" => options.games = parse(&value()?)?,
+            "--openings" => openings = read_openings(&value()?)?,
+            "--concurrency" => concurrency = parse(&value()?)?,
+            "--tc" => options.time_control = Some(TimeControl::parse(&value()?)?),
+            "--draw" => {
+                let numbers: Vec<i32> = numbers(&value()?, 3)?;
+                options.draw = Some(DrawAdjudication {
+                    move_number: numbers[0].max(0) as usize,
+                    score: numbers[1],
+                    plies: numbers[2].max(1) as usize,
+                });
+            }
+            "--resign" => {
+                let numbers: Vec<i32> = numbers(&value()?, 2)?;
+                options.resign = Some(ResignAdjudication {
+                    score: numbers[0],
+                    plies: numbers[1].max(1) as usize,
+                });
+            }
+            "--max-moves" => options.max_moves = Some(parse(&value()?)?),
+            "-o" | "--output" => output = Some(value()?),
+            _ => return Err(format!("unknown option {}", arg)),
+        }
+    }
+    if players.len() < 2 {
+        return Err(String::from("at least two engines are needed"));
+    }
+    if let Some(path) = &output {
+        fs::write(path, "").map_err(|e| format!("could not write {}: {}", path, e))?;
+    }
+
+    let mut error: Option<String> = None;
+    let mut played = 0;
+    let crosstable = run_match(
+        &players,
+        &openings,
+        &options,
+        concurrency,
+        |record, crosstable| {
+            played += 1;
+            println!(
+                "Game {} (round {}): {} - {} {} ({})",
+                played,
+                record.pgn.tag("Round").unwrap_or("?"),
+                crosstable.names[record.white],
+                crosstable.names[record.black],
+                record.outcome.pgn_result(),
+                record.termination
+            );
+            if let Some(path) = &output {
+                let written = record.pgn.to_pgn().and_then(|text| {
+                    OpenOptions::new()
+                        .append(true)
+                        .open(path)
+                        .and_then(|mut file| file.write_all(text.as_bytes()))
+                        .map_err(|e| format!("could not write {}: {}", path, e))
+                });
+                if let Err(message) = written {
+                    error.get_or_insert(message);
+                }
+            }
+        },
+    )?;
+    if let Some(message) = error {
+        return Err(message);
+    }
+    println!("{}", crosstable);
+    Ok(())
+}
+
+// One KEY=VALUE of an --engine
+fn engine_field(player: &mut Player, field: &str) -> Result<(), String> {
+    let (key, value) = field
+        .split_once('=')
+        .ok_or(format!("{} is not KEY=VALUE", field))?;
+    match key {
+        "name" => player.name = value.to_string(),
+        "cmd" => player.command.insert(0, value.to_string()),
+        "arg" => player.command.push(value.to_string()),
+        "depth" => player.limits.depth = Some(parse(value)?),
+        "nodes" => player.limits.nodes = Some(parse(value)?),
+        "movetime" => player.limits.movetime = Some(Duration::from_millis(parse(value)?)),
+        _ => match key.strip_prefix("option.") {
+            Some(name) => player.options.push((name.to_string(), value.to_string())),
+            None => return Err(format!("unknown engine setting {}", key)),
+        },
+    }
+    Ok(())
+}
+
+fn numbers(text: &str, count: usize) -> Result<Vec<i32>, String> {
+    let numbers: Vec<i32> = text.split(',').map(parse).collect::<Result<_, _>>()?;
+    if numbers.len() != count {
+        return Err(format!("{} needs {} values", text, count));
+    }
+    Ok(numbers)
+}
+
+fn parse<T: std::str::FromStr>(text: &str) -> Result<T, String> {
+    text.trim()
+        .parse()
+        .map_err(|_| format!("{} is not a valid number", text))
+}
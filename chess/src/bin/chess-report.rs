@@ -0,0 +1,86 @@
+// Analyses the games of a pgn file move by move and prints every move's
+// classification and each player's accuracy, optionally writing the games
+// annotated with the engine's evaluations and lines:
+// chess-report [--depth N] [--nodes N] [--movetime MS] [--game N]
+//     [--option NAME=VALUE]... [-o annotated.pgn] games.pgn
+use std::env;
+use std::fs;
+use std::process;
+use std::time::Duration;
+
+use chess::engine::Engine;
+use chess::pgn;
+use chess::report::GameReport;
+use chess::search::SearchLimits;
+
+fn main() {
+    if let Err(message) = run(env::args().skip(1).collect()) {
+        eprintln!("{}", message);
+        eprintln!(
+            "usage: chess-report [--depth N] [--nodes N] [--movetime MS] [--game N] \
+             [--option NAME=VALUE]... [-o annotated.pgn] games.pgn"
+        );
+        process::exit(1);
+    }
+}
+
+fn run(args: Vec<String>) -> Result<(), String> {
+    let mut engine = Engine::default();
+    let mut limits = SearchLimits::default();
+    let mut only: Option<usize> = None;
+    let mut input: Option<String> = None;
+    let mut output: Option<String> = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--depth" => limits.depth = Some(parse(&value()?)?),
+            "--nodes" => limits.nodes = Some(parse(&value()?)?),
+            "--movetime" => limits.movetime = Some(Duration::from_millis(parse(&value()?)?)),
+            "--game" => only = Some(parse(&value()?)?),
+            "--option" => {
+                let text = value()?;
+                let (name, value) = text
+                    .split_once('=')
+                    .ok_or(format!("{} is not NAME=VALUE", text))?;
+                engine.set_option(name, value)?;
+            }
+            "-o" | "--output" => output = Some(value()?),
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}", arg)),
+            _ => input = Some(arg),
+        }
+    }
+    let input = input.ok_or("no pgn file given")?;
+    if limits.depth.is_none() && limits.nodes.is_none() && limits.movetime.is_none() {
+        limits.movetime = Some(Duration::from_millis(500));
+    }
+
+    let mut annotated = String::new();
+    for (index, game) in pgn::read_games_tagged(&input)?.into_iter().enumerate() {
+        if only.is_some_and(|only| only != index + 1) {
+            continue;
+        }
+        let mut game = game.map_err(|e| format!("game {}: {}", index + 1, e))?;
+        engine.new_game();
+        let report = GameReport::from_pgn(&engine, &game, &limits, |_| {})?;
+        println!(
+            "Game {}: {} - {} {}",
+            index + 1,
+            report.names[0],
+            report.names[1],
+            game.result
+        );
+        println!("{}", report);
+        report.annotate(&mut game);
+        annotated.push_str(&game.to_pgn()?);
+    }
+    if let Some(path) = &output {
+        fs::write(path, annotated).map_err(|e| format!("could not write {}: {}", path, e))?;
+    }
+    Ok(())
+}
+
+fn parse<T: std::str::FromStr>(text: &str) -> Result<T, String> {
+    text.parse()
+        .map_err(|_| format!("{} is not a valid number", text))
+}
@@ -0,0 +1,172 @@
+// Plays two configurations of the engine against each other:
+// chess-tournament [--games N] [--openings FILE] [--tc TC] [--depth N] [--nodes N]
+//     [--name1 NAME] [--name2 NAME] [--option1 NAME=VALUE]... [--option2 NAME=VALUE]...
+//     [--draw MOVE,SCORE,PLIES] [--resign SCORE,PLIES] [--max-moves N]
+//     [--sprt ELO0,ELO1[,ALPHA,BETA]] [-o games.pgn]
+use std::env;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::process;
+
+use chess::tournament::{
+    read_openings, DrawAdjudication, Player, ResignAdjudication, Sprt, SprtVerdict, TimeControl,
+    Tournament, TournamentOptions,
+};
+
+fn main() {
+    if let Err(message) = run(env::args().skip(1).collect()) {
+        eprintln!("{}", message);
+        eprintln!(
+            "usage: chess-tournament [--games N] [--openings FILE] [--tc TC] [--depth N] [--nodes N] \
+             [--name1 NAME] [--name2 NAME] [--option1 NAME=VALUE]... [--option2 NAME=VALUE]... \
+             [--draw MOVE,SCORE,PLIES] [--resign SCORE,PLIES] [--max-moves N] \
+             [--sprt ELO0,ELO1[,ALPHA,BETA]] [-o games.pgn]"
+        );
+        process::exit(1);
+    }
+}
+
+fn run(args: Vec<String>) -> Result<(), String> {
+    let mut players = [
+        Player {
+            name: String::from("engine1"),
+            ..Player::default()
+        },
+        Player {
+            name: String::from("engine2"),
+            ..Player::default()
+        },
+    ];
+    let mut options = TournamentOptions {
+        games: 2,
+        event: String::from("chess tournament"),
+        ..TournamentOptions::default()
+    };
+    let mut openings = vec![];
+    let mut output: Option<String> = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--games" => options.games = parse(&value()?)?,
+            "--openings" => openings = read_openings(&value()?)?,
+            "--tc" => options.time_control = Some(TimeControl::parse(&value()?)?),
+            "--depth" => {
+                let depth = parse(&value()?)?;
+                players.iter_mut().for_each(|p| p.limits.depth = Some(depth));
+            }
+            "--nodes" => {
+                let nodes = parse(&value()?)?;
+                players.iter_mut().for_each(|p| p.limits.nodes = Some(nodes));
+            }
+            "--name1" => players[0].name = value()?,
+            "--name2" => players[1].name = value()?,
+            "--option1" => players[0].options.push(option(&value()?)?),
+            "--option2" => players[1].options.push(option(&value()?)?),
+            "--draw" => {
+                let numbers: Vec<i32> = numbers(&value()?, 3)?;
+                options.draw = Some(DrawAdjudication {
+                    move_number: numbers[0].max(0) as usize,
+                    score: numbers[1],
+                    plies: numbers[2].max(1) as usize,
+                });
+            }
+            "--resign" => {
+                let numbers: Vec<i32> = numbers(&value()?, 2)?;
+                options.resign = Some(ResignAdjudication {
+                    score: numbers[0],
+                    plies: numbers[1].max(1) as usize,
+                });
+            }
+            "--max-moves" => options.max_moves = Some(parse(&value()?)?),
+            "--sprt" => {
+                let text = value()?;
+                let numbers: Vec<f64> = text.split(',').map(parse).collect::<Result<_, _>>()?;
+                let mut sprt = match numbers[..] {
+                    [elo0, elo1, ..] => Sprt::new(elo0, elo1),
+                    _ => return Err(format!("{} needs at least two values", arg)),
+                };
+                if let [_, _, alpha, beta] = numbers[..] {
+                    sprt.alpha = alpha;
+                    sprt.beta = beta;
+                }
+                options.sprt = Some(sprt);
+            }
+            "-o" | "--output" => output = Some(value()?),
+            _ => return Err(format!("unknown option {}", arg)),
+        }
+    }
+    if let Some(path) = &output {
+        fs::write(path, "").map_err(|e| format!("could not write {}: {}", path, e))?;
+    }
+
+    let names = [players[0].name.clone(), players[1].name.clone()];
+    let sprt = options.sprt;
+    let tournament = Tournament::new(players, openings, options);
+    let mut error: Option<String> = None;
+    let standings = tournament.run(|record, standings| {
+        println!(
+            "Game {}: {} - {} {} ({}), {} {}",
+            standings.games(),
+            names[record.white],
+            names[1 - record.white],
+            record.outcome.pgn_result(),
+            record.termination,
+            names[0],
+            standings
+        );
+        if let Some(path) = &output {
+            let written = record.pgn.to_pgn().and_then(|text| {
+                OpenOptions::new()
+                    .append(true)
+                    .open(path)
+                    .and_then(|mut file| file.write_all(text.as_bytes()))
+                    .map_err(|e| format!("could not write {}: {}", path, e))
+            });
+            if let Err(message) = written {
+                error.get_or_insert(message);
+            }
+        }
+    })?;
+    if let Some(message) = error {
+        return Err(message);
+    }
+
+    println!("{} vs {}: {}", names[0], names[1], standings);
+    if let Some(sprt) = sprt {
+        let (lower, upper) = sprt.bounds();
+        let llr = sprt.llr(standings.wins, standings.draws, standings.losses);
+        let verdict = match sprt.verdict(standings.wins, standings.draws, standings.losses) {
+            SprtVerdict::AcceptH0 => "H0 accepted",
+            SprtVerdict::AcceptH1 => "H1 accepted",
+            SprtVerdict::Continue => "no decision",
+        };
+        println!(
+            "SPRT elo0 {} elo1 {}: LLR {:.2} ({:.2}, {:.2}), {}",
+            sprt.elo0, sprt.elo1, llr, lower, upper, verdict
+        );
+    }
+    Ok(())
+}
+
+fn option(text: &str) -> Result<(String, String), String> {
+    match text.split_once('=') {
+        Some((name, value)) => Ok((name.to_string(), value.to_string())),
+        None => Err(format!("{} is not NAME=VALUE", text)),
+    }
+}
+
+fn numbers(text: &str, count: usize) -> Result<Vec<i32>, String> {
+    let numbers: Vec<i32> = text.split(',').map(parse).collect::<Result<_, _>>()?;
+    if numbers.len() != count {
+        return Err(format!("{} needs {} values", text, count));
+    }
+    Ok(numbers)
+}
+
+fn parse<T: std::str::FromStr>(text: &str) -> Result<T, String> {
+    text.trim()
+        .parse()
+        .map_err(|_| format!("{} is not a valid number", text))
+}
@@ -0,0 +1,97 @@
+// Tunes the evaluation weights on the quiet positions of pgn games and
+// writes them as the definition of DEFAULT_WEIGHTS in src/eval.rs:
+// chess-tune [--method gradient|local] [--iterations N] [--rate R] [--threads N]
+//     [--skip PLIES] [--k K] [--weights FILE] -o weights.rs games.pgn...
+use std::env;
+use std::fs;
+use std::process;
+
+use chess::eval::{Weights, DEFAULT_WEIGHTS};
+use chess::tuner;
+use chess::tuner::{Method, TunerOptions};
+
+fn main() {
+    if let Err(message) = run(env::args().skip(1).collect()) {
+        eprintln!("{}", message);
+        eprintln!(
+            "usage: chess-tune [--method gradient|local] [--iterations N] [--rate R] \
+             [--threads N] [--skip PLIES] [--k K] [--weights FILE] -o weights.rs games.pgn..."
+        );
+        process::exit(1);
+    }
+}
+
+fn run(args: Vec<String>) -> Result<(), String> {
+    let mut options = TunerOptions::default();
+    let mut skip_plies = 8;
+    let mut weights = DEFAULT_WEIGHTS;
+    let mut output: Option<String> = None;
+    let mut inputs: Vec<String> = vec![];
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--method" => {
+                options.method = match value()?.as_str() {
+                    "gradient" => Method::GradientDescent,
+                    "local" => Method::LocalSearch,
+                    method => return Err(format!("unknown method {}", method)),
+                }
+            }
+            "--iterations" => options.iterations = parse(&value()?)?,
+            "--rate" => options.learning_rate = parse(&value()?)?,
+            "--threads" => options.threads = parse(&value()?)?,
+            "--skip" => skip_plies = parse(&value()?)?,
+            "--k" => options.k = Some(parse(&value()?)?),
+            "--weights" => {
+                let path = value()?;
+                let text = fs::read_to_string(&path)
+                    .map_err(|e| format!("could not read {}: {}", path, e))?;
+                weights = Weights::parse(&text)?;
+            }
+            "-o" | "--output" => output = Some(value()?),
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}", arg)),
+            _ => inputs.push(arg),
+        }
+    }
+    let output = output.ok_or("no output file given")?;
+    if inputs.is_empty() {
+        return Err(String::from("no pgn files given"));
+    }
+
+    let positions = tuner::read_positions(&inputs, skip_plies)?;
+    if positions.is_empty() {
+        return Err(String::from("no quiet positions in the games"));
+    }
+    let start: Vec<f64> = weights.to_vec().iter().map(|&w| w as f64).collect();
+    let k = match options.k {
+        Some(k) => k,
+        None => tuner::optimal_k(&positions, &start, options.threads),
+    };
+    options.k = Some(k);
+    println!(
+        "{} positions, k {:.4}, error {:.6}",
+        positions.len(),
+        k,
+        tuner::error(&positions, &start, k, options.threads)
+    );
+
+    let every = match options.method {
+        Method::GradientDescent => 50,
+        Method::LocalSearch => 1,
+    };
+    let tuned = tuner::tune(&positions, &weights, &options, |iteration, error| {
+        if iteration % every == 0 {
+            println!("iteration {}: error {:.6}", iteration, error);
+        }
+    });
+    fs::write(&output, tuned.to_string())
+        .map_err(|e| format!("could not write {}: {}", output, e))?;
+    println!("weights written to {}", output);
+    Ok(())
+}
+
+fn parse<T: std::str::FromStr>(text: &str) -> Result<T, String> {
+    text.parse()
+        .map_err(|_| format!("{} is not a valid number", text))
+}
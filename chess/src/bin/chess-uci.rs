@@ -0,0 +1,4 @@
+// Universal Chess Interface engine, for use with chess GUIs
+fn main() {
+    chess::uci::run();
+}
@@ -0,0 +1,4 @@
+// Chess Engine Communication Protocol engine, for xboard and winboard
+fn main() {
+    chess::xboard::run();
+}
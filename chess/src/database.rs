@@ -0,0 +1,551 @@
+// A local database of games imported from pgn files. Only the tags, the
+// result and the main line of a game are kept, every move in two bytes, and
+// a tag value shared by many games (a player, an event) is stored once.
+// Every position the games reach is indexed by its polyglot hash, so the
+// games reaching a position are found by a binary search of the index
+// whatever move order led to it.
+//
+// Database file: the magic "CGDB" and the version u32 (1) little endian,
+// then every count, string index and ply as a LEB128 varint (seven bits to
+// a byte, the lowest first, the high bit set on all but the last byte):
+//   strings, then each string as its length and its utf-8 bytes
+//   games, then each game as
+//     result u8: 0 unknown, 1 white won, 2 black won, 3 draw
+//     tags, then a name and a value string index for each
+//     plies, then each move as a u16 little endian polyglot move
+//   positions, then for each its key as a u64 little endian, the number of
+//     games reaching it, and for each game the difference of its number to
+//     the one before (to 0 for the first) and the ply reaching the position
+// Positions are in the order of their keys, their games in order. A game is
+// indexed once for each position, at the first ply reaching it.
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+
+use crate::game::Game;
+use crate::moves::Action;
+use crate::opening_book::{decode_move, encode_move};
+use crate::pgn;
+use crate::pgn::PgnGame;
+
+const MAGIC: &[u8; 4] = b"CGDB";
+const VERSION: u32 = 1;
+// the pgn results by their code in the file
+const RESULTS: [&str; 4] = ["*", "1-0", "0-1", "1/2-1/2"];
+const MAX_PLIES: usize = u16::MAX as usize;
+
+// A position reached `ply` moves into a game
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Occurrence {
+    pub key: u64,
+    pub game: u32,
+    pub ply: u16,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Record {
+    result: u8,
+    tags: Vec<(u32, u32)>,
+    moves: Vec<u16>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Database {
+    strings: Vec<String>,
+    string_ids: HashMap<String, u32>,
+    games: Vec<Record>,
+    positions: Vec<Occurrence>,
+}
+
+// Which games to find, every filter given has to match. Names match
+// case-insensitively anywhere in the tag, so "carlsen" finds
+// "Carlsen, Magnus".
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Query {
+    // the polyglot hash of a position the games reach
+    pub position: Option<u64>,
+    pub white: Option<String>,
+    pub black: Option<String>,
+    // either player
+    pub player: Option<String>,
+    pub event: Option<String>,
+    // "1-0", "0-1", "1/2-1/2" or "*"
+    pub result: Option<String>,
+}
+
+// The results of a number of games, unfinished ones only counted in `games`
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Results {
+    pub games: u32,
+    pub white_wins: u32,
+    pub draws: u32,
+    pub black_wins: u32,
+}
+
+impl Results {
+    fn add(&mut self, result: u8) {
+        self.games += 1;
+        match result {
+            1 => self.white_wins += 1,
+            2 => self.black_wins += 1,
+            3 => self.draws += 1,
+            _ => {}
+        }
+    }
+
+    // White's share of the points of the finished games
+    pub fn white_score(&self) -> Option<f64> {
+        let finished = self.white_wins + self.draws + self.black_wins;
+        if finished == 0 {
+            return None;
+        }
+        Some((self.white_wins as f64 + self.draws as f64 / 2.0) / finished as f64)
+    }
+}
+
+impl fmt::Display for Results {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} games +{} ={} -{}",
+            self.games, self.white_wins, self.draws, self.black_wins
+        )?;
+        if let Some(score) = self.white_score() {
+            write!(f, " ({:.1}%)", 100.0 * score)?;
+        }
+        Ok(())
+    }
+}
+
+// The games reaching a position: their results, and the moves played from
+// it with the results of each, most played first
+#[derive(Debug, Clone, Default)]
+pub struct PositionStats {
+    pub results: Results,
+    pub moves: Vec<(Action, Results)>,
+}
+
+impl Database {
+    pub fn new() -> Database {
+        Database::default()
+    }
+
+    pub fn open(filepath: &str) -> Result<Database, String> {
+        let bytes = fs::read(filepath)
+            .map_err(|e| format!("could not read database {}: {}", filepath, e))?;
+        Database::from_bytes(&bytes)
+    }
+
+    pub fn write(&self, filepath: &str) -> Result<(), String> {
+        fs::write(filepath, self.to_bytes())
+            .map_err(|e| format!("could not write {}: {}", filepath, e))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Database, String> {
+        let mut reader = Reader { bytes, position: 0 };
+        if reader.take(4).ok() != Some(&MAGIC[..]) {
+            return Err(String::from("not a game database"));
+        }
+        let version = reader.u32()?;
+        if version != VERSION {
+            return Err(format!("unsupported database version {}", version));
+        }
+
+        let mut database = Database::new();
+        for _ in 0..reader.varint()? {
+            let length = reader.varint()? as usize;
+            let text = std::str::from_utf8(reader.take(length)?)
+                .map_err(|_| String::from("a string of the database isn't utf-8"))?;
+            let id = database.strings.len() as u32;
+            database.strings.push(text.to_string());
+            database.string_ids.entry(text.to_string()).or_insert(id);
+        }
+        let strings = database.strings.len() as u64;
+        for _ in 0..reader.varint()? {
+            let corrupt = format!("game {} of the database is corrupt", database.games.len());
+            let result = reader.u8()?;
+            let mut tags = vec![];
+            for _ in 0..reader.varint()? {
+                let (name, value) = (reader.varint()?, reader.varint()?);
+                if name >= strings || value >= strings {
+                    return Err(corrupt);
+                }
+                tags.push((name as u32, value as u32));
+            }
+            let mut moves = vec![];
+            for _ in 0..reader.varint()? {
+                moves.push(reader.u16()?);
+            }
+            if result as usize >= RESULTS.len() || moves.len() > MAX_PLIES {
+                return Err(corrupt);
+            }
+            database.games.push(Record {
+                result,
+                tags,
+                moves,
+            });
+        }
+        for _ in 0..reader.varint()? {
+            let key = reader.u64()?;
+            let mut game: u64 = 0;
+            for _ in 0..reader.varint()? {
+                game = game.saturating_add(reader.varint()?);
+                let ply = reader.varint()?;
+                let record = database.games.get(game as usize);
+                if record.map_or(true, |record| ply > record.moves.len() as u64) {
+                    return Err(String::from(
+                        "the position index of the database is corrupt",
+                    ));
+                }
+                database.positions.push(Occurrence {
+                    key,
+                    game: game as u32,
+                    ply: ply as u16,
+                });
+            }
+        }
+        if reader.position != bytes.len() {
+            return Err(String::from("the database has trailing bytes"));
+        }
+        database.sort_index();
+        Ok(database)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        push_varint(&mut bytes, self.strings.len() as u64);
+        for text in self.strings.iter() {
+            push_varint(&mut bytes, text.len() as u64);
+            bytes.extend_from_slice(text.as_bytes());
+        }
+        push_varint(&mut bytes, self.games.len() as u64);
+        for record in self.games.iter() {
+            bytes.push(record.result);
+            push_varint(&mut bytes, record.tags.len() as u64);
+            for &(name, value) in record.tags.iter() {
+                push_varint(&mut bytes, name as u64);
+                push_varint(&mut bytes, value as u64);
+            }
+            push_varint(&mut bytes, record.moves.len() as u64);
+            for raw_move in record.moves.iter() {
+                bytes.extend_from_slice(&raw_move.to_le_bytes());
+            }
+        }
+        let positions: Vec<&[Occurrence]> = self
+            .positions
+            .chunk_by(|occurrence, next| occurrence.key == next.key)
+            .collect();
+        push_varint(&mut bytes, positions.len() as u64);
+        for occurrences in positions {
+            bytes.extend_from_slice(&occurrences[0].key.to_le_bytes());
+            push_varint(&mut bytes, occurrences.len() as u64);
+            let mut previous = 0;
+            for occurrence in occurrences {
+                push_varint(&mut bytes, (occurrence.game - previous) as u64);
+                push_varint(&mut bytes, occurrence.ply as u64);
+                previous = occurrence.game;
+            }
+        }
+        bytes
+    }
+
+    // Number of games
+    pub fn len(&self) -> usize {
+        self.games.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.games.is_empty()
+    }
+
+    // Number of entries of the position index
+    pub fn positions(&self) -> usize {
+        self.positions.len()
+    }
+
+    // Adds a game and indexes its positions, giving its number. Fails for a
+    // game that doesn't start from a legal position.
+    pub fn add_game(&mut self, pgn_game: &PgnGame) -> Result<usize, String> {
+        let id = self.push_game(pgn_game)?;
+        // the new game's entries are a sorted run at the end, which the
+        // stable sort merges in linear time
+        self.sort_index();
+        Ok(id)
+    }
+
+    // Adds every game of a pgn file, returns how many were added. Games that
+    // can't be read are skipped.
+    pub fn add_pgn_file(&mut self, filepath: &str) -> Result<usize, String> {
+        let mut added = 0;
+        for pgn_game in pgn::read_games(filepath)?.iter().flatten() {
+            if self.push_game(pgn_game).is_ok() {
+                added += 1;
+            }
+        }
+        self.sort_index();
+        Ok(added)
+    }
+
+    fn push_game(&mut self, pgn_game: &PgnGame) -> Result<usize, String> {
+        let id = self.games.len();
+        if id >= u32::MAX as usize {
+            return Err(String::from("the database is full"));
+        }
+        let mut game = pgn_game.start_position()?;
+        let mut seen = HashSet::new();
+        let mut occurrences = vec![];
+        let mut moves = vec![];
+        let actions = &pgn_game.actions[..pgn_game.actions.len().min(MAX_PLIES)];
+        for ply in 0..=actions.len() {
+            if seen.insert(game.hash()) {
+                occurrences.push(Occurrence {
+                    key: game.hash(),
+                    game: id as u32,
+                    ply: ply as u16,
+                });
+            }
+            if let Some(action) = actions.get(ply) {
+                moves.push(encode_move(action));
+                game.perform_action(*action);
+            }
+        }
+        occurrences.sort_by_key(|occurrence| occurrence.key);
+        self.positions.extend(occurrences);
+
+        let tags: Vec<(u32, u32)> = pgn_game
+            .tags
+            .iter()
+            .map(|(name, value)| (self.intern(name), self.intern(value)))
+            .collect();
+        let result = RESULTS
+            .iter()
+            .position(|&result| result == pgn_game.result)
+            .unwrap_or(0);
+        self.games.push(Record {
+            result: result as u8,
+            tags,
+            moves,
+        });
+        Ok(id)
+    }
+
+    fn intern(&mut self, text: &str) -> u32 {
+        if let Some(&id) = self.string_ids.get(text) {
+            return id;
+        }
+        let id = self.strings.len() as u32;
+        self.strings.push(text.to_string());
+        self.string_ids.insert(text.to_string(), id);
+        id
+    }
+
+    fn sort_index(&mut self) {
+        self.positions
+            .sort_by_key(|occurrence| (occurrence.key, occurrence.game, occurrence.ply));
+    }
+
+    // The game with the given number, as it was imported but for comments
+    // and variations
+    pub fn game(&self, id: usize) -> Result<PgnGame, String> {
+        let record = self
+            .games
+            .get(id)
+            .ok_or(format!("there is no game {}", id))?;
+        let mut pgn_game = PgnGame {
+            tags: record
+                .tags
+                .iter()
+                .map(|&(name, value)| {
+                    (
+                        self.strings[name as usize].clone(),
+                        self.strings[value as usize].clone(),
+                    )
+                })
+                .collect(),
+            actions: vec![],
+            annotations: vec![],
+            result: RESULTS[record.result as usize].to_string(),
+        };
+        let mut game = pgn_game.start_position()?;
+        for &raw_move in record.moves.iter() {
+            let legal = game.all_moves();
+            let action =
+                decode_move(&legal, raw_move).ok_or(format!("game {} has an illegal move", id))?;
+            game.perform_action(action);
+            pgn_game.actions.push(action);
+        }
+        Ok(pgn_game)
+    }
+
+    pub fn tag(&self, id: usize, name: &str) -> Option<&str> {
+        let name = *self.string_ids.get(name)?;
+        self.games
+            .get(id)?
+            .tags
+            .iter()
+            .find(|&&(tag, _)| tag == name)
+            .map(|&(_, value)| self.strings[value as usize].as_str())
+    }
+
+    pub fn result(&self, id: usize) -> Option<&str> {
+        let record = self.games.get(id)?;
+        Some(RESULTS[record.result as usize])
+    }
+
+    // Where the position with the given polyglot hash was reached, by game
+    pub fn occurrences(&self, key: u64) -> &[Occurrence] {
+        let start = self
+            .positions
+            .partition_point(|occurrence| occurrence.key < key);
+        let end = self
+            .positions
+            .partition_point(|occurrence| occurrence.key <= key);
+        &self.positions[start..end]
+    }
+
+    // The numbers of the games matching the query, in the order they were
+    // added
+    pub fn search(&self, query: &Query) -> Vec<usize> {
+        let candidates: Vec<usize> = match query.position {
+            Some(key) => self
+                .occurrences(key)
+                .iter()
+                .map(|occurrence| occurrence.game as usize)
+                .collect(),
+            None => (0..self.games.len()).collect(),
+        };
+        let result = match &query.result {
+            Some(result) => match RESULTS.iter().position(|other| other == result) {
+                Some(code) => Some(code as u8),
+                None => return vec![],
+            },
+            None => None,
+        };
+        let white = query.white.as_deref().map(|name| self.matching(name));
+        let black = query.black.as_deref().map(|name| self.matching(name));
+        let player = query.player.as_deref().map(|name| self.matching(name));
+        let event = query.event.as_deref().map(|name| self.matching(name));
+        let has = |record: &Record, tag: &str, values: &Option<HashSet<u32>>| match values {
+            Some(values) => self.string_ids.get(tag).is_some_and(|name| {
+                record
+                    .tags
+                    .iter()
+                    .any(|(tag, value)| tag == name && values.contains(value))
+            }),
+            None => true,
+        };
+        candidates
+            .into_iter()
+            .filter(|&id| {
+                let record = &self.games[id];
+                result.map_or(true, |result| record.result == result)
+                    && has(record, "White", &white)
+                    && has(record, "Black", &black)
+                    && (player.is_none()
+                        || has(record, "White", &player)
+                        || has(record, "Black", &player))
+                    && has(record, "Event", &event)
+            })
+            .collect()
+    }
+
+    // The ids of the strings containing `text`, ignoring case
+    fn matching(&self, text: &str) -> HashSet<u32> {
+        let text = text.to_lowercase();
+        self.strings
+            .iter()
+            .enumerate()
+            .filter(|(_, other)| other.to_lowercase().contains(&text))
+            .map(|(id, _)| id as u32)
+            .collect()
+    }
+
+    // How the games reaching the position went on
+    pub fn stats(&self, game: &Game) -> PositionStats {
+        let mut game = game.clone();
+        let legal = game.all_moves();
+        let mut stats = PositionStats::default();
+        for occurrence in self.occurrences(game.hash()) {
+            let record = &self.games[occurrence.game as usize];
+            stats.results.add(record.result);
+            let next = record.moves.get(occurrence.ply as usize);
+            if let Some(action) = next.and_then(|&raw_move| decode_move(&legal, raw_move)) {
+                match stats
+                    .moves
+                    .iter_mut()
+                    .find(|(other, _)| other.key() == action.key())
+                {
+                    Some((_, results)) => results.add(record.result),
+                    None => {
+                        let mut results = Results::default();
+                        results.add(record.result);
+                        stats.moves.push((action, results));
+                    }
+                }
+            }
+        }
+        stats
+            .moves
+            .sort_by_key(|(_, results)| std::cmp::Reverse(results.games));
+        stats
+    }
+}
+
+fn push_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], String> {
+        let end = self.position.saturating_add(length);
+        let bytes = self
+            .bytes
+            .get(self.position..end)
+            .ok_or("the database is cut off")?;
+        self.position += length;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn varint(&mut self) -> Result<u64, String> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte < 0x80 {
+                return Ok(value);
+            }
+        }
+        Err(String::from("a number of the database is too long"))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+}
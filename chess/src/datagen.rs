@@ -0,0 +1,168 @@
+// Training data for evaluation networks out of self-play games: every quiet
+// position the engine moved in, with the score of its search and the result
+// of the game. A record is a line "FEN | SCORE | RESULT", the score in
+// centipawns and the result (1.0, 0.5 or 0.0) both for white.
+use std::fmt;
+
+use crate::engine::Engine;
+use crate::game::{Game, GameState, Outcome, Rank, Team};
+use crate::moves;
+use crate::moves::ActionType;
+use crate::random::xorshift;
+use crate::search::{SearchResult, TB_WIN};
+use crate::tournament;
+use crate::tournament::{Contestant, Opening, Player, TournamentOptions};
+use crate::uci_client::GoOptions;
+
+#[derive(Debug, Clone)]
+pub struct TrainingRecord {
+    pub fen: String,
+    pub score: i32,
+    pub result: f64,
+}
+
+impl fmt::Display for TrainingRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} | {} | {:.1}", self.fen, self.score, self.result)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DatagenOptions {
+    // random moves played before the engine takes over, so the games differ
+    pub random_plies: usize,
+    // the adjudication and move limit of the games
+    pub game: TournamentOptions,
+}
+
+impl Default for DatagenOptions {
+    fn default() -> Self {
+        DatagenOptions {
+            random_plies: 8,
+            game: TournamentOptions {
+                event: String::from("datagen"),
+                ..TournamentOptions::default()
+            },
+        }
+    }
+}
+
+// Plays the engine against itself, each side with its own engine
+pub struct Datagen {
+    player: Player,
+    recorders: [Recorder; 2],
+    options: DatagenOptions,
+}
+
+// An engine keeping the positions it moved in with its scores for white
+struct Recorder {
+    engine: Engine,
+    positions: Vec<(String, i32)>,
+}
+
+impl Contestant for Recorder {
+    fn new_game(&mut self) -> Result<(), String> {
+        self.positions.clear();
+        Contestant::new_game(&mut self.engine)
+    }
+
+    // Positions in check, with a tactical best move or a decided score are
+    // no good for training and left out
+    fn play(&mut self, game: &Game, go: &GoOptions) -> Result<SearchResult, String> {
+        let result = Contestant::play(&mut self.engine, game, go)?;
+        let quiet = result
+            .best_move
+            .is_some_and(|action| !moves::is_tactical(&action));
+        if quiet
+            && result.score.abs() < TB_WIN / 2
+            && game.clone().get_game_state() == GameState::Active
+        {
+            let score = match game.player {
+                Team::White => result.score,
+                Team::Black => -result.score,
+            };
+            self.positions.push((game.to_fen(), score));
+        }
+        Ok(result)
+    }
+}
+
+impl Datagen {
+    pub fn new(player: &Player, options: DatagenOptions) -> Result<Datagen, String> {
+        let recorder = || -> Result<Recorder, String> {
+            Ok(Recorder {
+                engine: player.engine()?,
+                positions: vec![],
+            })
+        };
+        Ok(Datagen {
+            player: player.clone(),
+            recorders: [recorder()?, recorder()?],
+            options,
+        })
+    }
+
+    // Plays one game from the random opening `random` picks and returns its
+    // records. An opening ending the game gives none.
+    pub fn play_game(&mut self, random: u64) -> Result<Vec<TrainingRecord>, String> {
+        let opening = random_opening(self.options.random_plies, random);
+        let mut start = opening.position()?;
+        if start.all_moves().is_empty() {
+            return Ok(vec![]);
+        }
+        let [white, black] = &mut self.recorders;
+        let record = tournament::play_game(
+            [&self.player, &self.player],
+            [white, black],
+            0,
+            &opening,
+            &self.options.game,
+            1,
+        )?;
+        let result = match record.outcome {
+            Outcome::Win(Team::White) => 1.0,
+            Outcome::Win(Team::Black) => 0.0,
+            Outcome::Draw => 0.5,
+        };
+        let mut records: Vec<TrainingRecord> = self
+            .recorders
+            .iter()
+            .flat_map(|recorder| recorder.positions.iter())
+            .map(|(fen, score)| TrainingRecord {
+                fen: fen.clone(),
+                score: *score,
+                result,
+            })
+            .collect();
+        records.sort_by_key(|record| fullmove(&record.fen));
+        Ok(records)
+    }
+}
+
+// `plies` random legal moves from the starting position, fewer if the game
+// ends before
+pub fn random_opening(plies: usize, random: u64) -> Opening {
+    let mut state = random | 1;
+    let mut game = Game::new();
+    let mut actions = vec![];
+    for _ in 0..plies {
+        let legal = game.all_moves();
+        if legal.is_empty() {
+            break;
+        }
+        let mut action = legal[(xorshift(&mut state) % legal.len() as u64) as usize];
+        if action.action_type == ActionType::Promotion {
+            action.promotion = Some(Rank::Queen);
+        }
+        game.perform_action(action);
+        actions.push(action);
+    }
+    Opening { fen: None, actions }
+}
+
+fn fullmove(fen: &str) -> (u32, bool) {
+    let mut fields = fen.split(' ');
+    let black = fields.nth(1) == Some("b");
+    let number = fields.nth(3).and_then(|n| n.parse().ok()).unwrap_or(0);
+    (number, black)
+}
@@ -0,0 +1,827 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{OnceLock, RwLock};
+
+use crate::game::{Game, Piece, Rank, Team};
+use crate::moves::ActionType;
+
+// Distance to mate tables for endgames of up to four pieces, made by
+// retrograde analysis: starting from the mates, every position one move
+// before a lost position is won, and every position whose moves all lead
+// to won positions is lost. The move generator of the crate gives the
+// moves of every position, so the tables double as a check of it.
+//
+// Pawns can only be on one side. With pawns on both sides a double step can
+// give the other side an en passant capture, and the positions of the
+// tables, indexed by their pieces alone, can't tell whether there is one;
+// KPvKP, the only such table of four pieces, isn't generated.
+
+// The result of a position for the side to move
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Dtm {
+    Draw,
+    // mates in this many moves
+    Win(u32),
+    // is mated in this many moves, 0 when already mated
+    Loss(u32),
+}
+
+impl Dtm {
+    fn from_byte(byte: u8) -> Dtm {
+        match byte {
+            0 | UNSEARCHED => Dtm::Draw,
+            _ => {
+                let plies = byte as u32 - 1;
+                if plies % 2 == 1 {
+                    Dtm::Win(plies.div_ceil(2))
+                } else {
+                    Dtm::Loss(plies / 2)
+                }
+            }
+        }
+    }
+
+    // Plies to mate, None for draws
+    fn plies(&self) -> Option<usize> {
+        match *self {
+            Dtm::Draw => None,
+            Dtm::Win(moves) => Some(2 * moves as usize - 1),
+            Dtm::Loss(moves) => Some(2 * moves as usize),
+        }
+    }
+}
+
+const MAGIC: &[u8; 4] = b"CDTM";
+const MAX_PIECES: usize = 4;
+// marks positions the analysis skips: illegal ones and stalemates
+const UNSEARCHED: u8 = 255;
+const RANKS: [Rank; 6] = [
+    Rank::King,
+    Rank::Queen,
+    Rank::Rook,
+    Rank::Bishop,
+    Rank::Knight,
+    Rank::Pawn,
+];
+
+// A position of a table: the square of every piece (a1 = 0, h8 = 63) in
+// the order of the table's pieces, and the side to move
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Board {
+    squares: [usize; MAX_PIECES],
+    black_to_move: bool,
+}
+
+// One endgame, the white pieces as given in its name. Positions are stored
+// one byte each, 0 for draws and otherwise the plies to mate plus one, and
+// only with the white king on a1-d1-d4 (on files a-d with pawns): the other
+// positions are mirror images of these.
+#[derive(Debug, Clone)]
+pub struct DtmTable {
+    name: String,
+    pieces: Vec<(Team, Rank)>,
+    values: Vec<u8>,
+}
+
+impl DtmTable {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    // Number of stored positions
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    // The result of the position for the side to move, None if the table
+    // is for other material or the position can still castle
+    pub fn probe(&self, game: &Game) -> Option<Dtm> {
+        if game.castling_rights().contains(&true) {
+            return None;
+        }
+        let board = self.board_of(game)?;
+        Some(Dtm::from_byte(self.values[self.reduced_index(&board)]))
+    }
+
+    // The longest mate of the table in moves
+    pub fn longest_mate(&self) -> u32 {
+        self.values
+            .iter()
+            .map(|&byte| match Dtm::from_byte(byte) {
+                Dtm::Win(moves) => moves,
+                _ => 0,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(self.name.len() as u8);
+        bytes.extend_from_slice(self.name.as_bytes());
+        bytes.extend_from_slice(&self.values);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<DtmTable, String> {
+        if bytes.len() < 5 || &bytes[0..4] != MAGIC {
+            return Err(String::from("not a dtm table"));
+        }
+        let name_end = 5 + bytes[4] as usize;
+        let name = bytes
+            .get(5..name_end)
+            .and_then(|name| std::str::from_utf8(name).ok())
+            .ok_or("dtm table name is cut off")?;
+        let mut table = DtmTable::empty(name)?;
+        let values = &bytes[name_end..];
+        if values.len() != table.reduced_size() {
+            return Err(format!(
+                "{} table has {} positions instead of {}",
+                table.name,
+                values.len(),
+                table.reduced_size()
+            ));
+        }
+        table.values = values.to_vec();
+        Ok(table)
+    }
+
+    pub fn open(filepath: &str) -> Result<DtmTable, String> {
+        let bytes =
+            fs::read(filepath).map_err(|e| format!("could not read {}: {}", filepath, e))?;
+        DtmTable::from_bytes(&bytes)
+    }
+
+    pub fn write(&self, filepath: &str) -> Result<(), String> {
+        fs::write(filepath, self.to_bytes())
+            .map_err(|e| format!("could not write {}: {}", filepath, e))
+    }
+
+    fn empty(name: &str) -> Result<DtmTable, String> {
+        let (white, black) = split_material(name)?;
+        let name = format!("{}v{}", white, black);
+        let mut pieces = vec![];
+        for (team, side) in [(Team::White, &white), (Team::Black, &black)].iter() {
+            for letter in side.chars() {
+                pieces.push((*team, Rank::from_letter(letter).unwrap()));
+            }
+        }
+        if pieces.len() > MAX_PIECES {
+            return Err(format!("{} has more than {} pieces", name, MAX_PIECES));
+        }
+        if white.contains('P') && black.contains('P') {
+            return Err(format!(
+                "{} has pawns on both sides, which en passant rules out",
+                name
+            ));
+        }
+        Ok(DtmTable {
+            name,
+            pieces,
+            values: vec![],
+        })
+    }
+
+    fn has_pawns(&self) -> bool {
+        self.pieces.iter().any(|&(_, rank)| rank == Rank::Pawn)
+    }
+
+    // Squares the white king is stored on
+    fn king_squares(&self) -> usize {
+        if self.has_pawns() {
+            32
+        } else {
+            10
+        }
+    }
+
+    fn reduced_size(&self) -> usize {
+        2 * self.king_squares() * 64usize.pow(self.pieces.len() as u32 - 1)
+    }
+
+    fn full_size(&self) -> usize {
+        2 * 64usize.pow(self.pieces.len() as u32)
+    }
+
+    // Mirrors the board so that the white king is on a stored square
+    fn normalize(&self, board: &Board) -> Board {
+        let mut board = *board;
+        let n = self.pieces.len();
+        let transform = |board: &mut Board, f: fn(usize) -> usize| {
+            for square in board.squares[..n].iter_mut() {
+                *square = f(*square);
+            }
+        };
+        if board.squares[0] % 8 > 3 {
+            transform(&mut board, |s| s ^ 7);
+        }
+        if !self.has_pawns() {
+            if board.squares[0] / 8 > 3 {
+                transform(&mut board, |s| s ^ 56);
+            }
+            let king = board.squares[0];
+            if king / 8 > king % 8 {
+                transform(&mut board, |s| ((s >> 3) | (s << 3)) & 63);
+            }
+        }
+        board
+    }
+
+    fn reduced_index(&self, board: &Board) -> usize {
+        let board = self.normalize(board);
+        let king = board.squares[0];
+        let king_code = if self.has_pawns() {
+            (king / 8) * 4 + king % 8
+        } else {
+            TRIANGLE.iter().position(|&s| s == king).unwrap()
+        };
+        let mut index = board.black_to_move as usize * self.king_squares() + king_code;
+        for &square in board.squares[1..self.pieces.len()].iter() {
+            index = index * 64 + square;
+        }
+        index
+    }
+
+    fn reduced_board(&self, mut index: usize) -> Board {
+        let mut board = Board {
+            squares: [0; MAX_PIECES],
+            black_to_move: false,
+        };
+        for i in (1..self.pieces.len()).rev() {
+            board.squares[i] = index % 64;
+            index /= 64;
+        }
+        let king_code = index % self.king_squares();
+        board.squares[0] = if self.has_pawns() {
+            (king_code / 4) * 8 + king_code % 4
+        } else {
+            TRIANGLE[king_code]
+        };
+        board.black_to_move = index / self.king_squares() == 1;
+        board
+    }
+
+    fn full_index(&self, board: &Board) -> usize {
+        let mut index = board.black_to_move as usize;
+        for &square in board.squares[..self.pieces.len()].iter() {
+            index = index * 64 + square;
+        }
+        index
+    }
+
+    fn full_board(&self, mut index: usize) -> Board {
+        let mut board = Board {
+            squares: [0; MAX_PIECES],
+            black_to_move: false,
+        };
+        for i in (0..self.pieces.len()).rev() {
+            board.squares[i] = index % 64;
+            index /= 64;
+        }
+        board.black_to_move = index == 1;
+        board
+    }
+
+    // The board of the game if its material is the table's, with the
+    // colors swapped if it is the other side that has the pieces
+    fn board_of(&self, game: &Game) -> Option<Board> {
+        let white = material_name(game, Team::White);
+        let black = material_name(game, Team::Black);
+        let swap = if self.name == format!("{}v{}", white, black) {
+            false
+        } else if self.name == format!("{}v{}", black, white) {
+            true
+        } else {
+            return None;
+        };
+        let mut board = Board {
+            squares: [0; MAX_PIECES],
+            black_to_move: (game.player == Team::Black) != swap,
+        };
+        let mut used = [false; 64];
+        for (i, &(team, rank)) in self.pieces.iter().enumerate() {
+            let team = if swap { other(team) } else { team };
+            let square = (0..64).find(|&s| {
+                let piece = game.matrix[s % 8][s / 8].piece;
+                !used[s] && piece.is_some_and(|p| p.team == team && p.rank == rank)
+            })?;
+            used[square] = true;
+            board.squares[i] = if swap { square ^ 56 } else { square };
+        }
+        Some(board)
+    }
+
+    // Sets up the board on a copy of `template`, a game without castling
+    // rights or history. Cheaper than going through a fen, which works out
+    // the game state, since the analysis only needs the moves.
+    fn set_up(&self, template: &Game, board: &Board) -> Game {
+        let mut game = template.clone();
+        for square in game.matrix.iter_mut().flatten() {
+            square.piece = None;
+        }
+        for (i, &(team, rank)) in self.pieces.iter().enumerate() {
+            let square = board.squares[i];
+            let square = &mut game.matrix[square % 8][square / 8];
+            square.piece = Some(Piece { team, rank });
+            match (rank, team) {
+                (Rank::King, Team::White) => game.white_king_square = *square,
+                (Rank::King, Team::Black) => game.black_king_square = *square,
+                _ => {}
+            }
+        }
+        game.player = if board.black_to_move {
+            Team::Black
+        } else {
+            Team::White
+        };
+        game
+    }
+
+    // Pieces on their own squares, no pawns on the first or last rank and
+    // the side that just moved not in check
+    fn is_legal(&self, board: &Board) -> bool {
+        let n = self.pieces.len();
+        for i in 0..n {
+            let square = board.squares[i];
+            if board.squares[..i].contains(&square) {
+                return false;
+            }
+            if self.pieces[i].1 == Rank::Pawn && !(8..56).contains(&square) {
+                return false;
+            }
+        }
+        let moved = if board.black_to_move {
+            Team::White
+        } else {
+            Team::Black
+        };
+        let king = (0..n)
+            .find(|&i| self.pieces[i] == (moved, Rank::King))
+            .map(|i| board.squares[i])
+            .unwrap();
+        !self.attacked(board, king, other(moved))
+    }
+
+    fn attacked(&self, board: &Board, target: usize, by: Team) -> bool {
+        let occupied = |square: usize| board.squares[..self.pieces.len()].contains(&square);
+        self.pieces.iter().enumerate().any(|(i, &(team, rank))| {
+            let from = board.squares[i];
+            if team != by || from == target {
+                return false;
+            }
+            match rank {
+                Rank::King => step_targets(from, &KING_STEPS).contains(&target),
+                Rank::Knight => step_targets(from, &KNIGHT_STEPS).contains(&target),
+                Rank::Pawn => {
+                    let forward = if team == Team::White { 1 } else { -1 };
+                    let (x, y) = (from as isize % 8, from as isize / 8);
+                    let (tx, ty) = (target as isize % 8, target as isize / 8);
+                    ty == y + forward && (tx - x).abs() == 1
+                }
+                _ => slider_directions(rank)
+                    .iter()
+                    .any(|&direction| ray(from, direction, &occupied).contains(&target)),
+            }
+        })
+    }
+
+    // The squares a piece on the board can have come from with a move that
+    // didn't capture or promote
+    fn origins(&self, board: &Board, piece: usize) -> Vec<usize> {
+        let occupied = |square: usize| board.squares[..self.pieces.len()].contains(&square);
+        let from = board.squares[piece];
+        let (team, rank) = self.pieces[piece];
+        let origins: Vec<usize> = match rank {
+            Rank::King => step_targets(from, &KING_STEPS),
+            Rank::Knight => step_targets(from, &KNIGHT_STEPS),
+            Rank::Pawn => {
+                let (back, start_row, double_row) = match team {
+                    Team::White => (-8, 1, 3),
+                    Team::Black => (8, 6, 4),
+                };
+                let mut origins = vec![];
+                let one = (from as isize + back) as usize;
+                let row = |square: usize| square as isize / 8;
+                if !occupied(one) && (row(one) - start_row) * back.signum() <= 0 {
+                    origins.push(one);
+                    let two = (one as isize + back) as usize;
+                    if row(from) == double_row && !occupied(two) {
+                        origins.push(two);
+                    }
+                }
+                origins
+            }
+            _ => slider_directions(rank)
+                .iter()
+                .flat_map(|&direction| ray(from, direction, &occupied))
+                .collect(),
+        };
+        origins.into_iter().filter(|&s| !occupied(s)).collect()
+    }
+}
+
+// a1-d1-d4, the squares the white king is stored on without pawns
+const TRIANGLE: [usize; 10] = [0, 1, 2, 3, 9, 10, 11, 18, 19, 27];
+const KING_STEPS: [(isize, isize); 8] = [
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+    (0, -1),
+    (0, 1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+];
+const KNIGHT_STEPS: [(isize, isize); 8] = [
+    (-2, -1),
+    (-2, 1),
+    (-1, -2),
+    (-1, 2),
+    (1, -2),
+    (1, 2),
+    (2, -1),
+    (2, 1),
+];
+
+fn other(team: Team) -> Team {
+    match team {
+        Team::White => Team::Black,
+        Team::Black => Team::White,
+    }
+}
+
+fn step(square: usize, (dx, dy): (isize, isize)) -> Option<usize> {
+    let (x, y) = (square as isize % 8 + dx, square as isize / 8 + dy);
+    if (0..8).contains(&x) && (0..8).contains(&y) {
+        Some((y * 8 + x) as usize)
+    } else {
+        None
+    }
+}
+
+fn step_targets(square: usize, steps: &[(isize, isize)]) -> Vec<usize> {
+    steps.iter().filter_map(|&s| step(square, s)).collect()
+}
+
+fn slider_directions(rank: Rank) -> Vec<(isize, isize)> {
+    let straight = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+    let diagonal = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+    match rank {
+        Rank::Rook => straight.to_vec(),
+        Rank::Bishop => diagonal.to_vec(),
+        _ => [straight, diagonal].concat(),
+    }
+}
+
+// The squares in a direction up to and including the first occupied one
+fn ray(from: usize, direction: (isize, isize), occupied: &dyn Fn(usize) -> bool) -> Vec<usize> {
+    let mut squares = vec![];
+    let mut square = from;
+    while let Some(next) = step(square, direction) {
+        squares.push(next);
+        if occupied(next) {
+            break;
+        }
+        square = next;
+    }
+    squares
+}
+
+fn piece_value(letter: char) -> u32 {
+    match letter {
+        'Q' => 9,
+        'R' => 5,
+        'B' | 'N' => 3,
+        'P' => 1,
+        _ => 0,
+    }
+}
+
+// The two sides of a material name like "KRvK" or "KRK", the stronger side
+// first and each side's pieces in the order KQRBNP
+fn split_material(name: &str) -> Result<(String, String), String> {
+    let name = name.trim().to_uppercase();
+    let (white, black) = match name.split_once('V') {
+        Some(sides) => sides,
+        None => match name[1..].find('K') {
+            Some(index) => name.split_at(index + 1),
+            None => return Err(format!("{} is not a material like KQvK", name)),
+        },
+    };
+    let sort = |side: &str| -> Result<String, String> {
+        let mut letters: Vec<char> = side.chars().collect();
+        if letters.iter().filter(|&&c| c == 'K').count() != 1 {
+            return Err(format!("{} needs one king per side", name));
+        }
+        if let Some(&c) = letters.iter().find(|&&c| Rank::from_letter(c).is_none()) {
+            return Err(format!("{} is not a piece", c));
+        }
+        letters.sort_by_key(|&c| RANKS.iter().position(|r| r.letter() == c));
+        Ok(letters.into_iter().collect())
+    };
+    let (white, black) = (sort(white)?, sort(black)?);
+    let strength = |side: &String| (side.chars().map(piece_value).sum::<u32>(), side.clone());
+    if strength(&black) > strength(&white) {
+        Ok((black, white))
+    } else {
+        Ok((white, black))
+    }
+}
+
+fn material_name(game: &Game, team: Team) -> String {
+    let mut name = String::new();
+    for rank in RANKS.iter() {
+        for square in game.matrix.iter().flatten() {
+            if let Some(piece) = square.piece {
+                if piece.team == team && piece.rank == *rank {
+                    name.push(rank.letter());
+                }
+            }
+        }
+    }
+    name
+}
+
+fn only_kings(game: &Game) -> bool {
+    game.matrix
+        .iter()
+        .flatten()
+        .filter_map(|square| square.piece)
+        .all(|piece| piece.rank == Rank::King)
+}
+
+// What the move generator says about a stored position
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Status {
+    Illegal,
+    Mate,
+    Stalemate,
+    Moves(u8),
+}
+
+// Generates tables together with the smaller ones their captures and
+// promotions lead to
+#[derive(Debug, Clone, Default)]
+pub struct Generator {
+    tables: HashMap<String, DtmTable>,
+}
+
+impl Generator {
+    pub fn new() -> Generator {
+        Generator::default()
+    }
+
+    pub fn tables(&self) -> impl Iterator<Item = &DtmTable> {
+        self.tables.values()
+    }
+
+    pub fn into_tables(self) -> Vec<DtmTable> {
+        self.tables.into_values().collect()
+    }
+
+    pub fn generate(&mut self, material: &str) -> Result<&DtmTable, String> {
+        let mut table = DtmTable::empty(material)?;
+        if !self.tables.contains_key(&table.name) {
+            for smaller in smaller_materials(&table) {
+                self.generate(&smaller)?;
+            }
+            table.values = self.analyse(&table)?;
+            self.tables.insert(table.name.clone(), table.clone());
+        }
+        Ok(&self.tables[&table.name])
+    }
+
+    // The result of a position of a smaller table, None for draws
+    fn probe(&self, game: &Game) -> Result<Option<usize>, String> {
+        if only_kings(game) {
+            return Ok(None);
+        }
+        let white = material_name(game, Team::White);
+        let black = material_name(game, Team::Black);
+        let (stronger, weaker) = split_material(&format!("{}v{}", white, black))?;
+        let name = format!("{}v{}", stronger, weaker);
+        let table = self
+            .tables
+            .get(&name)
+            .ok_or(format!("{} hasn't been generated", name))?;
+        Ok(table.probe(game).and_then(|dtm| dtm.plies()))
+    }
+
+    fn analyse(&self, table: &DtmTable) -> Result<Vec<u8>, String> {
+        // the moves of every stored position, and at what distance to mate
+        // the ones leaving the table (captures and promotions) are decided
+        let mut status = vec![Status::Illegal; table.reduced_size()];
+        let mut exits: Vec<(usize, usize)> = vec![];
+        let template = Game::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        for (index, status) in status.iter_mut().enumerate() {
+            let board = table.reduced_board(index);
+            if !table.is_legal(&board) {
+                continue;
+            }
+            let mut game = table.set_up(&template, &board);
+            let actions = game.legal_moves();
+            *status = if actions.is_empty() {
+                let king = match game.player {
+                    Team::White => game.white_king_square,
+                    Team::Black => game.black_king_square,
+                };
+                if game.check_square_attacked(king) {
+                    Status::Mate
+                } else {
+                    Status::Stalemate
+                }
+            } else {
+                let mut count = 0;
+                for action in actions {
+                    count += 1;
+                    if action.to.piece.is_none() && action.action_type != ActionType::Promotion {
+                        continue;
+                    }
+                    let mut child = game.clone();
+                    child.perform_action(action);
+                    if let Some(plies) = self.probe(&child)? {
+                        exits.push((index, plies));
+                    }
+                }
+                Status::Moves(count)
+            };
+        }
+
+        let mut values = vec![0u8; table.full_size()];
+        let mut remaining = vec![0u8; table.full_size()];
+        let mut levels: Vec<Vec<usize>> = vec![vec![]];
+        let mut exit_levels: Vec<Vec<usize>> = vec![];
+        let mut exits_of: HashMap<usize, Vec<usize>> = HashMap::new();
+        for &(index, plies) in exits.iter() {
+            exits_of.entry(index).or_default().push(plies);
+        }
+        for full in 0..table.full_size() {
+            let board = table.full_board(full);
+            let reduced = table.reduced_index(&board);
+            match status[reduced] {
+                Status::Illegal | Status::Stalemate => values[full] = UNSEARCHED,
+                Status::Mate => {
+                    values[full] = 1;
+                    levels[0].push(full);
+                }
+                Status::Moves(count) => {
+                    remaining[full] = count;
+                    for &plies in exits_of.get(&reduced).into_iter().flatten() {
+                        if exit_levels.len() <= plies {
+                            exit_levels.resize(plies + 1, vec![]);
+                        }
+                        exit_levels[plies].push(full);
+                    }
+                }
+            }
+        }
+
+        // every decided child of a position, in the order of its distance
+        // to mate, takes away one of its moves or makes it won
+        let mut plies = 0;
+        while plies < levels.len() || plies < exit_levels.len() {
+            if plies + 2 >= UNSEARCHED as usize {
+                return Err(format!("{} has mates too long to store", table.name));
+            }
+            let mut decided = vec![];
+            let mut decide = |full: usize, values: &mut Vec<u8>, remaining: &mut Vec<u8>| {
+                if values[full] != 0 {
+                    return;
+                }
+                if plies % 2 == 0 {
+                    values[full] = plies as u8 + 2;
+                    decided.push(full);
+                } else {
+                    remaining[full] -= 1;
+                    if remaining[full] == 0 {
+                        values[full] = plies as u8 + 2;
+                        decided.push(full);
+                    }
+                }
+            };
+            for &full in levels.get(plies).into_iter().flatten() {
+                let board = table.full_board(full);
+                let mover = if board.black_to_move {
+                    Team::White
+                } else {
+                    Team::Black
+                };
+                for piece in 0..table.pieces.len() {
+                    if table.pieces[piece].0 != mover {
+                        continue;
+                    }
+                    for origin in table.origins(&board, piece) {
+                        let mut before = board;
+                        before.squares[piece] = origin;
+                        before.black_to_move = !board.black_to_move;
+                        decide(table.full_index(&before), &mut values, &mut remaining);
+                    }
+                }
+            }
+            for &full in exit_levels.get(plies).into_iter().flatten() {
+                decide(full, &mut values, &mut remaining);
+            }
+            if levels.len() <= plies + 1 {
+                levels.push(vec![]);
+            }
+            levels[plies + 1] = decided;
+            plies += 1;
+            if levels[plies].is_empty() && plies >= exit_levels.len() {
+                break;
+            }
+        }
+
+        Ok((0..table.reduced_size())
+            .map(|index| {
+                let board = table.reduced_board(index);
+                match values[table.full_index(&board)] {
+                    UNSEARCHED => 0,
+                    value => value,
+                }
+            })
+            .collect())
+    }
+}
+
+// The materials a capture or promotion of the table can lead to, leaving
+// out the ones with only kings
+fn smaller_materials(table: &DtmTable) -> Vec<String> {
+    let mut materials = vec![];
+    let name = |pieces: &[(Team, Rank)]| {
+        let side = |team: Team| -> String {
+            pieces
+                .iter()
+                .filter(|&&(t, _)| t == team)
+                .map(|&(_, rank)| rank.letter())
+                .collect()
+        };
+        format!("{}v{}", side(Team::White), side(Team::Black))
+    };
+    for (i, &(team, rank)) in table.pieces.iter().enumerate() {
+        if rank == Rank::King {
+            continue;
+        }
+        let mut pieces = table.pieces.clone();
+        pieces.remove(i);
+        if pieces.iter().any(|&(_, rank)| rank != Rank::King) {
+            materials.push(name(&pieces));
+        }
+        if rank == Rank::Pawn {
+            for promotion in [Rank::Queen, Rank::Rook, Rank::Bishop, Rank::Knight].iter() {
+                let mut pieces = table.pieces.clone();
+                pieces[i] = (team, *promotion);
+                materials.push(name(&pieces));
+            }
+        }
+    }
+    materials
+}
+
+fn global() -> &'static RwLock<HashMap<String, DtmTable>> {
+    static TABLES: OnceLock<RwLock<HashMap<String, DtmTable>>> = OnceLock::new();
+    TABLES.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+// Adds a table for `dtm` to use
+pub fn add_table(table: DtmTable) {
+    global().write().unwrap().insert(table.name.clone(), table);
+}
+
+// Loads every .dtm file of the directory, returns how many there were
+pub fn init(directory: &str) -> Result<usize, String> {
+    let entries =
+        fs::read_dir(directory).map_err(|e| format!("could not read {}: {}", directory, e))?;
+    let mut loaded = 0;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().and_then(|s| s.to_str()) == Some("dtm") {
+            add_table(DtmTable::open(&path.to_string_lossy())?);
+            loaded += 1;
+        }
+    }
+    Ok(loaded)
+}
+
+// Writes the tables as NAME.dtm files into the directory
+pub fn write_tables(tables: &[DtmTable], directory: &str) -> Result<(), String> {
+    for table in tables.iter() {
+        let path = Path::new(directory).join(format!("{}.dtm", table.name));
+        table.write(&path.to_string_lossy())?;
+    }
+    Ok(())
+}
+
+// Distance to mate of the position for the side to move, None if no table
+// has its material
+pub fn dtm(game: &Game) -> Option<Dtm> {
+    let white = material_name(game, Team::White);
+    let black = material_name(game, Team::Black);
+    let (stronger, weaker) = split_material(&format!("{}v{}", white, black)).ok()?;
+    let tables = global().read().unwrap();
+    tables.get(&format!("{}v{}", stronger, weaker))?.probe(game)
+}
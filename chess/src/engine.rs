@@ -0,0 +1,247 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::game::Game;
+use crate::nnue::Network;
+use crate::random::random_u64;
+use crate::search::{SearchLimits, SearchResult, Searcher};
+use crate::skill::{Skill, MAX_ELO, MAX_LEVEL, SKILL_LINES};
+use crate::tablebase;
+use crate::timeman::{Clock, TimeBudget, DEFAULT_MOVE_OVERHEAD};
+use crate::transposition::TranspositionTable;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EngineOptions {
+    pub hash_mb: usize,
+    pub threads: usize,
+    pub multi_pv: usize,
+    // 0 to 20, below 20 the engine plays weaker
+    pub skill_level: u32,
+    // play at about `elo` instead of the skill level
+    pub limit_strength: bool,
+    pub elo: u32,
+    // the network evaluating instead of the handcrafted evaluation, empty
+    // for none
+    pub eval_file: String,
+    // milliseconds lost every move to the gui, kept off the clock
+    pub move_overhead: u64,
+}
+
+impl EngineOptions {
+    pub fn skill(&self) -> Skill {
+        if self.limit_strength {
+            Skill::from_elo(self.elo)
+        } else {
+            Skill::from_level(self.skill_level)
+        }
+    }
+}
+
+impl Default for EngineOptions {
+    fn default() -> Self {
+        EngineOptions {
+            hash_mb: 16,
+            threads: 1,
+            multi_pv: 1,
+            skill_level: MAX_LEVEL,
+            limit_strength: false,
+            elo: MAX_ELO,
+            eval_file: String::new(),
+            move_overhead: DEFAULT_MOVE_OVERHEAD,
+        }
+    }
+}
+
+// The search together with the state kept between searches. With more than
+// one thread the helpers search the same position and only share results
+// through the transposition table (Lazy SMP); the main thread's result is
+// the one returned. A single thread always searches the same tree. With
+// `multi_pv` above one the result has that many ranked lines. Below full
+// strength the search is cut short and the move is picked among the best
+// lines by the `Skill`.
+pub struct Engine {
+    pub options: EngineOptions,
+    tt: Arc<TranspositionTable>,
+    stop: Arc<AtomicBool>,
+    // set while searching on the opponent's time, the time limits start
+    // counting once it is cleared
+    ponder: Arc<AtomicBool>,
+    network: Option<Arc<Network>>,
+}
+
+impl Engine {
+    pub fn new(options: EngineOptions) -> Engine {
+        Engine {
+            tt: Arc::new(TranspositionTable::new(options.hash_mb)),
+            stop: Arc::new(AtomicBool::new(false)),
+            ponder: Arc::new(AtomicBool::new(false)),
+            network: None,
+            options,
+        }
+    }
+
+    pub fn set_option(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let parse = |value: &str| {
+            value
+                .trim()
+                .parse::<usize>()
+                .map_err(|_| format!("invalid value {} for option {}", value, name))
+        };
+        match name.to_lowercase().as_str() {
+            "hash" => {
+                self.options.hash_mb = parse(value)?.max(1);
+                self.tt = Arc::new(TranspositionTable::new(self.options.hash_mb));
+            }
+            "threads" => self.options.threads = parse(value)?.max(1),
+            "multipv" => self.options.multi_pv = parse(value)?.max(1),
+            "skill level" => self.options.skill_level = parse(value)?.min(MAX_LEVEL as usize) as u32,
+            "uci_limitstrength" => self.options.limit_strength = value.trim() == "true",
+            "uci_elo" => self.options.elo = parse(value)? as u32,
+            "move overhead" => self.options.move_overhead = parse(value)? as u64,
+            "evalfile" => {
+                let path = match value.trim() {
+                    "<empty>" => "",
+                    path => path,
+                };
+                self.network = match path {
+                    "" => None,
+                    path => Some(Arc::new(Network::read(path)?)),
+                };
+                self.options.eval_file = path.to_string();
+            }
+            // the tablebases are shared by every engine of the process
+            "syzygypath" => {
+                let path = if value == "<empty>" { "" } else { value };
+                tablebase::init(path)?;
+            }
+            _ => return Err(format!("unknown option {}", name)),
+        }
+        Ok(())
+    }
+
+    // Forget everything learned in the previous game
+    pub fn new_game(&mut self) {
+        self.tt.clear();
+    }
+
+    // Can be set from another thread to end a running search. A stop set
+    // before the search starts ends it right away.
+    pub fn stop_handle(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.stop)
+    }
+
+    // Set before a search to ponder, cleared on a ponderhit
+    pub fn ponder_handle(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.ponder)
+    }
+
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    pub fn transposition_table(&self) -> &TranspositionTable {
+        &self.tt
+    }
+
+    // Evaluates with `network` instead of the handcrafted evaluation, or
+    // with the handcrafted one again without
+    pub fn set_network(&mut self, network: Option<Network>) {
+        self.network = network.map(Arc::new);
+    }
+
+    // The soft and hard time limits the engine gives itself on `clock`
+    pub fn time_budget(&self, clock: &Clock) -> TimeBudget {
+        TimeBudget::new(clock, Duration::from_millis(self.options.move_overhead))
+    }
+
+    pub fn search(&self, game: &Game, limits: &SearchLimits) -> SearchResult {
+        self.search_with_info(game, limits, |_| {})
+    }
+
+    // Calls `on_iteration` from the main thread after every completed depth
+    pub fn search_with_info<F: FnMut(&SearchResult)>(
+        &self,
+        game: &Game,
+        limits: &SearchLimits,
+        mut on_iteration: F,
+    ) -> SearchResult {
+        self.tt.new_search();
+        let skill = self.options.skill();
+        let limits = &skill.limits(limits);
+        let multi_pv = if skill.enabled() {
+            self.options.multi_pv.max(SKILL_LINES)
+        } else {
+            self.options.multi_pv
+        };
+        // the extra lines a weakened engine picks from aren't reported
+        let reported = self.options.multi_pv;
+        let on_iteration = |result: &SearchResult| {
+            if result.lines.len() > reported {
+                let mut result = result.clone();
+                result.lines.truncate(reported);
+                on_iteration(&result);
+            } else {
+                on_iteration(result);
+            }
+        };
+        let helpers_stop = AtomicBool::new(false);
+
+        let (mut result, helper_nodes) = thread::scope(|scope| {
+            let helpers: Vec<_> = (1..self.options.threads)
+                .map(|thread_index| {
+                    let tt = &*self.tt;
+                    let stop = &helpers_stop;
+                    let game = game.clone();
+                    let network = self.network.clone();
+                    // the main thread's time management ends them
+                    let limits = SearchLimits {
+                        nodes: None,
+                        clock: None,
+                        ..limits.clone()
+                    };
+                    scope.spawn(move || {
+                        let mut searcher = Searcher::new(tt, stop);
+                        searcher.set_thread_index(thread_index);
+                        searcher.set_network(network);
+                        searcher.iterate(&game, &limits, |_| {}).nodes
+                    })
+                })
+                .collect();
+
+            let mut searcher = Searcher::new(&self.tt, &self.stop);
+            searcher.set_multi_pv(multi_pv);
+            searcher.set_ponder(&self.ponder);
+            searcher.set_network(self.network.clone());
+            searcher.set_move_overhead(Duration::from_millis(self.options.move_overhead));
+            let result = searcher.iterate(game, limits, on_iteration);
+            helpers_stop.store(true, Ordering::Relaxed);
+            let helper_nodes: u64 = helpers
+                .into_iter()
+                .map(|helper| helper.join().unwrap_or(0))
+                .sum();
+            (result, helper_nodes)
+        });
+        result.nodes += helper_nodes;
+        if skill.enabled() {
+            if let Some(index) = skill.pick(&result.lines, random_u64()) {
+                let line = result.lines[index].clone();
+                result.best_move = Some(line.action);
+                result.score = line.score;
+                result.pv = line.pv;
+            }
+            result.lines.truncate(reported);
+        }
+        // a stop only ends the search it was meant for
+        self.stop.store(false, Ordering::Relaxed);
+        self.ponder.store(false, Ordering::Relaxed);
+        result
+    }
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new(EngineOptions::default())
+    }
+}
@@ -0,0 +1,391 @@
+// Extended Position Description: the first four fen fields followed by
+// operations, each an opcode with its operands and a closing semicolon, like
+//   r1b1k2r/... w kq - bm Qxf7+; id "WAC.042"; c0 "mate in 3";
+// Moves are written in standard algebraic notation. Test suites keep the
+// best moves under "bm" and the moves to avoid under "am"; the suite runner
+// searches every position and counts the ones the engine solves. Suites
+// like STS give points for several moves in "c0", as "f5=10, Be5+=2".
+use std::fmt;
+use std::fs;
+use std::time::{Duration, Instant};
+
+use crate::engine::Engine;
+use crate::game::Game;
+use crate::moves::Action;
+use crate::pgn;
+use crate::search::{play, SearchLimits, SearchResult};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Epd {
+    // board, player, castling rights and en passant square
+    pub position: String,
+    // opcode and operands in the order they were read or set, string
+    // operands without their quotes
+    pub operations: Vec<(String, Vec<String>)>,
+}
+
+impl Epd {
+    // The record of a position without operations. The clocks are kept in
+    // "hmvc" and "fmvn".
+    pub fn from_game(game: &Game) -> Epd {
+        let fen = game.to_fen();
+        let fields: Vec<&str> = fen.split(' ').collect();
+        let mut epd = Epd {
+            position: fields[..4].join(" "),
+            operations: vec![],
+        };
+        epd.set("hmvc", vec![fields[4].to_string()]);
+        epd.set("fmvn", vec![fields[5].to_string()]);
+        epd
+    }
+
+    pub fn parse(line: &str) -> Result<Epd, String> {
+        let line = line.trim();
+        let mut fields = vec![];
+        let mut rest = line;
+        for _ in 0..4 {
+            rest = rest.trim_start();
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            if end == 0 {
+                return Err(format!("{} has no four position fields", line));
+            }
+            fields.push(&rest[..end]);
+            rest = &rest[end..];
+        }
+        let epd = Epd {
+            position: fields.join(" "),
+            operations: parse_operations(rest)?,
+        };
+        epd.position()?;
+        Ok(epd)
+    }
+
+    // The position, with the clocks of "hmvc" and "fmvn" if given
+    pub fn position(&self) -> Result<Game, String> {
+        let halfmove = self.operand("hmvc").unwrap_or("0");
+        let fullmove = self.operand("fmvn").unwrap_or("1");
+        Game::from_fen(&format!("{} {} {}", self.position, halfmove, fullmove))
+    }
+
+    pub fn operands(&self, opcode: &str) -> Option<&[String]> {
+        self.operations
+            .iter()
+            .find(|(name, _)| name == opcode)
+            .map(|(_, operands)| operands.as_slice())
+    }
+
+    // The first operand of `opcode`
+    pub fn operand(&self, opcode: &str) -> Option<&str> {
+        self.operands(opcode)?.first().map(String::as_str)
+    }
+
+    // Replaces the operands of `opcode`, or adds it at the end
+    pub fn set(&mut self, opcode: &str, operands: Vec<String>) {
+        match self.operations.iter_mut().find(|(name, _)| name == opcode) {
+            Some(operation) => operation.1 = operands,
+            None => self.operations.push((opcode.to_string(), operands)),
+        }
+    }
+
+    pub fn remove(&mut self, opcode: &str) {
+        self.operations.retain(|(name, _)| name != opcode);
+    }
+
+    // The "id" of the record, empty without one
+    pub fn id(&self) -> &str {
+        self.operand("id").unwrap_or("")
+    }
+
+    // The moves of an operation listing alternatives in the position, like
+    // "bm" and "am", none without the operation
+    pub fn moves(&self, opcode: &str) -> Result<Vec<Action>, String> {
+        let mut game = self.position()?;
+        self.operands(opcode)
+            .unwrap_or(&[])
+            .iter()
+            .map(|san| pgn::action_from_san(&mut game, san))
+            .collect()
+    }
+
+    // The moves of "pv", each played after the one before
+    pub fn pv(&self) -> Result<Vec<Action>, String> {
+        let mut game = self.position()?;
+        let mut actions = vec![];
+        for san in self.operands("pv").unwrap_or(&[]) {
+            let action = pgn::action_from_san(&mut game, san)?;
+            game = play(&game, &action);
+            actions.push(action);
+        }
+        Ok(actions)
+    }
+
+    // The moves worth points in "c0", like "f5=10, Be5+=2", none if "c0" is
+    // only a comment
+    pub fn points(&self) -> Result<Vec<(Action, u32)>, String> {
+        let comment = match self.operand("c0") {
+            Some(comment) if comment.contains('=') => comment,
+            _ => return Ok(vec![]),
+        };
+        let mut game = self.position()?;
+        let mut points = vec![];
+        for entry in comment.split(',') {
+            // the "=" of a promotion comes before the one of the points
+            let (san, value) = entry
+                .trim()
+                .rsplit_once('=')
+                .ok_or(format!("{} is not MOVE=POINTS", entry.trim()))?;
+            let value = value
+                .parse()
+                .map_err(|_| format!("{} is not a number of points", value))?;
+            points.push((pgn::action_from_san(&mut game, san)?, value));
+        }
+        Ok(points)
+    }
+
+    // Records the search of the position: its depth ("acd"), nodes ("acn"),
+    // seconds ("acs"), score ("ce") and principal variation ("pv")
+    pub fn set_analysis(&mut self, result: &SearchResult, elapsed: Duration) -> Result<(), String> {
+        let mut game = self.position()?;
+        let mut pv = vec![];
+        for action in result.pv.iter() {
+            pv.push(pgn::to_san(&game, action));
+            game = play(&game, action);
+        }
+        self.set("acd", vec![result.depth.to_string()]);
+        self.set("acn", vec![result.nodes.to_string()]);
+        self.set("acs", vec![elapsed.as_secs().to_string()]);
+        self.set("ce", vec![result.score.to_string()]);
+        self.set("pv", pv);
+        Ok(())
+    }
+}
+
+impl fmt::Display for Epd {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.position)?;
+        for (opcode, operands) in self.operations.iter() {
+            write!(f, " {}", opcode)?;
+            for operand in operands.iter() {
+                if is_string_opcode(opcode) || operand.is_empty() || !is_plain(operand) {
+                    write!(f, " \"{}\"", operand)?;
+                } else {
+                    write!(f, " {}", operand)?;
+                }
+            }
+            write!(f, ";")?;
+        }
+        Ok(())
+    }
+}
+
+pub fn read_epd(filepath: &str) -> Result<Vec<Result<Epd, String>>, String> {
+    let text =
+        fs::read_to_string(filepath).map_err(|e| format!("could not read {}: {}", filepath, e))?;
+    Ok(parse_epd(&text))
+}
+
+// One record per line, empty lines and lines starting with # left out. A
+// record that can't be read gives an error naming its line without stopping
+// the records after it.
+pub fn parse_epd(text: &str) -> Vec<Result<Epd, String>> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim().starts_with('#'))
+        .map(|(index, line)| Epd::parse(line).map_err(|e| format!("line {}: {}", index + 1, e)))
+        .collect()
+}
+
+// "id" and the comments "c0" to "c9" hold text, so they are always quoted
+fn is_string_opcode(opcode: &str) -> bool {
+    let comment =
+        opcode.len() == 2 && opcode.starts_with('c') && opcode.as_bytes()[1].is_ascii_digit();
+    opcode == "id" || comment
+}
+
+fn is_plain(operand: &str) -> bool {
+    !operand.contains(|c: char| c.is_whitespace() || c == ';' || c == '"')
+}
+
+fn parse_operations(text: &str) -> Result<Vec<(String, Vec<String>)>, String> {
+    let mut operations = vec![];
+    let mut tokens: Vec<String> = vec![];
+    let mut token = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => string.push(c),
+                        None => return Err(format!("unterminated string \"{}", string)),
+                    }
+                }
+                // an empty string is still an operand
+                tokens.push(string);
+            }
+            ';' => {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+                if tokens.is_empty() {
+                    return Err(String::from("operation without an opcode"));
+                }
+                let opcode = tokens.remove(0);
+                operations.push((opcode, std::mem::take(&mut tokens)));
+            }
+            c if c.is_whitespace() => {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+            }
+            c => token.push(c),
+        }
+    }
+    if !token.is_empty() || !tokens.is_empty() {
+        tokens.push(token);
+        return Err(format!("{} has no closing ;", tokens.join(" ").trim()));
+    }
+    Ok(operations)
+}
+
+// The outcome of one position of a suite
+#[derive(Debug, Clone)]
+pub struct PositionResult {
+    pub id: String,
+    pub result: SearchResult,
+    // the best move in standard algebraic notation
+    pub san: String,
+    pub solved: bool,
+    // the points of the move and the most the position gives, with "c0"
+    // points
+    pub points: Option<(u32, u32)>,
+    pub elapsed: Duration,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SuiteReport {
+    pub results: Vec<PositionResult>,
+}
+
+impl SuiteReport {
+    pub fn solved(&self) -> usize {
+        self.results.iter().filter(|result| result.solved).count()
+    }
+
+    // Points scored and the most there were, over the positions giving them
+    pub fn points(&self) -> (u32, u32) {
+        self.results
+            .iter()
+            .filter_map(|result| result.points)
+            .fold((0, 0), |(sum, most), (points, max)| {
+                (sum + points, most + max)
+            })
+    }
+
+    pub fn nodes(&self) -> u64 {
+        self.results.iter().map(|result| result.result.nodes).sum()
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.results.iter().map(|result| result.elapsed).sum()
+    }
+}
+
+impl fmt::Display for SuiteReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let total = self.results.len();
+        write!(
+            f,
+            "solved {} of {} ({:.1}%)",
+            self.solved(),
+            total,
+            percent(self.solved() as u32, total as u32)
+        )?;
+        let (points, most) = self.points();
+        if most > 0 {
+            write!(
+                f,
+                ", {} of {} points ({:.1}%)",
+                points,
+                most,
+                percent(points, most)
+            )?;
+        }
+        write!(
+            f,
+            ", {} nodes in {:.1}s",
+            self.nodes(),
+            self.elapsed().as_secs_f64()
+        )
+    }
+}
+
+fn percent(part: u32, whole: u32) -> f64 {
+    if whole == 0 {
+        0.0
+    } else {
+        100.0 * part as f64 / whole as f64
+    }
+}
+
+// Searches every position of `suite` within `limits`, each with a cleared
+// hash, and calls `on_position` with every outcome. A position is solved
+// when the engine plays one of its "bm" moves and none of its "am" moves,
+// or, with neither, the move of the most "c0" points. A position with
+// nothing to check stops the run.
+pub fn run_suite<F: FnMut(&PositionResult)>(
+    engine: &mut Engine,
+    suite: &[Epd],
+    limits: &SearchLimits,
+    mut on_position: F,
+) -> Result<SuiteReport, String> {
+    let mut report = SuiteReport::default();
+    for (index, epd) in suite.iter().enumerate() {
+        let name = match epd.id() {
+            "" => format!("position {}", index + 1),
+            id => id.to_string(),
+        };
+        let error = |e: String| format!("{}: {}", name, e);
+        let game = epd.position().map_err(error)?;
+        let best = epd.moves("bm").map_err(error)?;
+        let avoid = epd.moves("am").map_err(error)?;
+        let points = epd.points().map_err(error)?;
+        if best.is_empty() && avoid.is_empty() && points.is_empty() {
+            return Err(error(String::from("no bm, am or c0 points to check")));
+        }
+
+        engine.new_game();
+        let start = Instant::now();
+        let result = engine.search(&game, limits);
+        let elapsed = start.elapsed();
+        // moves are told apart by their uci text: the search only promotes to
+        // queens, which to_uci also writes for a promotion without a piece
+        let played = result.best_move.map(|action| action.to_uci());
+        let is_played = |action: &Action| Some(action.to_uci()) == played;
+        let most = points.iter().map(|(_, value)| *value).max();
+        let scored = points
+            .iter()
+            .find(|(action, _)| is_played(action))
+            .map_or(0, |(_, value)| *value);
+        let solved = if best.is_empty() && avoid.is_empty() {
+            Some(scored) == most
+        } else {
+            (best.is_empty() || best.iter().any(is_played)) && !avoid.iter().any(is_played)
+        };
+        let position = PositionResult {
+            id: name,
+            san: result
+                .best_move
+                .map_or(String::from("-"), |action| pgn::to_san(&game, &action)),
+            solved,
+            points: most.map(|most| (scored, most)),
+            result,
+            elapsed,
+        };
+        on_position(&position);
+        report.results.push(position);
+    }
+    Ok(report)
+}
@@ -0,0 +1,310 @@
+use std::fmt;
+
+use crate::game::{Game, Rank, Team};
+
+// Piece-square tables are written as the board is printed, rank 8 first,
+// from white's point of view. Black uses the same tables mirrored.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Weights {
+    pub piece_values: [i32; 6],
+    pub piece_square: [[i32; 64]; 6],
+    pub king_endgame: [i32; 64],
+    pub bishop_pair: i32,
+}
+
+pub const DEFAULT_WEIGHTS: Weights = Weights {
+    // pawn, knight, bishop, rook, queen, king
+    piece_values: [100, 320, 330, 500, 900, 0],
+    piece_square: [
+        [
+            0, 0, 0, 0, 0, 0, 0, 0, //
+            50, 50, 50, 50, 50, 50, 50, 50, //
+            10, 10, 20, 30, 30, 20, 10, 10, //
+            5, 5, 10, 25, 25, 10, 5, 5, //
+            0, 0, 0, 20, 20, 0, 0, 0, //
+            5, -5, -10, 0, 0, -10, -5, 5, //
+            5, 10, 10, -20, -20, 10, 10, 5, //
+            0, 0, 0, 0, 0, 0, 0, 0,
+        ],
+        [
+            -50, -40, -30, -30, -30, -30, -40, -50, //
+            -40, -20, 0, 0, 0, 0, -20, -40, //
+            -30, 0, 10, 15, 15, 10, 0, -30, //
+            -30, 5, 15, 20, 20, 15, 5, -30, //
+            -30, 0, 15, 20, 20, 15, 0, -30, //
+            -30, 5, 10, 15, 15, 10, 5, -30, //
+            -40, -20, 0, 5, 5, 0, -20, -40, //
+            -50, -40, -30, -30, -30, -30, -40, -50,
+        ],
+        [
+            -20, -10, -10, -10, -10, -10, -10, -20, //
+            -10, 0, 0, 0, 0, 0, 0, -10, //
+            -10, 0, 5, 10, 10, 5, 0, -10, //
+            -10, 5, 5, 10, 10, 5, 5, -10, //
+            -10, 0, 10, 10, 10, 10, 0, -10, //
+            -10, 10, 10, 10, 10, 10, 10, -10, //
+            -10, 5, 0, 0, 0, 0, 5, -10, //
+            -20, -10, -10, -10, -10, -10, -10, -20,
+        ],
+        [
+            0, 0, 0, 0, 0, 0, 0, 0, //
+            5, 10, 10, 10, 10, 10, 10, 5, //
+            -5, 0, 0, 0, 0, 0, 0, -5, //
+            -5, 0, 0, 0, 0, 0, 0, -5, //
+            -5, 0, 0, 0, 0, 0, 0, -5, //
+            -5, 0, 0, 0, 0, 0, 0, -5, //
+            -5, 0, 0, 0, 0, 0, 0, -5, //
+            0, 0, 0, 5, 5, 0, 0, 0,
+        ],
+        [
+            -20, -10, -10, -5, -5, -10, -10, -20, //
+            -10, 0, 0, 0, 0, 0, 0, -10, //
+            -10, 0, 5, 5, 5, 5, 0, -10, //
+            -5, 0, 5, 5, 5, 5, 0, -5, //
+            0, 0, 5, 5, 5, 5, 0, -5, //
+            -10, 5, 5, 5, 5, 5, 0, -10, //
+            -10, 0, 5, 0, 0, 0, 0, -10, //
+            -20, -10, -10, -5, -5, -10, -10, -20,
+        ],
+        [
+            -30, -40, -40, -50, -50, -40, -40, -30, //
+            -30, -40, -40, -50, -50, -40, -40, -30, //
+            -30, -40, -40, -50, -50, -40, -40, -30, //
+            -30, -40, -40, -50, -50, -40, -40, -30, //
+            -20, -30, -30, -40, -40, -30, -30, -20, //
+            -10, -20, -20, -20, -20, -20, -20, -10, //
+            20, 20, 0, 0, 0, 0, 20, 20, //
+            20, 30, 10, 0, 0, 10, 30, 20,
+        ],
+    ],
+    king_endgame: [
+        -50, -40, -30, -20, -20, -30, -40, -50, //
+        -30, -20, -10, 0, 0, -10, -20, -30, //
+        -30, -10, 20, 30, 30, 20, -10, -30, //
+        -30, -10, 30, 40, 40, 30, -10, -30, //
+        -30, -10, 30, 40, 40, 30, -10, -30, //
+        -30, -10, 20, 30, 30, 20, -10, -30, //
+        -30, -30, 0, 0, 0, 0, -30, -30, //
+        -50, -30, -30, -30, -30, -30, -30, -50,
+    ],
+    bishop_pair: 30,
+};
+
+// The number of weights: piece values, piece-square tables, the endgame
+// king table and the bishop pair, in that order in `Weights::to_vec`
+pub const WEIGHT_COUNT: usize = 6 + 6 * 64 + 64 + 1;
+
+// Knights and bishops count 1, rooks 2 and queens 4 towards the phase,
+// the king tables are blended between the middlegame and the endgame by it
+const MAX_PHASE: i32 = 24;
+
+pub fn evaluate(game: &Game) -> i32 {
+    evaluate_with(game, &DEFAULT_WEIGHTS)
+}
+
+// Score in centipawns from the point of view of the player to move
+pub fn evaluate_with(game: &Game, weights: &Weights) -> i32 {
+    let mut score = 0;
+    let mut phase = 0;
+    let mut bishops = [0; 2];
+    let mut king_squares = [0; 2];
+
+    for column in game.matrix.iter() {
+        for square in column.iter() {
+            let piece = match square.piece {
+                Some(p) => p,
+                None => continue,
+            };
+            let (x, y) = (square.coordinate.0 as usize, square.coordinate.1 as usize);
+            let (table_index, sign, team_index) = match piece.team {
+                Team::White => ((7 - y) * 8 + x, 1, 0),
+                Team::Black => (y * 8 + x, -1, 1),
+            };
+            let rank = piece.rank.index();
+            phase += match piece.rank {
+                Rank::Knight | Rank::Bishop => 1,
+                Rank::Rook => 2,
+                Rank::Queen => 4,
+                _ => 0,
+            };
+            if piece.rank == Rank::Bishop {
+                bishops[team_index] += 1;
+            }
+            if piece.rank == Rank::King {
+                king_squares[team_index] = table_index;
+                score += sign * weights.piece_values[rank];
+                continue;
+            }
+            score += sign * (weights.piece_values[rank] + weights.piece_square[rank][table_index]);
+        }
+    }
+
+    let phase = phase.min(MAX_PHASE);
+    for (team_index, &sign) in [1, -1].iter().enumerate() {
+        let table_index = king_squares[team_index];
+        let middlegame = weights.piece_square[Rank::King.index()][table_index];
+        let endgame = weights.king_endgame[table_index];
+        score += sign * (middlegame * phase + endgame * (MAX_PHASE - phase)) / MAX_PHASE;
+        if bishops[team_index] >= 2 {
+            score += sign * weights.bishop_pair;
+        }
+    }
+
+    match game.player {
+        Team::White => score,
+        Team::Black => -score,
+    }
+}
+
+// The evaluation as a sum of weights times coefficients, as (index in
+// `Weights::to_vec`, coefficient) pairs from white's point of view. It's the
+// score of `evaluate_with` for white without its rounding.
+pub fn features(game: &Game) -> Vec<(usize, f64)> {
+    let mut features: Vec<(usize, f64)> = vec![];
+    let mut phase = 0;
+    let mut bishops = [0; 2];
+    let mut king_squares = [0; 2];
+    let table = |rank: usize, index: usize| 6 + rank * 64 + index;
+
+    for column in game.matrix.iter() {
+        for square in column.iter() {
+            let piece = match square.piece {
+                Some(p) => p,
+                None => continue,
+            };
+            let (x, y) = (square.coordinate.0 as usize, square.coordinate.1 as usize);
+            let (table_index, sign, team_index) = match piece.team {
+                Team::White => ((7 - y) * 8 + x, 1.0, 0),
+                Team::Black => (y * 8 + x, -1.0, 1),
+            };
+            let rank = piece.rank.index();
+            phase += match piece.rank {
+                Rank::Knight | Rank::Bishop => 1,
+                Rank::Rook => 2,
+                Rank::Queen => 4,
+                _ => 0,
+            };
+            if piece.rank == Rank::Bishop {
+                bishops[team_index] += 1;
+            }
+            features.push((rank, sign));
+            if piece.rank == Rank::King {
+                king_squares[team_index] = table_index;
+            } else {
+                features.push((table(rank, table_index), sign));
+            }
+        }
+    }
+
+    let phase = phase.min(MAX_PHASE) as f64 / MAX_PHASE as f64;
+    for (team_index, &sign) in [1.0, -1.0].iter().enumerate() {
+        let table_index = king_squares[team_index];
+        features.push((table(Rank::King.index(), table_index), sign * phase));
+        features.push((6 + 6 * 64 + table_index, sign * (1.0 - phase)));
+        if bishops[team_index] >= 2 {
+            features.push((WEIGHT_COUNT - 1, sign));
+        }
+    }
+
+    // one pair per weight, without the ones cancelling out
+    features.sort_by_key(|(index, _)| *index);
+    let mut merged: Vec<(usize, f64)> = vec![];
+    for (index, coefficient) in features {
+        match merged.last_mut() {
+            Some(last) if last.0 == index => last.1 += coefficient,
+            _ => merged.push((index, coefficient)),
+        }
+    }
+    merged.retain(|(_, coefficient)| *coefficient != 0.0);
+    merged
+}
+
+impl Weights {
+    pub fn to_vec(&self) -> Vec<i32> {
+        let mut values = self.piece_values.to_vec();
+        for table in self.piece_square.iter() {
+            values.extend_from_slice(table);
+        }
+        values.extend_from_slice(&self.king_endgame);
+        values.push(self.bishop_pair);
+        values
+    }
+
+    pub fn from_slice(values: &[i32]) -> Result<Weights, String> {
+        if values.len() != WEIGHT_COUNT {
+            return Err(format!("{} weights instead of {}", values.len(), WEIGHT_COUNT));
+        }
+        let mut weights = DEFAULT_WEIGHTS;
+        weights.piece_values.copy_from_slice(&values[..6]);
+        for (rank, table) in weights.piece_square.iter_mut().enumerate() {
+            table.copy_from_slice(&values[6 + rank * 64..6 + (rank + 1) * 64]);
+        }
+        weights.king_endgame.copy_from_slice(&values[6 + 6 * 64..WEIGHT_COUNT - 1]);
+        weights.bishop_pair = values[WEIGHT_COUNT - 1];
+        Ok(weights)
+    }
+
+    // Reads weights written by `Display`, or any text with the numbers in
+    // the same order. Comments are skipped.
+    pub fn parse(text: &str) -> Result<Weights, String> {
+        let mut values = vec![];
+        for line in text.lines() {
+            let line = line.split("//").next().unwrap_or("");
+            let chars: Vec<char> = line.chars().collect();
+            let mut i = 0;
+            while i < chars.len() {
+                let starts_number = chars[i].is_ascii_digit()
+                    && (i == 0 || !(chars[i - 1].is_alphanumeric() || chars[i - 1] == '_'));
+                if !starts_number {
+                    i += 1;
+                    continue;
+                }
+                let negative = i > 0 && chars[i - 1] == '-';
+                let start = i;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+                let number: String = chars[start..i].iter().collect();
+                let value: i32 = number
+                    .parse()
+                    .map_err(|_| format!("{} is not a valid weight", number))?;
+                values.push(if negative { -value } else { value });
+            }
+        }
+        Weights::from_slice(&values)
+    }
+}
+
+// The weights as the definition of `DEFAULT_WEIGHTS` in this file
+impl fmt::Display for Weights {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn write_table(f: &mut fmt::Formatter, table: &[i32; 64], indent: &str) -> fmt::Result {
+            for (row, values) in table.chunks(8).enumerate() {
+                let values: Vec<String> = values.iter().map(i32::to_string).collect();
+                let end = if row < 7 { " //" } else { "" };
+                writeln!(f, "{}{},{}", indent, values.join(", "), end)?;
+            }
+            Ok(())
+        }
+        writeln!(f, "pub const DEFAULT_WEIGHTS: Weights = Weights {{")?;
+        writeln!(f, "    // pawn, knight, bishop, rook, queen, king")?;
+        let values: Vec<String> = self.piece_values.iter().map(i32::to_string).collect();
+        writeln!(f, "    piece_values: [{}],", values.join(", "))?;
+        writeln!(f, "    piece_square: [")?;
+        for table in self.piece_square.iter() {
+            writeln!(f, "        [")?;
+            write_table(f, table, "            ")?;
+            writeln!(f, "        ],")?;
+        }
+        writeln!(f, "    ],")?;
+        writeln!(f, "    king_endgame: [")?;
+        write_table(f, &self.king_endgame, "        ")?;
+        writeln!(f, "    ],")?;
+        writeln!(f, "    bishop_pair: {},", self.bishop_pair)?;
+        writeln!(f, "}};")
+    }
+}
+
+pub fn piece_value(rank: Rank) -> i32 {
+    DEFAULT_WEIGHTS.piece_values[rank.index()]
+}
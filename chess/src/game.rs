@@ -4,6 +4,9 @@ use std::{fmt, io};
 
 use crate::moves;
 use crate::moves::{Action, ActionType};
+use crate::unmoves;
+use crate::unmoves::Unmove;
+use crate::zobrist;
 
 #[derive(Debug, Clone)]
 pub struct Game {
@@ -15,6 +18,17 @@ pub struct Game {
     pub black_king_square: Square,
     pub promotion_piece:Option<Rank>,
     fifty_turn: i32,
+    // the halfmove clock before each move in history, for undo
+    clock_history: Vec<i32>,
+    fullmove: i32,
+    // castling rights still available, cleared once the king or a rook leaves
+    // its square or a rook is captured on it
+    castling: [bool; 4],
+    // the castling rights before each move in history, for undo
+    castling_history: Vec<[bool; 4]>,
+    // en passant square of the starting position, the rest follows from the
+    // history
+    start_en_passant: Option<(isize, isize)>,
 }
 
 impl Game {
@@ -36,6 +50,164 @@ impl Game {
         
     }
 
+    pub fn from_fen(fen: &str) -> Result<Game, String> {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        if fields.len() < 4 {
+            return Err(String::from("fen needs at least four fields"));
+        }
+
+        let rows: Vec<&str> = fields[0].split('/').collect();
+        if rows.len() != 8 {
+            return Err(String::from("fen board doesnt have eight rows"));
+        }
+        let mut blocks: Vec<String> = vec![];
+        let mut kings = (0, 0);
+        for (i, row) in rows.iter().enumerate() {
+            let mut columns = 0;
+            for c in row.chars() {
+                if let Some(empty) = c.to_digit(10) {
+                    for _ in 0..empty {
+                        blocks.push(String::from("XX"));
+                    }
+                    columns += empty;
+                    continue;
+                }
+                let rank = match Rank::from_letter(c.to_ascii_uppercase()) {
+                    Some(rank) => rank,
+                    None => return Err(format!("{} is not a piece letter", c)),
+                };
+                if rank == Rank::Pawn && (i == 0 || i == 7) {
+                    return Err(String::from("pawns cant stand on the first or last row"));
+                }
+                match c {
+                    'K' => kings.0 += 1,
+                    'k' => kings.1 += 1,
+                    _ => {}
+                }
+                let team_letter = if c.is_ascii_uppercase() { 'W' } else { 'B' };
+                blocks.push(format!("{}{}", rank.letter(), team_letter));
+                columns += 1;
+            }
+            if columns != 8 {
+                return Err(format!("fen row {} doesnt have eight columns", row));
+            }
+        }
+        if kings != (1, 1) {
+            return Err(String::from("both players need exactly one king"));
+        }
+
+        let mut game = Game::blockstate_to_board(&blocks.join(" "));
+        game.player = match fields[1] {
+            "w" => Team::White,
+            "b" => Team::Black,
+            _ => return Err(format!("{} is not a player to move", fields[1])),
+        };
+        game.castling = [false; 4];
+        for c in fields[2].chars().filter(|&c| c != '-') {
+            match c {
+                'K' => game.castling[0] = true,
+                'Q' => game.castling[1] = true,
+                'k' => game.castling[2] = true,
+                'q' => game.castling[3] = true,
+                _ => return Err(format!("{} is not a castling right", c)),
+            }
+        }
+        if fields[3] != "-" {
+            game.start_en_passant = Some(coordinate_from_string(fields[3])?);
+        }
+        if let Some(halfmove) = fields.get(4) {
+            game.fifty_turn = halfmove
+                .parse()
+                .map_err(|_| format!("{} is not a halfmove clock", halfmove))?;
+        }
+        if let Some(fullmove) = fields.get(5) {
+            game.fullmove = fullmove
+                .parse()
+                .map_err(|_| format!("{} is not a move number", fullmove))?;
+        }
+        game.calculate_game_state();
+        Ok(game)
+    }
+
+    pub fn to_fen(&self) -> String {
+        let mut rows: Vec<String> = vec![];
+        for row in (0..8).rev() {
+            let mut text = String::new();
+            let mut empty = 0;
+            for column in 0..8 {
+                match self.matrix[column][row].piece {
+                    None => empty += 1,
+                    Some(piece) => {
+                        if empty > 0 {
+                            text.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        text.push(piece.fen_letter());
+                    }
+                }
+            }
+            if empty > 0 {
+                text.push_str(&empty.to_string());
+            }
+            rows.push(text);
+        }
+
+        let player = match self.player {
+            Team::White => "w",
+            Team::Black => "b",
+        };
+        let mut castling: String = self
+            .castling_rights()
+            .iter()
+            .zip(['K', 'Q', 'k', 'q'].iter())
+            .filter(|(right, _)| **right)
+            .map(|(_, letter)| *letter)
+            .collect();
+        if castling.is_empty() {
+            castling.push('-');
+        }
+        let en_passant = match self.en_passant_square() {
+            Some(coordinate) => coordinate_to_string(coordinate),
+            None => String::from("-"),
+        };
+        format!(
+            "{} {} {} {} {} {}",
+            rows.join("/"),
+            player,
+            castling,
+            en_passant,
+            self.fifty_turn,
+            self.fullmove
+        )
+    }
+
+    // Long algebraic notation as used by uci, e.g. "e2e4" or "e7e8q"
+    pub fn action_from_uci(&mut self, text: &str) -> Result<Action, String> {
+        if text.len() < 4 || text.len() > 5 || !text.is_ascii() {
+            return Err(format!("{} is not a uci move", text));
+        }
+        let from = self.square_from_string(&text[0..2])?;
+        let to = coordinate_from_string(&text[2..4])?;
+        let promotion = match text[4..].chars().next() {
+            Some(c) => match Rank::from_letter(c.to_ascii_uppercase()) {
+                Some(rank) if rank != Rank::Pawn && rank != Rank::King => Some(rank),
+                _ => return Err(format!("{} is not a promotion piece", c)),
+            },
+            None => None,
+        };
+        let mut action = match moves::generate_moves(self, from)?
+            .into_iter()
+            .find(|action| action.to.coordinate == to)
+        {
+            Some(action) => action,
+            None => return Err(format!("{} is not a legal move", text)),
+        };
+        if action.action_type == ActionType::Promotion {
+            action.promotion = Some(promotion.unwrap_or(Rank::Queen));
+        }
+        Ok(action)
+    }
+
     pub fn main(&mut self) {
         let mut error_msg = String::new();
         let mut turns_for_50 = 0;
@@ -123,22 +295,42 @@ impl Game {
     }
 
     pub fn perform_action(&mut self, action: Action) {
+        let mut action = action;
+        if action.action_type == ActionType::Promotion && action.promotion.is_none() {
+            action.promotion = self.promotion_piece;
+        }
         self.history.push(action);
+        self.clock_history.push(self.fifty_turn);
+        self.castling_history.push(self.castling);
         let coordinate_from = action.from.coordinate;
         let coordinate_to = action.to.coordinate;
 
+        let pawn_moved = action.from.piece.is_some_and(|p| p.rank == Rank::Pawn);
+        if pawn_moved || action.to.piece.is_some() {
+            self.fifty_turn = 0;
+        } else {
+            self.fifty_turn += 1;
+        }
+        if self.player == Team::Black {
+            self.fullmove += 1;
+        }
+        for (i, &(king, rook)) in CASTLING_SQUARES.iter().enumerate() {
+            if coordinate_from == king || coordinate_from == rook || coordinate_to == rook {
+                self.castling[i] = false;
+            }
+        }
+
         match action.action_type {
             ActionType::Promotion => {
                 self.matrix[coordinate_from.0 as usize][coordinate_from.1 as usize].piece = None;
 
                 let promotion_piece = Piece {
-                    rank: self.promotion_piece.unwrap(),
+                    rank: action.promotion.unwrap(),
                     team: self.player,
                 };
                 self.promotion_piece=None;
                 self.matrix[coordinate_to.0 as usize][coordinate_to.1 as usize].piece =
                     Some(promotion_piece);
-                println!("Promotion")
             }
             _ => self.make_move(&action),
         }
@@ -206,6 +398,51 @@ impl Game {
         all_moves
     }
 
+    // All moves with a promotion once for each piece it can promote to,
+    // instead of a single one for the promotion piece chosen when playing it
+    pub fn legal_moves(&mut self) -> Vec<Action> {
+        let mut legal_moves: Vec<Action> = vec![];
+        for action in self.all_moves() {
+            if action.action_type == ActionType::Promotion {
+                for rank in [Rank::Queen, Rank::Rook, Rank::Bishop, Rank::Knight].iter() {
+                    legal_moves.push(Action {
+                        promotion: Some(*rank),
+                        ..action
+                    });
+                }
+            } else {
+                legal_moves.push(action);
+            }
+        }
+        legal_moves
+    }
+
+    pub fn all_captures(&mut self) -> Vec<Action> {
+        let mut all_captures: Vec<Action> = vec![];
+        let matrix = self.matrix;
+        for row in matrix.iter() {
+            for square in row.iter() {
+                if let Ok(mut a) = moves::generate_captures(self, *square) {
+                    all_captures.append(&mut a);
+                }
+            }
+        }
+        all_captures
+    }
+
+    pub fn all_quiets(&mut self) -> Vec<Action> {
+        let mut all_quiets: Vec<Action> = vec![];
+        let matrix = self.matrix;
+        for row in matrix.iter() {
+            for square in row.iter() {
+                if let Ok(mut a) = moves::generate_quiets(self, *square) {
+                    all_quiets.append(&mut a);
+                }
+            }
+        }
+        all_quiets
+    }
+
     fn is_more_moves(&mut self) -> bool {
         let all_moves: Vec<Action> = self.all_moves();
 
@@ -295,12 +532,31 @@ impl Game {
             } // already checking for check before adding move
         }
     }
-    //there is an error with bishop undo, 
+
+    // Takes back the last move, returns None if there is nothing to take back
+    pub fn undo(&mut self) -> Option<Action> {
+        let action = self.history.pop()?;
+        self.player = next_player(self.player);
+        self.undo_move(&action);
+        self.fifty_turn = self.clock_history.pop().unwrap_or(0);
+        self.castling = self.castling_history.pop().unwrap_or(self.castling);
+        if self.player == Team::Black {
+            self.fullmove -= 1;
+        }
+        self.calculate_game_state();
+        Some(action)
+    }
+
+    // self.player has to be the player that made the action
     fn undo_move(&mut self, action: &Action) {
         let coordinate_from = action.from.coordinate;
         let coordinate_to = action.to.coordinate;
         match action.action_type {
             ActionType::Regular => {
+                self.matrix[coordinate_to.0 as usize][coordinate_to.1 as usize].piece =
+                    action.to.piece;
+                self.matrix[coordinate_from.0 as usize][coordinate_from.1 as usize].piece =
+                    action.from.piece;
                 if action.from.piece.unwrap().rank == Rank::King {
                     match self.player {
                         Team::White => {
@@ -313,10 +569,6 @@ impl Game {
                         }
                     }
                 }
-                self.matrix[coordinate_to.0 as usize][coordinate_to.1 as usize].piece =
-                    action.to.piece;
-                self.matrix[coordinate_from.0 as usize][coordinate_from.1 as usize].piece =
-                    action.from.piece;
             }
             ActionType::Enpassant => {
                 let (team_offset, other_player) = match self.player {
@@ -409,6 +661,19 @@ impl Game {
                 return true;
             }
         }
+        // the enemy king, so kings can't walk next to each other
+        for dx in -1..2 {
+            for dy in -1..2 {
+                let (x, y) = (square.coordinate.0 + dx, square.coordinate.1 + dy);
+                if (dx, dy) != (0, 0) && not_out_of_bounds(x, y) {
+                    let other = self.matrix[x as usize][y as usize];
+                    if not_same_team(self.player, other) && other.piece.unwrap().rank == Rank::King
+                    {
+                        return true;
+                    }
+                }
+            }
+        }
         is_attacked
     }
 
@@ -483,7 +748,12 @@ impl Game {
             black_king_square,
             white_king_square,
             fifty_turn: 0,
+            clock_history: vec![],
+            fullmove: 1,
             promotion_piece:None,
+            castling: [true; 4],
+            castling_history: vec![],
+            start_en_passant: None,
         };
         game.calculate_game_state();
         game
@@ -517,35 +787,22 @@ impl Game {
 
         if !self.is_more_moves() {
             if is_check {
-                println!("Checkmate");
                 self.gamestate=GameState::Checkmate;
                 return GameState::Checkmate;
             } else {
-                println!("Stalemate");
                 self.gamestate=GameState::Stalemate;
                 return GameState::Stalemate;
             }
         }
+        // fifty moves by each player without a capture or pawn move
+        if self.fifty_turn >= 100 {
+            self.gamestate=GameState::FiftyRule;
+            return GameState::FiftyRule;
+        }
         if is_check{
-            println!("Check");
             self.gamestate=GameState::Check;
             return GameState::Check;
         }
-
-        if  !self.history.is_empty() &&self.history.last().unwrap().to.piece.is_some() {
-            self.fifty_turn = 0;
-        } else if  !self.history.is_empty()&&self.history.last().unwrap().from.piece.is_some() {
-            if self.history.last().unwrap().from.piece.unwrap().rank == Rank::Pawn {
-                self.fifty_turn = 0;
-            }
-        } else {
-            self.fifty_turn += 1;
-        }
-        if self.fifty_turn / 2 == 100 {
-            println!("50-rule draw");
-            self.gamestate=GameState::FiftyRule;
-            return GameState::FiftyRule;
-        }
         self.gamestate=GameState::Active;
         GameState::Active
     }
@@ -554,7 +811,64 @@ impl Game {
     pub fn get_game_state(&mut self) -> GameState {
         self.gamestate
     }
-        
+
+    // [white kingside, white queenside, black kingside, black queenside]
+    pub fn castling_rights(&self) -> [bool; 4] {
+        let mut rights = [false; 4];
+        for (i, &(team, rook_column)) in [
+            (Team::White, 7),
+            (Team::White, 0),
+            (Team::Black, 7),
+            (Team::Black, 0),
+        ]
+        .iter()
+        .enumerate()
+        {
+            let row = match team {
+                Team::White => 0,
+                Team::Black => 7,
+            };
+            let king = self.matrix[4][row].piece;
+            let rook = self.matrix[rook_column][row].piece;
+            let in_place = match (king, rook) {
+                (Some(k), Some(r)) => {
+                    k.rank == Rank::King && k.team == team && r.rank == Rank::Rook && r.team == team
+                }
+                _ => false,
+            };
+            rights[i] = self.castling[i] && in_place;
+        }
+        rights
+    }
+
+    // The square a pawn skipped over with a double step on the last move
+    pub fn en_passant_square(&self) -> Option<(isize, isize)> {
+        let last = match self.history.last() {
+            Some(action) => action,
+            None => return self.start_en_passant,
+        };
+        let moved = last.from.piece?;
+        let (from, to) = (last.from.coordinate, last.to.coordinate);
+        if moved.rank == Rank::Pawn && (to.1 - from.1).abs() == 2 {
+            Some((from.0, (from.1 + to.1) / 2))
+        } else {
+            None
+        }
+    }
+
+    // Halfmoves since the last capture or pawn move
+    pub fn halfmove_clock(&self) -> i32 {
+        self.fifty_turn
+    }
+
+    pub fn hash(&self) -> u64 {
+        zobrist::hash(self)
+    }
+
+    // The legal positions the current one can have been reached from
+    pub fn unmoves(&self) -> Vec<Unmove> {
+        unmoves::generate_unmoves(self)
+    }
 }
 
 impl fmt::Display for Game {
@@ -595,6 +909,14 @@ pub fn coordinate_to_string(coordinate: (isize, isize)) -> String {
     String::from(column_letter) + &row_letter
 }
 
+pub fn coordinate_to_index(coordinate: (isize, isize)) -> usize {
+    (coordinate.1 * 8 + coordinate.0) as usize
+}
+
+pub fn coordinate_from_index(index: usize) -> (isize, isize) {
+    ((index % 8) as isize, (index / 8) as isize)
+}
+
 pub fn coordinate_from_string(letter_coordinate: &str) -> Result<(isize, isize), String> {
     if letter_coordinate.len() != 2 {
         return Result::Err(String::from("Coordinate wasnt in correct format"));
@@ -627,28 +949,37 @@ pub fn coordinate_from_string(letter_coordinate: &str) -> Result<(isize, isize),
     Ok((column, row - 1))
 }
 
+// king and rook squares for [white kingside, white queenside, black kingside,
+// black queenside]
+const CASTLING_SQUARES: [((isize, isize), (isize, isize)); 4] =
+    [((4, 0), (7, 0)), ((4, 0), (0, 0)), ((4, 7), (7, 7)), ((4, 7), (0, 7))];
+
 pub fn unmoved(game: &Game, from_square: Square) -> bool {
     match from_square.piece.unwrap().rank {
         Rank::Pawn => match from_square.piece.unwrap().team {
             Team::White => from_square.coordinate.1 == 1,
             Team::Black => from_square.coordinate.1 == 6,
         },
-        Rank::Rook => {
-            for action in game.history.iter() {
-                if action.from.coordinate == from_square.coordinate {
-                    return false;
-                }
-            }
-            true
-        }
-        _ => {
-            for action in game.history.iter() {
-                if action.from.coordinate == from_square.coordinate {
-                    return false;
-                }
-            }
-            true
-        }
+        _ => has_castling_right(game, from_square),
+    }
+}
+
+// Kings and rooks on their starting squares that have not lost their
+// castling right yet
+fn has_castling_right(game: &Game, square: Square) -> bool {
+    let piece = square.piece.unwrap();
+    let (row, offset) = match piece.team {
+        Team::White => (0, 0),
+        Team::Black => (7, 2),
+    };
+    if square.coordinate.1 != row {
+        return false;
+    }
+    match (piece.rank, square.coordinate.0) {
+        (Rank::King, 4) => game.castling[offset] || game.castling[offset + 1],
+        (Rank::Rook, 7) => game.castling[offset],
+        (Rank::Rook, 0) => game.castling[offset + 1],
+        _ => false,
     }
 }
 
@@ -699,6 +1030,43 @@ pub enum Rank {
     Queen,
     King,
 }
+impl Rank {
+    pub fn letter(&self) -> char {
+        match self {
+            Rank::Pawn => 'P',
+            Rank::Knight => 'N',
+            Rank::Bishop => 'B',
+            Rank::Rook => 'R',
+            Rank::Queen => 'Q',
+            Rank::King => 'K',
+        }
+    }
+
+    pub fn from_letter(letter: char) -> Option<Rank> {
+        match letter {
+            'P' => Some(Rank::Pawn),
+            'N' => Some(Rank::Knight),
+            'B' => Some(Rank::Bishop),
+            'R' => Some(Rank::Rook),
+            'Q' => Some(Rank::Queen),
+            'K' => Some(Rank::King),
+            _ => None,
+        }
+    }
+
+    // Pawn, knight, bishop, rook, queen, king, used to index tables by rank
+    pub fn index(&self) -> usize {
+        match self {
+            Rank::Pawn => 0,
+            Rank::Knight => 1,
+            Rank::Bishop => 2,
+            Rank::Rook => 3,
+            Rank::Queen => 4,
+            Rank::King => 5,
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum GameState {
     Active,
@@ -708,6 +1076,24 @@ pub enum GameState {
     FiftyRule,
 }
 
+// How a game ended, or would end with best play
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Outcome {
+    Win(Team),
+    Draw,
+}
+
+impl Outcome {
+    // The result as written in pgn
+    pub fn pgn_result(&self) -> &'static str {
+        match self {
+            Outcome::Win(Team::White) => "1-0",
+            Outcome::Win(Team::Black) => "0-1",
+            Outcome::Draw => "1/2-1/2",
+        }
+    }
+}
+
 impl fmt::Display for Rank {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         writeln!(f, "{}", self)
@@ -732,6 +1118,14 @@ impl Piece {
             Rank::King => ["♚", "♔"][team_index],
         }
     }
+    // Upper case for white and lower case for black
+    pub fn fen_letter(&self) -> char {
+        match self.team {
+            Team::White => self.rank.letter(),
+            Team::Black => self.rank.letter().to_ascii_lowercase(),
+        }
+    }
+
     fn team_to_int(&self) -> usize {
         match self.team {
             Team::White => 1,
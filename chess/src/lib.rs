@@ -1,4 +1,30 @@
+pub mod analysis;
+pub mod database;
+pub mod engine;
+pub mod epd;
+pub mod datagen;
+pub mod dtm;
+pub mod eval;
 pub mod game;
 pub mod moves;
+pub mod nnue;
+pub mod opening;
+pub mod opening_book;
+pub mod ordering;
 pub mod pgn;
+pub mod random;
+pub mod report;
+pub mod search;
+pub mod skill;
+pub mod solver;
+pub mod tablebase;
+pub mod timeman;
+pub mod tournament;
+pub mod transposition;
+pub mod tuner;
+pub mod unmoves;
+pub mod uci;
+pub mod uci_client;
+pub mod xboard;
+pub mod zobrist;
 mod tests;
@@ -8,6 +8,28 @@ pub struct Action {
     pub from: Square,
     pub to: Square,
     pub action_type: ActionType,
+    // the piece a pawn became, filled in when a promotion is performed
+    pub promotion: Option<Rank>,
+}
+
+impl Action {
+    // from and to square packed into 12 bits, enough to find the action again
+    // among the legal moves of the same position
+    pub fn key(&self) -> u16 {
+        let from = game::coordinate_to_index(self.from.coordinate) as u16;
+        let to = game::coordinate_to_index(self.to.coordinate) as u16;
+        from << 6 | to
+    }
+
+    pub fn to_uci(&self) -> String {
+        let mut text = game::coordinate_to_string(self.from.coordinate)
+            + &game::coordinate_to_string(self.to.coordinate);
+        if self.action_type == ActionType::Promotion {
+            let rank = self.promotion.unwrap_or(Rank::Queen);
+            text.push(rank.letter().to_ascii_lowercase());
+        }
+        text
+    }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -47,6 +69,53 @@ pub fn generate_moves(game: &mut game::Game, square: Square) -> Result<Vec<Actio
     Ok(legal_moveset)
 }
 
+// Captures, en passant and promotions. The search looks at these before the
+// quiet moves, so they are filtered out before the (expensive) legality check.
+pub fn generate_captures(game: &mut game::Game, square: Square) -> Result<Vec<Action>, String> {
+    generate_filtered_moves(game, square, true)
+}
+
+pub fn generate_quiets(game: &mut game::Game, square: Square) -> Result<Vec<Action>, String> {
+    generate_filtered_moves(game, square, false)
+}
+
+pub fn is_tactical(action: &Action) -> bool {
+    action.to.piece.is_some()
+        || action.action_type == ActionType::Enpassant
+        || action.action_type == ActionType::Promotion
+}
+
+fn generate_filtered_moves(
+    game: &mut game::Game,
+    square: Square,
+    captures: bool,
+) -> Result<Vec<Action>, String> {
+    let piece = match square.piece {
+        Some(p) => p,
+        None => return Err(String::from("Tried to move empty square")),
+    };
+    if piece.team != game.player {
+        return Err(String::from("Cant move enemy piece"));
+    };
+
+    let moveset: Vec<Action> = match piece.rank {
+        Rank::Pawn => gen_moveset_pawn(game, square),
+        Rank::Rook => gen_moveset_rook(game, square),
+        Rank::Knight => gen_moveset_knight(game, square),
+        Rank::Bishop => gen_moveset_bishop(game, square),
+        Rank::Queen => gen_moveset_queen(game, square),
+        Rank::King => gen_moveset_king(game, square),
+    };
+
+    let mut legal_moveset: Vec<Action> = vec![];
+    for action in moveset.iter().filter(|a| is_tactical(a) == captures) {
+        if !game.check(action) {
+            legal_moveset.push(*action);
+        }
+    }
+    Ok(legal_moveset)
+}
+
 fn gen_generic_moveset(
     game: &game::Game,
     start_square: Square,
@@ -92,6 +161,7 @@ fn psuedo_vaild_move(game: &game::Game, square: Square, x: isize, y: isize) -> (
                 from: square,
                 to: to_square,
                 action_type: ActionType::Regular,
+                promotion: None,
             };
             gen_moveset.push(action);
         } else if game::not_same_team(game.player, to_square) {
@@ -100,6 +170,7 @@ fn psuedo_vaild_move(game: &game::Game, square: Square, x: isize, y: isize) -> (
                 from: square,
                 to: to_square,
                 action_type: ActionType::Regular,
+                promotion: None,
             };
             gen_moveset.push(action)
         } else {
@@ -130,6 +201,7 @@ pub fn gen_moveset_pawn(game: &game::Game, start_square: Square) -> Vec<Action>
             from: start_square,
             to: new_square,
             action_type,
+            promotion: None,
         };
         available_moves.push(action);
     }
@@ -143,6 +215,7 @@ pub fn gen_moveset_pawn(game: &game::Game, start_square: Square) -> Vec<Action>
                     from: start_square,
                     to: new_square,
                     action_type: ActionType::Regular,
+                    promotion: None,
                 };
                 available_moves.push(action);
             }
@@ -151,29 +224,21 @@ pub fn gen_moveset_pawn(game: &game::Game, start_square: Square) -> Vec<Action>
 
     available_moves.append(&mut gen_pawn_attack_moveset(game, start_square));
 
-    let mut prev_action: &Action;
-
     //Enpassant
-    for dx in (-1..2).step_by(2) {
-        if game::not_out_of_bounds(x + dx, y) {
-            let side_square = game.matrix[(x + dx) as usize][y as usize];
-            if game::not_same_team(game.player, side_square)
-                && side_square.piece.unwrap().rank == Rank::Pawn
-                && !game.history.is_empty()
-            {
-                prev_action = game.history.last().unwrap();
-                if prev_action.from.piece.unwrap().rank == Rank::Pawn {
-                    let enemy_start_y = prev_action.from.coordinate.1;
-                    let dy = (y - enemy_start_y).abs();
-                    println!("{}", dy);
-                    if dy == 2 {
-                        let action = Action {
-                            from: start_square,
-                            to: game.matrix[(x + dx) as usize][(y + offset) as usize],
-                            action_type: ActionType::Enpassant,
-                        };
-                        available_moves.push(action);
-                    }
+    if let Some(en_passant) = game.en_passant_square() {
+        for dx in (-1..2).step_by(2) {
+            if en_passant == (x + dx, y + offset) {
+                let side_square = game.matrix[(x + dx) as usize][y as usize];
+                if game::not_same_team(game.player, side_square)
+                    && side_square.piece.unwrap().rank == Rank::Pawn
+                {
+                    let action = Action {
+                        from: start_square,
+                        to: game.matrix[(x + dx) as usize][(y + offset) as usize],
+                        action_type: ActionType::Enpassant,
+                        promotion: None,
+                    };
+                    available_moves.push(action);
                 }
             }
         }
@@ -268,6 +333,7 @@ pub fn gen_pawn_attack_moveset(game: &game::Game, from_square: Square) -> Vec<Ac
                     from: from_square,
                     to: to_square,
                     action_type,
+                    promotion: None,
                 };
                 gen_moveset.push(action);
             }
@@ -279,76 +345,35 @@ pub fn gen_pawn_attack_moveset(game: &game::Game, from_square: Square) -> Vec<Ac
 pub fn castling(game: &game::Game, start_square: Square) -> Vec<Action> {
     let (x, y) = (start_square.coordinate.0, start_square.coordinate.1);
     let mut gen_moveset: Vec<Action> = vec![];
-    let mut squares_is_safe: bool = false;
-    let mut can_castle: bool = false;
-    //kingside castling
-    if !game.check_square_attacked(start_square) {
-        if game::unmoved(game, start_square)
-            && game.matrix[7][y as usize].piece.is_some()
-            && game::unmoved(game, game.matrix[7][y as usize])
-        {
-            for dx in 1..3 {
-                if game::not_out_of_bounds(x + dx, y)
-                    && game.matrix[(x + dx) as usize][y as usize].piece.is_none()
-                {
-                    can_castle = true;
-                } else {
-                    can_castle = false;
-                }
-            }
-            if can_castle {
-                for dx in 1..3 {
-                    if !game.check_square_attacked(game.matrix[(x + dx) as usize][y as usize]) {
-                        squares_is_safe = true;
-                    } else {
-                        squares_is_safe = false;
-                    }
-                }
-            }
-        }
-        if squares_is_safe && can_castle {
-            let action = Action {
-                from: start_square,
-                to: game.matrix[(x + 2) as usize][y as usize],
-                action_type: ActionType::Castling,
-            };
-            gen_moveset.push(action);
-        }
-        squares_is_safe = false;
-        can_castle = false;
-
-        if game::unmoved(game, start_square)
-            && game.matrix[0][y as usize].piece.is_some()
-            && game::unmoved(game, game.matrix[0][y as usize])
+    if game.check_square_attacked(start_square) || !game::unmoved(game, start_square) {
+        return gen_moveset;
+    }
+    // kingside with the rook on the h file, queenside with the one on the a file
+    for (rook_x, direction) in [(7, 1), (0, -1)] {
+        let rook_square = game.matrix[rook_x as usize][y as usize];
+        if rook_square.piece.map_or(true, |piece| piece.rank != Rank::Rook)
+            || !game::unmoved(game, rook_square)
         {
-            for dx in 1..3 {
-                if game::not_out_of_bounds(x - dx, y)
-                    && game.matrix[(x - dx) as usize][y as usize].piece.is_none()
-                {
-                    can_castle = true;
-                } else {
-                    can_castle = false;
-                }
-            }
-            if can_castle {
-                for dx in 1..3 {
-                    if !game.check_square_attacked(game.matrix[(x - dx) as usize][y as usize]) {
-                        squares_is_safe = true;
-                    } else {
-                        squares_is_safe = false;
-                    }
-                }
-            }
+            continue;
         }
-        if squares_is_safe && can_castle {
+        // every square between king and rook is empty, and the squares the
+        // king crosses and lands on aren't attacked
+        let between = (x.min(rook_x) + 1)..x.max(rook_x);
+        let empty = between
+            .into_iter()
+            .all(|square_x| game.matrix[square_x as usize][y as usize].piece.is_none());
+        let safe = (1..3).all(|dx| {
+            !game.check_square_attacked(game.matrix[(x + direction * dx) as usize][y as usize])
+        });
+        if empty && safe {
             let action = Action {
                 from: start_square,
-                to: game.matrix[(x - 2) as usize][y as usize],
+                to: game.matrix[(x + 2 * direction) as usize][y as usize],
                 action_type: ActionType::Castling,
+                promotion: None,
             };
             gen_moveset.push(action);
         }
     }
-
     gen_moveset
 }
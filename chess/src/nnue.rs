@@ -0,0 +1,313 @@
+// An efficiently updatable neural network evaluation (NNUE) with HalfKP
+// inputs, used in place of the handcrafted evaluation when a network file is
+// loaded. Every input is a (king square, piece, square) triple seen from one
+// side: the side's own king square and every piece but the kings, the board
+// flipped for black so both sides see themselves at the bottom. The first
+// layer's sums for both sides (the accumulator) only change by the pieces a
+// move adds and removes, except for the side whose king moved, so the search
+// updates them move by move instead of summing every input again.
+//
+// Network file, all numbers little endian:
+//   magic "CNUE", version u32 (1)
+//   l1 u32: accumulator size of each side, l2 u32: hidden layer size
+//   l1 i16: accumulator biases
+//   INPUTS * l1 i16: accumulator weights, l1 for each input in input order
+//   l2 i32: hidden biases
+//   l2 * 2 * l1 i8: hidden weights, 2 * l1 for each hidden neuron, the side
+//     to move's accumulator first
+//   i32: output bias
+//   l2 i8: output weights
+// The accumulator is clipped to 0..=127 (127 being 1.0), the hidden layer
+// divides by 64 (64 being a weight of 1.0) and is clipped the same way, and
+// the output divided by OUTPUT_SCALE is the score in centipawns for the side
+// to move.
+use std::fs;
+
+use crate::game::{Game, Rank, Team};
+use crate::random::xorshift;
+
+const MAGIC: &[u8; 4] = b"CNUE";
+const VERSION: u32 = 1;
+// pawn, knight, bishop, rook and queen of both colors on every square
+const PIECE_INPUTS: usize = 10 * 64;
+pub const INPUTS: usize = 64 * PIECE_INPUTS;
+const ACTIVATION_MAX: i32 = 127;
+const WEIGHT_SCALE: i32 = 64;
+pub const OUTPUT_SCALE: i32 = 16;
+// far from the mate and tablebase scores of the search
+const MAX_SCORE: i32 = 10000;
+
+// The accumulators of white and black
+#[derive(Debug, Clone, PartialEq)]
+pub struct Accumulator {
+    values: [Vec<i16>; 2],
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Network {
+    l1: usize,
+    l2: usize,
+    accumulator_biases: Vec<i16>,
+    accumulator_weights: Vec<i16>,
+    hidden_biases: Vec<i32>,
+    hidden_weights: Vec<i8>,
+    output_bias: i32,
+    output_weights: Vec<i8>,
+}
+
+impl Network {
+    // A network of the given sizes with small random weights, a starting point
+    // for training. `random` is any number, the same one gives the same network.
+    pub fn new(l1: usize, l2: usize, random: u64) -> Network {
+        let mut state = random;
+        let mut next = |range: i64| {
+            (xorshift(&mut state) % (2 * range as u64 + 1)) as i64 - range
+        };
+        Network {
+            l1,
+            l2,
+            accumulator_biases: (0..l1).map(|_| next(16) as i16).collect(),
+            accumulator_weights: (0..INPUTS * l1).map(|_| next(8) as i16).collect(),
+            hidden_biases: (0..l2).map(|_| next(64) as i32).collect(),
+            hidden_weights: (0..l2 * 2 * l1).map(|_| next(16) as i8).collect(),
+            output_bias: 0,
+            output_weights: (0..l2).map(|_| next(32) as i8).collect(),
+        }
+    }
+
+    pub fn read(filepath: &str) -> Result<Network, String> {
+        let bytes = fs::read(filepath)
+            .map_err(|e| format!("could not read network {}: {}", filepath, e))?;
+        Network::from_bytes(&bytes)
+    }
+
+    pub fn write(&self, filepath: &str) -> Result<(), String> {
+        fs::write(filepath, self.to_bytes())
+            .map_err(|e| format!("could not write {}: {}", filepath, e))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Network, String> {
+        if bytes.len() < 16 || &bytes[0..4] != MAGIC {
+            return Err(String::from("not a network file"));
+        }
+        let u32_at =
+            |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        if u32_at(4) != VERSION {
+            return Err(format!("unsupported network version {}", u32_at(4)));
+        }
+        let (l1, l2) = (u32_at(8) as usize, u32_at(12) as usize);
+        let size = 16 + 2 * l1 + 2 * INPUTS * l1 + 4 * l2 + 2 * l1 * l2 + 4 + l2;
+        if l1 == 0 || l2 == 0 || bytes.len() != size {
+            return Err(format!(
+                "network of sizes {} and {} needs {} bytes, not {}",
+                l1,
+                l2,
+                size,
+                bytes.len()
+            ));
+        }
+
+        let mut reader = Reader {
+            bytes,
+            position: 16,
+        };
+        Ok(Network {
+            l1,
+            l2,
+            accumulator_biases: reader.i16s(l1),
+            accumulator_weights: reader.i16s(INPUTS * l1),
+            hidden_biases: reader.i32s(l2),
+            hidden_weights: reader.i8s(2 * l1 * l2),
+            output_bias: reader.i32s(1)[0],
+            output_weights: reader.i8s(l2),
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        for value in [VERSION, self.l1 as u32, self.l2 as u32].iter() {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        for value in self
+            .accumulator_biases
+            .iter()
+            .chain(self.accumulator_weights.iter())
+        {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        for value in self.hidden_biases.iter() {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend(self.hidden_weights.iter().map(|&value| value as u8));
+        bytes.extend_from_slice(&self.output_bias.to_le_bytes());
+        bytes.extend(self.output_weights.iter().map(|&value| value as u8));
+        bytes
+    }
+
+    // The accumulator of a position, summed over all its inputs
+    pub fn refresh(&self, game: &Game) -> Accumulator {
+        let mut accumulator = Accumulator {
+            values: [vec![0; self.l1], vec![0; self.l1]],
+        };
+        for side in 0..2 {
+            self.refresh_side(game, side, &mut accumulator.values[side]);
+        }
+        accumulator
+    }
+
+    // The accumulator of `child`, a position one move after `parent`
+    pub fn update(
+        &self,
+        parent_accumulator: &Accumulator,
+        parent: &Game,
+        child: &Game,
+        accumulator: &mut Accumulator,
+    ) {
+        for side in 0..2 {
+            let values = &mut accumulator.values[side];
+            let king = king_index(child, side);
+            if king != king_index(parent, side) {
+                self.refresh_side(child, side, values);
+                continue;
+            }
+            values.copy_from_slice(&parent_accumulator.values[side]);
+            for (before, after) in parent
+                .matrix
+                .iter()
+                .flatten()
+                .zip(child.matrix.iter().flatten())
+            {
+                let (old, new) = (before.piece, after.piece);
+                if old.map(|p| (p.team, p.rank)) == new.map(|p| (p.team, p.rank)) {
+                    continue;
+                }
+                let index = square_index(before.coordinate);
+                if let Some(piece) = old {
+                    if let Some(input) = input_index(side, king, piece.team, piece.rank, index) {
+                        self.add_input(values, input, -1);
+                    }
+                }
+                if let Some(piece) = new {
+                    if let Some(input) = input_index(side, king, piece.team, piece.rank, index) {
+                        self.add_input(values, input, 1);
+                    }
+                }
+            }
+        }
+    }
+
+    // The score in centipawns for the side to move of the accumulator's
+    // position
+    pub fn evaluate(&self, accumulator: &Accumulator, player: Team) -> i32 {
+        let (us, them) = match player {
+            Team::White => (0, 1),
+            Team::Black => (1, 0),
+        };
+        let inputs: Vec<i32> = accumulator.values[us]
+            .iter()
+            .chain(accumulator.values[them].iter())
+            .map(|&value| (value as i32).clamp(0, ACTIVATION_MAX))
+            .collect();
+        let mut output = self.output_bias;
+        for neuron in 0..self.l2 {
+            let weights = &self.hidden_weights[neuron * 2 * self.l1..(neuron + 1) * 2 * self.l1];
+            let sum: i32 = weights
+                .iter()
+                .zip(inputs.iter())
+                .map(|(&weight, &input)| weight as i32 * input)
+                .sum();
+            let hidden =
+                ((self.hidden_biases[neuron] + sum) / WEIGHT_SCALE).clamp(0, ACTIVATION_MAX);
+            output += self.output_weights[neuron] as i32 * hidden;
+        }
+        (output / OUTPUT_SCALE).clamp(-MAX_SCORE, MAX_SCORE)
+    }
+
+    // The score of a position without an accumulator kept up to date
+    pub fn evaluate_game(&self, game: &Game) -> i32 {
+        self.evaluate(&self.refresh(game), game.player)
+    }
+
+    fn refresh_side(&self, game: &Game, side: usize, values: &mut [i16]) {
+        values.copy_from_slice(&self.accumulator_biases);
+        let king = king_index(game, side);
+        for square in game.matrix.iter().flatten() {
+            if let Some(piece) = square.piece {
+                let index = square_index(square.coordinate);
+                if let Some(input) = input_index(side, king, piece.team, piece.rank, index) {
+                    self.add_input(values, input, 1);
+                }
+            }
+        }
+    }
+
+    fn add_input(&self, values: &mut [i16], input: usize, sign: i16) {
+        let weights = &self.accumulator_weights[input * self.l1..(input + 1) * self.l1];
+        for (value, &weight) in values.iter_mut().zip(weights.iter()) {
+            *value = value.wrapping_add(sign.wrapping_mul(weight));
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> &'a [u8] {
+        let slice = &self.bytes[self.position..self.position + count];
+        self.position += count;
+        slice
+    }
+
+    fn i8s(&mut self, count: usize) -> Vec<i8> {
+        self.take(count).iter().map(|&byte| byte as i8).collect()
+    }
+
+    fn i16s(&mut self, count: usize) -> Vec<i16> {
+        self.take(2 * count)
+            .chunks(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .collect()
+    }
+
+    fn i32s(&mut self, count: usize) -> Vec<i32> {
+        self.take(4 * count)
+            .chunks(4)
+            .map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect()
+    }
+}
+
+fn square_index(coordinate: (isize, isize)) -> usize {
+    (coordinate.1 * 8 + coordinate.0) as usize
+}
+
+// The square of the side's king as the side sees the board
+fn king_index(game: &Game, side: usize) -> usize {
+    let square = if side == 0 {
+        game.white_king_square
+    } else {
+        game.black_king_square
+    };
+    orient(side, square_index(square.coordinate))
+}
+
+fn orient(side: usize, index: usize) -> usize {
+    if side == 0 {
+        index
+    } else {
+        index ^ 56
+    }
+}
+
+// The input of a piece seen from `side`, none for kings
+fn input_index(side: usize, king: usize, team: Team, rank: Rank, index: usize) -> Option<usize> {
+    if rank == Rank::King {
+        return None;
+    }
+    let own = (team == Team::White) == (side == 0);
+    let piece = rank.index() * 2 + if own { 0 } else { 1 };
+    Some(king * PIECE_INPUTS + piece * 64 + orient(side, index))
+}
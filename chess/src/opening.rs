@@ -0,0 +1,317 @@
+// ECO classification of openings. The lines of the table below are played
+// out once and every position they reach is indexed by its hash, so a game
+// is classified by the positions it reaches and transposed move orders are
+// recognised. The deepest line matched names the opening.
+//
+// The table is partial: it has 192 lines for 153 of the 500 ECO codes, the
+// main lines of the common openings. A game in an opening it lacks gets the
+// code of the last line of the table it went through, or none, rather than
+// the code a complete table would give.
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use crate::game::Game;
+use crate::moves::Action;
+use crate::pgn;
+
+// positions further into a game aren't looked up
+const SCAN_PLIES: usize = 40;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Opening {
+    pub eco: String,
+    pub name: String,
+    pub variation: Option<String>,
+}
+
+struct Entry {
+    opening: Opening,
+    plies: usize,
+}
+
+struct Book {
+    entries: Vec<Entry>,
+    // the entry ending in each position, the shortest line if several do
+    positions: HashMap<u64, usize>,
+}
+
+// The opening of a game from the starting position, none if it leaves the
+// table at once
+pub fn classify(actions: &[Action]) -> Option<Opening> {
+    let book = book();
+    let mut game = Game::new();
+    let mut deepest: Option<&Entry> = None;
+    for action in actions.iter().take(SCAN_PLIES) {
+        game.perform_action(*action);
+        if let Some(&index) = book.positions.get(&game.hash()) {
+            let entry = &book.entries[index];
+            if deepest.map_or(true, |deepest| entry.plies >= deepest.plies) {
+                deepest = Some(entry);
+            }
+        }
+    }
+    deepest.map(|entry| entry.opening.clone())
+}
+
+// Every opening of the table with its moves
+pub fn openings() -> Vec<(Opening, Vec<Action>)> {
+    ECO.lines()
+        .map(|line| parse_line(line).unwrap_or_else(|e| panic!("eco table: {}: {}", line, e)))
+        .collect()
+}
+
+fn book() -> &'static Book {
+    static BOOK: OnceLock<Book> = OnceLock::new();
+    BOOK.get_or_init(|| {
+        let mut book = Book {
+            entries: vec![],
+            positions: HashMap::new(),
+        };
+        for (opening, actions) in openings() {
+            let mut game = Game::new();
+            for action in actions.iter() {
+                game.perform_action(*action);
+            }
+            let index = book.entries.len();
+            book.entries.push(Entry {
+                opening,
+                plies: actions.len(),
+            });
+            let entries = &book.entries;
+            book.positions
+                .entry(game.hash())
+                .and_modify(|other| {
+                    if entries[*other].plies > actions.len() {
+                        *other = index;
+                    }
+                })
+                .or_insert(index);
+        }
+        book
+    })
+}
+
+// "ECO<tab>Name: Variation<tab>moves"
+fn parse_line(line: &str) -> Result<(Opening, Vec<Action>), String> {
+    let mut fields = line.split('\t');
+    let (Some(eco), Some(full_name), Some(moves)) = (fields.next(), fields.next(), fields.next())
+    else {
+        return Err(String::from("expected three fields"));
+    };
+    let (name, variation) = match full_name.split_once(": ") {
+        Some((name, variation)) => (name, Some(variation.to_string())),
+        None => (full_name, None),
+    };
+    let mut game = Game::new();
+    let mut actions = vec![];
+    for san in moves
+        .split_whitespace()
+        .filter(|token| !token.ends_with('.'))
+    {
+        let action = pgn::action_from_san(&mut game, san)?;
+        game.perform_action(action);
+        actions.push(action);
+    }
+    let opening = Opening {
+        eco: eco.to_string(),
+        name: name.to_string(),
+        variation,
+    };
+    Ok((opening, actions))
+}
+
+// ECO code, name and moves of every opening, a tab between them. The name of
+// a variation follows the opening's name after ": ".
+const ECO: &str = "\
+A00\tPolish Opening\t1. b4\n\
+A00\tGrob Opening\t1. g4\n\
+A00\tVan't Kruijs Opening\t1. e3\n\
+A00\tMieses Opening\t1. d3\n\
+A00\tHungarian Opening\t1. g3\n\
+A00\tSaragossa Opening\t1. c3\n\
+A00\tAnderssen's Opening\t1. a3\n\
+A00\tSodium Attack\t1. Na3\n\
+A00\tAmar Opening\t1. Nh3\n\
+A00\tDunst Opening\t1. Nc3\n\
+A01\tNimzo-Larsen Attack\t1. b3\n\
+A02\tBird Opening\t1. f4\n\
+A02\tBird Opening: From's Gambit\t1. f4 e5\n\
+A03\tBird Opening: Dutch Variation\t1. f4 d5\n\
+A04\tZukertort Opening\t1. Nf3\n\
+A04\tZukertort Opening: Sicilian Invitation\t1. Nf3 c5\n\
+A05\tZukertort Opening: Indian Defense\t1. Nf3 Nf6\n\
+A06\tZukertort Opening: Queen's Gambit Invitation\t1. Nf3 d5\n\
+A07\tKing's Indian Attack\t1. Nf3 d5 2. g3\n\
+A09\tRéti Opening\t1. Nf3 d5 2. c4\n\
+A10\tEnglish Opening\t1. c4\n\
+A10\tEnglish Opening: Anglo-Dutch Defense\t1. c4 f5\n\
+A13\tEnglish Opening: Agincourt Defense\t1. c4 e6\n\
+A15\tEnglish Opening: Anglo-Indian Defense\t1. c4 Nf6\n\
+A16\tEnglish Opening: Anglo-Indian Defense, Queen's Knight Variation\t1. c4 Nf6 2. Nc3\n\
+A20\tEnglish Opening: King's English Variation\t1. c4 e5\n\
+A21\tEnglish Opening: Reversed Sicilian\t1. c4 e5 2. Nc3\n\
+A25\tEnglish Opening: Closed\t1. c4 e5 2. Nc3 Nc6 3. g3 g6 4. Bg2 Bg7\n\
+A28\tEnglish Opening: Four Knights System\t1. c4 e5 2. Nc3 Nf6 3. Nf3 Nc6\n\
+A30\tEnglish Opening: Symmetrical Variation\t1. c4 c5\n\
+A40\tQueen's Pawn Game\t1. d4\n\
+A40\tEnglund Gambit\t1. d4 e5\n\
+A40\tModern Defense\t1. d4 g6\n\
+A40\tHorwitz Defense\t1. d4 e6\n\
+A43\tOld Benoni Defense\t1. d4 c5\n\
+A45\tIndian Defense\t1. d4 Nf6\n\
+A45\tTrompowsky Attack\t1. d4 Nf6 2. Bg5\n\
+A46\tIndian Defense: Knights Variation\t1. d4 Nf6 2. Nf3\n\
+A48\tEast Indian Defense\t1. d4 Nf6 2. Nf3 g6\n\
+A50\tIndian Defense: Normal Variation\t1. d4 Nf6 2. c4\n\
+A51\tBudapest Defense\t1. d4 Nf6 2. c4 e5\n\
+A53\tOld Indian Defense\t1. d4 Nf6 2. c4 d6\n\
+A56\tBenoni Defense\t1. d4 Nf6 2. c4 c5\n\
+A57\tBenko Gambit\t1. d4 Nf6 2. c4 c5 3. d5 b5\n\
+A60\tBenoni Defense: Modern Variation\t1. d4 Nf6 2. c4 c5 3. d5 e6\n\
+A80\tDutch Defense\t1. d4 f5\n\
+A83\tDutch Defense: Staunton Gambit\t1. d4 f5 2. e4\n\
+A84\tDutch Defense: Classical Variation\t1. d4 f5 2. c4\n\
+A87\tDutch Defense: Leningrad Variation\t1. d4 f5 2. c4 Nf6 3. g3 g6 4. Bg2 Bg7 5. Nf3\n\
+A90\tDutch Defense: Stonewall Variation\t1. d4 f5 2. c4 Nf6 3. g3 e6 4. Bg2 d5\n\
+B00\tKing's Pawn Game\t1. e4\n\
+B00\tNimzowitsch Defense\t1. e4 Nc6\n\
+B00\tOwen Defense\t1. e4 b6\n\
+B01\tScandinavian Defense\t1. e4 d5\n\
+B01\tScandinavian Defense: Main Line\t1. e4 d5 2. exd5 Qxd5 3. Nc3 Qa5\n\
+B01\tScandinavian Defense: Modern Variation\t1. e4 d5 2. exd5 Nf6\n\
+B02\tAlekhine Defense\t1. e4 Nf6\n\
+B03\tAlekhine Defense: Four Pawns Attack\t1. e4 Nf6 2. e5 Nd5 3. d4 d6 4. c4 Nb6 5. f4\n\
+B04\tAlekhine Defense: Modern Variation\t1. e4 Nf6 2. e5 Nd5 3. d4 d6 4. Nf3\n\
+B06\tModern Defense\t1. e4 g6\n\
+B07\tPirc Defense\t1. e4 d6 2. d4 Nf6\n\
+B08\tPirc Defense: Classical Variation\t1. e4 d6 2. d4 Nf6 3. Nc3 g6 4. Nf3\n\
+B09\tPirc Defense: Austrian Attack\t1. e4 d6 2. d4 Nf6 3. Nc3 g6 4. f4\n\
+B10\tCaro-Kann Defense\t1. e4 c6\n\
+B12\tCaro-Kann Defense: Advance Variation\t1. e4 c6 2. d4 d5 3. e5\n\
+B13\tCaro-Kann Defense: Exchange Variation\t1. e4 c6 2. d4 d5 3. exd5 cxd5\n\
+B13\tCaro-Kann Defense: Panov Attack\t1. e4 c6 2. d4 d5 3. exd5 cxd5 4. c4\n\
+B15\tCaro-Kann Defense\t1. e4 c6 2. d4 d5 3. Nc3\n\
+B17\tCaro-Kann Defense: Karpov Variation\t1. e4 c6 2. d4 d5 3. Nc3 dxe4 4. Nxe4 Nd7\n\
+B18\tCaro-Kann Defense: Classical Variation\t1. e4 c6 2. d4 d5 3. Nc3 dxe4 4. Nxe4 Bf5\n\
+B20\tSicilian Defense\t1. e4 c5\n\
+B21\tSicilian Defense: Smith-Morra Gambit\t1. e4 c5 2. d4 cxd4 3. c3\n\
+B22\tSicilian Defense: Alapin Variation\t1. e4 c5 2. c3\n\
+B23\tSicilian Defense: Closed\t1. e4 c5 2. Nc3\n\
+B27\tSicilian Defense\t1. e4 c5 2. Nf3\n\
+B30\tSicilian Defense: Old Sicilian\t1. e4 c5 2. Nf3 Nc6\n\
+B30\tSicilian Defense: Rossolimo Variation\t1. e4 c5 2. Nf3 Nc6 3. Bb5\n\
+B32\tSicilian Defense: Open\t1. e4 c5 2. Nf3 Nc6 3. d4 cxd4 4. Nxd4\n\
+B33\tSicilian Defense: Sveshnikov Variation\t1. e4 c5 2. Nf3 Nc6 3. d4 cxd4 4. Nxd4 Nf6 5. Nc3 e5\n\
+B34\tSicilian Defense: Accelerated Dragon\t1. e4 c5 2. Nf3 Nc6 3. d4 cxd4 4. Nxd4 g6\n\
+B40\tSicilian Defense: French Variation\t1. e4 c5 2. Nf3 e6\n\
+B41\tSicilian Defense: Kan Variation\t1. e4 c5 2. Nf3 e6 3. d4 cxd4 4. Nxd4 a6\n\
+B44\tSicilian Defense: Taimanov Variation\t1. e4 c5 2. Nf3 e6 3. d4 cxd4 4. Nxd4 Nc6\n\
+B50\tSicilian Defense: Modern Variations\t1. e4 c5 2. Nf3 d6\n\
+B51\tSicilian Defense: Moscow Variation\t1. e4 c5 2. Nf3 d6 3. Bb5+\n\
+B54\tSicilian Defense: Open\t1. e4 c5 2. Nf3 d6 3. d4 cxd4 4. Nxd4\n\
+B56\tSicilian Defense: Classical Variation\t1. e4 c5 2. Nf3 d6 3. d4 cxd4 4. Nxd4 Nf6 5. Nc3 Nc6\n\
+B70\tSicilian Defense: Dragon Variation\t1. e4 c5 2. Nf3 d6 3. d4 cxd4 4. Nxd4 Nf6 5. Nc3 g6\n\
+B76\tSicilian Defense: Dragon Variation, Yugoslav Attack\t1. e4 c5 2. Nf3 d6 3. d4 cxd4 4. Nxd4 Nf6 5. Nc3 g6 6. Be3 Bg7 7. f3\n\
+B80\tSicilian Defense: Scheveningen Variation\t1. e4 c5 2. Nf3 d6 3. d4 cxd4 4. Nxd4 Nf6 5. Nc3 e6\n\
+B90\tSicilian Defense: Najdorf Variation\t1. e4 c5 2. Nf3 d6 3. d4 cxd4 4. Nxd4 Nf6 5. Nc3 a6\n\
+B90\tSicilian Defense: Najdorf Variation, English Attack\t1. e4 c5 2. Nf3 d6 3. d4 cxd4 4. Nxd4 Nf6 5. Nc3 a6 6. Be3\n\
+B92\tSicilian Defense: Najdorf Variation, Opocensky Variation\t1. e4 c5 2. Nf3 d6 3. d4 cxd4 4. Nxd4 Nf6 5. Nc3 a6 6. Be2\n\
+B94\tSicilian Defense: Najdorf Variation, Main Line\t1. e4 c5 2. Nf3 d6 3. d4 cxd4 4. Nxd4 Nf6 5. Nc3 a6 6. Bg5\n\
+C00\tFrench Defense\t1. e4 e6\n\
+C01\tFrench Defense: Exchange Variation\t1. e4 e6 2. d4 d5 3. exd5 exd5\n\
+C02\tFrench Defense: Advance Variation\t1. e4 e6 2. d4 d5 3. e5\n\
+C03\tFrench Defense: Tarrasch Variation\t1. e4 e6 2. d4 d5 3. Nd2\n\
+C10\tFrench Defense: Paulsen Variation\t1. e4 e6 2. d4 d5 3. Nc3\n\
+C10\tFrench Defense: Rubinstein Variation\t1. e4 e6 2. d4 d5 3. Nc3 dxe4\n\
+C11\tFrench Defense: Classical Variation\t1. e4 e6 2. d4 d5 3. Nc3 Nf6\n\
+C11\tFrench Defense: Steinitz Variation\t1. e4 e6 2. d4 d5 3. Nc3 Nf6 4. e5\n\
+C15\tFrench Defense: Winawer Variation\t1. e4 e6 2. d4 d5 3. Nc3 Bb4\n\
+C20\tKing's Pawn Game\t1. e4 e5\n\
+C21\tCenter Game\t1. e4 e5 2. d4 exd4\n\
+C21\tDanish Gambit\t1. e4 e5 2. d4 exd4 3. c3\n\
+C23\tBishop's Opening\t1. e4 e5 2. Bc4\n\
+C25\tVienna Game\t1. e4 e5 2. Nc3\n\
+C29\tVienna Game: Vienna Gambit\t1. e4 e5 2. Nc3 Nf6 3. f4\n\
+C30\tKing's Gambit\t1. e4 e5 2. f4\n\
+C31\tKing's Gambit Declined: Falkbeer Countergambit\t1. e4 e5 2. f4 d5\n\
+C33\tKing's Gambit Accepted\t1. e4 e5 2. f4 exf4\n\
+C40\tKing's Knight Opening\t1. e4 e5 2. Nf3\n\
+C40\tLatvian Gambit\t1. e4 e5 2. Nf3 f5\n\
+C40\tElephant Gambit\t1. e4 e5 2. Nf3 d5\n\
+C41\tPhilidor Defense\t1. e4 e5 2. Nf3 d6\n\
+C42\tPetrov's Defense\t1. e4 e5 2. Nf3 Nf6\n\
+C43\tPetrov's Defense: Modern Attack\t1. e4 e5 2. Nf3 Nf6 3. d4\n\
+C44\tKing's Knight Opening: Normal Variation\t1. e4 e5 2. Nf3 Nc6\n\
+C44\tPonziani Opening\t1. e4 e5 2. Nf3 Nc6 3. c3\n\
+C44\tScotch Game\t1. e4 e5 2. Nf3 Nc6 3. d4\n\
+C44\tScotch Game: Scotch Gambit\t1. e4 e5 2. Nf3 Nc6 3. d4 exd4 4. Bc4\n\
+C45\tScotch Game: Main Line\t1. e4 e5 2. Nf3 Nc6 3. d4 exd4 4. Nxd4\n\
+C46\tThree Knights Opening\t1. e4 e5 2. Nf3 Nc6 3. Nc3\n\
+C47\tFour Knights Game\t1. e4 e5 2. Nf3 Nc6 3. Nc3 Nf6\n\
+C47\tFour Knights Game: Scotch Variation\t1. e4 e5 2. Nf3 Nc6 3. Nc3 Nf6 4. d4\n\
+C48\tFour Knights Game: Spanish Variation\t1. e4 e5 2. Nf3 Nc6 3. Nc3 Nf6 4. Bb5\n\
+C50\tItalian Game\t1. e4 e5 2. Nf3 Nc6 3. Bc4\n\
+C50\tItalian Game: Giuoco Piano\t1. e4 e5 2. Nf3 Nc6 3. Bc4 Bc5\n\
+C50\tItalian Game: Giuoco Pianissimo\t1. e4 e5 2. Nf3 Nc6 3. Bc4 Bc5 4. d3\n\
+C51\tItalian Game: Evans Gambit\t1. e4 e5 2. Nf3 Nc6 3. Bc4 Bc5 4. b4\n\
+C53\tItalian Game: Classical Variation\t1. e4 e5 2. Nf3 Nc6 3. Bc4 Bc5 4. c3\n\
+C55\tItalian Game: Two Knights Defense\t1. e4 e5 2. Nf3 Nc6 3. Bc4 Nf6\n\
+C57\tItalian Game: Two Knights Defense, Knight Attack\t1. e4 e5 2. Nf3 Nc6 3. Bc4 Nf6 4. Ng5\n\
+C57\tItalian Game: Two Knights Defense, Fried Liver Attack\t1. e4 e5 2. Nf3 Nc6 3. Bc4 Nf6 4. Ng5 d5 5. exd5 Nxd5 6. Nxf7\n\
+C60\tRuy Lopez\t1. e4 e5 2. Nf3 Nc6 3. Bb5\n\
+C62\tRuy Lopez: Steinitz Defense\t1. e4 e5 2. Nf3 Nc6 3. Bb5 d6\n\
+C64\tRuy Lopez: Classical Variation\t1. e4 e5 2. Nf3 Nc6 3. Bb5 Bc5\n\
+C65\tRuy Lopez: Berlin Defense\t1. e4 e5 2. Nf3 Nc6 3. Bb5 Nf6\n\
+C67\tRuy Lopez: Berlin Defense, Berlin Wall\t1. e4 e5 2. Nf3 Nc6 3. Bb5 Nf6 4. O-O Nxe4 5. d4 Nd6 6. Bxc6 dxc6 7. dxe5 Nf5 8. Qxd8+ Kxd8\n\
+C68\tRuy Lopez: Morphy Defense\t1. e4 e5 2. Nf3 Nc6 3. Bb5 a6\n\
+C68\tRuy Lopez: Exchange Variation\t1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 4. Bxc6\n\
+C78\tRuy Lopez: Morphy Defense, Normal Variation\t1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 4. Ba4 Nf6 5. O-O\n\
+C80\tRuy Lopez: Open\t1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 4. Ba4 Nf6 5. O-O Nxe4\n\
+C84\tRuy Lopez: Closed\t1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 4. Ba4 Nf6 5. O-O Be7\n\
+C88\tRuy Lopez: Closed, Main Line\t1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 4. Ba4 Nf6 5. O-O Be7 6. Re1 b5 7. Bb3\n\
+C89\tRuy Lopez: Marshall Attack\t1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 4. Ba4 Nf6 5. O-O Be7 6. Re1 b5 7. Bb3 O-O 8. c3 d5\n\
+C90\tRuy Lopez: Closed, Pilnik Variation\t1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 4. Ba4 Nf6 5. O-O Be7 6. Re1 b5 7. Bb3 O-O 8. c3 d6\n\
+D00\tQueen's Pawn Game\t1. d4 d5\n\
+D00\tBlackmar-Diemer Gambit\t1. d4 d5 2. e4\n\
+D02\tQueen's Pawn Game: Zukertort Variation\t1. d4 d5 2. Nf3\n\
+D02\tQueen's Pawn Game: London System\t1. d4 d5 2. Nf3 Nf6 3. Bf4\n\
+D04\tQueen's Pawn Game: Colle System\t1. d4 d5 2. Nf3 Nf6 3. e3\n\
+D06\tQueen's Gambit\t1. d4 d5 2. c4\n\
+D07\tQueen's Gambit Declined: Chigorin Defense\t1. d4 d5 2. c4 Nc6\n\
+D08\tQueen's Gambit Declined: Albin Countergambit\t1. d4 d5 2. c4 e5\n\
+D10\tSlav Defense\t1. d4 d5 2. c4 c6\n\
+D11\tSlav Defense: Modern Line\t1. d4 d5 2. c4 c6 3. Nf3\n\
+D15\tSlav Defense: Three Knights Variation\t1. d4 d5 2. c4 c6 3. Nf3 Nf6 4. Nc3\n\
+D17\tSlav Defense: Czech Variation\t1. d4 d5 2. c4 c6 3. Nf3 Nf6 4. Nc3 dxc4 5. a4 Bf5\n\
+D20\tQueen's Gambit Accepted\t1. d4 d5 2. c4 dxc4\n\
+D30\tQueen's Gambit Declined\t1. d4 d5 2. c4 e6\n\
+D31\tQueen's Gambit Declined: Queen's Knight Variation\t1. d4 d5 2. c4 e6 3. Nc3\n\
+D35\tQueen's Gambit Declined: Exchange Variation\t1. d4 d5 2. c4 e6 3. Nc3 Nf6 4. cxd5 exd5\n\
+D37\tQueen's Gambit Declined: Three Knights Variation\t1. d4 d5 2. c4 e6 3. Nc3 Nf6 4. Nf3\n\
+D43\tSemi-Slav Defense\t1. d4 d5 2. c4 c6 3. Nf3 Nf6 4. Nc3 e6\n\
+D45\tSemi-Slav Defense: Normal Variation\t1. d4 d5 2. c4 c6 3. Nf3 Nf6 4. Nc3 e6 5. e3 Nbd7\n\
+D47\tSemi-Slav Defense: Meran Variation\t1. d4 d5 2. c4 c6 3. Nf3 Nf6 4. Nc3 e6 5. e3 Nbd7 6. Bd3 dxc4 7. Bxc4 b5\n\
+D50\tQueen's Gambit Declined: Modern Variation\t1. d4 d5 2. c4 e6 3. Nc3 Nf6 4. Bg5\n\
+D53\tQueen's Gambit Declined: Modern Variation, Normal Line\t1. d4 d5 2. c4 e6 3. Nc3 Nf6 4. Bg5 Be7\n\
+D58\tQueen's Gambit Declined: Tartakower Defense\t1. d4 d5 2. c4 e6 3. Nc3 Nf6 4. Bg5 Be7 5. e3 O-O 6. Nf3 h6 7. Bh4 b6\n\
+D80\tGrünfeld Defense\t1. d4 Nf6 2. c4 g6 3. Nc3 d5\n\
+D85\tGrünfeld Defense: Exchange Variation\t1. d4 Nf6 2. c4 g6 3. Nc3 d5 4. cxd5 Nxd5\n\
+E00\tIndian Defense: East Indian Defense\t1. d4 Nf6 2. c4 e6\n\
+E00\tCatalan Opening\t1. d4 Nf6 2. c4 e6 3. g3\n\
+E04\tCatalan Opening: Open Defense\t1. d4 Nf6 2. c4 e6 3. g3 d5 4. Bg2 dxc4\n\
+E06\tCatalan Opening: Closed\t1. d4 Nf6 2. c4 e6 3. g3 d5 4. Bg2 Be7\n\
+E10\tIndian Defense: Anglo-Indian Variation\t1. d4 Nf6 2. c4 e6 3. Nf3\n\
+E11\tBogo-Indian Defense\t1. d4 Nf6 2. c4 e6 3. Nf3 Bb4+\n\
+E12\tQueen's Indian Defense\t1. d4 Nf6 2. c4 e6 3. Nf3 b6\n\
+E20\tNimzo-Indian Defense\t1. d4 Nf6 2. c4 e6 3. Nc3 Bb4\n\
+E21\tNimzo-Indian Defense: Three Knights Variation\t1. d4 Nf6 2. c4 e6 3. Nc3 Bb4 4. Nf3\n\
+E32\tNimzo-Indian Defense: Classical Variation\t1. d4 Nf6 2. c4 e6 3. Nc3 Bb4 4. Qc2\n\
+E40\tNimzo-Indian Defense: Normal Variation\t1. d4 Nf6 2. c4 e6 3. Nc3 Bb4 4. e3\n\
+E60\tKing's Indian Defense\t1. d4 Nf6 2. c4 g6\n\
+E61\tKing's Indian Defense: Normal Variation\t1. d4 Nf6 2. c4 g6 3. Nc3 Bg7\n\
+E62\tKing's Indian Defense: Fianchetto Variation\t1. d4 Nf6 2. c4 g6 3. Nf3 Bg7 4. g3\n\
+E70\tKing's Indian Defense: Normal Variation, King's Pawn Line\t1. d4 Nf6 2. c4 g6 3. Nc3 Bg7 4. e4 d6\n\
+E76\tKing's Indian Defense: Four Pawns Attack\t1. d4 Nf6 2. c4 g6 3. Nc3 Bg7 4. e4 d6 5. f4\n\
+E80\tKing's Indian Defense: Sämisch Variation\t1. d4 Nf6 2. c4 g6 3. Nc3 Bg7 4. e4 d6 5. f3\n\
+E91\tKing's Indian Defense: Orthodox Variation\t1. d4 Nf6 2. c4 g6 3. Nc3 Bg7 4. e4 d6 5. Nf3 O-O 6. Be2\n\
+E97\tKing's Indian Defense: Mar del Plata Variation\t1. d4 Nf6 2. c4 g6 3. Nc3 Bg7 4. e4 d6 5. Nf3 O-O 6. Be2 e5 7. O-O Nc6 8. d5 Ne7";
@@ -0,0 +1,380 @@
+use std::collections::HashMap;
+use std::fs;
+
+use crate::game::{Game, Rank, Team};
+use crate::moves::{Action, ActionType};
+use crate::pgn;
+use crate::pgn::PgnGame;
+use crate::random::random_u64;
+
+// One 16 byte record of a polyglot book, stored big endian and sorted by key
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BookEntry {
+    pub key: u64,
+    pub raw_move: u16,
+    pub weight: u16,
+    pub learn: u32,
+}
+
+pub const ENTRY_SIZE: usize = 16;
+
+impl BookEntry {
+    pub fn from_bytes(bytes: &[u8]) -> BookEntry {
+        let mut key = [0u8; 8];
+        key.copy_from_slice(&bytes[0..8]);
+        BookEntry {
+            key: u64::from_be_bytes(key),
+            raw_move: u16::from_be_bytes([bytes[8], bytes[9]]),
+            weight: u16::from_be_bytes([bytes[10], bytes[11]]),
+            learn: u32::from_be_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]),
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; ENTRY_SIZE] {
+        let mut bytes = [0u8; ENTRY_SIZE];
+        bytes[0..8].copy_from_slice(&self.key.to_be_bytes());
+        bytes[8..10].copy_from_slice(&self.raw_move.to_be_bytes());
+        bytes[10..12].copy_from_slice(&self.weight.to_be_bytes());
+        bytes[12..16].copy_from_slice(&self.learn.to_be_bytes());
+        bytes
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct BookMove {
+    pub action: Action,
+    pub weight: u16,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Selection {
+    // the move with the highest weight
+    Best,
+    // any book move, with a chance proportional to its weight
+    WeightedRandom,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct OpeningBook {
+    entries: Vec<BookEntry>,
+}
+
+impl OpeningBook {
+    pub fn open(filepath: &str) -> Result<OpeningBook, String> {
+        let bytes =
+            fs::read(filepath).map_err(|e| format!("could not read book {}: {}", filepath, e))?;
+        OpeningBook::from_bytes(&bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<OpeningBook, String> {
+        if bytes.len() % ENTRY_SIZE != 0 {
+            return Err(format!(
+                "book size {} is not a multiple of {} bytes",
+                bytes.len(),
+                ENTRY_SIZE
+            ));
+        }
+        let mut entries: Vec<BookEntry> = bytes
+            .chunks(ENTRY_SIZE)
+            .map(BookEntry::from_bytes)
+            .collect();
+        // lookups need the entries sorted, a well formed book already is
+        entries.sort_by_key(|entry| entry.key);
+        Ok(OpeningBook { entries })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // All entries with the given polyglot key
+    pub fn entries(&self, key: u64) -> &[BookEntry] {
+        let start = self.entries.partition_point(|entry| entry.key < key);
+        let end = self.entries.partition_point(|entry| entry.key <= key);
+        &self.entries[start..end]
+    }
+
+    // The legal book moves of the position, highest weight first. Entries
+    // with moves that aren't legal in the position are left out.
+    pub fn candidates(&self, game: &Game) -> Vec<BookMove> {
+        let mut game = game.clone();
+        let legal = game.all_moves();
+        let mut candidates: Vec<BookMove> = self
+            .entries(game.hash())
+            .iter()
+            .filter_map(|entry| {
+                let action = decode_move(&legal, entry.raw_move)?;
+                Some(BookMove {
+                    action,
+                    weight: entry.weight,
+                })
+            })
+            .collect();
+        candidates.sort_by_key(|candidate| std::cmp::Reverse(candidate.weight));
+        candidates
+    }
+
+    pub fn select(&self, game: &Game, selection: Selection) -> Option<Action> {
+        let candidates = self.candidates(game);
+        match selection {
+            Selection::Best => candidates.first().map(|candidate| candidate.action),
+            Selection::WeightedRandom => weighted_choice(&candidates, random_u64()),
+        }
+    }
+}
+
+// Picks a move with a chance proportional to its weight, `random` being
+// any uniformly distributed number. Moves with weight 0 are only picked if
+// every move has weight 0.
+pub fn weighted_choice(candidates: &[BookMove], random: u64) -> Option<Action> {
+    let total: u64 = candidates.iter().map(|c| c.weight as u64).sum();
+    if total == 0 {
+        return candidates.first().map(|candidate| candidate.action);
+    }
+    let mut target = random % total;
+    for candidate in candidates.iter() {
+        if target < candidate.weight as u64 {
+            return Some(candidate.action);
+        }
+        target -= candidate.weight as u64;
+    }
+    None
+}
+
+// Polyglot moves: bits 0-5 the to square, 6-11 the from square (square =
+// row * 8 + file) and 12-14 the promotion piece (knight 1 to queen 4).
+// Castling is written as the king taking its own rook.
+pub fn encode_move(action: &Action) -> u16 {
+    let from = action.from.coordinate;
+    let mut to = action.to.coordinate;
+    if action.action_type == ActionType::Castling {
+        to.0 = if to.0 > from.0 { 7 } else { 0 };
+    }
+    let promotion = match action.action_type {
+        ActionType::Promotion => match action.promotion.unwrap_or(Rank::Queen) {
+            Rank::Knight => 1,
+            Rank::Bishop => 2,
+            Rank::Rook => 3,
+            _ => 4,
+        },
+        _ => 0,
+    };
+    let square = |(x, y): (isize, isize)| (y * 8 + x) as u16;
+    promotion << 12 | square(from) << 6 | square(to)
+}
+
+// Finds the legal action the polyglot move stands for
+pub fn decode_move(legal: &[Action], raw_move: u16) -> Option<Action> {
+    let square = |index: u16| ((index % 8) as isize, (index / 8) as isize);
+    let from = square((raw_move >> 6) & 63);
+    let to = square(raw_move & 63);
+    let promotion = match (raw_move >> 12) & 7 {
+        0 => None,
+        1 => Some(Rank::Knight),
+        2 => Some(Rank::Bishop),
+        3 => Some(Rank::Rook),
+        4 => Some(Rank::Queen),
+        _ => return None,
+    };
+
+    let mut action = *legal.iter().find(|action| {
+        action.from.coordinate == from
+            && if action.action_type == ActionType::Castling {
+                // the king moves towards the rook it castles with
+                to.1 == from.1
+                    && (to.0 > from.0) == (action.to.coordinate.0 > from.0)
+                    && (to.0 == 0 || to.0 == 7)
+            } else {
+                action.to.coordinate == to
+            }
+    })?;
+    if action.action_type == ActionType::Promotion {
+        action.promotion = Some(promotion.unwrap_or(Rank::Queen));
+    } else if promotion.is_some() {
+        return None;
+    }
+    Some(action)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BuilderOptions {
+    // moves after this many plies aren't added
+    pub max_ply: usize,
+    // moves played in fewer games are left out of the book
+    pub min_games: u32,
+    // only moves of players rated at least this much are counted
+    pub min_rating: Option<u32>,
+}
+
+impl Default for BuilderOptions {
+    fn default() -> Self {
+        BuilderOptions {
+            max_ply: 20,
+            min_games: 1,
+            min_rating: None,
+        }
+    }
+}
+
+// How a move did in the games it was played in, for the side playing it
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct MoveStats {
+    pub games: u32,
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl MoveStats {
+    // The polyglot convention: two points for a win, one for a draw
+    pub fn weight(&self) -> u64 {
+        2 * self.wins as u64 + self.draws as u64
+    }
+}
+
+// Collects the moves of pgn games per position and writes them as a
+// polyglot book
+#[derive(Debug, Clone, Default)]
+pub struct BookBuilder {
+    pub options: BuilderOptions,
+    moves: HashMap<(u64, u16), MoveStats>,
+    games: usize,
+}
+
+impl BookBuilder {
+    pub fn new(options: BuilderOptions) -> BookBuilder {
+        BookBuilder {
+            options,
+            moves: HashMap::new(),
+            games: 0,
+        }
+    }
+
+    // Number of games that added moves
+    pub fn games(&self) -> usize {
+        self.games
+    }
+
+    pub fn stats(&self, game: &Game, action: &Action) -> MoveStats {
+        self.moves
+            .get(&(game.hash(), encode_move(action)))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    // Adds the opening of a game, returns false if nothing of it was used:
+    // the result is unknown, it doesn't start from a legal position or
+    // neither player is rated high enough
+    pub fn add_game(&mut self, pgn_game: &PgnGame) -> bool {
+        let white_score = match pgn_game.white_score() {
+            Some(score) => score,
+            None => return false,
+        };
+        let mut game = match pgn_game.start_position() {
+            Ok(game) => game,
+            Err(_) => return false,
+        };
+        let counts = |team: Team| match self.options.min_rating {
+            Some(min_rating) => {
+                let tag = match team {
+                    Team::White => "WhiteElo",
+                    Team::Black => "BlackElo",
+                };
+                pgn_game
+                    .tag(tag)
+                    .and_then(|elo| elo.trim().parse::<u32>().ok())
+                    .is_some_and(|elo| elo >= min_rating)
+            }
+            None => true,
+        };
+        let counted = [counts(Team::White), counts(Team::Black)];
+        if !counted[0] && !counted[1] {
+            return false;
+        }
+
+        for action in pgn_game.actions.iter().take(self.options.max_ply) {
+            let (side, score) = match game.player {
+                Team::White => (0, white_score),
+                Team::Black => (1, 1.0 - white_score),
+            };
+            if counted[side] {
+                let stats = self
+                    .moves
+                    .entry((game.hash(), encode_move(action)))
+                    .or_default();
+                stats.games += 1;
+                if score == 1.0 {
+                    stats.wins += 1;
+                } else if score == 0.0 {
+                    stats.losses += 1;
+                } else {
+                    stats.draws += 1;
+                }
+            }
+            game.perform_action(*action);
+        }
+        self.games += 1;
+        true
+    }
+
+    // Adds every game of a pgn file, returns how many were used. Games that
+    // can't be read are skipped.
+    pub fn add_pgn_file(&mut self, filepath: &str) -> Result<usize, String> {
+        let mut added = 0;
+        for pgn_game in pgn::read_games(filepath)?.iter().flatten() {
+            if self.add_game(pgn_game) {
+                added += 1;
+            }
+        }
+        Ok(added)
+    }
+
+    // The book entries sorted by key, best move first. Moves played too
+    // rarely or that never scored are left out, and the weights are scaled
+    // down if they don't fit in 16 bits.
+    pub fn entries(&self) -> Vec<BookEntry> {
+        let kept: Vec<(&(u64, u16), &MoveStats)> = self
+            .moves
+            .iter()
+            .filter(|(_, stats)| stats.games >= self.options.min_games && stats.weight() > 0)
+            .collect();
+        let max_weight = kept.iter().map(|(_, s)| s.weight()).max().unwrap_or(0);
+        let scale = |weight: u64| {
+            if max_weight > u16::MAX as u64 {
+                (weight * u16::MAX as u64 / max_weight).max(1)
+            } else {
+                weight
+            }
+        };
+        let mut entries: Vec<BookEntry> = kept
+            .iter()
+            .map(|(&(key, raw_move), stats)| BookEntry {
+                key,
+                raw_move,
+                weight: scale(stats.weight()) as u16,
+                learn: 0,
+            })
+            .collect();
+        entries.sort_by_key(|entry| (entry.key, std::cmp::Reverse(entry.weight), entry.raw_move));
+        entries
+    }
+
+    pub fn to_book(&self) -> OpeningBook {
+        OpeningBook {
+            entries: self.entries(),
+        }
+    }
+
+    pub fn write(&self, filepath: &str) -> Result<(), String> {
+        let bytes: Vec<u8> = self
+            .entries()
+            .iter()
+            .flat_map(|entry| entry.to_bytes().to_vec())
+            .collect();
+        fs::write(filepath, bytes).map_err(|e| format!("could not write {}: {}", filepath, e))
+    }
+}
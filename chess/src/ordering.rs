@@ -0,0 +1,380 @@
+use crate::eval;
+use crate::game;
+use crate::game::{Game, Rank, Square, Team};
+use crate::moves;
+use crate::moves::{Action, ActionType};
+use crate::transposition::MAX_PLY;
+
+const HISTORY_MAX: i32 = 16384;
+
+// Killer moves per ply, history scores per side and from/to square, and the
+// move that refuted each previous move last time (countermove)
+#[derive(Debug, Clone)]
+pub struct Heuristics {
+    killers: Vec<[Option<u16>; 2]>,
+    history: Vec<i32>,
+    countermoves: Vec<Option<u16>>,
+}
+
+impl Heuristics {
+    pub fn new() -> Heuristics {
+        Heuristics {
+            killers: vec![[None; 2]; MAX_PLY as usize + 1],
+            history: vec![0; 2 * 64 * 64],
+            countermoves: vec![None; 64 * 64],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Heuristics::new();
+    }
+
+    pub fn killers(&self, ply: i32) -> [Option<u16>; 2] {
+        self.killers[ply as usize]
+    }
+
+    pub fn countermove(&self, previous: Option<&Action>) -> Option<u16> {
+        previous.and_then(|action| self.countermoves[action.key() as usize])
+    }
+
+    pub fn history_score(&self, team: Team, action: &Action) -> i32 {
+        self.history[history_index(team, action)]
+    }
+
+    // A quiet move caused a beta cutoff. The quiet moves tried before it
+    // didn't, so they get a penalty.
+    pub fn update_cutoff(
+        &mut self,
+        team: Team,
+        ply: i32,
+        depth: i32,
+        action: &Action,
+        previous: Option<&Action>,
+        tried_quiets: &[Action],
+    ) {
+        let key = action.key();
+        let killers = &mut self.killers[ply as usize];
+        if killers[0] != Some(key) {
+            killers[1] = killers[0];
+            killers[0] = Some(key);
+        }
+
+        if let Some(previous) = previous {
+            self.countermoves[previous.key() as usize] = Some(key);
+        }
+
+        let bonus = (depth * depth).min(HISTORY_MAX);
+        self.add_history(team, action, bonus);
+        for quiet in tried_quiets.iter() {
+            self.add_history(team, quiet, -bonus);
+        }
+    }
+
+    fn add_history(&mut self, team: Team, action: &Action, bonus: i32) {
+        // scaled so the scores stay within +-HISTORY_MAX
+        let entry = &mut self.history[history_index(team, action)];
+        *entry += bonus - *entry * bonus.abs() / HISTORY_MAX;
+    }
+}
+
+impl Default for Heuristics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn history_index(team: Team, action: &Action) -> usize {
+    let team_index = match team {
+        Team::White => 0,
+        Team::Black => 1,
+    };
+    team_index * 64 * 64 + action.key() as usize
+}
+
+fn captured_value(action: &Action) -> i32 {
+    let mut value = match action.to.piece {
+        Some(piece) => eval::piece_value(piece.rank),
+        None if action.action_type == ActionType::Enpassant => eval::piece_value(Rank::Pawn),
+        None => 0,
+    };
+    if action.action_type == ActionType::Promotion {
+        value += eval::piece_value(Rank::Queen) - eval::piece_value(Rank::Pawn);
+    }
+    value
+}
+
+// Most valuable victim, least valuable attacker
+pub fn mvv_lva(action: &Action) -> i32 {
+    let attacker = action
+        .from
+        .piece
+        .map_or(0, |piece| eval::piece_value(piece.rank));
+    10 * captured_value(action) - attacker
+}
+
+// Static exchange evaluation: the material won or lost if both sides keep
+// recapturing on the target square with their least valuable piece
+pub fn see(game: &Game, action: &Action) -> i32 {
+    let target = action.to.coordinate;
+    let mut board = game.clone();
+    let mut gain = [0; 32];
+    let mut depth = 0;
+
+    gain[0] = captured_value(action);
+    let mut attacker_value = match action.action_type {
+        ActionType::Promotion => eval::piece_value(Rank::Queen),
+        _ => action
+            .from
+            .piece
+            .map_or(0, |piece| eval::piece_value(piece.rank)),
+    };
+    if action.action_type == ActionType::Enpassant {
+        board.matrix[target.0 as usize][action.from.coordinate.1 as usize].piece = None;
+    }
+    move_piece(&mut board, action.from.coordinate, target);
+    let mut team = other_team(game.player);
+
+    loop {
+        depth += 1;
+        gain[depth] = attacker_value - gain[depth - 1];
+        if depth + 1 >= gain.len() {
+            break;
+        }
+        match least_valuable_attacker(&mut board, target, team) {
+            Some((from, rank)) => {
+                attacker_value = eval::piece_value(rank);
+                move_piece(&mut board, from, target);
+                team = other_team(team);
+            }
+            None => break,
+        }
+    }
+    for d in (1..depth).rev() {
+        gain[d - 1] = -(-gain[d - 1]).max(gain[d]);
+    }
+    gain[0]
+}
+
+fn move_piece(board: &mut Game, from: (isize, isize), to: (isize, isize)) {
+    board.matrix[to.0 as usize][to.1 as usize].piece =
+        board.matrix[from.0 as usize][from.1 as usize].piece;
+    board.matrix[from.0 as usize][from.1 as usize].piece = None;
+}
+
+fn other_team(team: Team) -> Team {
+    match team {
+        Team::White => Team::Black,
+        Team::Black => Team::White,
+    }
+}
+
+fn least_valuable_attacker(
+    board: &mut Game,
+    target: (isize, isize),
+    team: Team,
+) -> Option<((isize, isize), Rank)> {
+    // the move generators capture pieces of the team not to move, so
+    // generating from the target square as the other team finds the attackers
+    board.player = other_team(team);
+    let square = Square {
+        piece: None,
+        coordinate: target,
+    };
+    let attackers_of = |actions: Vec<Action>, ranks: &[Rank]| {
+        actions.iter().find_map(|a| match a.to.piece {
+            Some(piece) if ranks.contains(&piece.rank) => Some((a.to.coordinate, piece.rank)),
+            _ => None,
+        })
+    };
+
+    let candidates = [
+        attackers_of(moves::gen_pawn_attack_moveset(board, square), &[Rank::Pawn]),
+        attackers_of(moves::gen_moveset_knight(board, square), &[Rank::Knight]),
+        attackers_of(moves::gen_moveset_bishop(board, square), &[Rank::Bishop]),
+        attackers_of(moves::gen_moveset_rook(board, square), &[Rank::Rook]),
+        attackers_of(moves::gen_moveset_queen(board, square), &[Rank::Queen]),
+    ];
+    if let Some(attacker) = candidates.iter().flatten().next() {
+        return Some(*attacker);
+    }
+
+    for dx in -1..2 {
+        for dy in -1..2 {
+            let (x, y) = (target.0 + dx, target.1 + dy);
+            if (dx, dy) != (0, 0) && game::not_out_of_bounds(x, y) {
+                if let Some(piece) = board.matrix[x as usize][y as usize].piece {
+                    if piece.rank == Rank::King && piece.team == team {
+                        return Some(((x, y), Rank::King));
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
+// Finds the legal action with the given key, only generating moves for the
+// piece on its from square
+pub fn find_action(game: &mut Game, key: u16) -> Option<Action> {
+    let from = game::coordinate_from_index((key >> 6) as usize);
+    let square = game.matrix[from.0 as usize][from.1 as usize];
+    match moves::generate_moves(game, square) {
+        Ok(actions) => actions.into_iter().find(|action| action.key() == key),
+        Err(_) => None,
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Stage {
+    HashMove,
+    GenerateCaptures,
+    GoodCaptures,
+    Killers,
+    Countermove,
+    GenerateQuiets,
+    Quiets,
+    BadCaptures,
+    Done,
+}
+
+// Hands out moves one at a time, best first. Quiet moves are only
+// generated once the hash move, the good captures and the killers failed to
+// produce a cutoff.
+pub struct MovePicker {
+    stage: Stage,
+    hash_move: Option<u16>,
+    killers: [Option<u16>; 2],
+    countermove: Option<u16>,
+    quiescence: bool,
+    captures: Vec<(Action, i32)>,
+    bad_captures: Vec<Action>,
+    quiets: Vec<(Action, i32)>,
+    killer_index: usize,
+    played: Vec<u16>,
+}
+
+impl MovePicker {
+    pub fn new(
+        hash_move: Option<u16>,
+        killers: [Option<u16>; 2],
+        countermove: Option<u16>,
+    ) -> MovePicker {
+        MovePicker {
+            stage: Stage::HashMove,
+            hash_move,
+            killers,
+            countermove,
+            quiescence: false,
+            captures: vec![],
+            bad_captures: vec![],
+            quiets: vec![],
+            killer_index: 0,
+            played: vec![],
+        }
+    }
+
+    // Only captures that don't lose material, for the quiescence search
+    pub fn quiescence() -> MovePicker {
+        let mut picker = MovePicker::new(None, [None; 2], None);
+        picker.quiescence = true;
+        picker.stage = Stage::GenerateCaptures;
+        picker
+    }
+
+    pub fn next(&mut self, game: &mut Game, heuristics: &Heuristics) -> Option<Action> {
+        loop {
+            match self.stage {
+                Stage::HashMove => {
+                    self.stage = Stage::GenerateCaptures;
+                    if let Some(action) = self.hash_move.and_then(|key| find_action(game, key)) {
+                        self.played.push(action.key());
+                        return Some(action);
+                    }
+                }
+                Stage::GenerateCaptures => {
+                    for action in game.all_captures() {
+                        if self.played.contains(&action.key()) {
+                            continue;
+                        }
+                        if see(game, &action) >= 0 {
+                            self.captures.push((action, mvv_lva(&action)));
+                        } else if !self.quiescence {
+                            self.bad_captures.push(action);
+                        }
+                    }
+                    self.stage = Stage::GoodCaptures;
+                }
+                Stage::GoodCaptures => match pop_best(&mut self.captures) {
+                    Some(action) => return Some(action),
+                    None => {
+                        self.stage = if self.quiescence {
+                            Stage::Done
+                        } else {
+                            Stage::Killers
+                        }
+                    }
+                },
+                Stage::Killers => {
+                    if self.killer_index >= self.killers.len() {
+                        self.stage = Stage::Countermove;
+                        continue;
+                    }
+                    let killer = self.killers[self.killer_index];
+                    self.killer_index += 1;
+                    if let Some(action) = self.playable_quiet(game, killer) {
+                        return Some(action);
+                    }
+                }
+                Stage::Countermove => {
+                    self.stage = Stage::GenerateQuiets;
+                    if let Some(action) = self.playable_quiet(game, self.countermove) {
+                        return Some(action);
+                    }
+                }
+                Stage::GenerateQuiets => {
+                    for action in game.all_quiets() {
+                        if !self.played.contains(&action.key()) {
+                            let score = heuristics.history_score(game.player, &action);
+                            self.quiets.push((action, score));
+                        }
+                    }
+                    self.stage = Stage::Quiets;
+                }
+                Stage::Quiets => match pop_best(&mut self.quiets) {
+                    Some(action) => return Some(action),
+                    None => self.stage = Stage::BadCaptures,
+                },
+                Stage::BadCaptures => {
+                    if self.bad_captures.is_empty() {
+                        self.stage = Stage::Done;
+                    } else {
+                        return Some(self.bad_captures.remove(0));
+                    }
+                }
+                Stage::Done => return None,
+            }
+        }
+    }
+
+    fn playable_quiet(&mut self, game: &mut Game, key: Option<u16>) -> Option<Action> {
+        let key = key?;
+        if self.played.contains(&key) {
+            return None;
+        }
+        let action = find_action(game, key)?;
+        if moves::is_tactical(&action) {
+            return None;
+        }
+        self.played.push(key);
+        Some(action)
+    }
+}
+
+fn pop_best(scored: &mut Vec<(Action, i32)>) -> Option<Action> {
+    let best = scored
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, (_, score))| *score)
+        .map(|(index, _)| index)?;
+    Some(scored.swap_remove(best).0)
+}
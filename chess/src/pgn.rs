@@ -1,6 +1,7 @@
 #[allow(dead_code)]
 use crate::game;
 use crate::moves;
+use crate::opening;
 use std::fs;
 pub fn read_pgn(filepath: &str) ->(Vec<moves::Action>,Vec<game::GameState>) {
     let half_turns = file_to_half_turn(filepath);
@@ -203,3 +204,557 @@ fn file_to_half_turn(filepath: &str) -> Vec<String> {
     }
     half_turns
 }
+
+// A game of a pgn collection: its tag pairs, the moves of the main line
+// and the result ("1-0", "0-1", "1/2-1/2" or "*"). `annotations` go with
+// the moves of the same index and can be fewer than them.
+#[derive(Debug, Clone)]
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    pub actions: Vec<moves::Action>,
+    pub annotations: Vec<Annotation>,
+    pub result: String,
+}
+
+// What follows a move in the movetext: numeric annotation glyphs like $2
+// for a mistake, comments, and variations played instead of the move
+#[derive(Debug, Clone, Default)]
+pub struct Annotation {
+    pub nags: Vec<u8>,
+    pub comments: Vec<String>,
+    pub variations: Vec<Variation>,
+}
+
+// A line of moves from the position before the move it is attached to
+#[derive(Debug, Clone, Default)]
+pub struct Variation {
+    pub actions: Vec<moves::Action>,
+    pub annotations: Vec<Annotation>,
+}
+
+// The glyphs written after a move instead of their $ numbers
+const SUFFIX_NAGS: [(&str, u8); 6] = [
+    ("!!", 3),
+    ("??", 4),
+    ("!?", 5),
+    ("?!", 6),
+    ("!", 1),
+    ("?", 2),
+];
+
+impl PgnGame {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    // The position the moves start from, the FEN tag if there is one
+    pub fn start_position(&self) -> Result<game::Game, String> {
+        match self.tag("FEN") {
+            Some(fen) => game::Game::from_fen(fen),
+            None => Ok(game::Game::new()),
+        }
+    }
+
+    // The game in pgn export format: the tag pairs, then the moves in
+    // standard algebraic notation with their annotations and the result, in
+    // lines of at most 80 characters
+    pub fn to_pgn(&self) -> Result<String, String> {
+        let mut game = self.start_position()?;
+        let mut text = String::new();
+        for (name, value) in self.tags.iter() {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            text.push_str(&format!("[{} \"{}\"]\n", name, value));
+        }
+        text.push('\n');
+
+        let mut tokens: Vec<String> = vec![];
+        line_tokens(&mut game, &self.actions, &self.annotations, &mut tokens);
+        tokens.push(self.result.clone());
+
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + 1 + token.len() > 80 {
+                text.push_str(&line);
+                text.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+        text.push_str(&line);
+        text.push_str("\n\n");
+        Ok(text)
+    }
+
+    // The annotation of the move at `index`, added if there is none yet
+    pub fn annotation_mut(&mut self, index: usize) -> &mut Annotation {
+        if self.annotations.len() <= index {
+            self.annotations.resize(index + 1, Annotation::default());
+        }
+        &mut self.annotations[index]
+    }
+
+    // 1.0 for a white win, 0.5 for a draw, 0.0 for a black win
+    pub fn white_score(&self) -> Option<f64> {
+        match self.result.as_str() {
+            "1-0" => Some(1.0),
+            "1/2-1/2" => Some(0.5),
+            "0-1" => Some(0.0),
+            _ => None,
+        }
+    }
+}
+
+pub fn read_games(filepath: &str) -> Result<Vec<Result<PgnGame, String>>, String> {
+    let text =
+        fs::read_to_string(filepath).map_err(|e| format!("could not read {}: {}", filepath, e))?;
+    Ok(parse_games(&text))
+}
+
+// Same as `read_games` with the opening tags of `parse_games_tagged`
+pub fn read_games_tagged(filepath: &str) -> Result<Vec<Result<PgnGame, String>>, String> {
+    let text =
+        fs::read_to_string(filepath).map_err(|e| format!("could not read {}: {}", filepath, e))?;
+    Ok(parse_games_tagged(&text))
+}
+
+// Parses every game of a pgn collection. A game that can't be read gives an
+// error without stopping the games after it.
+pub fn parse_games(text: &str) -> Vec<Result<PgnGame, String>> {
+    let mut games = vec![];
+    let mut tags: Vec<(String, String)> = vec![];
+    let mut movetext = String::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with('[') && line.ends_with(']') && !in_comment(&movetext) {
+            if !movetext.trim().is_empty() {
+                games.push(parse_game(std::mem::take(&mut tags), &movetext));
+                movetext.clear();
+            }
+            if let Some(tag) = parse_tag(line) {
+                tags.push(tag);
+            }
+        } else if !line.starts_with('%') {
+            movetext.push_str(line);
+            movetext.push('\n');
+        }
+    }
+    if !movetext.trim().is_empty() || !tags.is_empty() {
+        games.push(parse_game(tags, &movetext));
+    }
+    games
+}
+
+// Same as `parse_games`, and a game from the starting position without any
+// of the ECO, Opening and Variation tags gets them from the classification
+// of its moves
+pub fn parse_games_tagged(text: &str) -> Vec<Result<PgnGame, String>> {
+    let mut games = parse_games(text);
+    for pgn_game in games.iter_mut().flatten() {
+        tag_opening(pgn_game);
+    }
+    games
+}
+
+fn in_comment(movetext: &str) -> bool {
+    movetext.matches('{').count() > movetext.matches('}').count()
+}
+
+fn parse_tag(line: &str) -> Option<(String, String)> {
+    let inner = line[1..line.len() - 1].trim();
+    let space = inner.find(char::is_whitespace)?;
+    let value = inner[space..].trim().trim_matches('"');
+    Some((
+        inner[..space].to_string(),
+        value.replace("\\\"", "\"").replace("\\\\", "\\"),
+    ))
+}
+
+fn parse_game(tags: Vec<(String, String)>, movetext: &str) -> Result<PgnGame, String> {
+    let mut pgn_game = PgnGame {
+        result: String::from("*"),
+        tags,
+        actions: vec![],
+        annotations: vec![],
+    };
+    let game = pgn_game.start_position()?;
+    let tokens = movetext_tokens(movetext);
+    let mut position = 0;
+    let (line, result) = parse_line(&tokens, &mut position, game, 0)?;
+    pgn_game.actions = line.actions;
+    pgn_game.annotations = line.annotations;
+    if let Some(result) = result {
+        pgn_game.result = result;
+    }
+    if pgn_game.result == "*" {
+        if let Some(result) = pgn_game.tag("Result") {
+            pgn_game.result = result.to_string();
+        }
+    }
+    Ok(pgn_game)
+}
+
+fn tag_opening(pgn_game: &mut PgnGame) {
+    let tagged = ["ECO", "Opening", "Variation"]
+        .iter()
+        .any(|name| pgn_game.tag(name).is_some());
+    let from_start = pgn_game
+        .start_position()
+        .is_ok_and(|game| game.hash() == game::Game::new().hash());
+    if tagged || !from_start {
+        return;
+    }
+    let Some(opening) = opening::classify(&pgn_game.actions) else {
+        return;
+    };
+    pgn_game.tags.push((String::from("ECO"), opening.eco));
+    pgn_game.tags.push((String::from("Opening"), opening.name));
+    if let Some(variation) = opening.variation {
+        pgn_game.tags.push((String::from("Variation"), variation));
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Move(String),
+    Nag(u8),
+    Comment(String),
+    Open,
+    Close,
+    Result(String),
+}
+
+// The moves of a line from `game` with their annotations, up to the end of
+// the variation at `depth` above 0 or of the movetext, and the result if
+// one was given. Comments before the first move are left out. A variation
+// that can't be read is left out as well, only the main line must be legal.
+fn parse_line(
+    tokens: &[Token],
+    position: &mut usize,
+    mut game: game::Game,
+    depth: usize,
+) -> Result<(Variation, Option<String>), String> {
+    let mut line = Variation::default();
+    let mut result = None;
+    // the position before the last move, where its variations start
+    let mut before: Option<game::Game> = None;
+    while *position < tokens.len() {
+        let token = &tokens[*position];
+        *position += 1;
+        match token {
+            Token::Move(san) => {
+                let action = action_from_san(&mut game, san)?;
+                before = Some(game.clone());
+                game.perform_action(action);
+                line.actions.push(action);
+                let mut annotation = Annotation::default();
+                let glyph = san.trim_start_matches(|c| c != '!' && c != '?');
+                if let Some((_, nag)) = SUFFIX_NAGS.iter().find(|(text, _)| *text == glyph) {
+                    annotation.nags.push(*nag);
+                }
+                line.annotations.push(annotation);
+            }
+            Token::Nag(nag) => {
+                if let Some(annotation) = line.annotations.last_mut() {
+                    annotation.nags.push(*nag);
+                }
+            }
+            Token::Comment(text) => {
+                if let Some(annotation) = line.annotations.last_mut() {
+                    annotation.comments.push(text.clone());
+                }
+            }
+            Token::Open => {
+                let start = *position;
+                let variation = match &before {
+                    Some(before) => parse_line(tokens, position, before.clone(), depth + 1).ok(),
+                    None => None,
+                };
+                match (variation, line.annotations.last_mut()) {
+                    (Some((variation, _)), Some(annotation)) => {
+                        annotation.variations.push(variation)
+                    }
+                    _ => *position = variation_end(tokens, start),
+                }
+            }
+            Token::Close if depth > 0 => return Ok((line, result)),
+            Token::Close => return Err(String::from("unmatched ) in the movetext")),
+            Token::Result(text) => result = Some(text.clone()),
+        }
+    }
+    if depth > 0 {
+        return Err(String::from("unterminated variation"));
+    }
+    Ok((line, result))
+}
+
+// The position after the ) closing the variation whose moves start at `start`
+fn variation_end(tokens: &[Token], start: usize) -> usize {
+    let mut depth = 1;
+    for (index, token) in tokens.iter().enumerate().skip(start) {
+        match token {
+            Token::Open => depth += 1,
+            Token::Close => depth -= 1,
+            _ => {}
+        }
+        if depth == 0 {
+            return index + 1;
+        }
+    }
+    tokens.len()
+}
+
+// The moves, annotations, variation brackets and results of the movetext,
+// without move numbers
+fn movetext_tokens(movetext: &str) -> Vec<Token> {
+    let mut tokens = vec![];
+    let mut token = String::new();
+    let mut chars = movetext.chars();
+    while let Some(c) = chars.next() {
+        let bracket = match c {
+            '{' | ';' => {
+                let end = if c == '{' { '}' } else { '\n' };
+                let text: String = chars.by_ref().take_while(|&c| c != end).collect();
+                let words: Vec<&str> = text.split_whitespace().collect();
+                Some(Token::Comment(words.join(" ")))
+            }
+            '(' => Some(Token::Open),
+            ')' => Some(Token::Close),
+            c if c.is_whitespace() => None,
+            c => {
+                token.push(c);
+                continue;
+            }
+        };
+        push_token(&mut tokens, &mut token);
+        tokens.extend(bracket);
+    }
+    push_token(&mut tokens, &mut token);
+    tokens
+}
+
+fn push_token(tokens: &mut Vec<Token>, token: &mut String) {
+    // move numbers like "12." or "12..." can be glued to the move
+    let text = match token.as_str() {
+        "1-0" | "0-1" | "1/2-1/2" | "*" => {
+            tokens.push(Token::Result(token.clone()));
+            token.clear();
+            return;
+        }
+        _ => token.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.'),
+    };
+    if let Some(nag) = text.strip_prefix('$') {
+        if let Ok(nag) = nag.parse() {
+            tokens.push(Token::Nag(nag));
+        }
+    } else if !text.is_empty() {
+        tokens.push(Token::Move(text.to_string()));
+    }
+    token.clear();
+}
+
+// The movetext tokens of a line from `game`: move numbers, moves, their
+// glyphs, comments (one token a word, so long comments can be wrapped) and
+// variations in brackets
+fn line_tokens(
+    game: &mut game::Game,
+    actions: &[moves::Action],
+    annotations: &[Annotation],
+    tokens: &mut Vec<String>,
+) {
+    let mut number = true;
+    for (i, action) in actions.iter().enumerate() {
+        let fen = game.to_fen();
+        let fullmove = fen.split(' ').nth(5).unwrap_or("1");
+        if game.player == game::Team::White {
+            tokens.push(format!("{}.", fullmove));
+        } else if number {
+            tokens.push(format!("{}...", fullmove));
+        }
+        number = false;
+        tokens.push(to_san(game, action));
+        let before = game.clone();
+        game.perform_action(*action);
+
+        let annotation = match annotations.get(i) {
+            Some(annotation) => annotation,
+            None => continue,
+        };
+        for nag in annotation.nags.iter() {
+            tokens.push(format!("${}", nag));
+        }
+        for comment in annotation.comments.iter() {
+            // a } would end the comment early
+            let comment = comment.replace('}', ")");
+            let mut words: Vec<String> = comment.split_whitespace().map(String::from).collect();
+            if words.is_empty() {
+                words.push(String::new());
+            }
+            words[0].insert(0, '{');
+            words.last_mut().unwrap().push('}');
+            tokens.extend(words);
+            number = true;
+        }
+        for variation in annotation.variations.iter() {
+            let mut variation_tokens = vec![];
+            line_tokens(
+                &mut before.clone(),
+                &variation.actions,
+                &variation.annotations,
+                &mut variation_tokens,
+            );
+            if variation_tokens.is_empty() {
+                continue;
+            }
+            variation_tokens[0].insert(0, '(');
+            variation_tokens.last_mut().unwrap().push(')');
+            tokens.extend(variation_tokens);
+            number = true;
+        }
+    }
+}
+
+// Finds the legal action written in standard algebraic notation. Check
+// marks and annotations are ignored, and "0-0" is read as "O-O".
+pub fn action_from_san(game: &mut game::Game, san: &str) -> Result<moves::Action, String> {
+    let text = san.trim_end_matches(['+', '#', '!', '?']);
+    let legal = game.all_moves();
+    let castle = match text {
+        "O-O" | "0-0" => Some(6),
+        "O-O-O" | "0-0-0" => Some(2),
+        _ => None,
+    };
+    if let Some(file) = castle {
+        return legal
+            .into_iter()
+            .find(|a| a.action_type == moves::ActionType::Castling && a.to.coordinate.0 == file)
+            .ok_or(format!("{} is not a legal move", san));
+    }
+
+    let (text, promotion) = match text.find('=') {
+        Some(index) => (&text[..index], text[index + 1..].chars().next()),
+        None => match text.chars().last() {
+            // promotions are sometimes written without "=", like e8Q
+            Some(c) if "NBRQ".contains(c) && text.len() > 2 => (&text[..text.len() - 1], Some(c)),
+            _ => (text, None),
+        },
+    };
+    let promotion = match promotion {
+        Some(c) => Some(game::Rank::from_letter(c).ok_or(format!("{} is not a move", san))?),
+        None => None,
+    };
+    let mut chars: Vec<char> = text.chars().filter(|&c| c != 'x' && c != '-').collect();
+    let rank = match chars.first() {
+        Some(&c) if c.is_ascii_uppercase() => {
+            chars.remove(0);
+            game::Rank::from_letter(c).ok_or(format!("{} is not a move", san))?
+        }
+        _ => game::Rank::Pawn,
+    };
+    if chars.len() < 2 {
+        return Err(format!("{} is not a move", san));
+    }
+    let target: String = chars[chars.len() - 2..].iter().collect();
+    let to = game::coordinate_from_string(&target)?;
+    let hints = &chars[..chars.len() - 2];
+
+    let matching: Vec<moves::Action> = legal
+        .into_iter()
+        .filter(|action| {
+            action.to.coordinate == to
+                && action.action_type != moves::ActionType::Castling
+                && action.from.piece.map(|p| p.rank) == Some(rank)
+                && hints.iter().all(|&hint| match hint {
+                    'a'..='h' => action.from.coordinate.0 == hint as isize - 'a' as isize,
+                    '1'..='8' => action.from.coordinate.1 == hint as isize - '1' as isize,
+                    _ => false,
+                })
+        })
+        .collect();
+    match matching.len() {
+        0 => Err(format!("{} is not a legal move", san)),
+        1 => {
+            let mut action = matching[0];
+            if action.action_type == moves::ActionType::Promotion {
+                action.promotion = Some(promotion.unwrap_or(game::Rank::Queen));
+            }
+            Ok(action)
+        }
+        _ => Err(format!("{} is ambiguous", san)),
+    }
+}
+
+// Writes the action in standard algebraic notation, with "+" for check and
+// "#" for mate
+pub fn to_san(game: &game::Game, action: &moves::Action) -> String {
+    let mut board = game.clone();
+    let mut san = match action.action_type {
+        moves::ActionType::Castling if action.to.coordinate.0 > action.from.coordinate.0 => {
+            String::from("O-O")
+        }
+        moves::ActionType::Castling => String::from("O-O-O"),
+        _ => {
+            let rank = action.from.piece.map_or(game::Rank::Pawn, |p| p.rank);
+            let capture =
+                action.to.piece.is_some() || action.action_type == moves::ActionType::Enpassant;
+            let from = game::coordinate_to_string(action.from.coordinate);
+            let mut san = String::new();
+            if rank == game::Rank::Pawn {
+                if capture {
+                    san.push_str(&from[..1]);
+                }
+            } else {
+                san.push(rank.letter());
+                let others: Vec<moves::Action> = board
+                    .all_moves()
+                    .into_iter()
+                    .filter(|other| {
+                        other.to.coordinate == action.to.coordinate
+                            && other.from.coordinate != action.from.coordinate
+                            && other.from.piece.map(|p| p.rank) == Some(rank)
+                    })
+                    .collect();
+                if !others.is_empty() {
+                    let same_file = others
+                        .iter()
+                        .any(|o| o.from.coordinate.0 == action.from.coordinate.0);
+                    let same_row = others
+                        .iter()
+                        .any(|o| o.from.coordinate.1 == action.from.coordinate.1);
+                    if !same_file {
+                        san.push_str(&from[..1]);
+                    } else if !same_row {
+                        san.push_str(&from[1..]);
+                    } else {
+                        san.push_str(&from);
+                    }
+                }
+            }
+            if capture {
+                san.push('x');
+            }
+            san.push_str(&game::coordinate_to_string(action.to.coordinate));
+            if action.action_type == moves::ActionType::Promotion {
+                san.push('=');
+                san.push(action.promotion.unwrap_or(game::Rank::Queen).letter());
+            }
+            san
+        }
+    };
+    let mut action = *action;
+    if action.action_type == moves::ActionType::Promotion && action.promotion.is_none() {
+        action.promotion = Some(game::Rank::Queen);
+    }
+    board.perform_action(action);
+    match board.get_game_state() {
+        game::GameState::Checkmate => san.push('#'),
+        game::GameState::Check => san.push('+'),
+        _ => {}
+    }
+    san
+}
@@ -0,0 +1,18 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+// A random number that differs between calls: std seeds every RandomState
+// randomly, which is enough to vary the book moves, the moves of a weakened
+// engine and the openings of generated games
+pub fn random_u64() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+// The next number of a xorshift sequence, for repeatable randomness from a
+// seed. A state of 0 stays 0.
+pub fn xorshift(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    *state
+}
@@ -0,0 +1,415 @@
+// Analysis of a finished game: the engine searches the position before every
+// move, the loss of a move is how much worse the position got for the player
+// moving than with the engine's move, and the move is classified by it.
+// Scores are capped at a winning margin first, so missing a faster mate in
+// a won position costs nothing. A player's accuracy comes from the loss of
+// winning chances every move, 100 for a game of engine moves.
+use std::fmt;
+
+use crate::engine::Engine;
+use crate::game::{Game, GameState, Team};
+use crate::moves::Action;
+use crate::pgn;
+use crate::pgn::{PgnGame, Variation};
+use crate::search::{is_mate_score, SearchLimits, SearchResult};
+use crate::transposition::MATE;
+
+// beyond this many centipawns the game is won whatever the score
+const SCORE_CAP: i32 = 1000;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Classification {
+    Best,
+    Excellent,
+    Good,
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+pub const CLASSIFICATIONS: [Classification; 6] = [
+    Classification::Best,
+    Classification::Excellent,
+    Classification::Good,
+    Classification::Inaccuracy,
+    Classification::Mistake,
+    Classification::Blunder,
+];
+
+impl Classification {
+    // The engine's move is best whatever its loss at the depth searched,
+    // then up to 10, 50, 100 and 300 centipawns lost
+    pub fn from_loss(loss: i32, engine_move: bool) -> Classification {
+        match loss {
+            _ if engine_move => Classification::Best,
+            i32::MIN..=0 => Classification::Best,
+            1..=10 => Classification::Excellent,
+            11..=50 => Classification::Good,
+            51..=100 => Classification::Inaccuracy,
+            101..=300 => Classification::Mistake,
+            _ => Classification::Blunder,
+        }
+    }
+
+    pub fn index(&self) -> usize {
+        *self as usize
+    }
+
+    // The annotation glyph: $6 (?!), $2 (?) and $4 (??) for inaccuracies,
+    // mistakes and blunders
+    pub fn nag(&self) -> Option<u8> {
+        match self {
+            Classification::Inaccuracy => Some(6),
+            Classification::Mistake => Some(2),
+            Classification::Blunder => Some(4),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Classification::Best => "best",
+            Classification::Excellent => "excellent",
+            Classification::Good => "good",
+            Classification::Inaccuracy => "inaccuracy",
+            Classification::Mistake => "mistake",
+            Classification::Blunder => "blunder",
+        }
+    }
+}
+
+impl fmt::Display for Classification {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MoveReport {
+    // index of the move in the game, from 0
+    pub ply: usize,
+    pub fullmove: u32,
+    pub player: Team,
+    pub action: Action,
+    pub san: String,
+    // the position before the move for the player moving, with the engine's
+    // move, and after the move played
+    pub score: i32,
+    pub score_after: i32,
+    // centipawns lost, 0 or more
+    pub loss: i32,
+    pub classification: Classification,
+    // 0 to 100, from the winning chances lost
+    pub accuracy: f64,
+    // the engine's preferred move and line, from the position before the move
+    pub best_move: Option<Action>,
+    pub best_san: String,
+    pub pv: Vec<Action>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PlayerSummary {
+    pub moves: usize,
+    // average accuracy of the moves, 0 to 100
+    pub accuracy: f64,
+    // average centipawn loss
+    pub average_loss: f64,
+    // moves of every classification, in the order of `CLASSIFICATIONS`
+    pub counts: [usize; 6],
+}
+
+impl PlayerSummary {
+    pub fn count(&self, classification: Classification) -> usize {
+        self.counts[classification.index()]
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GameReport {
+    // the names of white and black
+    pub names: [String; 2],
+    pub start: Game,
+    pub moves: Vec<MoveReport>,
+    // white and black
+    pub players: [PlayerSummary; 2],
+}
+
+impl GameReport {
+    // Analyses the moves of `game` from the position before its first one
+    pub fn from_game<F: FnMut(&MoveReport)>(
+        engine: &Engine,
+        game: &Game,
+        limits: &SearchLimits,
+        on_move: F,
+    ) -> GameReport {
+        let mut start = game.clone();
+        while start.undo().is_some() {}
+        analyze(engine, &start, &game.history, limits, on_move)
+    }
+
+    // Analyses the main line of a pgn game, with the names of its players
+    pub fn from_pgn<F: FnMut(&MoveReport)>(
+        engine: &Engine,
+        game: &PgnGame,
+        limits: &SearchLimits,
+        on_move: F,
+    ) -> Result<GameReport, String> {
+        let start = game.start_position()?;
+        let mut report = analyze(engine, &start, &game.actions, limits, on_move);
+        for (index, tag) in ["White", "Black"].iter().enumerate() {
+            if let Some(name) = game.tag(tag) {
+                report.names[index] = name.to_string();
+            }
+        }
+        Ok(report)
+    }
+
+    pub fn player(&self, team: Team) -> &PlayerSummary {
+        &self.players[side(team)]
+    }
+
+    // Writes the analysis into `game`, the game it was made of: every move
+    // gets the evaluation after it for white as a comment like
+    // "[%eval 0.35]" or "[%eval #-3]", and inaccuracies, mistakes and
+    // blunders their glyph and the engine's line as a variation
+    pub fn annotate(&self, game: &mut PgnGame) {
+        for report in self.moves.iter() {
+            let annotation = game.annotation_mut(report.ply);
+            let score = match report.player {
+                Team::White => report.score_after,
+                Team::Black => -report.score_after,
+            };
+            if let Some(eval) = eval_text(score) {
+                annotation.comments.push(format!("[%eval {}]", eval));
+            }
+            if let Some(nag) = report.classification.nag() {
+                annotation.nags.push(nag);
+                if !report.pv.is_empty() {
+                    annotation.variations.push(Variation {
+                        actions: report.pv.clone(),
+                        annotations: vec![],
+                    });
+                }
+            }
+        }
+    }
+
+    // The analysed game as an annotated pgn game, with the players' names
+    // and the result if the game ended on the board
+    pub fn pgn_game(&self) -> PgnGame {
+        let mut end = self.start.clone();
+        for report in self.moves.iter() {
+            end.perform_action(report.action);
+        }
+        let result = match end.get_game_state() {
+            GameState::Checkmate if end.player == Team::White => "0-1",
+            GameState::Checkmate => "1-0",
+            GameState::Stalemate | GameState::FiftyRule => "1/2-1/2",
+            _ => "*",
+        };
+        let mut tags = vec![
+            (String::from("White"), self.names[0].clone()),
+            (String::from("Black"), self.names[1].clone()),
+            (String::from("Result"), String::from(result)),
+        ];
+        let fen = self.start.to_fen();
+        if fen != Game::new().to_fen() {
+            tags.push((String::from("SetUp"), String::from("1")));
+            tags.push((String::from("FEN"), fen));
+        }
+        let mut game = PgnGame {
+            tags,
+            actions: self.moves.iter().map(|report| report.action).collect(),
+            annotations: vec![],
+            result: String::from(result),
+        };
+        self.annotate(&mut game);
+        game
+    }
+}
+
+impl fmt::Display for GameReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for report in self.moves.iter() {
+            let number = match report.player {
+                Team::White => format!("{}.", report.fullmove),
+                Team::Black => format!("{}...", report.fullmove),
+            };
+            let score = match report.player {
+                Team::White => report.score_after,
+                Team::Black => -report.score_after,
+            };
+            write!(
+                f,
+                "{} {} {} ({})",
+                number,
+                report.san,
+                format_score(score),
+                report.classification
+            )?;
+            if report.loss > 0 {
+                write!(f, ", {} lost, best {}", report.loss, report.best_san)?;
+            }
+            writeln!(f)?;
+        }
+        for (name, summary) in self.names.iter().zip(self.players.iter()) {
+            write!(
+                f,
+                "{}: accuracy {:.1}%, average loss {:.0}",
+                name, summary.accuracy, summary.average_loss
+            )?;
+            for classification in CLASSIFICATIONS[3..].iter() {
+                write!(
+                    f,
+                    ", {}: {}",
+                    classification,
+                    summary.count(*classification)
+                )?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+// Searches the position before every move and the one after the last,
+// calling `on_move` as each move's report is done
+fn analyze<F: FnMut(&MoveReport)>(
+    engine: &Engine,
+    start: &Game,
+    actions: &[Action],
+    limits: &SearchLimits,
+    mut on_move: F,
+) -> GameReport {
+    let mut game = start.clone();
+    let mut before = evaluate(engine, &game, limits);
+    let mut moves = vec![];
+    for (ply, action) in actions.iter().enumerate() {
+        let fen = game.to_fen();
+        let fullmove = fen.split(' ').nth(5).and_then(|n| n.parse().ok());
+        let player = game.player;
+        let san = pgn::to_san(&game, action);
+        let previous = game.clone();
+        game.perform_action(*action);
+        let after = evaluate(engine, &game, limits);
+
+        let score = before.score;
+        let score_after = -after.score;
+        let engine_move = before
+            .best_move
+            .is_some_and(|best| best.to_uci() == action.to_uci());
+        let loss = if engine_move {
+            0
+        } else {
+            (capped(score) - capped(score_after)).max(0)
+        };
+        let report = MoveReport {
+            ply,
+            fullmove: fullmove.unwrap_or(1),
+            player,
+            action: *action,
+            san,
+            score,
+            score_after,
+            loss,
+            classification: Classification::from_loss(loss, engine_move),
+            accuracy: if engine_move {
+                100.0
+            } else {
+                move_accuracy(score, score_after)
+            },
+            best_move: before.best_move,
+            best_san: before
+                .best_move
+                .map_or(String::new(), |best| pgn::to_san(&previous, &best)),
+            pv: before.pv.clone(),
+        };
+        on_move(&report);
+        moves.push(report);
+        before = after;
+    }
+
+    let mut players = [PlayerSummary::default(), PlayerSummary::default()];
+    for report in moves.iter() {
+        let summary = &mut players[side(report.player)];
+        summary.moves += 1;
+        summary.accuracy += report.accuracy;
+        summary.average_loss += report.loss as f64;
+        summary.counts[report.classification.index()] += 1;
+    }
+    for summary in players.iter_mut() {
+        if summary.moves > 0 {
+            summary.accuracy /= summary.moves as f64;
+            summary.average_loss /= summary.moves as f64;
+        } else {
+            summary.accuracy = 100.0;
+        }
+    }
+    GameReport {
+        names: [String::from("White"), String::from("Black")],
+        start: start.clone(),
+        moves,
+        players,
+    }
+}
+
+// The search of a position, a finished game scored as mate or draw
+fn evaluate(engine: &Engine, game: &Game, limits: &SearchLimits) -> SearchResult {
+    match game.clone().get_game_state() {
+        GameState::Checkmate => SearchResult {
+            score: -MATE,
+            ..SearchResult::default()
+        },
+        GameState::Stalemate | GameState::FiftyRule => SearchResult::default(),
+        _ => engine.search(game, limits),
+    }
+}
+
+fn capped(score: i32) -> i32 {
+    score.clamp(-SCORE_CAP, SCORE_CAP)
+}
+
+// The chance to win in percent of a score, 50 for an even position
+pub fn win_chance(score: i32) -> f64 {
+    50.0 + 50.0 * (2.0 / (1.0 + (-0.00368208 * capped(score) as f64).exp()) - 1.0)
+}
+
+// 100 for a move keeping the winning chances, falling off quickly with the
+// chances lost
+pub fn move_accuracy(score: i32, score_after: i32) -> f64 {
+    let lost = (win_chance(score) - win_chance(score_after)).max(0.0);
+    (103.1668 * (-0.04354 * lost).exp() - 3.1669).clamp(0.0, 100.0)
+}
+
+// A score for the %eval command: pawns, or #N and #-N for a mate in N
+// moves, none once mated
+fn eval_text(score: i32) -> Option<String> {
+    if !is_mate_score(score) {
+        return Some(format!("{:.2}", score as f64 / 100.0));
+    }
+    let sign = if score < 0 { "-" } else { "" };
+    match (MATE - score.abs() + 1) / 2 {
+        0 => None,
+        moves => Some(format!("#{}{}", sign, moves)),
+    }
+}
+
+// A score for white in pawns, or the moves to mate as M3 or -M3, "mate"
+// once mated
+fn format_score(score: i32) -> String {
+    if !is_mate_score(score) {
+        return format!("{:+.2}", score as f64 / 100.0);
+    }
+    let sign = if score < 0 { "-" } else { "" };
+    match (MATE - score.abs() + 1) / 2 {
+        0 => format!("{}mate", sign),
+        moves => format!("{}M{}", sign, moves),
+    }
+}
+
+fn side(team: Team) -> usize {
+    match team {
+        Team::White => 0,
+        Team::Black => 1,
+    }
+}
@@ -0,0 +1,592 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::eval;
+use crate::game::{Game, GameState, Rank};
+use crate::moves;
+use crate::moves::{Action, ActionType};
+use crate::nnue::{Accumulator, Network};
+use crate::ordering::{Heuristics, MovePicker};
+use crate::tablebase;
+use crate::tablebase::Wdl;
+use crate::timeman::{Clock, TimeBudget, TimeManager, DEFAULT_MOVE_OVERHEAD};
+use crate::transposition::{Bound, TranspositionTable, MATE, MATE_BOUND, MAX_PLY};
+
+const INFINITY: i32 = MATE + 1;
+// a won tablebase position, less any mate score but more than any evaluation
+pub const TB_WIN: i32 = MATE_BOUND - MAX_PLY;
+
+#[derive(Debug, Clone, Default)]
+pub struct SearchLimits {
+    pub depth: Option<i32>,
+    pub nodes: Option<u64>,
+    pub movetime: Option<Duration>,
+    // the clock of the side to move, the search allots its own time out of it
+    pub clock: Option<Clock>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SearchResult {
+    pub best_move: Option<Action>,
+    pub score: i32,
+    pub depth: i32,
+    pub nodes: u64,
+    pub pv: Vec<Action>,
+    // the best root moves, best first, as many as the multi-pv setting asks for
+    pub lines: Vec<AnalysisLine>,
+}
+
+impl SearchResult {
+    // The reply expected after the best move, to ponder on
+    pub fn ponder_move(&self) -> Option<Action> {
+        self.pv.get(1).copied()
+    }
+}
+
+// One candidate move at the root with its score and principal variation
+#[derive(Debug, Clone)]
+pub struct AnalysisLine {
+    pub action: Action,
+    pub score: i32,
+    pub pv: Vec<Action>,
+}
+
+// Plays an action the way the search does, promoting to a queen
+pub fn play(game: &Game, action: &Action) -> Game {
+    let mut child = game.clone();
+    if action.action_type == ActionType::Promotion {
+        child.set_promotion_piece(Rank::Queen);
+    }
+    child.perform_action(*action);
+    child
+}
+
+pub fn is_mate_score(score: i32) -> bool {
+    score.abs() >= MATE_BOUND
+}
+
+// Iterative deepening alpha-beta search of a single thread
+pub struct Searcher<'a> {
+    tt: &'a TranspositionTable,
+    stop: &'a AtomicBool,
+    pub heuristics: Heuristics,
+    limits: SearchLimits,
+    nodes: u64,
+    stopped: bool,
+    // hashes of the positions before the current one, starting with the game's
+    // positions since the last capture or pawn move
+    path: Vec<u64>,
+    pv_table: Vec<Vec<Action>>,
+    thread_index: usize,
+    multi_pv: usize,
+    // root moves already reported as a better line at the current depth
+    excluded: Vec<u16>,
+    // root moves that do worse than the best ones in the tablebases
+    tablebase_excluded: Vec<u16>,
+    tablebase_pieces: usize,
+    // evaluates instead of the handcrafted evaluation, with the accumulator
+    // of every position on the path by ply
+    network: Option<Arc<Network>>,
+    accumulators: Vec<Accumulator>,
+    move_overhead: Duration,
+    // with a clock, when to stop
+    time: Option<TimeManager>,
+    // set while searching on the opponent's time: the clock and the move
+    // time only start once it is cleared
+    ponder: Option<&'a AtomicBool>,
+    // when the clock started, None while pondering
+    clock_start: Option<Instant>,
+    // the time manager ended the search while pondering, it stops as soon
+    // as the move is played
+    stop_on_ponderhit: bool,
+}
+
+impl<'a> Searcher<'a> {
+    pub fn new(tt: &'a TranspositionTable, stop: &'a AtomicBool) -> Searcher<'a> {
+        Searcher {
+            tt,
+            stop,
+            heuristics: Heuristics::new(),
+            limits: SearchLimits::default(),
+            nodes: 0,
+            stopped: false,
+            path: vec![],
+            pv_table: vec![vec![]; MAX_PLY as usize + 1],
+            thread_index: 0,
+            multi_pv: 1,
+            excluded: vec![],
+            tablebase_excluded: vec![],
+            tablebase_pieces: 0,
+            network: None,
+            accumulators: vec![],
+            move_overhead: Duration::from_millis(DEFAULT_MOVE_OVERHEAD),
+            time: None,
+            ponder: None,
+            clock_start: None,
+            stop_on_ponderhit: false,
+        }
+    }
+
+    pub fn set_network(&mut self, network: Option<Arc<Network>>) {
+        self.network = network;
+    }
+
+    // Helper threads of a parallel search skip every other depth, half of
+    // them the even and half the odd ones, so they don't all search the same tree
+    pub fn set_thread_index(&mut self, thread_index: usize) {
+        self.thread_index = thread_index;
+    }
+
+    // Number of root moves to search with an exact score, each depth
+    // finds the best move not already found as a better line
+    pub fn set_multi_pv(&mut self, lines: usize) {
+        self.multi_pv = lines.max(1);
+    }
+
+    // The flag telling a search on the opponent's time that its move was
+    // played, from then on the time limits count
+    pub fn set_ponder(&mut self, ponder: &'a AtomicBool) {
+        self.ponder = Some(ponder);
+    }
+
+    // The time lost to communication every move, kept off the clock
+    pub fn set_move_overhead(&mut self, move_overhead: Duration) {
+        self.move_overhead = move_overhead;
+    }
+
+    pub fn nodes(&self) -> u64 {
+        self.nodes
+    }
+
+    // Calls `on_iteration` after every completed depth. With a clock the
+    // search ends early when there's a single legal move or a mate was found,
+    // and otherwise when the time manager says so.
+    pub fn iterate<F: FnMut(&SearchResult)>(
+        &mut self,
+        game: &Game,
+        limits: &SearchLimits,
+        mut on_iteration: F,
+    ) -> SearchResult {
+        self.limits = limits.clone();
+        self.nodes = 0;
+        self.stopped = false;
+        self.clock_start = None;
+        self.stop_on_ponderhit = false;
+        self.clock_elapsed();
+        self.time = limits
+            .clock
+            .map(|clock| TimeManager::new(TimeBudget::new(&clock, self.move_overhead)));
+
+        let mut root = game.clone();
+        let root_moves = root.all_moves();
+        let mut result = SearchResult {
+            best_move: root_moves.first().copied(),
+            ..SearchResult::default()
+        };
+        if result.best_move.is_none() {
+            return result;
+        }
+        self.tablebase_pieces = tablebase::max_pieces();
+        self.tablebase_excluded = tablebase_excluded(game);
+        self.path = reversible_hashes(game);
+        if let Some(network) = &self.network {
+            self.accumulators = vec![network.refresh(&root); MAX_PLY as usize + 1];
+        }
+
+        let max_depth = limits.depth.unwrap_or(MAX_PLY - 1).min(MAX_PLY - 1);
+        for depth in 1..=max_depth {
+            if self.thread_index > 0
+                && depth < max_depth
+                && (depth as usize + self.thread_index) % 2 == 1
+            {
+                continue;
+            }
+            let lines = self.search_lines(&mut root, depth, game.history.last());
+            if self.stopped || lines.is_empty() {
+                break;
+            }
+            let score = lines[0].score;
+            result.depth = depth;
+            result.score = score;
+            result.nodes = self.nodes;
+            result.pv = lines[0].pv.clone();
+            result.best_move = Some(lines[0].action);
+            result.lines = lines;
+            on_iteration(&result);
+            if self.multi_pv == 1 && is_mate_score(score) && MATE - score.abs() <= depth {
+                break;
+            }
+            if self.time.is_some() {
+                let forced = root_moves.len() == 1 || is_mate_score(score);
+                let best_move = result.lines[0].action.key();
+                let elapsed = self.clock_elapsed();
+                let time = self.time.as_mut().unwrap();
+                let done = time.depth_done(elapsed.unwrap_or_default(), best_move, score);
+                if done || forced {
+                    if elapsed.is_some() {
+                        break;
+                    }
+                    self.stop_on_ponderhit = true;
+                }
+            }
+        }
+        result.nodes = self.nodes;
+        result
+    }
+
+    // Searches the root once for every line, each time leaving out the
+    // moves of the lines found before
+    fn search_lines(
+        &mut self,
+        root: &mut Game,
+        depth: i32,
+        previous: Option<&Action>,
+    ) -> Vec<AnalysisLine> {
+        let mut lines: Vec<AnalysisLine> = vec![];
+        self.excluded.clear();
+        while lines.len() < self.multi_pv {
+            let score = self.negamax(root, depth, -INFINITY, INFINITY, 0, previous);
+            if self.stopped {
+                break;
+            }
+            let pv = self.pv_table[0].clone();
+            match pv.first() {
+                Some(&action) => {
+                    self.excluded.push(action.key());
+                    lines.push(AnalysisLine { action, score, pv });
+                }
+                None => break,
+            }
+        }
+        self.excluded.clear();
+        // a later line can score better than an earlier one once searched deeper
+        lines.sort_by_key(|line| -line.score);
+        lines
+    }
+
+    fn should_stop(&mut self) -> bool {
+        if self.stopped {
+            return true;
+        }
+        if self.stop.load(Ordering::Relaxed) {
+            self.stopped = true;
+        }
+        if let Some(nodes) = self.limits.nodes {
+            if self.nodes >= nodes {
+                self.stopped = true;
+            }
+        }
+        let hard_limit = self.time.as_ref().map(|time| time.hard_limit());
+        let movetime = match (self.limits.movetime, hard_limit) {
+            (Some(movetime), Some(hard_limit)) => Some(movetime.min(hard_limit)),
+            (movetime, hard_limit) => movetime.or(hard_limit),
+        };
+        if self.nodes % 64 == 0 && (movetime.is_some() || self.stop_on_ponderhit) {
+            if let Some(elapsed) = self.clock_elapsed() {
+                if self.stop_on_ponderhit || movetime.is_some_and(|movetime| elapsed >= movetime) {
+                    self.stopped = true;
+                }
+            }
+        }
+        self.stopped
+    }
+
+    // Time on the clock since the search started, or since the ponder move
+    // was played; None while still pondering
+    fn clock_elapsed(&mut self) -> Option<Duration> {
+        if self.clock_start.is_none() {
+            if self.ponder.is_some_and(|ponder| ponder.load(Ordering::Relaxed)) {
+                return None;
+            }
+            self.clock_start = Some(Instant::now());
+        }
+        self.clock_start.map(|start| start.elapsed())
+    }
+
+    // Brings the accumulator of the child at `ply + 1` up to date
+    fn make(&mut self, parent: &Game, child: &Game, ply: i32) {
+        if let Some(network) = &self.network {
+            let (before, after) = self.accumulators.split_at_mut(ply as usize + 1);
+            network.update(&before[ply as usize], parent, child, &mut after[0]);
+        }
+    }
+
+    fn evaluate(&self, game: &Game, ply: i32) -> i32 {
+        match &self.network {
+            Some(network) => network.evaluate(&self.accumulators[ply as usize], game.player),
+            None => eval::evaluate(game),
+        }
+    }
+
+    fn is_repetition(&self, hash: u64) -> bool {
+        self.path
+            .iter()
+            .rev()
+            .skip(1)
+            .step_by(2)
+            .any(|&h| h == hash)
+    }
+
+    // The perfect score of an endgame in the tablebases. Only probed right
+    // after a capture or pawn move, since the tables don't know about the
+    // moves already played towards the fifty move rule.
+    fn probe_tablebase(&self, game: &Game, ply: i32) -> Option<i32> {
+        if self.tablebase_pieces == 0 || game.halfmove_clock() != 0 {
+            return None;
+        }
+        let pieces = game
+            .matrix
+            .iter()
+            .flatten()
+            .filter(|square| square.piece.is_some())
+            .count();
+        if pieces > self.tablebase_pieces {
+            return None;
+        }
+        Some(match tablebase::probe_wdl(game)? {
+            Wdl::Win => TB_WIN - ply,
+            Wdl::Loss => -TB_WIN + ply,
+            _ => 0,
+        })
+    }
+
+    fn negamax(
+        &mut self,
+        game: &mut Game,
+        depth: i32,
+        mut alpha: i32,
+        beta: i32,
+        ply: i32,
+        previous: Option<&Action>,
+    ) -> i32 {
+        self.pv_table[ply as usize].clear();
+        match game.get_game_state() {
+            GameState::Checkmate => return -MATE + ply,
+            GameState::Stalemate | GameState::FiftyRule => return 0,
+            _ => {}
+        }
+        if depth <= 0 || ply >= MAX_PLY - 1 {
+            return self.quiescence(game, alpha, beta, ply);
+        }
+        self.nodes += 1;
+        if self.should_stop() {
+            return 0;
+        }
+
+        let hash = game.hash();
+        if ply > 0 && self.is_repetition(hash) {
+            return 0;
+        }
+        if ply > 0 {
+            if let Some(score) = self.probe_tablebase(game, ply) {
+                self.tt.store(hash, depth, Bound::Exact, None, score, ply);
+                return score;
+            }
+        }
+        let pv_node = beta - alpha > 1;
+        let entry = self.tt.probe(hash, ply);
+        if let Some(entry) = entry {
+            if ply > 0 && !pv_node && entry.depth as i32 >= depth {
+                let cutoff = match entry.bound {
+                    Bound::Exact => true,
+                    Bound::Lower => entry.score >= beta,
+                    Bound::Upper => entry.score <= alpha,
+                };
+                if cutoff {
+                    return entry.score;
+                }
+            }
+        }
+
+        let mut picker = MovePicker::new(
+            entry.and_then(|e| e.best_move),
+            self.heuristics.killers(ply),
+            self.heuristics.countermove(previous),
+        );
+        let original_alpha = alpha;
+        let mut best_score = -INFINITY;
+        let mut best_move: Option<Action> = None;
+        let mut tried_quiets: Vec<Action> = vec![];
+        let mut move_count = 0;
+
+        self.path.push(hash);
+        while let Some(action) = picker.next(game, &self.heuristics) {
+            if ply == 0
+                && (self.excluded.contains(&action.key())
+                    || self.tablebase_excluded.contains(&action.key()))
+            {
+                continue;
+            }
+            let mut child = play(game, &action);
+            self.make(game, &child, ply);
+            // check extension
+            let new_depth = if child.get_game_state() == GameState::Check {
+                depth
+            } else {
+                depth - 1
+            };
+
+            let score = if move_count == 0 {
+                -self.negamax(&mut child, new_depth, -beta, -alpha, ply + 1, Some(&action))
+            } else {
+                let score = -self.negamax(
+                    &mut child,
+                    new_depth,
+                    -alpha - 1,
+                    -alpha,
+                    ply + 1,
+                    Some(&action),
+                );
+                if score > alpha && score < beta {
+                    -self.negamax(&mut child, new_depth, -beta, -alpha, ply + 1, Some(&action))
+                } else {
+                    score
+                }
+            };
+            move_count += 1;
+            if self.stopped {
+                self.path.pop();
+                return 0;
+            }
+
+            if score > best_score {
+                best_score = score;
+                best_move = Some(action);
+                if score > alpha {
+                    alpha = score;
+                    let mut pv = vec![action];
+                    pv.extend_from_slice(&self.pv_table[ply as usize + 1]);
+                    self.pv_table[ply as usize] = pv;
+                }
+            }
+            if score >= beta {
+                if !moves::is_tactical(&action) {
+                    self.heuristics.update_cutoff(
+                        game.player,
+                        ply,
+                        depth,
+                        &action,
+                        previous,
+                        &tried_quiets,
+                    );
+                }
+                break;
+            }
+            if !moves::is_tactical(&action) {
+                tried_quiets.push(action);
+            }
+        }
+        self.path.pop();
+
+        if move_count == 0 {
+            return self.evaluate(game, ply);
+        }
+
+        // with excluded moves the root score isn't the score of the position
+        if ply == 0 && !self.excluded.is_empty() {
+            return best_score;
+        }
+
+        let bound = if best_score >= beta {
+            Bound::Lower
+        } else if best_score > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        self.tt
+            .store(hash, depth, bound, best_move.as_ref(), best_score, ply);
+        best_score
+    }
+
+    fn quiescence(&mut self, game: &mut Game, mut alpha: i32, beta: i32, ply: i32) -> i32 {
+        self.pv_table[ply as usize].clear();
+        self.nodes += 1;
+        if self.should_stop() {
+            return 0;
+        }
+        let in_check = match game.get_game_state() {
+            GameState::Checkmate => return -MATE + ply,
+            GameState::Stalemate | GameState::FiftyRule => return 0,
+            state => state == GameState::Check,
+        };
+        if ply >= MAX_PLY - 1 {
+            return self.evaluate(game, ply);
+        }
+
+        let mut best_score = -INFINITY;
+        if !in_check {
+            best_score = self.evaluate(game, ply);
+            if best_score >= beta {
+                return best_score;
+            }
+            alpha = alpha.max(best_score);
+        }
+
+        // in check every evasion is searched, otherwise only winning captures
+        let mut picker = if in_check {
+            MovePicker::new(None, [None; 2], None)
+        } else {
+            MovePicker::quiescence()
+        };
+        while let Some(action) = picker.next(game, &self.heuristics) {
+            let mut child = play(game, &action);
+            self.make(game, &child, ply);
+            let score = -self.quiescence(&mut child, -beta, -alpha, ply + 1);
+            if self.stopped {
+                return 0;
+            }
+            if score > best_score {
+                best_score = score;
+                if score > alpha {
+                    alpha = score;
+                    let mut pv = vec![action];
+                    pv.extend_from_slice(&self.pv_table[ply as usize + 1]);
+                    self.pv_table[ply as usize] = pv;
+                }
+            }
+            if score >= beta {
+                break;
+            }
+        }
+        best_score
+    }
+}
+
+// Hashes of the positions played before the current one since the last
+// capture or pawn move, oldest first
+fn reversible_hashes(game: &Game) -> Vec<u64> {
+    let mut game = game.clone();
+    let plies = (game.halfmove_clock() as usize).min(game.history.len());
+    let mut hashes = vec![];
+    for _ in 0..plies {
+        game.undo();
+        hashes.push(game.hash());
+    }
+    hashes.reverse();
+    hashes
+}
+
+// The root moves to leave out when the root is in the tablebases: all but
+// the ones keeping the best result, and of the wins all but the fastest
+// to the next capture or pawn move so the search can't go around in circles
+fn tablebase_excluded(game: &Game) -> Vec<u16> {
+    let moves = match tablebase::root_moves(game) {
+        Some(moves) if !moves.is_empty() => moves,
+        _ => return vec![],
+    };
+    let (_, best_wdl, best_dtz) = moves[0];
+    moves
+        .iter()
+        .filter(|&&(_, wdl, dtz)| wdl != best_wdl || (wdl == Wdl::Win && dtz != best_dtz))
+        .map(|(action, _, _)| action.key())
+        .collect()
+}
+
+pub fn search(game: &Game, limits: &SearchLimits, tt: &TranspositionTable) -> SearchResult {
+    let stop = AtomicBool::new(false);
+    let mut searcher = Searcher::new(tt, &stop);
+    tt.new_search();
+    searcher.iterate(game, limits, |_| {})
+}
@@ -0,0 +1,108 @@
+
+use crate::search::{AnalysisLine, SearchLimits};
+
+pub const MAX_LEVEL: u32 = 20;
+pub const MIN_ELO: u32 = 600;
+pub const MAX_ELO: u32 = 2400;
+// the number of best root moves a weakened engine chooses from
+pub const SKILL_LINES: usize = 4;
+
+// Playing strength below the full engine, as a skill level from 0 to 20
+// (20 being full strength) or an Elo rating mapped onto the levels. Weaker
+// levels search shallower and fewer nodes, and pick among the best multi-pv
+// lines with a random bonus that grows with the weakness. Moves scored far
+// below the best one are never picked, so even level 0 doesn't play
+// pointless moves.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Skill {
+    level: f64,
+}
+
+impl Skill {
+    pub fn from_level(level: u32) -> Skill {
+        Skill {
+            level: level.min(MAX_LEVEL) as f64,
+        }
+    }
+
+    // Linear between MIN_ELO (level 0) and MAX_ELO (full strength)
+    pub fn from_elo(elo: u32) -> Skill {
+        let elo = elo.clamp(MIN_ELO, MAX_ELO);
+        Skill {
+            level: (elo - MIN_ELO) as f64 * MAX_LEVEL as f64 / (MAX_ELO - MIN_ELO) as f64,
+        }
+    }
+
+    pub fn level(&self) -> f64 {
+        self.level
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.level < MAX_LEVEL as f64
+    }
+
+    // The limits with the depth and node caps of the level added: depth 1 at
+    // levels 0 and 1, then one more every two levels with 5000 nodes doubling
+    // every two levels. Depth 1 isn't capped by nodes, as a search that
+    // doesn't finish it has no lines to pick from.
+    pub fn limits(&self, limits: &SearchLimits) -> SearchLimits {
+        if !self.enabled() {
+            return limits.clone();
+        }
+        let depth = 1 + (self.level / 2.0) as i32;
+        let nodes = if depth > 1 {
+            Some((5000.0 * 2f64.powf(self.level / 2.0)) as u64)
+        } else {
+            None
+        };
+        SearchLimits {
+            depth: Some(limits.depth.map_or(depth, |limit| limit.min(depth))),
+            nodes: match (limits.nodes, nodes) {
+                (Some(limit), Some(nodes)) => Some(limit.min(nodes)),
+                (limit, nodes) => limit.or(nodes),
+            },
+            ..limits.clone()
+        }
+    }
+
+    // The centipawns a move can be worse than the best one and still be
+    // picked, 300 at level 0
+    pub fn margin(&self) -> i32 {
+        ((MAX_LEVEL as f64 - self.level) * 15.0) as i32
+    }
+
+    // The index of the line to play among `lines` (best first): each score
+    // gets a bonus up to the margin and the highest total wins. `random` is
+    // any uniformly distributed number.
+    pub fn pick(&self, lines: &[AnalysisLine], random: u64) -> Option<usize> {
+        if lines.is_empty() {
+            return None;
+        }
+        let margin = self.margin() as u64;
+        let mut state = random;
+        let mut best = 0;
+        let mut best_score = i32::MIN;
+        for (i, line) in lines.iter().enumerate() {
+            let bonus = (split_mix(&mut state) % (margin + 1)) as i32;
+            if line.score.saturating_add(bonus) > best_score {
+                best = i;
+                best_score = line.score.saturating_add(bonus);
+            }
+        }
+        Some(best)
+    }
+}
+
+impl Default for Skill {
+    fn default() -> Self {
+        Skill::from_level(MAX_LEVEL)
+    }
+}
+
+fn split_mix(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
@@ -0,0 +1,138 @@
+// Exhaustive solver for mate problems. Unlike the search it looks at every
+// move, underpromotions included, so it proves a mate in N (or that there is
+// none) and finds every key move of a composition.
+use crate::game::{Game, GameState};
+use crate::moves::Action;
+use crate::search::play;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Stipulation {
+    // the side to move mates, whatever the other side does
+    Mate,
+    // both sides cooperate so the side to move gets mated
+    Helpmate,
+    // the side to move forces the other side to mate it
+    Selfmate,
+}
+
+// The shortest forced mate of at most `n` moves by the side to move: the key
+// move, then the longest defence and the attacker's answer, up to the mate
+pub fn find_mate(game: &Game, n: u32) -> Option<Vec<Action>> {
+    solve(game, Stipulation::Mate, n)
+}
+
+// The shortest solution of at most `n` moves of the side to move
+pub fn solve(game: &Game, stipulation: Stipulation, n: u32) -> Option<Vec<Action>> {
+    (1..=n).find_map(|moves| attack(game, stipulation, moves))
+}
+
+// Every first move that solves the problem in at most `n` moves, each with
+// a line following it. A sound composition has exactly one.
+pub fn solutions(game: &Game, stipulation: Stipulation, n: u32) -> Vec<Vec<Action>> {
+    let mut solutions: Vec<Vec<Action>> = vec![];
+    for action in game.clone().legal_moves() {
+        let mut child = play(game, &action);
+        let line = (1..=n).find_map(|moves| answer(&mut child, stipulation, moves));
+        if let Some(mut line) = line {
+            line.insert(0, action);
+            solutions.push(line);
+        }
+    }
+    solutions
+}
+
+// The first move of the side to move that solves the problem in `moves`
+fn attack(game: &Game, stipulation: Stipulation, moves: u32) -> Option<Vec<Action>> {
+    let actions = game.clone().legal_moves();
+    let mut children: Vec<(Action, Game)> = actions
+        .into_iter()
+        .map(|action| {
+            let child = play(game, &action);
+            (action, child)
+        })
+        .collect();
+    // mates in one are the cheapest to find and give the shortest lines
+    if stipulation == Stipulation::Mate {
+        if let Some(index) = children.iter_mut().position(|(_, child)| is_mate(child)) {
+            return Some(vec![children[index].0]);
+        }
+        if moves == 1 {
+            return None;
+        }
+    }
+    for (action, mut child) in children {
+        if let Some(mut line) = answer(&mut child, stipulation, moves) {
+            line.insert(0, action);
+            return Some(line);
+        }
+    }
+    None
+}
+
+// The rest of the solution after the side to move played its move in
+// `game`, so that the other side is to move
+fn answer(game: &mut Game, stipulation: Stipulation, moves: u32) -> Option<Vec<Action>> {
+    match stipulation {
+        Stipulation::Mate => {
+            if is_mate(game) {
+                return Some(vec![]);
+            }
+            if moves == 1 {
+                return None;
+            }
+            defend(game, |child| attack(child, stipulation, moves - 1))
+        }
+        Stipulation::Selfmate => defend(game, |child| {
+            if is_mate(child) {
+                Some(vec![])
+            } else if moves > 1 {
+                attack(child, stipulation, moves - 1)
+            } else {
+                None
+            }
+        }),
+        Stipulation::Helpmate => {
+            for action in game.clone().legal_moves() {
+                let mut child = play(game, &action);
+                let line = if is_mate(&mut child) {
+                    Some(vec![])
+                } else if moves > 1 {
+                    attack(&child, stipulation, moves - 1)
+                } else {
+                    None
+                };
+                if let Some(mut line) = line {
+                    line.insert(0, action);
+                    return Some(line);
+                }
+            }
+            None
+        }
+    }
+}
+
+// The longest line if every move of the side to move leaves a position
+// `continuation` solves, `None` if one doesn't or the side can't move
+fn defend<F>(game: &Game, mut continuation: F) -> Option<Vec<Action>>
+where
+    F: FnMut(&mut Game) -> Option<Vec<Action>>,
+{
+    let actions = game.clone().legal_moves();
+    if actions.is_empty() {
+        return None;
+    }
+    let mut longest: Option<Vec<Action>> = None;
+    for action in actions {
+        let mut line = continuation(&mut play(game, &action))?;
+        line.insert(0, action);
+        if longest.as_ref().map_or(true, |longest| line.len() > longest.len()) {
+            longest = Some(line);
+        }
+    }
+    longest
+}
+
+// The legal moves with one action per promotion piece
+fn is_mate(game: &mut Game) -> bool {
+    game.get_game_state() == GameState::Checkmate
+}
@@ -0,0 +1,1194 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock, RwLock};
+
+use crate::game::{Game, GameState, Outcome, Rank, Team};
+use crate::moves::{Action, ActionType};
+use crate::search::play;
+
+// Syzygy endgame tablebases. A WDL file (.rtbw) tells whether a position is
+// won, drawn or lost, a DTZ file (.rtbz) how many plies it takes to the
+// next capture or pawn move when playing perfectly, which is enough to win
+// within the fifty move rule. Files are read into memory the first time a
+// position with their material is probed.
+
+// Win/draw/loss for the side to move. Cursed wins and blessed losses are
+// wins and losses that the fifty move rule turns into draws.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Wdl {
+    Loss = -2,
+    BlessedLoss = -1,
+    Draw = 0,
+    CursedWin = 1,
+    Win = 2,
+}
+
+impl Wdl {
+    fn from_value(value: i32) -> Wdl {
+        match value {
+            v if v <= -2 => Wdl::Loss,
+            -1 => Wdl::BlessedLoss,
+            0 => Wdl::Draw,
+            1 => Wdl::CursedWin,
+            _ => Wdl::Win,
+        }
+    }
+
+    fn negate(self) -> Wdl {
+        Wdl::from_value(-(self as i32))
+    }
+}
+
+// Plies to the next capture or pawn move with perfect play: positive when
+// the side to move wins, negative when it loses and 0 for draws. Like the
+// tables themselves it can be one ply too high for wins and losses that
+// aren't stored ply exact.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Dtz(pub i32);
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xe8, 0x23, 0x5d];
+const DTZ_MAGIC: [u8; 4] = [0xd7, 0x66, 0x0c, 0xa5];
+
+// flags of a PairsData record
+const STM: u8 = 1;
+const MAPPED: u8 = 2;
+const WIN_PLIES: u8 = 4;
+const LOSS_PLIES: u8 = 8;
+const WIDE: u8 = 16;
+const SINGLE_VALUE: u8 = 128;
+
+const MAX_PIECES: usize = 7;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Kind {
+    Wdl,
+    Dtz,
+}
+
+// Squares are numbered like the files do: a1 = 0, b1 = 1, ..., h8 = 63
+fn rank_of(square: usize) -> usize {
+    square >> 3
+}
+
+fn file_of(square: usize) -> usize {
+    square & 7
+}
+
+fn off_diagonal(square: usize) -> i32 {
+    rank_of(square) as i32 - file_of(square) as i32
+}
+
+fn flip_file(square: usize) -> usize {
+    square ^ 7
+}
+
+fn flip_rank(square: usize) -> usize {
+    square ^ 56
+}
+
+// The index tables shared by every file
+struct Maps {
+    pawns: [usize; 64],
+    b1_h1_h7: [usize; 64],
+    a1_d1_d4: [usize; 64],
+    kk: [[u64; 64]; 10],
+    binomial: [[u64; 64]; 6],
+    lead_pawn_index: [[u64; 64]; 6],
+    lead_pawns_size: [[u64; 4]; 6],
+}
+
+fn maps() -> &'static Maps {
+    static MAPS: OnceLock<Maps> = OnceLock::new();
+    MAPS.get_or_init(Maps::new)
+}
+
+impl Maps {
+    fn new() -> Maps {
+        let mut maps = Maps {
+            pawns: [0; 64],
+            b1_h1_h7: [0; 64],
+            a1_d1_d4: [0; 64],
+            kk: [[0; 64]; 10],
+            binomial: [[0; 64]; 6],
+            lead_pawn_index: [[0; 64]; 6],
+            lead_pawns_size: [[0; 4]; 6],
+        };
+
+        // squares below the a1-h8 diagonal to 0..27
+        let mut code = 0;
+        for square in 0..64 {
+            if off_diagonal(square) < 0 {
+                maps.b1_h1_h7[square] = code;
+                code += 1;
+            }
+        }
+
+        // the a1-d1-d4 triangle to 0..9, the diagonal squares last
+        let mut diagonal = vec![];
+        code = 0;
+        for square in 0..28 {
+            if off_diagonal(square) < 0 && file_of(square) <= 3 {
+                maps.a1_d1_d4[square] = code;
+                code += 1;
+            } else if off_diagonal(square) == 0 && file_of(square) <= 3 {
+                diagonal.push(square);
+            }
+        }
+        for square in diagonal {
+            maps.a1_d1_d4[square] = code;
+            code += 1;
+        }
+
+        // the 462 legal placements of two kings with the first one in the
+        // a1-d1-d4 triangle, both kings on the diagonal coming last
+        let mut both_on_diagonal = vec![];
+        let mut code: u64 = 0;
+        for index in 0..10 {
+            for first in 0..28 {
+                let in_triangle = off_diagonal(first) <= 0 && file_of(first) <= 3;
+                if !in_triangle || maps.a1_d1_d4[first] != index {
+                    continue;
+                }
+                for second in 0..64 {
+                    let adjacent = (rank_of(first) as i32 - rank_of(second) as i32).abs() <= 1
+                        && (file_of(first) as i32 - file_of(second) as i32).abs() <= 1;
+                    let above = off_diagonal(first) == 0 && off_diagonal(second) > 0;
+                    if adjacent || above {
+                        continue;
+                    } else if off_diagonal(first) == 0 && off_diagonal(second) == 0 {
+                        both_on_diagonal.push((index, second));
+                    } else {
+                        maps.kk[index][second] = code;
+                        code += 1;
+                    }
+                }
+            }
+        }
+        for (index, second) in both_on_diagonal {
+            maps.kk[index][second] = code;
+            code += 1;
+        }
+
+        maps.binomial[0][0] = 1;
+        for n in 1..64 {
+            for k in 0..6.min(n + 1) {
+                maps.binomial[k][n] = if k > 0 {
+                    maps.binomial[k - 1][n - 1]
+                } else {
+                    0
+                } + if k < n { maps.binomial[k][n - 1] } else { 0 };
+            }
+        }
+
+        // a2-h7 to 0..47, the higher the nearer the edge and the lower the
+        // rank, so the leading pawn is the one with the highest value
+        let mut available = 47;
+        for lead_pawns in 1..6 {
+            for file in 0..4 {
+                let mut index = 0;
+                for rank in 1..7 {
+                    let square = rank * 8 + file;
+                    if lead_pawns == 1 {
+                        maps.pawns[square] = available;
+                        available = available.saturating_sub(1);
+                        maps.pawns[flip_file(square)] = available;
+                        available = available.saturating_sub(1);
+                    }
+                    maps.lead_pawn_index[lead_pawns][square] = index;
+                    index += maps.binomial[lead_pawns - 1][maps.pawns[square]];
+                }
+                maps.lead_pawns_size[lead_pawns][file] = index;
+            }
+        }
+        maps
+    }
+}
+
+fn read_u16_le(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn read_u32_le(bytes: &[u8], offset: usize) -> u32 {
+    let mut buffer = [0u8; 4];
+    buffer.copy_from_slice(&bytes[offset..offset + 4]);
+    u32::from_le_bytes(buffer)
+}
+
+fn read_u32_be(bytes: &[u8], offset: usize) -> u32 {
+    // blocks can end right at the end of the file
+    let mut buffer = [0u8; 4];
+    for (i, byte) in buffer.iter_mut().enumerate() {
+        *byte = bytes.get(offset + i).copied().unwrap_or(0);
+    }
+    u32::from_be_bytes(buffer)
+}
+
+// Decoding information of one sub-table: per side to move and, with pawns,
+// per file of the leading pawn
+#[derive(Debug, Clone, Default)]
+struct PairsData {
+    flags: u8,
+    max_sym_len: u8,
+    min_sym_len: u8,
+    num_blocks: u32,
+    block_size: usize,
+    span: usize,
+    lowest_sym: usize,
+    btree: usize,
+    block_length: usize,
+    block_length_size: usize,
+    sparse_index: usize,
+    sparse_index_size: usize,
+    data: usize,
+    base64: Vec<u64>,
+    symlen: Vec<u32>,
+    pieces: [u8; MAX_PIECES],
+    group_idx: [u64; MAX_PIECES + 1],
+    group_len: [usize; MAX_PIECES + 1],
+    map_idx: [usize; 4],
+}
+
+impl PairsData {
+    fn left(&self, bytes: &[u8], symbol: usize) -> usize {
+        let offset = self.btree + 3 * symbol;
+        ((bytes[offset + 1] as usize & 0xf) << 8) | bytes[offset] as usize
+    }
+
+    fn right(&self, bytes: &[u8], symbol: usize) -> usize {
+        let offset = self.btree + 3 * symbol;
+        ((bytes[offset + 2] as usize) << 4) | (bytes[offset + 1] as usize >> 4)
+    }
+
+    fn lowest_symbol(&self, bytes: &[u8], length: usize) -> u64 {
+        read_u16_le(bytes, self.lowest_sym + 2 * length) as u64
+    }
+
+    // The number of values a symbol stands for, minus one
+    fn set_symlen(&mut self, bytes: &[u8], symbol: usize, visited: &mut [bool]) -> u32 {
+        visited[symbol] = true;
+        let right = self.right(bytes, symbol);
+        if right == 0xfff {
+            return 0;
+        }
+        let left = self.left(bytes, symbol);
+        if !visited[left] {
+            self.symlen[left] = self.set_symlen(bytes, left, visited);
+        }
+        if !visited[right] {
+            self.symlen[right] = self.set_symlen(bytes, right, visited);
+        }
+        self.symlen[left] + self.symlen[right] + 1
+    }
+
+    fn set_sizes(&mut self, bytes: &[u8], mut offset: usize) -> Result<usize, String> {
+        self.flags = bytes[offset];
+        offset += 1;
+        if self.flags & SINGLE_VALUE != 0 {
+            // the single value is kept in min_sym_len
+            self.min_sym_len = bytes[offset];
+            return Ok(offset + 1);
+        }
+
+        let groups = self.group_len.iter().position(|&len| len == 0).unwrap_or(0);
+        let size = self.group_idx[groups];
+        self.block_size = 1 << bytes[offset];
+        self.span = 1 << bytes[offset + 1];
+        self.sparse_index_size = size.div_ceil(self.span as u64) as usize;
+        let padding = bytes[offset + 2] as usize;
+        self.num_blocks = read_u32_le(bytes, offset + 3);
+        self.block_length_size = self.num_blocks as usize + padding;
+        self.max_sym_len = bytes[offset + 7];
+        self.min_sym_len = bytes[offset + 8];
+        offset += 9;
+        if self.max_sym_len < self.min_sym_len || self.max_sym_len > 32 {
+            return Err(String::from("invalid symbol lengths"));
+        }
+        self.lowest_sym = offset;
+
+        // canonical huffman codes: longer codes have lower values, base64[l]
+        // is the lowest code of length min_sym_len + l padded to 64 bits
+        let lengths = (self.max_sym_len - self.min_sym_len) as usize + 1;
+        self.base64 = vec![0; lengths];
+        for i in (0..lengths - 1).rev() {
+            self.base64[i] = self.base64[i + 1]
+                .wrapping_add(self.lowest_symbol(bytes, i))
+                .wrapping_sub(self.lowest_symbol(bytes, i + 1))
+                / 2;
+        }
+        for (i, base) in self.base64.iter_mut().enumerate() {
+            let shift = 64 - i - self.min_sym_len as usize;
+            *base = if shift >= 64 { 0 } else { *base << shift };
+        }
+        offset += 2 * lengths;
+
+        let symbols = read_u16_le(bytes, offset) as usize;
+        offset += 2;
+        self.btree = offset;
+        self.symlen = vec![0; symbols];
+        let mut visited = vec![false; symbols];
+        for symbol in 0..symbols {
+            if !visited[symbol] {
+                self.symlen[symbol] = self.set_symlen(bytes, symbol, &mut visited);
+            }
+        }
+        Ok(offset + 3 * symbols + (symbols & 1))
+    }
+
+    // The value stored at the index
+    fn decompress(&self, bytes: &[u8], index: u64) -> i32 {
+        if self.flags & SINGLE_VALUE != 0 {
+            return self.min_sym_len as i32;
+        }
+
+        // every span values there is a sparse entry with the block and the
+        // offset in it of the value in the middle of the span
+        let k = (index / self.span as u64) as usize;
+        let entry = self.sparse_index + 6 * k;
+        let mut block = read_u32_le(bytes, entry) as usize;
+        let mut offset = read_u16_le(bytes, entry + 4) as i64;
+        offset += (index % self.span as u64) as i64 - (self.span / 2) as i64;
+
+        let block_length = |block: usize| read_u16_le(bytes, self.block_length + 2 * block) as i64;
+        while offset < 0 {
+            block -= 1;
+            offset += block_length(block) + 1;
+        }
+        while offset > block_length(block) {
+            offset -= block_length(block) + 1;
+            block += 1;
+        }
+
+        let mut pointer = self.data + block * self.block_size;
+        let mut buffer =
+            (read_u32_be(bytes, pointer) as u64) << 32 | read_u32_be(bytes, pointer + 4) as u64;
+        pointer += 8;
+        let mut buffer_size = 64;
+        let min_sym_len = self.min_sym_len as usize;
+        let mut symbol;
+        loop {
+            let mut length = 0;
+            while length + 1 < self.base64.len() && buffer < self.base64[length] {
+                length += 1;
+            }
+            symbol = ((buffer - self.base64[length]) >> (64 - length - min_sym_len)) as usize;
+            symbol += self.lowest_symbol(bytes, length) as usize;
+            if offset < self.symlen[symbol] as i64 + 1 {
+                break;
+            }
+            offset -= self.symlen[symbol] as i64 + 1;
+            let length = length + min_sym_len;
+            buffer <<= length;
+            buffer_size -= length;
+            if buffer_size <= 32 {
+                buffer_size += 32;
+                buffer |= (read_u32_be(bytes, pointer) as u64) << (64 - buffer_size);
+                pointer += 4;
+            }
+        }
+
+        // the symbol stands for a pair of symbols, expand it until the value
+        while self.symlen[symbol] != 0 {
+            let left = self.left(bytes, symbol);
+            if offset < self.symlen[left] as i64 + 1 {
+                symbol = left;
+            } else {
+                offset -= self.symlen[left] as i64 + 1;
+                symbol = self.right(bytes, symbol);
+            }
+        }
+        self.left(bytes, symbol) as i32
+    }
+}
+
+// What the name of a file tells about its positions
+#[derive(Debug, Clone)]
+struct Material {
+    piece_count: usize,
+    has_pawns: bool,
+    has_unique_pieces: bool,
+    // pawns of the leading color and of the other color
+    pawn_count: [usize; 2],
+    // both sides have the same pieces
+    symmetric: bool,
+}
+
+impl Material {
+    fn from_name(name: &str) -> Option<Material> {
+        let (white, black) = name.split_once('v')?;
+        let valid =
+            |side: &str| side.starts_with('K') && side.chars().all(|c| "KQRBNP".contains(c));
+        if !valid(white) || !valid(black) {
+            return None;
+        }
+        let count = |side: &str, c: char| side.chars().filter(|&p| p == c).count();
+        let has_unique_pieces = [white, black]
+            .iter()
+            .any(|side| "QRBNP".chars().any(|c| count(side, c) == 1));
+        let (white_pawns, black_pawns) = (count(white, 'P'), count(black, 'P'));
+        let white_leads = black_pawns == 0 || (white_pawns > 0 && black_pawns >= white_pawns);
+        let pawn_count = if white_leads {
+            [white_pawns, black_pawns]
+        } else {
+            [black_pawns, white_pawns]
+        };
+        Some(Material {
+            piece_count: white.len() + black.len(),
+            has_pawns: white_pawns + black_pawns > 0,
+            has_unique_pieces,
+            pawn_count,
+            symmetric: white == black,
+        })
+    }
+}
+
+struct TableData {
+    bytes: Vec<u8>,
+    // [side to move][file of the leading pawn]
+    items: Vec<Vec<PairsData>>,
+    map: usize,
+}
+
+struct Table {
+    path: PathBuf,
+    kind: Kind,
+    material: Material,
+    data: OnceLock<Option<TableData>>,
+}
+
+impl Table {
+    fn new(path: PathBuf, kind: Kind, material: Material) -> Table {
+        Table {
+            path,
+            kind,
+            material,
+            data: OnceLock::new(),
+        }
+    }
+
+    fn data(&self) -> Option<&TableData> {
+        self.data
+            .get_or_init(|| {
+                let bytes = fs::read(&self.path).ok()?;
+                self.init(bytes).ok()
+            })
+            .as_ref()
+    }
+
+    fn init(&self, bytes: Vec<u8>) -> Result<TableData, String> {
+        let magic = match self.kind {
+            Kind::Wdl => WDL_MAGIC,
+            Kind::Dtz => DTZ_MAGIC,
+        };
+        if bytes.len() < 6 || bytes[0..4] != magic {
+            return Err(format!("{} is not a tablebase file", self.path.display()));
+        }
+        let material = &self.material;
+        let mut offset = 4;
+        let split = bytes[offset] & 1 != 0;
+        if (bytes[offset] & 2 != 0) != material.has_pawns {
+            return Err(format!("{} doesn't match its name", self.path.display()));
+        }
+        offset += 1;
+
+        let sides = if self.kind == Kind::Wdl && split {
+            2
+        } else {
+            1
+        };
+        let files = if material.has_pawns { 4 } else { 1 };
+        let both_pawns = material.has_pawns && material.pawn_count[1] > 0;
+        let mut items = vec![vec![PairsData::default(); files]; sides];
+
+        for file in 0..files {
+            let order = [
+                [
+                    bytes[offset] & 0xf,
+                    if both_pawns {
+                        bytes[offset + 1] & 0xf
+                    } else {
+                        0xf
+                    },
+                ],
+                [
+                    bytes[offset] >> 4,
+                    if both_pawns {
+                        bytes[offset + 1] >> 4
+                    } else {
+                        0xf
+                    },
+                ],
+            ];
+            offset += 1 + both_pawns as usize;
+            for k in 0..material.piece_count {
+                for (side, sub_tables) in items.iter_mut().enumerate() {
+                    sub_tables[file].pieces[k] = if side == 0 {
+                        bytes[offset] & 0xf
+                    } else {
+                        bytes[offset] >> 4
+                    };
+                }
+                offset += 1;
+            }
+            for (side, sub_tables) in items.iter_mut().enumerate() {
+                set_groups(material, &mut sub_tables[file], order[side], file);
+            }
+        }
+        offset += offset & 1;
+
+        for file in 0..files {
+            for sub_tables in items.iter_mut() {
+                offset = sub_tables[file].set_sizes(&bytes, offset)?;
+            }
+        }
+
+        let map = offset;
+        if self.kind == Kind::Dtz {
+            for pairs in items[0].iter_mut() {
+                if pairs.flags & MAPPED == 0 {
+                    continue;
+                }
+                if pairs.flags & WIDE != 0 {
+                    offset += offset & 1;
+                    for i in 0..4 {
+                        pairs.map_idx[i] = (offset - map) / 2 + 1;
+                        offset += 2 * read_u16_le(&bytes, offset) as usize + 2;
+                    }
+                } else {
+                    for i in 0..4 {
+                        pairs.map_idx[i] = offset - map + 1;
+                        offset += bytes[offset] as usize + 1;
+                    }
+                }
+            }
+            offset += offset & 1;
+        }
+
+        for file in 0..files {
+            for sub_tables in items.iter_mut() {
+                sub_tables[file].sparse_index = offset;
+                offset += 6 * sub_tables[file].sparse_index_size;
+            }
+        }
+        for file in 0..files {
+            for sub_tables in items.iter_mut() {
+                sub_tables[file].block_length = offset;
+                offset += 2 * sub_tables[file].block_length_size;
+            }
+        }
+        for file in 0..files {
+            for sub_tables in items.iter_mut() {
+                offset = (offset + 0x3f) & !0x3f;
+                let pairs = &mut sub_tables[file];
+                pairs.data = offset;
+                offset += pairs.num_blocks as usize * pairs.block_size;
+            }
+        }
+        if offset > bytes.len() + 64 {
+            return Err(format!("{} is truncated", self.path.display()));
+        }
+        Ok(TableData { bytes, items, map })
+    }
+}
+
+// Works out which pieces are encoded together and the factor of every group
+// in the index
+fn set_groups(material: &Material, pairs: &mut PairsData, order: [u8; 2], file: usize) {
+    let maps = maps();
+    let mut n = 0;
+    let mut first_len: i32 = if material.has_pawns {
+        0
+    } else if material.has_unique_pieces {
+        3
+    } else {
+        2
+    };
+    pairs.group_len[n] = 1;
+    for i in 1..material.piece_count {
+        first_len -= 1;
+        if first_len > 0 || pairs.pieces[i] == pairs.pieces[i - 1] {
+            pairs.group_len[n] += 1;
+        } else {
+            n += 1;
+            pairs.group_len[n] = 1;
+        }
+    }
+    n += 1;
+    pairs.group_len[n] = 0;
+
+    let both_pawns = material.has_pawns && material.pawn_count[1] > 0;
+    let mut next = if both_pawns { 2 } else { 1 };
+    let mut free_squares =
+        64 - pairs.group_len[0] - if both_pawns { pairs.group_len[1] } else { 0 };
+    let mut index: u64 = 1;
+    let mut k = 0;
+    while next < n || k == order[0] as usize || k == order[1] as usize {
+        if k == order[0] as usize {
+            pairs.group_idx[0] = index;
+            index *= if material.has_pawns {
+                maps.lead_pawns_size[pairs.group_len[0]][file]
+            } else if material.has_unique_pieces {
+                31332
+            } else {
+                462
+            };
+        } else if k == order[1] as usize {
+            pairs.group_idx[1] = index;
+            index *= maps.binomial[pairs.group_len[1]][48 - pairs.group_len[0]];
+        } else {
+            pairs.group_idx[next] = index;
+            index *= maps.binomial[pairs.group_len[next]][free_squares];
+            free_squares -= pairs.group_len[next];
+            next += 1;
+        }
+        k += 1;
+    }
+    pairs.group_idx[n] = index;
+}
+
+// The piece codes of the files: pawn 1 to king 6, plus 8 for black
+fn piece_code(rank: Rank, team: Team) -> u8 {
+    let code = match rank {
+        Rank::Pawn => 1,
+        Rank::Knight => 2,
+        Rank::Bishop => 3,
+        Rank::Rook => 4,
+        Rank::Queen => 5,
+        Rank::King => 6,
+    };
+    match team {
+        Team::White => code,
+        Team::Black => code + 8,
+    }
+}
+
+enum Probe {
+    Value(i32),
+    // a DTZ table only has the other side to move
+    ChangeSideToMove,
+}
+
+// The tables found in a set of directories
+#[derive(Default)]
+pub struct Tablebases {
+    wdl: HashMap<String, Arc<Table>>,
+    dtz: HashMap<String, Arc<Table>>,
+    max_pieces: usize,
+}
+
+impl Tablebases {
+    pub fn new() -> Tablebases {
+        Tablebases::default()
+    }
+
+    // Adds the tables of a directory, returns how many WDL tables it has
+    pub fn add_directory(&mut self, directory: &Path) -> Result<usize, String> {
+        let entries = fs::read_dir(directory)
+            .map_err(|e| format!("could not read {}: {}", directory.display(), e))?;
+        let mut added = 0;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let (name, kind) = match (
+                path.file_stem().and_then(|s| s.to_str()),
+                path.extension().and_then(|s| s.to_str()),
+            ) {
+                (Some(name), Some("rtbw")) => (name.to_string(), Kind::Wdl),
+                (Some(name), Some("rtbz")) => (name.to_string(), Kind::Dtz),
+                _ => continue,
+            };
+            let material = match Material::from_name(&name) {
+                Some(material) if material.piece_count <= MAX_PIECES => material,
+                _ => continue,
+            };
+            let tables = match kind {
+                Kind::Wdl => {
+                    added += 1;
+                    self.max_pieces = self.max_pieces.max(material.piece_count);
+                    &mut self.wdl
+                }
+                Kind::Dtz => &mut self.dtz,
+            };
+            tables.insert(name, Arc::new(Table::new(path, kind, material)));
+        }
+        Ok(added)
+    }
+
+    // The most pieces of any table, 0 without tables
+    pub fn max_pieces(&self) -> usize {
+        self.max_pieces
+    }
+
+    pub fn probe_wdl(&self, game: &Game) -> Option<Wdl> {
+        if !self.can_probe(game) {
+            return None;
+        }
+        self.search(game, false).map(|(wdl, _)| wdl)
+    }
+
+    pub fn probe_dtz(&self, game: &Game) -> Option<Dtz> {
+        if !self.can_probe(game) {
+            return None;
+        }
+        self.dtz(game).map(Dtz)
+    }
+
+    pub fn probe(&self, game: &Game) -> Option<(Wdl, Dtz)> {
+        Some((self.probe_wdl(game)?, self.probe_dtz(game)?))
+    }
+
+    fn can_probe(&self, game: &Game) -> bool {
+        let pieces = game
+            .matrix
+            .iter()
+            .flatten()
+            .filter(|square| square.piece.is_some())
+            .count();
+        // the tables have no castling
+        pieces <= self.max_pieces && !game.castling_rights().contains(&true)
+    }
+
+    fn table(&self, kind: Kind, game: &Game) -> Option<(Arc<Table>, bool)> {
+        let white = material_name(game, Team::White);
+        let black = material_name(game, Team::Black);
+        let tables = match kind {
+            Kind::Wdl => &self.wdl,
+            Kind::Dtz => &self.dtz,
+        };
+        if let Some(table) = tables.get(&format!("{}v{}", white, black)) {
+            return Some((Arc::clone(table), false));
+        }
+        tables
+            .get(&format!("{}v{}", black, white))
+            .map(|table| (Arc::clone(table), true))
+    }
+
+    fn probe_table(&self, game: &Game, kind: Kind, wdl: Wdl) -> Option<Probe> {
+        if count_pieces(game) == 2 {
+            return Some(Probe::Value(0));
+        }
+        let (table, black_stronger) = self.table(kind, game)?;
+        let data = table.data()?;
+        probe_position(&table, data, game, black_stronger, wdl)
+    }
+
+    // The WDL score with captures searched, since the tables don't need to
+    // store the right value when a capture is best. The flag is set when a
+    // capture or pawn move is the best move, where the DTZ tables don't
+    // store a usable value either.
+    fn search(&self, game: &Game, zeroing_pawn_moves: bool) -> Option<(Wdl, bool)> {
+        let moves = game.clone().legal_moves();
+        let mut best = Wdl::Loss;
+        let mut searched = 0;
+        for action in moves.iter() {
+            let zeroing = is_capture(action) || (zeroing_pawn_moves && is_pawn_move(action));
+            if !zeroing {
+                continue;
+            }
+            searched += 1;
+            let child = play(game, action);
+            let value = self.search(&child, false)?.0.negate();
+            if value > best {
+                best = value;
+                if value == Wdl::Win {
+                    return Some((value, true));
+                }
+            }
+        }
+
+        let no_more_moves = searched > 0 && searched == moves.len();
+        let value = if no_more_moves {
+            best
+        } else {
+            match self.probe_table(game, Kind::Wdl, Wdl::Draw)? {
+                Probe::Value(value) => Wdl::from_value(value),
+                Probe::ChangeSideToMove => return None,
+            }
+        };
+        if best >= value {
+            Some((best, best > Wdl::Draw || no_more_moves))
+        } else {
+            Some((value, false))
+        }
+    }
+
+    fn dtz(&self, game: &Game) -> Option<i32> {
+        let (wdl, zeroing_best) = self.search(game, true)?;
+        if wdl == Wdl::Draw {
+            return Some(0);
+        }
+        if zeroing_best {
+            return Some(dtz_before_zeroing(wdl));
+        }
+        let sign = (wdl as i32).signum();
+        match self.probe_table(game, Kind::Dtz, wdl)? {
+            Probe::Value(dtz) => {
+                let cursed = matches!(wdl, Wdl::CursedWin | Wdl::BlessedLoss);
+                Some((dtz + if cursed { 100 } else { 0 }) * sign)
+            }
+            Probe::ChangeSideToMove => {
+                // the table has the other side to move: one ply search for
+                // the move keeping the result with the lowest DTZ
+                let mut min_dtz = i32::MAX;
+                for action in game.clone().legal_moves().iter() {
+                    let zeroing = is_capture(action) || is_pawn_move(action);
+                    let mut child = play(game, action);
+                    let mut dtz = if zeroing {
+                        -dtz_before_zeroing(self.search(&child, false)?.0)
+                    } else {
+                        -self.dtz(&child)?
+                    };
+                    if dtz == 1 && child.get_game_state() == GameState::Checkmate {
+                        min_dtz = 1;
+                    }
+                    if !zeroing {
+                        dtz += dtz.signum();
+                    }
+                    if dtz < min_dtz && dtz.signum() == sign {
+                        min_dtz = dtz;
+                    }
+                }
+                Some(if min_dtz == i32::MAX { -1 } else { min_dtz })
+            }
+        }
+    }
+
+    // The result of the game with perfect play from here. Cursed wins and
+    // blessed losses are draws.
+    pub fn adjudicate(&self, game: &Game) -> Option<Outcome> {
+        let winner = match self.probe_wdl(game)? {
+            Wdl::Win => game.player,
+            Wdl::Loss => match game.player {
+                Team::White => Team::Black,
+                Team::Black => Team::White,
+            },
+            _ => return Some(Outcome::Draw),
+        };
+        Some(Outcome::Win(winner))
+    }
+
+    // The legal moves of the position with the result after each of them,
+    // for the side to move, best first: wins in the fewest plies, then
+    // draws, then losses in the most plies. Wins and losses the fifty move
+    // rule comes before are cursed wins and blessed losses.
+    pub fn root_moves(&self, game: &Game) -> Option<Vec<(Action, Wdl, Dtz)>> {
+        if !self.can_probe(game) {
+            return None;
+        }
+        let mut moves = vec![];
+        for action in game.clone().legal_moves().iter() {
+            let mut child = play(game, action);
+            let (wdl, dtz) = if is_capture(action) || is_pawn_move(action) {
+                let wdl = self.probe_wdl(&child)?.negate();
+                (wdl, dtz_before_zeroing(wdl))
+            } else {
+                let (wdl, Dtz(dtz)) = self.probe(&child)?;
+                let dtz = if child.get_game_state() == GameState::Checkmate {
+                    1
+                } else {
+                    -dtz - dtz.signum()
+                };
+                let plies = dtz.abs() + game.halfmove_clock();
+                let wdl = match wdl.negate() {
+                    Wdl::Win if plies > 100 => Wdl::CursedWin,
+                    Wdl::Loss if plies > 100 => Wdl::BlessedLoss,
+                    wdl => wdl,
+                };
+                (wdl, dtz)
+            };
+            moves.push((*action, wdl, Dtz(dtz)));
+        }
+        moves.sort_by_key(|&(_, wdl, dtz)| (std::cmp::Reverse(wdl), dtz));
+        Some(moves)
+    }
+}
+
+fn dtz_before_zeroing(wdl: Wdl) -> i32 {
+    match wdl {
+        Wdl::Win => 1,
+        Wdl::CursedWin => 101,
+        Wdl::BlessedLoss => -101,
+        Wdl::Loss => -1,
+        Wdl::Draw => 0,
+    }
+}
+
+fn count_pieces(game: &Game) -> usize {
+    game.matrix
+        .iter()
+        .flatten()
+        .filter(|square| square.piece.is_some())
+        .count()
+}
+
+// Pieces of one side the way the files are named, like KRP
+fn material_name(game: &Game, team: Team) -> String {
+    let mut name = String::new();
+    for rank in [
+        Rank::King,
+        Rank::Queen,
+        Rank::Rook,
+        Rank::Bishop,
+        Rank::Knight,
+        Rank::Pawn,
+    ]
+    .iter()
+    {
+        for square in game.matrix.iter().flatten() {
+            if let Some(piece) = square.piece {
+                if piece.team == team && piece.rank == *rank {
+                    name.push(rank.letter());
+                }
+            }
+        }
+    }
+    name
+}
+
+fn is_capture(action: &Action) -> bool {
+    action.to.piece.is_some() || action.action_type == ActionType::Enpassant
+}
+
+fn is_pawn_move(action: &Action) -> bool {
+    action.from.piece.map(|piece| piece.rank) == Some(Rank::Pawn)
+}
+
+// Legal moves with every promotion piece
+// Turns the position into the index of its table and reads the value
+fn probe_position(
+    table: &Table,
+    data: &TableData,
+    game: &Game,
+    black_stronger: bool,
+    wdl: Wdl,
+) -> Option<Probe> {
+    let maps = maps();
+    let material = &table.material;
+    let black_to_move = game.player == Team::Black;
+    // the files only have white as the stronger side, and with the same
+    // pieces on both sides only white to move
+    let flip = (material.symmetric && black_to_move) || black_stronger;
+    let flip_color = if flip { 8 } else { 0 };
+    let flip_squares = if flip { 56 } else { 0 };
+    let side_to_move = (flip ^ black_to_move) as usize;
+
+    let mut position: Vec<(usize, u8)> = vec![];
+    for square in 0..64 {
+        let (x, y) = (square % 8, square / 8);
+        if let Some(piece) = game.matrix[x][y].piece {
+            position.push((square, piece_code(piece.rank, piece.team)));
+        }
+    }
+
+    let mut squares: Vec<usize> = vec![];
+    let mut pieces: Vec<u8> = vec![];
+    let mut lead_pawns = 0;
+    let mut file = 0;
+    if material.has_pawns {
+        let lead = data.items[0][0].pieces[0] ^ flip_color;
+        for &(square, code) in position.iter() {
+            if code == lead {
+                squares.push(square ^ flip_squares);
+                pieces.push(code ^ flip_color);
+            }
+        }
+        lead_pawns = squares.len();
+        if lead_pawns == 0 {
+            return None;
+        }
+        let leading =
+            (0..lead_pawns).max_by_key(|&i| (maps.pawns[squares[i]], std::cmp::Reverse(i)))?;
+        squares.swap(0, leading);
+        file = file_of(squares[0]).min(7 - file_of(squares[0]));
+    }
+
+    if table.kind == Kind::Dtz {
+        let pairs = &data.items[0][file];
+        let stored = (pairs.flags & STM) as usize;
+        let both_sides_stored = material.symmetric && !material.has_pawns;
+        if stored != side_to_move && !both_sides_stored {
+            return Some(Probe::ChangeSideToMove);
+        }
+    }
+
+    let lead_code = pieces.first().copied();
+    for &(square, code) in position.iter() {
+        if material.has_pawns && Some(code ^ flip_color) == lead_code {
+            continue;
+        }
+        squares.push(square ^ flip_squares);
+        pieces.push(code ^ flip_color);
+    }
+    let size = squares.len();
+    if size != material.piece_count {
+        return None;
+    }
+
+    let side = side_to_move % data.items.len();
+    let pairs = &data.items[side][file];
+
+    // same order as the pieces of the table
+    for i in lead_pawns..size.saturating_sub(1) {
+        for j in i + 1..size {
+            if pairs.pieces[i] == pieces[j] {
+                pieces.swap(i, j);
+                squares.swap(i, j);
+                break;
+            }
+        }
+    }
+
+    if file_of(squares[0]) > 3 {
+        for square in squares.iter_mut() {
+            *square = flip_file(*square);
+        }
+    }
+
+    let mut index: u64;
+    if material.has_pawns {
+        index = maps.lead_pawn_index[lead_pawns][squares[0]];
+        squares[1..lead_pawns].sort_by_key(|&square| maps.pawns[square]);
+        for (i, &square) in squares.iter().enumerate().take(lead_pawns).skip(1) {
+            index += maps.binomial[i][maps.pawns[square]];
+        }
+    } else {
+        if rank_of(squares[0]) > 3 {
+            for square in squares.iter_mut() {
+                *square = flip_rank(*square);
+            }
+        }
+        // the first piece of the leading group off the diagonal goes below it
+        for i in 0..pairs.group_len[0] {
+            if off_diagonal(squares[i]) == 0 {
+                continue;
+            }
+            if off_diagonal(squares[i]) > 0 {
+                for square in squares[i..].iter_mut() {
+                    *square = ((*square >> 3) | (*square << 3)) & 63;
+                }
+            }
+            break;
+        }
+
+        if material.has_unique_pieces {
+            let adjust1 = (squares[1] > squares[0]) as usize;
+            let adjust2 = (squares[2] > squares[0]) as usize + (squares[2] > squares[1]) as usize;
+            index = if off_diagonal(squares[0]) != 0 {
+                ((maps.a1_d1_d4[squares[0]] * 63 + (squares[1] - adjust1)) * 62 + squares[2]
+                    - adjust2) as u64
+            } else if off_diagonal(squares[1]) != 0 {
+                ((6 * 63 + rank_of(squares[0]) * 28 + maps.b1_h1_h7[squares[1]]) * 62 + squares[2]
+                    - adjust2) as u64
+            } else if off_diagonal(squares[2]) != 0 {
+                (6 * 63 * 62
+                    + 4 * 28 * 62
+                    + rank_of(squares[0]) * 7 * 28
+                    + (rank_of(squares[1]) - adjust1) * 28
+                    + maps.b1_h1_h7[squares[2]]) as u64
+            } else {
+                (6 * 63 * 62
+                    + 4 * 28 * 62
+                    + 4 * 7 * 28
+                    + rank_of(squares[0]) * 7 * 6
+                    + (rank_of(squares[1]) - adjust1) * 6
+                    + (rank_of(squares[2]) - adjust2)) as u64
+            };
+        } else {
+            index = maps.kk[maps.a1_d1_d4[squares[0]]][squares[1]];
+        }
+    }
+
+    // the other groups, each as a combination of squares not taken by the
+    // groups before it
+    index *= pairs.group_idx[0];
+    let mut start = pairs.group_len[0];
+    let mut remaining_pawns = material.has_pawns && material.pawn_count[1] > 0;
+    let mut next = 1;
+    while pairs.group_len[next] != 0 {
+        let len = pairs.group_len[next];
+        squares[start..start + len].sort();
+        let mut n: u64 = 0;
+        for i in 0..len {
+            let square = squares[start + i];
+            let adjust = squares[..start].iter().filter(|&&s| square > s).count();
+            let free = square - adjust - if remaining_pawns { 8 } else { 0 };
+            n += maps.binomial[i + 1][free];
+        }
+        remaining_pawns = false;
+        index += n * pairs.group_idx[next];
+        start += len;
+        next += 1;
+    }
+
+    let value = pairs.decompress(&data.bytes, index);
+    Some(Probe::Value(match table.kind {
+        Kind::Wdl => value - 2,
+        Kind::Dtz => map_dtz(data, file, value, wdl),
+    }))
+}
+
+// DTZ values are stored as their rank by frequency per result, the map turns
+// them back into plies
+fn map_dtz(data: &TableData, file: usize, value: i32, wdl: Wdl) -> i32 {
+    let pairs = &data.items[0][file];
+    let flags = pairs.flags;
+    let mut value = value as usize;
+    if flags & MAPPED != 0 {
+        let index = pairs.map_idx[[1, 3, 0, 2, 0][(wdl as i32 + 2) as usize]];
+        value = if flags & WIDE != 0 {
+            read_u16_le(&data.bytes, data.map + 2 * (index + value)) as usize
+        } else {
+            data.bytes[data.map + index + value] as usize
+        };
+    }
+    let in_moves = match wdl {
+        Wdl::Win => flags & WIN_PLIES == 0,
+        Wdl::Loss => flags & LOSS_PLIES == 0,
+        Wdl::CursedWin | Wdl::BlessedLoss => true,
+        Wdl::Draw => false,
+    };
+    let value = if in_moves { value * 2 } else { value };
+    value as i32 + 1
+}
+
+fn global() -> &'static RwLock<Tablebases> {
+    static TABLEBASES: OnceLock<RwLock<Tablebases>> = OnceLock::new();
+    TABLEBASES.get_or_init(|| RwLock::new(Tablebases::new()))
+}
+
+// Uses the tables of the directories (separated like PATH), replacing any
+// tables used before. Returns the number of WDL tables found.
+pub fn init(directories: &str) -> Result<usize, String> {
+    let mut tablebases = Tablebases::new();
+    let mut found = 0;
+    for directory in std::env::split_paths(directories) {
+        if !directory.as_os_str().is_empty() {
+            found += tablebases.add_directory(&directory)?;
+        }
+    }
+    *global().write().unwrap() = tablebases;
+    Ok(found)
+}
+
+pub fn max_pieces() -> usize {
+    global().read().unwrap().max_pieces()
+}
+
+// WDL and DTZ of the position for the side to move, None if there is no
+// table for it or it still has castling rights
+pub fn probe(game: &Game) -> Option<(Wdl, Dtz)> {
+    global().read().unwrap().probe(game)
+}
+
+pub fn probe_wdl(game: &Game) -> Option<Wdl> {
+    global().read().unwrap().probe_wdl(game)
+}
+
+pub fn root_moves(game: &Game) -> Option<Vec<(Action, Wdl, Dtz)>> {
+    global().read().unwrap().root_moves(game)
+}
+
+// The result of the game with perfect play from here, if the position is
+// in the tables
+pub fn adjudicate(game: &Game) -> Option<Outcome> {
+    global().read().unwrap().adjudicate(game)
+}
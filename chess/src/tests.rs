@@ -1,10 +1,50 @@
 #[cfg(test)]
 mod tests {
     use super::*;
-    use game::{Piece, Rank, Team};
+    use game::{GameState, Outcome, Piece, Rank, Team};
     use crate::pgn;
     use crate::game;
     use crate::{game::Game, moves};
+    use crate::engine::Engine;
+    use crate::moves::{Action, ActionType};
+    use crate::uci::Uci;
+    use crate::uci_client;
+    use crate::uci_client::{GoOptions, UciClient};
+    use crate::xboard::Xboard;
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use crate::opening;
+    use crate::opening_book;
+    use crate::opening_book::{BookBuilder, BookEntry, BuilderOptions, OpeningBook, Selection};
+    use crate::ordering;
+    use crate::ordering::{Heuristics, MovePicker};
+    use crate::search;
+    use crate::solver;
+    use crate::solver::Stipulation;
+    use crate::search::SearchLimits;
+    use crate::skill::{Skill, SKILL_LINES};
+    use crate::tablebase::{Dtz, Tablebases, Wdl};
+    use crate::database::{Database, Query, Results};
+    use crate::dtm;
+    use crate::dtm::Dtm;
+    use crate::unmoves::Unmove;
+    use crate::tournament;
+    use crate::tuner;
+    use crate::analysis::{AnalysisUpdate, Analyzer};
+    use crate::nnue::Network;
+    use crate::epd;
+    use crate::report;
+    use crate::report::{Classification, GameReport};
+    use crate::epd::Epd;
+    use crate::timeman::{Clock, TimeBudget, TimeManager};
+    use crate::datagen;
+    use crate::datagen::{Datagen, DatagenOptions};
+    use crate::tuner::{Method, TunerOptions, TuningPosition};
+    use crate::eval;
+    use crate::eval::{Weights, DEFAULT_WEIGHTS};
+    use crate::tournament::{Contestant, Crosstable, GameRecord, Player, Sprt, SprtVerdict, Standings, TimeControl, Tournament, TournamentOptions};
+    use crate::transposition::{Bound, TranspositionTable, MATE};
 
     #[test]
     fn it_works() {
@@ -100,6 +140,77 @@ mod tests {
         assert_eq!(0, moveset.len());
     }
 
+    #[test]
+    fn test_castling_squares() {
+        let castles = |fen: &str| -> Vec<String> {
+            let mut game = Game::from_fen(fen).unwrap();
+            game.all_moves()
+                .iter()
+                .filter(|action| action.action_type == ActionType::Castling)
+                .map(|action| action.to_uci())
+                .collect()
+        };
+        // a bishop still on f1, a queen on d1, a knight on b1
+        assert!(castles("4k3/8/8/8/8/8/8/R3KB1R w KQ - 0 1") == vec!["e1c1"]);
+        assert!(castles("4k3/8/8/8/8/8/8/R2QK2R w KQ - 0 1") == vec!["e1g1"]);
+        assert!(castles("4k3/8/8/8/8/8/8/RN2K2R w KQ - 0 1") == vec!["e1g1"]);
+        // f8 attacked by the bishop on c5, the king would pass through it
+        assert!(castles("r3k2r/8/8/2B5/8/8/8/4K3 b kq - 0 1") == vec!["e8c8"]);
+        // b8 may be attacked, only the king's squares have to be safe
+        assert!(castles("r3k2r/8/8/8/8/8/8/1R2K3 b kq - 0 1").len() == 2);
+    }
+
+    // Number of leaf nodes of the legal move tree, every promotion counted
+    // once for each piece
+    fn perft(game: &mut Game, depth: usize) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let mut nodes = 0;
+        for action in game.legal_moves() {
+            game.perform_action(action);
+            nodes += perft(game, depth - 1);
+            game.undo();
+        }
+        nodes
+    }
+
+    fn assert_perft(fen: &str, counts: &[u64]) {
+        let mut game = Game::from_fen(fen).unwrap();
+        for (depth, &count) in counts.iter().enumerate() {
+            assert_eq!(count, perft(&mut game, depth + 1), "{} depth {}", fen, depth + 1);
+        }
+    }
+
+    #[test]
+    fn test_perft_start() {
+        assert_perft("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", &[20, 400, 8902]);
+    }
+
+    #[test]
+    fn test_perft_kiwipete() {
+        assert_perft(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            &[48, 2039, 97862],
+        );
+    }
+
+    #[test]
+    fn test_perft_position_5() {
+        assert_perft("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8", &[44, 1486, 62379]);
+    }
+
+    #[test]
+    fn test_perft_castling() {
+        // both sides castling either way, with rooks that can be captured
+        assert_perft("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", &[26, 568, 13744]);
+        // castling rights lost to captures on the rook squares
+        assert_perft(
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            &[6, 264, 9467],
+        );
+    }
+
     #[test]
     fn test_stalemate() {
         let init_state: &str = "KB XX XX XX XX XX XX XX
@@ -156,6 +267,1964 @@ mod tests {
            }
     }
 
+    fn play(game: &mut Game, from: &str, to: &str) {
+        let from = game::coordinate_from_string(from).unwrap();
+        let to = game::coordinate_from_string(to).unwrap();
+        let action = *game
+            .all_moves()
+            .iter()
+            .find(|a| a.from.coordinate == from && a.to.coordinate == to)
+            .unwrap();
+        game.perform_action(action);
+    }
+
+    #[test]
+    fn test_hash_transposition() {
+        let mut first = Game::new();
+        play(&mut first, "g1", "f3");
+        play(&mut first, "g8", "f6");
+        play(&mut first, "b1", "c3");
+        let mut second = Game::new();
+        play(&mut second, "b1", "c3");
+        play(&mut second, "g8", "f6");
+        play(&mut second, "g1", "f3");
+        assert_eq!(first.hash(), second.hash());
+
+        let mut other_player = first.clone();
+        other_player.player = Team::White;
+        assert_ne!(first.hash(), other_player.hash());
+        assert_ne!(first.hash(), Game::new().hash());
+    }
+
+    #[test]
+    fn test_hash_castling_rights() {
+        let mut game = Game::new();
+        play(&mut game, "g1", "f3");
+        play(&mut game, "g8", "f6");
+        let before = game.hash();
+        play(&mut game, "h1", "g1");
+        play(&mut game, "f6", "g8");
+        play(&mut game, "g1", "h1");
+        play(&mut game, "g8", "f6");
+        assert_eq!([false, true, true, true], game.castling_rights());
+        assert_ne!(before, game.hash());
+
+        // taking the rook move back gives the right back
+        for _ in 0..4 {
+            game.undo();
+        }
+        assert_eq!([true; 4], game.castling_rights());
+        assert_eq!(before, game.hash());
+    }
+
+    #[test]
+    fn test_transposition_table() {
+        let mut game = Game::new();
+        let action = game.all_moves()[0];
+        let tt = TranspositionTable::new(1);
+        tt.store(42, 5, Bound::Lower, Some(&action), 120, 3);
+        let entry = tt.probe(42, 7).unwrap();
+        assert_eq!(5, entry.depth);
+        assert_eq!(Bound::Lower, entry.bound);
+        assert_eq!(120, entry.score);
+        assert!(entry.is_best_move(&action));
+        assert!(tt.probe(43, 0).is_none());
+
+        // mate in 2 found at ply 3 is a mate in 4 from ply 1
+        tt.store(7, 2, Bound::Exact, None, MATE - 5, 3);
+        assert_eq!(MATE - 3, tt.probe(7, 1).unwrap().score);
+
+        tt.clear();
+        assert!(tt.probe(42, 0).is_none());
+        // an entry whose fields are all zero is still found
+        tt.store(9, 0, Bound::Exact, None, 0, 0);
+        assert_eq!(Some(0), tt.probe(9, 0).map(|entry| entry.score));
+    }
+
+    #[test]
+    fn test_static_exchange() {
+        let init_state = "KB XX XX XX XX XX XX XX
+         XX XX XX XX XX XX XX XX
+         XX XX XX XX PB XX XX XX
+         XX XX XX NB XX XX XX XX
+         XX XX PW XX XX XX XX XX
+         XX XX XX XX XX XX XX XX
+         XX XX XX XX XX XX XX XX
+         XX XX XX QW KW XX XX XX";
+        let mut game = Game::game_from_blockstate(init_state);
+        let actions = game.all_moves();
+        let pawn_takes = actions
+            .iter()
+            .find(|a| a.from.coordinate == (2, 3) && a.to.coordinate == (3, 4))
+            .unwrap();
+        let queen_takes = actions
+            .iter()
+            .find(|a| a.from.coordinate == (3, 0) && a.to.coordinate == (3, 4))
+            .unwrap();
+        // the queen behind the pawn wins the recapture back
+        assert_eq!(320, ordering::see(&game, pawn_takes));
+        assert_eq!(320 - 900 + 100, ordering::see(&game, queen_takes));
+    }
+
+    #[test]
+    fn test_move_picker() {
+        let mut game = Game::new();
+        play(&mut game, "e2", "e4");
+        play(&mut game, "d7", "d5");
+        let mut all_moves = game.all_moves();
+        let hash_move = all_moves
+            .iter()
+            .find(|a| a.from.coordinate == (6, 0) && a.to.coordinate == (5, 2))
+            .unwrap()
+            .key();
+        let heuristics = Heuristics::new();
+        let mut picker = MovePicker::new(Some(hash_move), [None; 2], None);
+        let mut picked = vec![];
+        while let Some(action) = picker.next(&mut game, &heuristics) {
+            picked.push(action.key());
+        }
+        assert_eq!(hash_move, picked[0]);
+        // exd5 is the only capture
+        assert_eq!((4, 3), game::coordinate_from_index((picked[1] >> 6) as usize));
+        let mut expected: Vec<u16> = all_moves.drain(..).map(|a| a.key()).collect();
+        expected.sort();
+        picked.sort();
+        assert_eq!(expected, picked);
+    }
+
+    #[test]
+    fn test_search_mate_in_one() {
+        let init_state = "KB XX XX XX XX XX XX XX
+         XX XX XX XX XX XX XX XX
+         XX KW XX XX XX XX XX XX
+         XX XX XX XX XX XX XX XX
+         XX XX XX XX XX XX XX XX
+         XX XX XX XX XX XX XX XX
+         XX XX XX XX XX XX XX XX
+         XX XX XX XX XX XX XX RW";
+        let game = Game::game_from_blockstate(init_state);
+        let tt = TranspositionTable::new(1);
+        let limits = SearchLimits {
+            depth: Some(3),
+            ..SearchLimits::default()
+        };
+        let result = search::search(&game, &limits, &tt);
+        let best = result.best_move.unwrap();
+        assert_eq!((7, 7), best.to.coordinate);
+        assert_eq!(MATE - 1, result.score);
+    }
+
+    #[test]
+    fn test_search_wins_hanging_queen() {
+        let init_state = "XX XX XX XX KB XX XX XX
+         PB PB XX XX XX XX XX XX
+         XX XX XX XX XX XX XX XX
+         XX XX XX QB XX XX XX XX
+         XX XX XX XX XX XX XX XX
+         XX XX NW XX XX XX XX XX
+         PW PW XX XX XX XX XX XX
+         XX XX XX XX KW XX XX XX";
+        let game = Game::game_from_blockstate(init_state);
+        let tt = TranspositionTable::new(1);
+        let limits = SearchLimits {
+            depth: Some(2),
+            ..SearchLimits::default()
+        };
+        let result = search::search(&game, &limits, &tt);
+        assert_eq!((3, 4), result.best_move.unwrap().to.coordinate);
+        assert!(result.score > 200);
+    }
+
+    #[test]
+    fn test_search_repeats_game_positions() {
+        // down a queen, white can go back to a position already on the board
+        let mut game = Game::from_fen("3q3k/8/8/8/8/8/8/R6K w - - 0 1").unwrap();
+        for _ in 0..2 {
+            play(&mut game, "a1", "a2");
+            play(&mut game, "d8", "d7");
+            play(&mut game, "a2", "a1");
+            play(&mut game, "d7", "d8");
+        }
+        play(&mut game, "a1", "a2");
+        play(&mut game, "d8", "d7");
+        let tt = TranspositionTable::new(1);
+        let limits = SearchLimits {
+            depth: Some(3),
+            ..SearchLimits::default()
+        };
+        let result = search::search(&game, &limits, &tt);
+        assert_eq!((0, 0), result.best_move.unwrap().to.coordinate);
+        assert_eq!(0, result.score);
+    }
+
+    #[test]
+    fn test_single_thread_is_deterministic() {
+        let mut game = Game::new();
+        play(&mut game, "e2", "e4");
+        let limits = SearchLimits {
+            depth: Some(3),
+            ..SearchLimits::default()
+        };
+        let mut engine = Engine::default();
+        let first = engine.search(&game, &limits);
+        engine.new_game();
+        let second = engine.search(&game, &limits);
+        assert_eq!(first.nodes, second.nodes);
+        assert_eq!(first.score, second.score);
+        assert_eq!(first.best_move.unwrap().key(), second.best_move.unwrap().key());
+    }
+
+    #[test]
+    fn test_parallel_search() {
+        let init_state = "KB XX XX XX XX XX XX XX
+         XX XX XX XX XX XX XX XX
+         XX KW XX XX XX XX XX XX
+         XX XX XX XX XX XX XX XX
+         XX XX XX XX XX XX XX XX
+         XX XX XX XX XX XX XX XX
+         XX XX XX XX XX XX XX XX
+         XX XX XX XX XX XX XX RW";
+        let game = Game::game_from_blockstate(init_state);
+        let mut engine = Engine::default();
+        engine.set_option("Threads", "4").unwrap();
+        assert_eq!(4, engine.options.threads);
+        assert!(engine.set_option("Threads", "many").is_err());
+        let limits = SearchLimits {
+            depth: Some(3),
+            ..SearchLimits::default()
+        };
+        let result = engine.search(&game, &limits);
+        assert_eq!(MATE - 1, result.score);
+        assert_eq!((7, 7), result.best_move.unwrap().to.coordinate);
+    }
+
+    #[test]
+    fn test_fen() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert_eq!(start, Game::new().to_fen());
+
+        let mut game = Game::new();
+        play(&mut game, "e2", "e4");
+        assert_eq!(
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+            game.to_fen()
+        );
+
+        let fen = "r3k2r/8/8/3pP3/8/8/8/R3K2R w Kq d6 3 20";
+        let mut game = Game::from_fen(fen).unwrap();
+        assert_eq!(fen, game.to_fen());
+        assert_eq!([true, false, false, true], game.castling_rights());
+        let castling: Vec<Action> = game
+            .all_moves()
+            .into_iter()
+            .filter(|a| a.action_type == ActionType::Castling)
+            .collect();
+        assert_eq!(1, castling.len());
+        assert_eq!((6, 0), castling[0].to.coordinate);
+        let en_passant = game.action_from_uci("e5d6").unwrap();
+        assert_eq!(ActionType::Enpassant, en_passant.action_type);
+
+        assert!(Game::from_fen("8/8/8/8/8/8/8/8 w - - 0 1").is_err());
+        assert!(Game::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP w KQkq - 0 1").is_err());
+    }
+
+    #[test]
+    fn test_uci_promotion() {
+        let mut game = Game::from_fen("8/4P1k1/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let action = game.action_from_uci("e7e8n").unwrap();
+        assert_eq!("e7e8n", action.to_uci());
+        game.perform_action(action);
+        assert_eq!(Rank::Knight, game.matrix[4][7].piece.unwrap().rank);
+        assert_eq!(Some(Rank::Knight), game.history[0].promotion);
+        assert!(game.action_from_uci("e1e3").is_err());
+    }
+
+    #[derive(Clone)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuffer {
+        fn new() -> SharedBuffer {
+            SharedBuffer(Arc::new(Mutex::new(vec![])))
+        }
+        fn text(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    #[test]
+    fn test_uci_session() {
+        let buffer = SharedBuffer::new();
+        let mut uci = Uci::new(Box::new(buffer.clone()));
+        assert!(uci.handle("uci"));
+        assert!(uci.handle("setoption name Hash value 2"));
+        assert!(uci.handle("isready"));
+        assert!(uci.handle("position fen k7/8/1K6/8/8/8/8/7R w - - 0 1 moves h1h2 a8b8 h2h1 b8a8"));
+        assert!(uci.handle("go depth 3"));
+        uci.wait_for_search();
+        assert!(uci.handle("go infinite"));
+        assert!(uci.handle("stop"));
+        assert!(!uci.handle("quit"));
+
+        let text = buffer.text();
+        assert!(text.contains("uciok"));
+        assert!(text.contains("readyok"));
+        assert!(text.contains("score mate 1"));
+        assert!(text.contains("bestmove h1h8"));
+        assert_eq!(2, text.matches("bestmove").count());
+        assert!(!text.contains("info string"));
+    }
+
+    #[test]
+    fn test_undo() {
+        let positions = [
+            // capture and castling on both sides
+            "r3k2r/ppp2ppp/2n5/3pp3/4P3/2N5/PPP2PPP/R3K2R w KQkq - 4 9",
+            // en passant
+            "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2",
+            // promotion with and without capture
+            "1n2k3/P7/8/8/8/8/8/4K3 w - - 0 40",
+        ];
+        for fen in positions.iter() {
+            let mut game = Game::from_fen(fen).unwrap();
+            let original_hash = game.hash();
+            for action in game.all_moves() {
+                let mut child = search::play(&game, &action);
+                assert!(child.undo().is_some());
+                assert_eq!(*fen, child.to_fen());
+                assert_eq!(original_hash, child.hash());
+            }
+        }
+
+        let mut game = Game::new();
+        play(&mut game, "e2", "e4");
+        play(&mut game, "e7", "e5");
+        game.undo();
+        game.undo();
+        assert_eq!(Game::new().to_fen(), game.to_fen());
+        assert!(game.undo().is_none());
+    }
+
+    #[test]
+    fn test_xboard_session() {
+        let buffer = SharedBuffer::new();
+        let mut xboard = Xboard::new(Box::new(buffer.clone()));
+        assert!(xboard.handle("xboard"));
+        assert!(xboard.handle("protover 2"));
+        assert!(xboard.handle("new"));
+        assert!(xboard.handle("force"));
+        assert!(xboard.handle("usermove e2e5"));
+        assert!(xboard.handle("usermove e2e4"));
+        assert!(xboard.handle("undo"));
+        assert!(xboard.handle("setboard k7/8/1K6/8/8/8/8/7R w - - 0 1"));
+        assert!(xboard.handle("post"));
+        assert!(xboard.handle("sd 3"));
+        assert!(xboard.handle("go"));
+        xboard.wait_for_search();
+        assert!(xboard.handle("ping 7"));
+        assert!(xboard.handle("undo"));
+        assert!(!xboard.handle("quit"));
+
+        let text = buffer.text();
+        assert!(text.contains("feature ping=1 setboard=1 usermove=1"));
+        assert!(text.contains("done=1"));
+        assert!(text.contains("Illegal move: e2e5"));
+        assert!(text.contains(" 100001 "));
+        assert!(text.contains("move h1h8"));
+        assert!(text.contains("1-0 {White mates}"));
+        assert!(text.contains("pong 7"));
+        assert!(!text.contains("Error"));
+    }
+
+    #[test]
+    fn test_multi_pv() {
+        // white wins the queen, the other lines are far worse
+        let game = Game::from_fen("6k1/5ppp/8/1n1q4/7r/8/Q4P2/4K1R1 w - - 0 1").unwrap();
+        let mut engine = Engine::default();
+        engine.set_option("MultiPV", "3").unwrap();
+        let limits = SearchLimits {
+            depth: Some(3),
+            ..SearchLimits::default()
+        };
+        let mut depths = vec![];
+        let result = engine.search_with_info(&game, &limits, |result| {
+            assert_eq!(3, result.lines.len());
+            depths.push(result.depth);
+        });
+        assert_eq!(vec![1, 2, 3], depths);
+        assert_eq!(3, result.lines.len());
+        assert_eq!(result.best_move.unwrap().key(), result.lines[0].action.key());
+        assert_eq!(result.score, result.lines[0].score);
+        for pair in result.lines.windows(2) {
+            assert!(pair[0].score >= pair[1].score);
+            assert!(pair[0].action.key() != pair[1].action.key());
+        }
+        for line in result.lines.iter() {
+            assert_eq!(line.action.key(), line.pv[0].key());
+        }
+        assert_eq!((3, 4), result.lines[0].action.to.coordinate);
+        assert!(result.lines[0].score > result.lines[1].score + 500);
+
+        // never more lines than legal moves
+        let mut game = Game::from_fen("k7/8/8/8/8/8/8/K7 w - - 0 1").unwrap();
+        engine.set_option("MultiPV", "10").unwrap();
+        let result = engine.search(&game, &limits);
+        assert_eq!(game.all_moves().len(), result.lines.len());
+    }
+
+    #[test]
+    fn test_skill_levels() {
+        assert!(!Skill::from_level(20).enabled());
+        assert!(!Skill::from_elo(3000).enabled());
+        assert_eq!(0.0, Skill::from_elo(100).level());
+        assert_eq!(10.0, Skill::from_elo(1500).level());
+        let limits = Skill::from_level(0).limits(&SearchLimits::default());
+        assert_eq!((Some(1), None), (limits.depth, limits.nodes));
+        let limits = Skill::from_level(10).limits(&SearchLimits {
+            depth: Some(2),
+            ..SearchLimits::default()
+        });
+        assert_eq!((Some(2), Some(160000)), (limits.depth, limits.nodes));
+
+        // white wins the queen, every other move is far worse
+        let game = Game::from_fen("6k1/5ppp/8/1n1q4/7r/8/Q4P2/4K1R1 w - - 0 1").unwrap();
+        let mut engine = Engine::default();
+        engine.set_option("Skill Level", "0").unwrap();
+        let mut reported = vec![];
+        let result = engine.search_with_info(&game, &SearchLimits::default(), |info| {
+            reported.push(info.lines.len())
+        });
+        assert_eq!(1, result.depth);
+        assert_eq!((3, 4), result.best_move.unwrap().to.coordinate);
+        // the lines picked from are searched but only MultiPV ones reported
+        assert_eq!(vec![1], reported);
+        assert_eq!(1, result.lines.len());
+        engine.set_option("MultiPV", "6").unwrap();
+        assert_eq!(6, engine.search(&game, &SearchLimits::default()).lines.len());
+        engine.set_option("MultiPV", &SKILL_LINES.to_string()).unwrap();
+        let result = engine.search(&game, &SearchLimits::default());
+
+        // close moves are picked at random, bad and losing ones never
+        let mut lines = result.lines.clone();
+        for (line, score) in lines.iter_mut().zip([50, 40, -300, -MATE + 5].iter()) {
+            line.score = *score;
+        }
+        let weakest = Skill::from_level(0);
+        let picks: Vec<usize> = (0..100).map(|random| weakest.pick(&lines, random).unwrap()).collect();
+        assert!(picks.contains(&0) && picks.contains(&1));
+        assert!(picks.iter().all(|&pick| pick < 2));
+        assert_eq!(Some(0), Skill::from_level(20).pick(&lines, 12345));
+        assert_eq!(None, weakest.pick(&[], 0));
+
+        engine.set_option("UCI_LimitStrength", "true").unwrap();
+        engine.set_option("UCI_Elo", "1500").unwrap();
+        assert_eq!(10.0, engine.options.skill().level());
+        engine.set_option("UCI_LimitStrength", "false").unwrap();
+        engine.set_option("Skill Level", "20").unwrap();
+        assert!(!engine.options.skill().enabled());
+    }
+
+    #[test]
+    fn test_polyglot_keys() {
+        // the test positions of the polyglot book format
+        let expected: [(&str, u64); 9] = [
+            ("", 0x463b96181691fc9c),
+            ("e2e4", 0x823c9b50fd114196),
+            ("e2e4 d7d5", 0x0756b94461c50fb0),
+            ("e2e4 d7d5 e4e5", 0x662fafb965db29d4),
+            ("e2e4 d7d5 e4e5 f7f5", 0x22a48b5a8e47ff78),
+            ("e2e4 d7d5 e4e5 f7f5 e1e2", 0x652a607ca3f242c1),
+            ("e2e4 d7d5 e4e5 f7f5 e1e2 e8f7", 0x00fdd303c946bdd9),
+            ("a2a4 b7b5 h2h4 b5b4 c2c4", 0x3c8123ea7b067637),
+            ("a2a4 b7b5 h2h4 b5b4 c2c4 b4c3 a1a3", 0x5c3f9b829b279560),
+        ];
+        for (moves, key) in expected.iter() {
+            let mut game = Game::new();
+            for text in moves.split_whitespace() {
+                let action = game.action_from_uci(text).unwrap();
+                game.perform_action(action);
+            }
+            assert_eq!(*key, game.hash(), "after {}", moves);
+        }
+    }
+
+    #[test]
+    fn test_opening_book() {
+        let castling = Game::from_fen("r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq - 0 1").unwrap();
+        let promotion = Game::from_fen("4k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let start_key = Game::new().hash();
+        let entry = |key: u64, raw_move: u16, weight: u16| BookEntry {
+            key,
+            raw_move,
+            weight,
+            learn: 0,
+        };
+        let square = |text: &str| {
+            let bytes = text.as_bytes();
+            ((bytes[1] - b'1') * 8 + (bytes[0] - b'a')) as u16
+        };
+        let raw = |from: &str, to: &str| square(from) << 6 | square(to);
+        let entries = [
+            entry(castling.hash(), raw("e1", "h1"), 3),
+            entry(start_key, raw("d2", "d4"), 5),
+            entry(start_key, raw("e2", "e4"), 10),
+            // not legal in the start position
+            entry(start_key, raw("e2", "e5"), 100),
+            entry(promotion.hash(), 1 << 12 | raw("a7", "a8"), 1),
+        ];
+        let bytes: Vec<u8> = entries.iter().flat_map(|e| e.to_bytes().to_vec()).collect();
+        let path = std::env::temp_dir().join("chess_test_book.bin");
+        std::fs::write(&path, &bytes).unwrap();
+        let book = OpeningBook::open(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(5, book.len());
+        assert!(OpeningBook::from_bytes(&bytes[1..]).is_err());
+
+        let candidates = book.candidates(&Game::new());
+        assert_eq!(2, candidates.len());
+        assert_eq!("e2e4", candidates[0].action.to_uci());
+        assert_eq!("d2d4", candidates[1].action.to_uci());
+        let best = book.select(&Game::new(), Selection::Best).unwrap();
+        assert_eq!("e2e4", best.to_uci());
+        let random = book.select(&Game::new(), Selection::WeightedRandom).unwrap();
+        assert!(["e2e4", "d2d4"].contains(&random.to_uci().as_str()));
+        assert_eq!("e2e4", opening_book::weighted_choice(&candidates, 9).unwrap().to_uci());
+        assert_eq!("d2d4", opening_book::weighted_choice(&candidates, 10).unwrap().to_uci());
+
+        let castle = book.select(&castling, Selection::Best).unwrap();
+        assert_eq!(ActionType::Castling, castle.action_type);
+        assert_eq!("e1g1", castle.to_uci());
+        let promote = book.select(&promotion, Selection::Best).unwrap();
+        assert_eq!("a7a8n", promote.to_uci());
+        let unbooked = Game::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert!(book.select(&unbooked, Selection::Best).is_none());
+
+        // every move survives being written as a polyglot move
+        for game in [castling, promotion, Game::new()].iter_mut() {
+            let legal = game.all_moves();
+            for action in legal.iter() {
+                let decoded =
+                    opening_book::decode_move(&legal, opening_book::encode_move(action)).unwrap();
+                assert_eq!(action.key(), decoded.key());
+                assert_eq!(action.action_type, decoded.action_type);
+            }
+        }
+    }
+
+    #[test]
+    fn test_pgn_collection() {
+        for i in 1..7 {
+            let filepath = format!("pgn_files/PGN{}.txt", i);
+            let games = pgn::read_games(&filepath).unwrap();
+            assert_eq!(1, games.len());
+            let pgn_game = games[0].as_ref().unwrap();
+            assert!(pgn_game.actions.len() > 20);
+
+            // writing every move as SAN and reading it back gives the same game
+            let mut game = Game::new();
+            for action in pgn_game.actions.iter() {
+                let san = pgn::to_san(&game, action);
+                let read = pgn::action_from_san(&mut game, &san).unwrap();
+                assert_eq!(action.key(), read.key(), "{}", san);
+                game.perform_action(read);
+            }
+        }
+
+        let text = "[Event \"Test\"]\n[Result \"0-1\"]\n\n\
+            1. f3 e5 {a comment} 2. g4 (2. e4 Nc6) 2... Qh4# 0-1\n\n\
+            [Event \"Broken\"]\n\n1. e4 e4 *\n\n\
+            [Event \"Promotion\"]\n[FEN \"4k3/P7/8/8/8/8/8/4K3 w - - 0 1\"]\n\n1. a8=N $1 Kd7 *\n";
+        let games = pgn::parse_games(text);
+        assert_eq!(3, games.len());
+        let mate = games[0].as_ref().unwrap();
+        assert_eq!(Some("Test"), mate.tag("Event"));
+        assert_eq!("0-1", mate.result);
+        assert_eq!(Some(0.0), mate.white_score());
+        assert_eq!(4, mate.actions.len());
+        assert!(games[1].is_err());
+        let promotion = games[2].as_ref().unwrap();
+        assert_eq!(Some(Rank::Knight), promotion.actions[0].promotion);
+        assert_eq!(None, promotion.white_score());
+
+        let game = Game::from_fen("4k3/8/8/8/8/8/1R6/R3K3 w Q - 0 1").unwrap();
+        let mut board = game.clone();
+        let mut rook_moves: Vec<String> = board
+            .all_moves()
+            .iter()
+            .filter(|a| a.to.coordinate == (0, 1))
+            .map(|a| pgn::to_san(&game, a))
+            .collect();
+        rook_moves.sort();
+        assert_eq!(vec!["Raa2", "Rba2"], rook_moves);
+    }
+
+    #[test]
+    fn test_book_builder() {
+        let games = [
+            ("1-0", "2400", "2300", "1. e4 e5 2. Nf3 Nc6 1-0"),
+            ("1/2-1/2", "2400", "2300", "1. e4 c5 2. Nf3 d6 1/2-1/2"),
+            ("0-1", "1500", "2300", "1. d4 d5 2. c4 e6 0-1"),
+            ("*", "2400", "2400", "1. c4 *"),
+        ];
+        let text: String = games
+            .iter()
+            .map(|(result, white, black, moves)| {
+                format!(
+                    "[Result \"{}\"]\n[WhiteElo \"{}\"]\n[BlackElo \"{}\"]\n\n{}\n\n",
+                    result, white, black, moves
+                )
+            })
+            .collect();
+        let path = std::env::temp_dir().join("chess_test_games.pgn");
+        std::fs::write(&path, text).unwrap();
+
+        let mut builder = BookBuilder::new(BuilderOptions {
+            max_ply: 2,
+            ..BuilderOptions::default()
+        });
+        // the game without a result is skipped
+        assert_eq!(3, builder.add_pgn_file(path.to_str().unwrap()).unwrap());
+        let mut start = Game::new();
+        let e4 = start.action_from_uci("e2e4").unwrap();
+        let stats = builder.stats(&start, &e4);
+        assert_eq!((2, 1, 1, 0), (stats.games, stats.wins, stats.draws, stats.losses));
+
+        let book_path = std::env::temp_dir().join("chess_test_built_book.bin");
+        builder.write(book_path.to_str().unwrap()).unwrap();
+        let book = OpeningBook::open(book_path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&book_path).unwrap();
+        let candidates = book.candidates(&Game::new());
+        // d4 only lost, so it isn't in the book
+        assert_eq!(1, candidates.len());
+        assert_eq!("e2e4", candidates[0].action.to_uci());
+        assert_eq!(3, candidates[0].weight);
+        let mut after_e4 = Game::new();
+        play(&mut after_e4, "e2", "e4");
+        let replies = book.candidates(&after_e4);
+        // e5 lost, only the draw with c5 scored
+        assert_eq!(1, replies.len());
+        assert_eq!("c7c5", replies[0].action.to_uci());
+        // moves after the ply limit aren't added
+        let mut after_e5 = after_e4.clone();
+        play(&mut after_e5, "e7", "e5");
+        assert!(book.candidates(&after_e5).is_empty());
+
+        let mut builder = BookBuilder::new(BuilderOptions {
+            min_games: 2,
+            min_rating: Some(2000),
+            ..BuilderOptions::default()
+        });
+        builder.add_pgn_file(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let book = builder.to_book();
+        assert_eq!(1, book.len());
+        assert_eq!(1, book.candidates(&Game::new()).len());
+        // black's win counts, white was rated too low
+        let mut after_d4 = Game::new();
+        play(&mut after_d4, "d2", "d4");
+        let d4 = start.action_from_uci("d2d4").unwrap();
+        assert_eq!(0, builder.stats(&Game::new(), &d4).games);
+        let d5 = after_d4.clone().action_from_uci("d7d5").unwrap();
+        assert_eq!(1, builder.stats(&after_d4, &d5).wins);
+    }
+
+    #[test]
+    fn test_tablebase() {
+        // KQvK, KRvK and KPvK tables in the Syzygy format, the DTZ tables of
+        // the first two with white to move and of the last with black to
+        // move, so the other side needs a search. test_dtm_generator checks
+        // the files against the retrograde generator.
+        let mut tablebases = Tablebases::new();
+        let fixtures = std::path::Path::new("syzygy_files");
+        assert_eq!(5, tablebases.add_directory(fixtures).unwrap());
+        assert_eq!(3, tablebases.max_pieces());
+
+        let probe = |fen: &str| tablebases.probe(&Game::from_fen(fen).unwrap());
+        let mate_in_one = "k7/8/1K6/8/8/8/8/2Q5 w - - 0 1";
+        assert_eq!(Some((Wdl::Win, Dtz(1))), probe(mate_in_one));
+        assert_eq!(Some((Wdl::Loss, Dtz(-1))), probe("k6Q/8/1K6/8/8/8/8/8 b - - 0 1"));
+        // the longest queen and rook wins, ten and sixteen moves to mate
+        assert_eq!(Some((Wdl::Win, Dtz(19))), probe("7K/6Q1/8/8/2k5/8/8/8 w - - 0 1"));
+        assert_eq!(Some((Wdl::Loss, Dtz(-20))), probe("8/8/8/8/4k3/8/1Q6/K7 b - - 0 1"));
+        assert_eq!(Some((Wdl::Win, Dtz(31))), probe("7K/6R1/5k2/8/8/8/8/8 w - - 0 1"));
+        assert_eq!(Some((Wdl::Loss, Dtz(-32))), probe("8/8/8/8/8/8/1Rk5/K7 b - - 0 1"));
+        // black as the stronger side
+        assert_eq!(Some((Wdl::Win, Dtz(19))), probe("8/8/8/2K5/8/8/6q1/7k b - - 0 1"));
+        // taking the queen draws
+        assert_eq!(Some((Wdl::Draw, Dtz(0))), probe("k7/1Q6/8/8/8/8/8/7K b - - 0 1"));
+
+        // pawn endings, the king in front of its pawn wins whoever moves
+        assert_eq!(Some((Wdl::Win, Dtz(3))), probe("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1"));
+        assert_eq!(Some((Wdl::Loss, Dtz(-4))), probe("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1"));
+        assert_eq!(Some((Wdl::Win, Dtz(19))), probe("8/8/6k1/8/7K/1P6/8/8 w - - 0 1"));
+        assert_eq!(Some((Wdl::Loss, Dtz(-20))), probe("8/8/8/k7/8/K7/6P1/8 b - - 0 1"));
+        assert_eq!(Some((Wdl::Draw, Dtz(0))), probe("8/8/8/4k3/8/8/4P3/4K3 w - - 0 1"));
+        assert_eq!(Some((Wdl::Draw, Dtz(0))), probe("k7/P7/1K6/8/8/8/8/8 w - - 0 1"));
+        assert_eq!(None, probe("4k3/8/8/8/8/8/8/3RK2R w K - 0 1"));
+        assert_eq!(None, probe("4k3/8/8/8/8/8/8/2NNK3 w - - 0 1"));
+
+        let game = Game::from_fen(mate_in_one).unwrap();
+        assert_eq!(Some(Outcome::Win(Team::White)), tablebases.adjudicate(&game));
+        let moves = tablebases.root_moves(&game).unwrap();
+        assert_eq!("c1c8", moves[0].0.to_uci());
+        assert_eq!((Wdl::Win, Dtz(1)), (moves[0].1, moves[0].2));
+        let stalemate = moves.iter().find(|m| m.0.to_uci() == "c1c7").unwrap();
+        assert_eq!(Wdl::Draw, stalemate.1);
+        assert_eq!(None, Tablebases::new().probe(&game));
+
+        // broken files are not used
+        let directory = std::env::temp_dir().join(format!(
+            "chess_test_tablebase_{}_{:?}",
+            std::process::id(),
+            std::thread::current().id()
+        ));
+        std::fs::create_dir_all(&directory).unwrap();
+        let wdl = std::fs::read(fixtures.join("KQvK.rtbw")).unwrap();
+        std::fs::write(directory.join("KQvK.rtbw"), &wdl[..wdl.len() / 2]).unwrap();
+        let dtz = std::fs::read(fixtures.join("KRvK.rtbz")).unwrap();
+        std::fs::write(directory.join("KRvK.rtbw"), &dtz).unwrap();
+        let mut broken = Tablebases::new();
+        assert_eq!(2, broken.add_directory(&directory).unwrap());
+        assert_eq!(None, broken.probe_wdl(&game));
+        let rook = Game::from_fen("7K/6R1/5k2/8/8/8/8/8 w - - 0 1").unwrap();
+        assert_eq!(None, broken.probe_wdl(&rook));
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    // Places the pieces, (piece, square) with a1 = 0 and h8 = 63, on an
+    // otherwise empty board
+    fn endgame_fen(pieces: &[(char, usize)], black_to_move: bool) -> String {
+        let mut board = [None; 64];
+        for &(piece, square) in pieces {
+            board[square] = Some(piece);
+        }
+        let mut rows = vec![];
+        for rank in (0..8).rev() {
+            let mut row = String::new();
+            let mut empty = 0;
+            for file in 0..8 {
+                match board[rank * 8 + file] {
+                    Some(piece) => {
+                        if empty > 0 {
+                            row.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        row.push(piece);
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                row.push_str(&empty.to_string());
+            }
+            rows.push(row);
+        }
+        let side = if black_to_move { "b" } else { "w" };
+        format!("{} {} - - 0 1", rows.join("/"), side)
+    }
+
+    // Samples the positions of the Syzygy files and compares them with the
+    // independently generated KQvK, KRvK and KPvK distance to mate tables:
+    // the same results, and without pawns the distance to zeroing is the one
+    // to mate
+    fn assert_syzygy_files_match(generator: &dtm::Generator) {
+        let mut tablebases = Tablebases::new();
+        tablebases
+            .add_directory(std::path::Path::new("syzygy_files"))
+            .unwrap();
+        let dtm = |game: &Game| generator.tables().find_map(|table| table.probe(game));
+
+        for &piece in ['Q', 'R', 'P', 'B', 'N'].iter() {
+            let mut checked = 0;
+            for index in (0..64 * 64 * 64 * 2).step_by(397) {
+                let (white_king, black_king) = (index % 64, index / 64 % 64);
+                let (square, black_to_move) = (index / 4096 % 64, index >= 64 * 64 * 64);
+                if white_king == black_king || square == white_king || square == black_king {
+                    continue;
+                }
+                if piece == 'P' && !(8..56).contains(&square) {
+                    continue;
+                }
+                let pieces = [('K', white_king), ('k', black_king), (piece, square)];
+                // the side that just moved can't be in check
+                let mut other = Game::from_fen(&endgame_fen(&pieces, !black_to_move)).unwrap();
+                if matches!(other.get_game_state(), GameState::Check | GameState::Checkmate) {
+                    continue;
+                }
+                let mut game = Game::from_fen(&endgame_fen(&pieces, black_to_move)).unwrap();
+                let (wdl, Dtz(dtz)) = tablebases.probe(&game).unwrap();
+                checked += 1;
+                if piece == 'B' || piece == 'N' {
+                    assert_eq!((Wdl::Draw, 0), (wdl, dtz), "{}", game.to_fen());
+                    continue;
+                }
+                let expected = match dtm(&game).unwrap() {
+                    Dtm::Draw => (Wdl::Draw, 0),
+                    Dtm::Win(moves) => (Wdl::Win, 2 * moves as i32 - 1),
+                    Dtm::Loss(0) => (Wdl::Loss, -1),
+                    Dtm::Loss(moves) => (Wdl::Loss, -2 * moves as i32),
+                };
+                if piece != 'P' {
+                    assert_eq!(expected, (wdl, dtz), "{}", game.to_fen());
+                    continue;
+                }
+                assert_eq!(expected.0, wdl, "{}", game.to_fen());
+                if wdl == Wdl::Win {
+                    // a win is one ply from zeroing when a pawn move keeps it
+                    let pawn_move_wins = game.legal_moves().into_iter().any(|action| {
+                        action.from.piece.unwrap().rank == Rank::Pawn
+                            && matches!(dtm(&search::play(&game, &action)), Some(Dtm::Loss(_)))
+                    });
+                    assert_eq!(pawn_move_wins, dtz == 1, "{}", game.to_fen());
+                }
+            }
+            assert!(checked > 500, "{} {}", piece, checked);
+        }
+    }
+
+    #[test]
+    fn test_dtm_generator() {
+        let mut generator = dtm::Generator::new();
+        let table = generator.generate("KQK").unwrap().clone();
+        assert_eq!("KQvK", table.name());
+        // the longest queen mate is known to take ten moves, the rook and
+        // pawn ones sixteen and twenty eight
+        assert_eq!(10, table.longest_mate());
+
+        let probe = |fen: &str| table.probe(&Game::from_fen(fen).unwrap());
+        assert_eq!(Some(Dtm::Win(1)), probe("k7/8/1K6/8/8/8/8/6Q1 w - - 0 1"));
+        assert_eq!(Some(Dtm::Loss(0)), probe("k6Q/8/1K6/8/8/8/8/8 b - - 0 1"));
+        // the same with the colors swapped
+        assert_eq!(Some(Dtm::Win(1)), probe("6q1/8/8/8/8/1k6/8/K7 b - - 0 1"));
+        // the queen can be taken
+        assert_eq!(Some(Dtm::Draw), probe("k7/1Q6/8/8/8/8/8/7K b - - 0 1"));
+        assert_eq!(Some(Dtm::Draw), probe("k7/2Q5/8/8/8/8/8/1K6 b - - 0 1"));
+        assert_eq!(None, probe("k7/8/8/8/8/8/8/R6K w - - 0 1"));
+
+        let copy = dtm::DtmTable::from_bytes(&table.to_bytes()).unwrap();
+        assert_eq!(table.len(), copy.len());
+        assert!(dtm::DtmTable::from_bytes(&table.to_bytes()[..100]).is_err());
+        assert_eq!(16, generator.generate("KRvK").unwrap().longest_mate());
+        assert_eq!(28, generator.generate("KPvK").unwrap().longest_mate());
+        // pawns on both sides would need en passant
+        assert!(generator.generate("KPvKP").is_err());
+        assert!(generator.generate("KQRvKR").is_err());
+
+        dtm::add_table(copy);
+        let mut game = Game::from_fen("8/8/8/3k4/8/8/8/4K1Q1 w - - 0 1").unwrap();
+        assert!(matches!(dtm::dtm(&game), Some(Dtm::Win(_))));
+        while let Some(Dtm::Win(moves)) = dtm::dtm(&game) {
+            // playing a move that keeps the shortest mate mates in time
+            let best = game
+                .all_moves()
+                .into_iter()
+                .find(|action| {
+                    let mut child = game.clone();
+                    child.perform_action(*action);
+                    dtm::dtm(&child) == Some(Dtm::Loss(moves - 1))
+                })
+                .unwrap();
+            game.perform_action(best);
+            let reply = game.all_moves().into_iter().next();
+            match reply {
+                Some(reply) => game.perform_action(reply),
+                None => break,
+            }
+        }
+        assert_eq!(game::GameState::Checkmate, game.get_game_state());
+
+        assert_syzygy_files_match(&generator);
+    }
+
+    #[test]
+    fn test_unmoves() {
+        let board = |game: &Game| game.to_fen().split(' ').next().unwrap().to_string();
+        let unmoves = |fen: &str| {
+            let game = Game::from_fen(fen).unwrap();
+            let unmoves = game.unmoves();
+            // every predecessor leads back to the position
+            for unmove in unmoves.iter() {
+                let mut before = unmove.game.clone();
+                before.perform_action(unmove.action);
+                assert_eq!(board(&game), board(&before), "{}", unmove.action.to_uci());
+                assert_eq!(game.player, before.player);
+            }
+            unmoves
+        };
+        let count = |fen: &str, action_type: ActionType| {
+            unmoves(fen)
+                .iter()
+                .filter(|unmove| unmove.action.action_type == action_type)
+                .count()
+        };
+
+        // after a double step only the double step can be taken back
+        let after_e4 = unmoves("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1");
+        assert_eq!(1, after_e4.len());
+        assert_eq!("e2e4", after_e4[0].action.to_uci());
+        assert_eq!(Game::new().to_fen(), after_e4[0].game.to_fen());
+
+        // d7-d8 and c7xd8 or e7xd8 taking any of four pieces
+        assert_eq!(9, count("3Q4/8/8/8/8/8/8/k6K b - - 0 1", ActionType::Promotion));
+        // c5xd6 or e5xd6 en passant
+        let en_passant: Vec<Unmove> = unmoves("8/8/3P4/8/8/8/8/k6K b - - 0 1")
+            .into_iter()
+            .filter(|unmove| unmove.action.action_type == ActionType::Enpassant)
+            .collect();
+        assert_eq!(2, en_passant.len());
+        assert_eq!("8/8/8/2Pp4/8/8/8/k6K w - d6 0 1", en_passant[0].game.to_fen());
+        // not with the pawn's way back blocked
+        assert_eq!(0, count("8/3n4/3P4/8/8/8/8/k6K b - - 0 1", ActionType::Enpassant));
+
+        let castled = unmoves("8/8/8/8/8/8/8/k4RK1 b - - 3 20");
+        let castling: Vec<&Unmove> = castled
+            .iter()
+            .filter(|unmove| unmove.action.action_type == ActionType::Castling)
+            .collect();
+        assert_eq!(1, castling.len());
+        assert_eq!("8/8/8/8/8/8/8/k3K2R w K - 2 20", castling[0].game.to_fen());
+        // the king can't castle out of check
+        assert_eq!(0, count("4r3/8/8/8/8/8/8/k4RK1 b - - 0 1", ActionType::Castling));
+
+        // moving the king or a rook off its square can have given up rights
+        let rights_before = |fen: &str, uci: &str, taken: Option<Rank>| {
+            let mut rights: Vec<String> = unmoves(fen)
+                .iter()
+                .filter(|unmove| unmove.action.to_uci() == uci)
+                .filter(|unmove| unmove.action.to.piece.map(|piece| piece.rank) == taken)
+                .map(|unmove| unmove.game.to_fen().split(' ').nth(2).unwrap().to_string())
+                .collect();
+            rights.sort();
+            rights
+        };
+        assert_eq!(vec!["-", "K", "KQ", "Q"], rights_before("4k3/8/8/8/8/8/8/R4K1R b - - 1 1", "e1f1", None));
+        assert_eq!(vec!["KQ", "Q"], rights_before("4k3/8/8/8/8/8/8/R3K1R1 b Q - 1 1", "h1g1", None));
+        // or taking a rook on its corner, here from the rook's own corner
+        let rook = Some(Rank::Rook);
+        assert_eq!(vec!["-", "K", "Kk", "k"], rights_before("4k3/8/8/8/8/8/8/4K2r w - - 0 1", "h8h1", rook));
+        // the other side can have made a double step just before
+        let before_king_move: Vec<String> = unmoves("4k3/8/8/8/4P3/8/8/4K3 w - - 2 5")
+            .iter()
+            .map(|unmove| unmove.game.to_fen())
+            .collect();
+        assert!(before_king_move.contains(&String::from("3k4/8/8/8/4P3/8/8/4K3 b - - 1 4")));
+        assert!(before_king_move.contains(&String::from("3k4/8/8/8/4P3/8/8/4K3 b - e3 0 4")));
+
+        // the king and rook with a castling right haven't moved, the knight
+        // came from c3 or e3 and a bishop can only have been taken on d1
+        let rights = unmoves("4k3/8/8/8/8/8/8/R2NK2R b KQ - 0 1");
+        assert!(rights.iter().all(|unmove| unmove.action.from.piece.unwrap().rank == Rank::Knight));
+        assert!(rights.iter().any(|unmove| unmove.action.to_uci() == "e3d1"
+            && unmove.action.to.piece.map(|piece| piece.rank) == Some(Rank::Bishop)));
+        assert!(rights.iter().all(|unmove| unmove.action.to.piece.map(|piece| piece.rank) != Some(Rank::Pawn)));
+
+        // black's pieces are all on the board, nothing of theirs was taken
+        assert!(unmoves("rnbqkbnr/pppppppp/8/8/8/4N3/PPPPPPPP/R1BQKBNR b KQkq - 1 1")
+            .iter()
+            .all(|unmove| unmove.action.to.piece.is_none()));
+        // the side that just moved can't be in check
+        assert!(unmoves("4k3/8/8/8/8/8/8/4K2r b - - 0 1").is_empty());
+
+        // the positions of a game are among the predecessors of the next one
+        let mut game = Game::new();
+        for uci in "e2e4 d7d5 e4d5 d8d5 b1c3 d5a5 d2d4 c7c6 g1f3 c8g4 f1e2 e7e6 e1g1".split(' ') {
+            let before = board(&game);
+            let action = game.action_from_uci(uci).unwrap();
+            game.perform_action(action);
+            let position = Game::from_fen(&game.to_fen()).unwrap();
+            assert!(position.unmoves().iter().any(|unmove| board(&unmove.game) == before), "{}", uci);
+        }
+    }
+
+    #[test]
+    fn test_solver() {
+        let uci = |line: &[Action]| line.iter().map(|a| a.to_uci()).collect::<Vec<String>>();
+        let mated = |fen: &str, line: &[Action]| {
+            let mut game = Game::from_fen(fen).unwrap();
+            for action in line {
+                game.perform_action(*action);
+            }
+            game.get_game_state() == GameState::Checkmate
+        };
+
+        // Qg8 is the only mate, the king blocks the way to a7
+        let fen = "k7/8/1K6/8/8/8/8/6Q1 w - - 0 1";
+        let game = Game::from_fen(fen).unwrap();
+        let line = solver::find_mate(&game, 3).unwrap();
+        assert_eq!(1, line.len());
+        assert!(mated(fen, &line));
+        let solutions = solver::solutions(&game, Stipulation::Mate, 1);
+        assert_eq!(1, solutions.len());
+        assert_eq!(vec!["g1g8"], uci(&solutions[0]));
+
+        // Kc6 and Rb1 against Ka8: there's no mate in one and Kb6 stalemates
+        let fen = "k7/8/2K5/8/8/8/8/1R6 w - - 0 1";
+        let game = Game::from_fen(fen).unwrap();
+        assert!(solver::find_mate(&game, 1).is_none());
+        let line = solver::find_mate(&game, 2).unwrap();
+        assert_eq!(3, line.len());
+        assert!(mated(fen, &line));
+        assert!(solver::solutions(&game, Stipulation::Mate, 2)
+            .iter()
+            .all(|line| line[0].to_uci() != "c6b6"));
+
+        // black's only move is Kb8, after which white mates
+        let fen = "k7/8/1K6/8/8/8/8/6Q1 b - - 0 1";
+        let game = Game::from_fen(fen).unwrap();
+        let line = solver::solve(&game, Stipulation::Helpmate, 1).unwrap();
+        assert_eq!("a8b8", line[0].to_uci());
+        assert!(mated(fen, &line));
+        assert_eq!(1, solver::solutions(&game, Stipulation::Helpmate, 1).len());
+
+        // Rf2+ Kxf2 uncovers the black rook's mate
+        let fen = "8/8/8/8/8/8/R5PP/4rk1K w - - 0 1";
+        let game = Game::from_fen(fen).unwrap();
+        let solutions = solver::solutions(&game, Stipulation::Selfmate, 1);
+        assert_eq!(1, solutions.len());
+        assert_eq!(vec!["a2f2", "f1f2"], uci(&solutions[0]));
+        assert!(mated(fen, &solutions[0]));
+        assert!(solver::solve(&game, Stipulation::Mate, 1).is_none());
+
+        // underpromoting to a knight is the only mate
+        let fen = "5rbq/4P1pk/6pp/8/8/8/8/K7 w - - 0 1";
+        let line = solver::find_mate(&Game::from_fen(fen).unwrap(), 1).unwrap();
+        assert_eq!("e7f8n", line[0].to_uci());
+    }
+
+    #[test]
+    fn test_tournament() {
+        let tc = TimeControl::parse("40/60+0.5").unwrap();
+        assert_eq!(Some(40), tc.moves);
+        assert_eq!(Duration::from_millis(500), tc.increment);
+        assert_eq!("40/60+0.5", tc.to_string());
+        assert_eq!("10", TimeControl::parse("10").unwrap().to_string());
+        assert!(TimeControl::parse("x/10").is_err());
+
+        let even = Standings { wins: 30, draws: 40, losses: 30 };
+        let (elo, error) = even.elo().unwrap();
+        assert!(elo.abs() < 1e-9 && error > 10.0 && error < 100.0);
+        let (elo, _) = Standings { wins: 60, draws: 20, losses: 20 }.elo().unwrap();
+        assert!((elo - 147.2).abs() < 0.1);
+        assert_eq!(None, Standings { wins: 3, draws: 0, losses: 0 }.elo());
+
+        let sprt = Sprt::new(0.0, 10.0);
+        assert_eq!(SprtVerdict::Continue, sprt.verdict(10, 10, 10));
+        assert_eq!(SprtVerdict::AcceptH1, sprt.verdict(600, 800, 400));
+        assert_eq!(SprtVerdict::AcceptH0, sprt.verdict(400, 800, 600));
+        assert!(sprt.llr(5, 0, 0) > 0.0);
+
+        let openings = tournament::parse_positions(
+            "# comment\n\n4k3/8/8/8/8/8/4P3/4K3 w - - 0 1\n\
+             rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 bm d5; id \"e4\";\n",
+        )
+        .unwrap();
+        assert_eq!(2, openings.len());
+        assert_eq!(Some("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3"), openings[1].fen.as_deref());
+        assert!(tournament::parse_positions("8/8/8 w - -").is_err());
+        assert!(tournament::insufficient_material(&Game::from_fen("4k3/8/8/8/8/8/4N3/4K3 w - - 0 1").unwrap()));
+        assert!(!tournament::insufficient_material(&Game::from_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").unwrap()));
+
+        let player = |name: &str| Player {
+            name: String::from(name),
+            command: vec![],
+            options: vec![(String::from("Hash"), String::from("1"))],
+            limits: SearchLimits { depth: Some(1), ..SearchLimits::default() },
+        };
+        let options = TournamentOptions {
+            games: 2,
+            max_moves: Some(4),
+            event: String::from("test"),
+            ..TournamentOptions::default()
+        };
+        let openings = tournament::parse_positions("k7/8/1K6/8/8/8/8/6Q1 w - - 0 1").unwrap();
+        let tournament = Tournament::new([player("one"), player("two")], openings, options);
+        let mut records = vec![];
+        let standings = tournament.run(|record, _| records.push(record.clone())).unwrap();
+        // the same opening with the colors swapped, mated the first move
+        assert_eq!(2, standings.games());
+        assert_eq!((1, 1), (standings.wins, standings.losses));
+        assert_eq!(vec![0, 1], records.iter().map(|r| r.white).collect::<Vec<usize>>());
+        assert_eq!(Some("two"), records[1].pgn.tag("White"));
+        assert_eq!(Outcome::Win(Team::White), records[0].outcome);
+
+        let text = records[0].pgn.to_pgn().unwrap();
+        assert!(text.contains("[FEN \"k7/8/1K6/8/8/8/8/6Q1 w - - 0 1\"]"));
+        assert!(text.contains("1. Qg8# 1-0"));
+        let read = pgn::parse_games(&text).remove(0).unwrap();
+        assert_eq!("1-0", read.result);
+        assert_eq!(records[0].pgn.actions.len(), read.actions.len());
+
+        // one move each and the game is adjudicated a draw
+        let openings = tournament::parse_positions("4k3/8/8/8/8/8/8/R3K3 w - - 0 1").unwrap();
+        let options = TournamentOptions {
+            games: 1,
+            max_moves: Some(1),
+            ..TournamentOptions::default()
+        };
+        let tournament = Tournament::new([player("one"), player("two")], openings, options);
+        let mut termination = String::new();
+        let standings = tournament.run(|record, _| termination = record.termination.clone()).unwrap();
+        assert_eq!(1, standings.draws);
+        assert_eq!("adjudication", termination);
+    }
+
+    #[test]
+    fn test_uci_client() {
+        let script = "while read -r line; do
+  case \"$line\" in
+    uci) echo 'id name Fake Engine'; echo 'id author Tests';
+      echo 'option name Hash type spin default 1 min 1 max 16'; echo uciok ;;
+    isready) echo readyok ;;
+    'go depth 1') echo 'info depth 1 multipv 1 score cp 30 nodes 20 pv e7e5 g1f3';
+      echo 'info depth 1 multipv 2 score mate -2 nodes 20 pv a7a6 zzzz';
+      echo 'bestmove e7e5 ponder g1f3' ;;
+    'go depth 2') echo 'info string e2e4 looks good'; echo 'bestmove e2e4' ;;
+    'go nodes 1') mode=silent ;;
+    'go nodes 2') mode=late ;;
+    stop) if [ \"$mode\" = late ]; then echo 'bestmove a7a6'; fi ;;
+    quit) exit 0 ;;
+  esac
+done
+";
+        let path = std::env::temp_dir().join("chess_test_fake_engine.sh");
+        std::fs::write(&path, script).unwrap();
+        assert!(UciClient::spawn("chess-test-no-such-engine", &[]).is_err());
+        let mut client = UciClient::spawn("sh", &[path.to_str().unwrap()]).unwrap();
+        assert_eq!("Fake Engine", client.name);
+        assert_eq!("Tests", client.author);
+        assert_eq!(1, client.options.len());
+        client.set_option("Hash", "4").unwrap();
+        client.new_game().unwrap();
+
+        let mut game = Game::new();
+        let action = game.action_from_uci("e2e4").unwrap();
+        game.perform_action(action);
+        assert_eq!("position startpos moves e2e4", uci_client::position_command(&game));
+        let mut endgame = Game::from_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").unwrap();
+        assert_eq!("position fen 4k3/8/8/8/8/8/4P3/4K3 w - - 0 1", uci_client::position_command(&endgame));
+        let action = endgame.action_from_uci("e2e4").unwrap();
+        endgame.perform_action(action);
+        assert_eq!(
+            "position fen 4k3/8/8/8/8/8/4P3/4K3 w - - 0 1 moves e2e4",
+            uci_client::position_command(&endgame)
+        );
+
+        let go = |depth: Option<i32>, nodes: Option<u64>| GoOptions {
+            limits: SearchLimits { depth, nodes, ..SearchLimits::default() },
+            ..GoOptions::default()
+        };
+        let second = Duration::from_secs(1);
+        let result = client.go(&game, &go(Some(1), None), second * 5).unwrap();
+        assert_eq!("e7e5", result.best_move.unwrap().to_uci());
+        assert_eq!((1, 30, 20), (result.depth, result.score, result.nodes));
+        assert_eq!(vec!["e7e5", "g1f3"], result.pv.iter().map(|a| a.to_uci()).collect::<Vec<String>>());
+        assert_eq!(2, result.lines.len());
+        // the illegal move ends the pv
+        assert_eq!(1, result.lines[1].pv.len());
+        assert_eq!(-MATE + 4, result.lines[1].score);
+
+        let error = client.go(&game, &go(Some(2), None), second * 5).unwrap_err();
+        assert!(error.contains("illegal move e2e4"));
+        // an engine answering the stop sent after the timeout is fine
+        let result = client.go(&game, &go(None, Some(2)), Duration::from_millis(50)).unwrap();
+        assert_eq!("a7a6", result.best_move.unwrap().to_uci());
+        assert!(client.go(&game, &go(None, Some(1)), Duration::from_millis(50)).is_err());
+
+        let options = GoOptions {
+            time: [Some(second * 60), Some(second * 30)],
+            increment: [Some(second), None],
+            movestogo: Some(20),
+            ..GoOptions::default()
+        };
+        assert_eq!("go wtime 60000 btime 30000 winc 1000 movestogo 20", uci_client::go_command(&options));
+        assert_eq!("go infinite", uci_client::go_command(&GoOptions::default()));
+        for score in [35, -120, MATE - 1, MATE - 5, -MATE + 2, -MATE + 6].iter() {
+            let text = crate::uci::score_to_uci(*score);
+            let fields: Vec<&str> = text.split(' ').collect();
+            assert_eq!(Some(*score), uci_client::score_from_uci(fields[0], fields[1].parse().unwrap()));
+        }
+    }
+
+    #[test]
+    fn test_tuner() {
+        let weights: Vec<f64> = DEFAULT_WEIGHTS.to_vec().iter().map(|&w| w as f64).collect();
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r1bqk2r/ppp2ppp/2n2n2/3pp3/1bPP4/2N1PN2/PP3PPP/R1BQKB1R b KQkq - 0 1",
+            "8/5k2/8/3B4/8/2B5/4K3/8 w - - 0 1",
+            "6k1/5ppp/8/8/8/8/1q3PPP/6K1 b - - 0 1",
+        ]
+        .iter()
+        {
+            let game = Game::from_fen(fen).unwrap();
+            let sum: f64 = eval::features(&game).iter().map(|&(i, c)| weights[i] * c).sum();
+            let white_score = if game.player == Team::White { eval::evaluate(&game) } else { -eval::evaluate(&game) };
+            assert!((sum - white_score as f64).abs() < 2.0, "{}: {} {}", fen, sum, white_score);
+        }
+        assert_eq!(Ok(DEFAULT_WEIGHTS), Weights::parse(&DEFAULT_WEIGHTS.to_string()));
+        // the written weights can replace the ones in the source
+        assert!(include_str!("eval.rs").contains(&DEFAULT_WEIGHTS.to_string()));
+        assert!(Weights::from_slice(&[1, 2, 3]).is_err());
+
+        let game = &pgn::parse_games("1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 4. Bxc6 dxc6 1-0")[0];
+        let positions = tuner::extract_positions(game.as_ref().unwrap(), 0);
+        // every position but the one after Bxc6, where the bishop hangs
+        assert_eq!(7, positions.len());
+        assert!(positions.iter().all(|p| p.result == 1.0));
+        assert_eq!(4, tuner::extract_positions(game.as_ref().unwrap(), 3).len());
+        let unfinished = &pgn::parse_games("1. e4 e5 *")[0];
+        assert!(tuner::extract_positions(unfinished.as_ref().unwrap(), 0).is_empty());
+
+        // an extra knight always wins, so the knight is worth more
+        let mut positions = vec![];
+        for _ in 0..20 {
+            positions.push(TuningPosition { features: vec![(1, 1.0)], result: 1.0 });
+            positions.push(TuningPosition { features: vec![(1, -1.0)], result: 0.0 });
+        }
+        let k = tuner::optimal_k(&tuner::extract_positions(game.as_ref().unwrap(), 0), &weights, 2);
+        assert!(k > 0.0 && k < 4.0);
+        for method in [Method::GradientDescent, Method::LocalSearch].iter() {
+            let options = TunerOptions { method: *method, iterations: 10, threads: 3, k: Some(1.0), ..TunerOptions::default() };
+            let mut errors = vec![];
+            let tuned = tuner::tune(&positions, &DEFAULT_WEIGHTS, &options, |_, error| errors.push(error));
+            assert_eq!(10, errors.len());
+            assert!(errors[9] < tuner::error(&positions, &weights, 1.0, 1));
+            assert!(tuned.piece_values[1] >= 325);
+            let mut values = tuned.to_vec();
+            values[1] = 320;
+            assert_eq!(DEFAULT_WEIGHTS, Weights::from_slice(&values).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_nnue() {
+        let network = Network::new(16, 8, 42);
+        let bytes = network.to_bytes();
+        assert_eq!(Ok(network.clone()), Network::from_bytes(&bytes));
+        assert!(Network::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Network::from_bytes(b"not a network at all").is_err());
+
+        // the accumulator updated move by move is the one summed from scratch,
+        // through en passant, castling, a promotion and king moves
+        let mut game = Game::from_fen("r3k2r/1P6/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1").unwrap();
+        let mut accumulator = network.refresh(&game);
+        for text in ["e5d6", "e8g8", "b7b8q", "g8g7", "e1c1", "f8b8", "c1c2"].iter() {
+            let action = game.action_from_uci(text).unwrap();
+            let mut child = game.clone();
+            child.perform_action(action);
+            let mut updated = accumulator.clone();
+            network.update(&accumulator, &game, &child, &mut updated);
+            assert_eq!(network.refresh(&child), updated, "{}", text);
+            assert_eq!(network.evaluate_game(&child), network.evaluate(&updated, child.player));
+            accumulator = updated;
+            game = child;
+        }
+        // both sides see the board the same way
+        let position = Game::from_fen("4k3/pp3ppp/2n5/8/3P4/5N2/PP3PPP/4K3 w - - 0 1").unwrap();
+        let mirrored = Game::from_fen("4k3/pp3ppp/5n2/3p4/8/2N5/PP3PPP/4K3 b - - 0 1").unwrap();
+        assert_eq!(network.evaluate_game(&position), network.evaluate_game(&mirrored));
+
+        let path = std::env::temp_dir().join("chess_test_network.nnue");
+        let path = path.to_str().unwrap();
+        network.write(path).unwrap();
+        let mut engine = Engine::default();
+        assert!(engine.set_option("EvalFile", "/no/such/network.nnue").is_err());
+        engine.set_option("EvalFile", path).unwrap();
+        let limits = SearchLimits { depth: Some(3), ..SearchLimits::default() };
+        let mut start = Game::new();
+        let result = engine.search(&start, &limits);
+        let best = result.best_move.unwrap();
+        assert!(start.all_moves().iter().any(|a| a.key() == best.key()));
+        assert_eq!(3, result.depth);
+        engine.set_option("EvalFile", "<empty>").unwrap();
+        assert_eq!("", engine.options.eval_file);
+    }
+
+    #[test]
+    fn test_datagen() {
+        let opening = datagen::random_opening(6, 7);
+        assert_eq!(6, opening.actions.len());
+        assert_eq!(
+            opening.actions.iter().map(|a| a.key()).collect::<Vec<u16>>(),
+            datagen::random_opening(6, 7).actions.iter().map(|a| a.key()).collect::<Vec<u16>>()
+        );
+
+        let player = Player {
+            limits: SearchLimits { depth: Some(1), ..SearchLimits::default() },
+            ..Player::default()
+        };
+        let mut options = DatagenOptions { random_plies: 4, ..DatagenOptions::default() };
+        options.game.max_moves = Some(12);
+        let mut datagen = Datagen::new(&player, options).unwrap();
+        let mut records = vec![];
+        for seed in 1..4 {
+            records.extend(datagen.play_game(seed).unwrap());
+        }
+        assert!(!records.is_empty());
+        for record in records.iter() {
+            let mut game = Game::from_fen(&record.fen).unwrap();
+            assert_eq!(GameState::Active, game.get_game_state());
+            assert!([0.0, 0.5, 1.0].contains(&record.result));
+            let line = record.to_string();
+            assert_eq!(3, line.split(" | ").count());
+            assert!(line.starts_with(&record.fen));
+        }
+    }
+
+    #[test]
+    fn test_pgn_annotations() {
+        let text = "[Event \"annotated\"]\n\n{a comment before the moves} 1. e4 e5!? $10 \
+                    {solid} (1... c5 2. Nf3 (2. Nc3 {closed}) 2... d6) (1... e6?? 2. d4) \
+                    2. Nf3 ; to the end of the line\n2... Nc6 (2... Bb4 3. Kd8) 3. Bb5 1-0\n";
+        let game = pgn::parse_games(text).remove(0).unwrap();
+        assert_eq!(5, game.actions.len());
+        assert_eq!("1-0", game.result);
+        assert!(game.annotations[0].comments.is_empty());
+        let e5 = &game.annotations[1];
+        assert_eq!((vec![5, 10], vec![String::from("solid")]), (e5.nags.clone(), e5.comments.clone()));
+        assert_eq!(2, e5.variations.len());
+        let sicilian = &e5.variations[0];
+        assert_eq!(3, sicilian.actions.len());
+        assert_eq!("c7c5", sicilian.actions[0].to_uci());
+        assert_eq!("b1c3", sicilian.annotations[1].variations[0].actions[0].to_uci());
+        assert_eq!(vec![4], e5.variations[1].annotations[0].nags);
+        assert_eq!(vec![String::from("to the end of the line")], game.annotations[2].comments);
+        // an illegal variation is left out, the main line goes on
+        assert!(game.annotations[3].variations.is_empty());
+        assert!(pgn::parse_games("1. e4 (1. d4 e5 2. Ke8) e5 *").remove(0).unwrap().annotations[0].variations.is_empty());
+        assert!(pgn::parse_games("1. e4 e5 ) 2. Nf3 *").remove(0).is_err());
+        let unterminated = pgn::parse_games("1. e4 (1. d4 *").remove(0).unwrap();
+        assert_eq!((1, 0), (unterminated.actions.len(), unterminated.annotations[0].variations.len()));
+
+        let written = game.to_pgn().unwrap();
+        assert_eq!(
+            "[Event \"annotated\"]\n\n1. e4 e5 $5 $10 {solid} (1... c5 2. Nf3 (2. Nc3 {closed}) 2... d6) (1... e6 $4\n\
+             2. d4) 2. Nf3 {to the end of the line} 2... Nc6 3. Bb5 1-0\n\n",
+            written
+        );
+        let again = pgn::parse_games(&written).remove(0).unwrap();
+        assert_eq!(written, again.to_pgn().unwrap());
+
+        // a long comment is wrapped between its words
+        let mut game = pgn::parse_games("1. e4 *").remove(0).unwrap();
+        game.annotation_mut(0).comments.push("word } ".repeat(20));
+        let written = game.to_pgn().unwrap();
+        assert!(written.lines().all(|line| line.len() <= 80));
+        assert!(written.contains("{word ) word"));
+
+        // the analysis of a game written into it
+        let mut game = Game::new();
+        for uci in ["e2e4", "e7e5", "f1c4", "b8c6", "d1h5", "g8f6", "h5f7"] {
+            let action = game.action_from_uci(uci).unwrap();
+            game.perform_action(action);
+        }
+        let engine = Engine::default();
+        let limits = SearchLimits {
+            depth: Some(3),
+            ..SearchLimits::default()
+        };
+        let review = GameReport::from_game(&engine, &game, &limits, |_| {});
+        let annotated = review.pgn_game();
+        assert_eq!(Some("1-0"), annotated.tag("Result"));
+        assert_eq!(None, annotated.tag("FEN"));
+        let blunder = &annotated.annotations[5];
+        assert_eq!(vec![4], blunder.nags);
+        assert_eq!(vec![String::from("[%eval #1]")], blunder.comments);
+        assert_eq!(review.moves[5].pv.len(), blunder.variations[0].actions.len());
+        assert!(annotated.annotations[6].comments.is_empty());
+        let text = annotated.to_pgn().unwrap();
+        assert!(text.replace('\n', " ").contains("3... Nf6 $4 {[%eval #1]} (3..."));
+        assert!(text.contains("{[%eval "));
+        let reread = pgn::parse_games(&text).remove(0).unwrap();
+        assert_eq!(text, reread.to_pgn().unwrap());
+    }
+
+    #[test]
+    fn test_game_report() {
+        let classes: Vec<Classification> = [0, 5, 30, 80, 200, 301]
+            .iter()
+            .map(|&loss| Classification::from_loss(loss, false))
+            .collect();
+        assert_eq!(report::CLASSIFICATIONS.to_vec(), classes);
+        assert_eq!(Classification::Best, Classification::from_loss(500, true));
+        assert!((report::win_chance(0) - 50.0).abs() < 1e-9);
+        assert!(report::win_chance(300) > 70.0);
+        assert!(report::move_accuracy(50, 50) > 99.9);
+        assert!(report::move_accuracy(0, -300) < report::move_accuracy(0, -100));
+        // a lost position stays lost, whatever is given up on top
+        assert!(report::move_accuracy(-2000, -5000) > 99.9);
+
+        let mut game = Game::new();
+        for uci in ["e2e4", "e7e5", "f1c4", "b8c6", "d1h5", "g8f6", "h5f7"] {
+            let action = game.action_from_uci(uci).unwrap();
+            game.perform_action(action);
+        }
+        let engine = Engine::default();
+        let limits = SearchLimits {
+            depth: Some(3),
+            ..SearchLimits::default()
+        };
+        let mut seen = 0;
+        let review = GameReport::from_game(&engine, &game, &limits, |_| seen += 1);
+        assert_eq!(7, seen);
+        assert_eq!("e4", review.moves[0].san);
+        assert_eq!(Team::Black, review.moves[5].player);
+        assert_eq!(3, review.moves[5].fullmove);
+        let blunder = &review.moves[5];
+        assert_eq!(Classification::Blunder, blunder.classification);
+        assert!(blunder.loss > 300 && blunder.best_san != "Nf6");
+        assert!(!blunder.pv.is_empty());
+        let mate = &review.moves[6];
+        assert_eq!(("Qxf7#", Classification::Best, 0), (mate.san.as_str(), mate.classification, mate.loss));
+        assert_eq!(1, review.player(Team::Black).count(Classification::Blunder));
+        assert_eq!(3, review.player(Team::Black).moves);
+        assert!(review.player(Team::White).accuracy > review.player(Team::Black).accuracy);
+        let text = review.to_string();
+        assert!(text.contains("4. Qxf7# mate (best)"));
+        assert!(text.contains("Black: accuracy"));
+
+        // a pgn game from a fen, with the names of its players
+        let pgn = "[White \"Ann\"]\n[Black \"Ben\"]\n[FEN \"6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1\"]\n\n\
+                   1. Ra8# 1-0\n";
+        let parsed = pgn::parse_games(pgn).remove(0).unwrap();
+        let review = GameReport::from_pgn(&engine, &parsed, &limits, |_| {}).unwrap();
+        assert_eq!(["Ann", "Ben"], [review.names[0].as_str(), review.names[1].as_str()]);
+        assert_eq!(Classification::Best, review.moves[0].classification);
+        assert_eq!(100.0, review.players[0].accuracy);
+        assert_eq!(0, review.players[1].moves);
+    }
+
+    #[test]
+    fn test_epd() {
+        let line = "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - \
+                    bm Qxf7#; am Qxe5+ Qh3;  id \"mate; in one\"; c1 \"\"; hmvc 4; fmvn 4;";
+        let record = Epd::parse(line).unwrap();
+        assert_eq!("mate; in one", record.id());
+        assert_eq!(Some(&[String::from("Qxe5+"), String::from("Qh3")][..]), record.operands("am"));
+        assert_eq!(Some(""), record.operand("c1"));
+        let game = record.position().unwrap();
+        assert_eq!(
+            "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4",
+            game.to_fen()
+        );
+        let best: Vec<String> = record.moves("bm").unwrap().iter().map(|a| a.to_uci()).collect();
+        assert_eq!(vec!["h5f7"], best);
+        assert_eq!(2, record.moves("am").unwrap().len());
+        assert!(record.moves("pm").unwrap().is_empty());
+        // written back the same, operations in order and strings quoted
+        let written = record.to_string();
+        assert_eq!(
+            "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - bm Qxf7#; \
+             am Qxe5+ Qh3; id \"mate; in one\"; c1 \"\"; hmvc 4; fmvn 4;",
+            written
+        );
+        assert_eq!(record, Epd::parse(&written).unwrap());
+
+        for bad in [
+            "8/8/8/8/8/8/8/8 w",
+            "4k3/8/8/8/8/8/8/4K3 w - - bm Kd2",
+            "4k3/8/8/8/8/8/8/4K3 w - - id \"open;",
+            "4k3/8/8/8/8/8/8/4K3 x - - id \"a\";",
+        ] {
+            assert!(Epd::parse(bad).is_err(), "{}", bad);
+        }
+        let records = epd::parse_epd("# suite\n\n4k3/8/8/8/8/8/8/4K3 w - -\nnonsense\n");
+        assert_eq!(2, records.len());
+        assert!(records[0].as_ref().unwrap().operations.is_empty());
+        assert!(records[1].as_ref().unwrap_err().starts_with("line 4:"));
+
+        // positions and analysis go out as epd
+        let mut record = Epd::from_game(&Game::new());
+        assert_eq!(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - hmvc 0; fmvn 1;",
+            record.to_string()
+        );
+        let engine = Engine::default();
+        let limits = SearchLimits {
+            depth: Some(2),
+            ..SearchLimits::default()
+        };
+        let result = engine.search(&game, &limits);
+        let mut analysed = Epd::parse(line).unwrap();
+        analysed.set_analysis(&result, Duration::from_secs(3)).unwrap();
+        assert_eq!(Some(result.depth.to_string().as_str()), analysed.operand("acd"));
+        assert_eq!(Some("3"), analysed.operand("acs"));
+        assert_eq!(Some("Qxf7#"), analysed.operand("pv"));
+        assert_eq!("h5f7", analysed.pv().unwrap()[0].to_uci());
+        record.remove("hmvc");
+        assert_eq!(1, record.operations.len());
+
+        // the runner checks bm, am and points
+        let suite: Vec<Epd> = [
+            line,
+            "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - am Qxf7#;",
+            "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - \
+             c0 \"Qxf7#=10, Qh3=1\"; id \"points\";",
+        ]
+        .iter()
+        .map(|line| Epd::parse(line).unwrap())
+        .collect();
+        let mut engine = Engine::default();
+        let mut seen = vec![];
+        let report = epd::run_suite(&mut engine, &suite, &limits, |position| {
+            seen.push(position.id.clone())
+        })
+        .unwrap();
+        assert_eq!(vec!["mate; in one", "position 2", "points"], seen);
+        let solved: Vec<bool> = report.results.iter().map(|r| r.solved).collect();
+        assert_eq!(vec![true, false, true], solved);
+        assert_eq!("Qxf7#", report.results[0].san);
+        assert_eq!((10, 10), report.points());
+        assert!(report.to_string().starts_with("solved 2 of 3 (66.7%), 10 of 10 points"));
+        let nothing = [Epd::from_game(&Game::new())];
+        assert!(epd::run_suite(&mut engine, &nothing, &limits, |_| {}).is_err());
+    }
+
+    #[test]
+    fn test_time_management() {
+        let overhead = Duration::from_millis(30);
+        let clock = Clock {
+            time: Duration::from_millis(60030),
+            ..Clock::default()
+        };
+        let budget = TimeBudget::new(&clock, overhead);
+        assert_eq!(Duration::from_millis(2000), budget.soft);
+        assert_eq!(Duration::from_millis(6000), budget.hard);
+        // the last move before the time control may use nearly all of it
+        let last = TimeBudget::new(&Clock { movestogo: Some(1), ..clock }, overhead);
+        assert_eq!(Duration::from_millis(54000), last.hard);
+        assert_eq!(last.hard, last.soft);
+        // the increment is added, the hard limit keeps half the clock
+        let low = Clock {
+            time: Duration::from_millis(1030),
+            increment: Duration::from_secs(2),
+            movestogo: None,
+        };
+        let budget = TimeBudget::new(&low, overhead);
+        assert_eq!(Duration::from_millis(500), budget.hard);
+        assert!(budget.soft <= budget.hard);
+        let flagging = Clock {
+            time: Duration::from_millis(10),
+            ..Clock::default()
+        };
+        assert_eq!(Duration::from_millis(1), TimeBudget::new(&flagging, overhead).hard);
+
+        // more time while the best move changes or the score drops
+        let budget = TimeBudget {
+            soft: Duration::from_millis(100),
+            hard: Duration::from_millis(300),
+        };
+        let mut stable = TimeManager::new(budget);
+        assert!(!stable.depth_done(Duration::from_millis(50), 1, 20));
+        assert!(stable.depth_done(Duration::from_millis(120), 1, 20));
+        let mut changing = TimeManager::new(budget);
+        assert!(!changing.depth_done(Duration::from_millis(50), 1, 20));
+        assert!(!changing.depth_done(Duration::from_millis(120), 2, 20));
+        let mut dropping = TimeManager::new(budget);
+        assert!(!dropping.depth_done(Duration::from_millis(50), 1, 20));
+        assert!(!dropping.depth_done(Duration::from_millis(120), 1, -80));
+        assert!(dropping.depth_done(Duration::from_millis(300), 1, -80));
+
+        // a single legal move is played after the first depth
+        let engine = Engine::default();
+        let clock = Clock {
+            time: Duration::from_secs(600),
+            ..Clock::default()
+        };
+        let limits = SearchLimits {
+            clock: Some(clock),
+            ..SearchLimits::default()
+        };
+        let game = Game::from_fen("r6k/8/8/8/3b4/8/8/K7 w - - 0 1").unwrap();
+        let result = engine.search(&game, &limits);
+        assert_eq!(1, result.depth);
+        assert_eq!("a1b1", result.best_move.unwrap().to_uci());
+
+        // a short clock is kept to, whatever the depth
+        let clock = Clock {
+            time: Duration::from_millis(1000),
+            ..Clock::default()
+        };
+        let limits = SearchLimits {
+            clock: Some(clock),
+            ..SearchLimits::default()
+        };
+        let start = std::time::Instant::now();
+        let result = engine.search(&Game::new(), &limits);
+        assert!(result.best_move.is_some());
+        assert!(start.elapsed() < Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_analysis() {
+        let wait = Duration::from_secs(60);
+        let mut game = Game::new();
+        let action = game.action_from_uci("e2e4").unwrap();
+        game.perform_action(action);
+        let mut analyzer = Analyzer::new(Engine::default());
+
+        // infinite analysis streams every depth until stopped
+        let updates = analyzer.analyze(&game);
+        let mut depths = vec![];
+        while depths.len() < 2 {
+            match updates.recv_timeout(wait).unwrap() {
+                AnalysisUpdate::Info(info) => {
+                    assert!(!info.pv.is_empty());
+                    assert_eq!(info.pv[0].key(), info.lines[0].action.key());
+                    depths.push(info.depth);
+                }
+                AnalysisUpdate::Done(_) => panic!("analysis ended by itself"),
+            }
+        }
+        assert!(depths[0] < depths[1]);
+        assert!(analyzer.is_searching());
+        analyzer.stop();
+        assert!(!analyzer.is_searching());
+        let done = updates.try_iter().filter_map(|update| match update {
+            AnalysisUpdate::Done(result) => Some(result),
+            AnalysisUpdate::Info(_) => None,
+        });
+        let result = done.last().unwrap();
+        let best = result.best_move.unwrap();
+        assert!(game.all_moves().iter().any(|a| a.key() == best.key()));
+
+        // a limited search ends by itself
+        analyzer.engine_mut().set_option("MultiPV", "2").unwrap();
+        let updates = analyzer.go(&game, &SearchLimits { depth: Some(2), ..SearchLimits::default() });
+        analyzer.wait();
+        let updates: Vec<AnalysisUpdate> = updates.try_iter().collect();
+        assert_eq!(3, updates.len());
+        match &updates[1] {
+            AnalysisUpdate::Info(info) => assert_eq!((2, 2), (info.depth, info.lines.len())),
+            AnalysisUpdate::Done(_) => panic!("no update for depth 2"),
+        }
+        let result = match &updates[2] {
+            AnalysisUpdate::Done(result) => result.clone(),
+            AnalysisUpdate::Info(_) => panic!("no result"),
+        };
+        assert_eq!(result.pv.get(1).map(|a| a.key()), result.ponder_move().map(|a| a.key()));
+        analyzer.engine_mut().set_option("MultiPV", "1").unwrap();
+
+        // pondering holds the result back until the ponder move is played
+        let mut start = Game::new();
+        let e4 = start.action_from_uci("e2e4").unwrap();
+        let d5 = game.action_from_uci("d7d5").unwrap();
+        assert!(analyzer.ponder(&start, &d5, &SearchLimits::default()).is_err());
+        let limits = SearchLimits {
+            depth: Some(2),
+            movetime: Some(Duration::from_millis(100)),
+            ..SearchLimits::default()
+        };
+        let updates = analyzer.ponder(&start, &e4, &limits).unwrap();
+        let mut infos = 0;
+        while let Ok(update) = updates.recv_timeout(Duration::from_millis(500)) {
+            match update {
+                AnalysisUpdate::Info(_) => infos += 1,
+                AnalysisUpdate::Done(_) => panic!("result before the ponderhit"),
+            }
+        }
+        assert_eq!(2, infos);
+        analyzer.ponderhit();
+        match updates.recv_timeout(wait).unwrap() {
+            AnalysisUpdate::Done(result) => {
+                let best = result.best_move.unwrap();
+                assert!(game.all_moves().iter().any(|a| a.key() == best.key()));
+            }
+            AnalysisUpdate::Info(_) => panic!("no result after the ponderhit"),
+        }
+
+        // without a depth the ponderhit starts the move time
+        let limits = SearchLimits { movetime: Some(Duration::from_millis(100)), ..SearchLimits::default() };
+        let updates = analyzer.ponder(&start, &e4, &limits).unwrap();
+        std::thread::sleep(Duration::from_millis(200));
+        analyzer.ponderhit();
+        let finished = updates.iter().any(|update| matches!(update, AnalysisUpdate::Done(_)));
+        assert!(finished);
+
+        // a clock only starts at the ponderhit, then the time manager ends
+        // the search by its hard limit at the latest
+        let clock = |seconds| Clock {
+            time: Duration::from_secs(seconds),
+            increment: Duration::ZERO,
+            movestogo: None,
+        };
+        let limits = SearchLimits { clock: Some(clock(3)), ..SearchLimits::default() };
+        let updates = analyzer.ponder(&start, &e4, &limits).unwrap();
+        std::thread::sleep(Duration::from_millis(500));
+        assert!(analyzer.is_searching());
+        let ponderhit = std::time::Instant::now();
+        analyzer.ponderhit();
+        assert!(updates.iter().any(|update| matches!(update, AnalysisUpdate::Done(_))));
+        assert!(ponderhit.elapsed() < Duration::from_secs(2));
+
+        // and a forced reply is played right away, long before the soft limit
+        let mut rook = Game::from_fen("7k/5K2/8/8/8/8/8/R7 w - - 0 1").unwrap();
+        let check = rook.action_from_uci("a1a8").unwrap();
+        let limits = SearchLimits { clock: Some(clock(600)), ..SearchLimits::default() };
+        let updates = analyzer.ponder(&rook, &check, &limits).unwrap();
+        std::thread::sleep(Duration::from_millis(300));
+        assert!(analyzer.is_searching());
+        let ponderhit = std::time::Instant::now();
+        analyzer.ponderhit();
+        let result = updates.iter().find_map(|update| match update {
+            AnalysisUpdate::Done(result) => Some(result),
+            AnalysisUpdate::Info(_) => None,
+        });
+        assert_eq!("h8h7", result.unwrap().best_move.unwrap().to_uci());
+        assert!(ponderhit.elapsed() < Duration::from_secs(2));
+    }
+
+    // Plays e2e4 whatever the position
+    struct Stubborn;
+
+    impl Contestant for Stubborn {
+        fn new_game(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn play(&mut self, _: &Game, _: &GoOptions) -> Result<search::SearchResult, String> {
+            let action = Game::new().action_from_uci("e2e4")?;
+            Ok(search::SearchResult { best_move: Some(action), ..search::SearchResult::default() })
+        }
+    }
+
+    // Castles short whatever stands in the way
+    struct Castler;
+
+    impl Contestant for Castler {
+        fn new_game(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn play(&mut self, game: &Game, _: &GoOptions) -> Result<search::SearchResult, String> {
+            let row = if game.player == Team::White { 0 } else { 7 };
+            let action = Action {
+                from: game.matrix[4][row],
+                to: game.matrix[6][row],
+                action_type: ActionType::Castling,
+                promotion: None,
+            };
+            Ok(search::SearchResult { best_move: Some(action), ..search::SearchResult::default() })
+        }
+    }
+
+    #[test]
+    fn test_match() {
+        let script = "while read -r line; do
+  case \"$line\" in
+    uci) echo 'id name Blunder'; echo uciok ;;
+    isready) echo readyok ;;
+    go*) echo 'bestmove a1a8' ;;
+    quit) exit 0 ;;
+  esac
+done
+";
+        let path = std::env::temp_dir().join("chess_test_illegal_engine.sh");
+        std::fs::write(&path, script).unwrap();
+        let builtin = |name: &str| Player {
+            name: String::from(name),
+            limits: SearchLimits { depth: Some(1), ..SearchLimits::default() },
+            ..Player::default()
+        };
+        let blunder = Player {
+            name: String::from("blunder"),
+            command: vec![String::from("sh"), path.to_str().unwrap().to_string()],
+            ..Player::default()
+        };
+        let options = TournamentOptions {
+            games: 2,
+            max_moves: Some(3),
+            event: String::from("test"),
+            ..TournamentOptions::default()
+        };
+        let players = [builtin("one"), builtin("two"), blunder];
+        let mut records: Vec<GameRecord> = vec![];
+        let crosstable = tournament::run_match(&players, &[], &options, 2, |record, _| records.push(record.clone())).unwrap();
+        assert_eq!(6, records.len());
+        for record in records.iter() {
+            assert_ne!(record.white, record.black);
+            let winner = if record.white == 2 { Some(Team::Black) } else if record.black == 2 { Some(Team::White) } else { None };
+            match winner {
+                // blunder loses every game on its first move
+                Some(team) => {
+                    assert_eq!(Outcome::Win(team), record.outcome);
+                    assert_eq!("rules infraction", record.termination);
+                    assert_eq!(if team == Team::White { 1 } else { 0 }, record.pgn.actions.len());
+                }
+                None => assert_eq!(Outcome::Draw, record.outcome),
+            }
+        }
+        assert_eq!(vec![4, 4, 4], (0..3).map(|i| crosstable.games(i)).collect::<Vec<usize>>());
+        assert_eq!(vec![3.0, 3.0, 0.0], (0..3).map(|i| crosstable.points(i)).collect::<Vec<f64>>());
+        assert_eq!(2, crosstable.ranking()[2]);
+        let table = crosstable.to_string();
+        assert!(table.lines().nth(3).unwrap().contains("blunder"));
+        assert!(tournament::run_match(&players[..1], &[], &options, 1, |_, _| {}).is_err());
+
+        // moves are checked against the legal moves of the position
+        let game_options = TournamentOptions { event: String::from("test"), ..TournamentOptions::default() };
+        let mut engine = builtin("one").engine().unwrap();
+        let opening = tournament::parse_positions("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        let record = tournament::play_game(
+            [&players[0], &players[1]],
+            [&mut engine, &mut Stubborn],
+            0,
+            &opening[0],
+            &game_options,
+            1,
+        )
+        .unwrap();
+        assert_eq!(Outcome::Win(Team::White), record.outcome);
+        assert_eq!("rules infraction", record.termination);
+        let mut crosstable = Crosstable::new(vec![String::from("one"), String::from("two")]);
+        crosstable.add(&record);
+        assert_eq!((1.0, 0.0), (crosstable.points(0), crosstable.points(1)));
+
+        // castling with the bishop still on f1 forfeits the game as well
+        let opening = tournament::parse_positions("r3k2r/pppppppp/8/8/8/8/PPPPPPPP/RNBQKB1R w KQkq - 0 1").unwrap();
+        let record = tournament::play_game(
+            [&players[0], &players[1]],
+            [&mut Castler, &mut engine],
+            0,
+            &opening[0],
+            &game_options,
+            1,
+        )
+        .unwrap();
+        assert_eq!(Outcome::Win(Team::Black), record.outcome);
+        assert_eq!("rules infraction", record.termination);
+        assert!(record.pgn.actions.is_empty());
+    }
+
+    #[test]
+    fn test_opening() {
+        // every line of the table is legal and classified as itself
+        let openings = opening::openings();
+        assert!(openings.len() > 150);
+        for (opening, actions) in openings.iter() {
+            assert_eq!(opening.eco, opening::classify(actions).unwrap().eco);
+        }
+
+        let play = |moves: &str| {
+            let mut game = Game::new();
+            let mut actions = vec![];
+            for san in moves.split_whitespace().filter(|token| !token.ends_with('.')) {
+                let action = pgn::action_from_san(&mut game, san).unwrap();
+                game.perform_action(action);
+                actions.push(action);
+            }
+            actions
+        };
+        let najdorf = opening::classify(&play("1. e4 c5 2. Nf3 d6 3. d4 cxd4 4. Nxd4 Nf6 5. Nc3 a6 6. h3")).unwrap();
+        assert_eq!(
+            ("B90", "Sicilian Defense", Some("Najdorf Variation")),
+            (najdorf.eco.as_str(), najdorf.name.as_str(), najdorf.variation.as_deref())
+        );
+        // the position is recognised whatever the move order
+        let transposed = opening::classify(&play("1. Nf3 Nf6 2. c4 g6 3. Nc3 Bg7 4. e4 d6 5. d4 O-O 6. Be2")).unwrap();
+        assert_eq!(("E91", Some("Orthodox Variation")), (transposed.eco.as_str(), transposed.variation.as_deref()));
+        // a game leaving the book keeps the deepest opening it went through
+        assert_eq!("C60", opening::classify(&play("1. e4 e5 2. Nf3 Nc6 3. Bb5 Qh4 4. Nxh4")).unwrap().eco);
+        assert!(opening::classify(&[]).is_none());
+        assert!(opening::classify(&play("1. h4 h5")).is_none());
+
+        // the tagging pgn reader adds the tags to games without any of them
+        let nimzo = "1. d4 Nf6 2. c4 e6 3. Nc3 Bb4 4. e3 *";
+        let game = pgn::parse_games_tagged(nimzo).remove(0).unwrap();
+        assert_eq!(
+            (Some("E40"), Some("Nimzo-Indian Defense"), Some("Normal Variation")),
+            (game.tag("ECO"), game.tag("Opening"), game.tag("Variation"))
+        );
+        assert!(pgn::parse_games(nimzo).remove(0).unwrap().tags.is_empty());
+        let game = pgn::parse_games_tagged("[ECO \"E20\"]\n\n1. d4 Nf6 2. c4 e6 3. Nc3 Bb4 4. e3 *").remove(0).unwrap();
+        assert_eq!((Some("E20"), None), (game.tag("ECO"), game.tag("Opening")));
+        let fen = "[FEN \"rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBN1 w Qkq - 0 1\"]\n\n1. e4 e5 *";
+        assert!(pgn::parse_games_tagged(fen).remove(0).unwrap().tag("ECO").is_none());
+    }
+
+    #[test]
+    fn test_database() {
+        let text = "[White \"Carlsen, Magnus\"]\n[Black \"Caruana, Fabiano\"]\n[Event \"Candidates\"]\n\n\
+                    1. e4 c5 2. Nf3 d6 3. d4 cxd4 4. Nxd4 Nf6 5. Nc3 a6 {Najdorf} 1-0\n\n\
+                    [White \"Caruana, Fabiano\"]\n[Black \"Carlsen, Magnus\"]\n\n\
+                    1. e4 c5 2. Nf3 d6 3. Bb5+ Bd7 1/2-1/2\n\n\
+                    [White \"Nepomniachtchi, Ian\"]\n[Black \"Carlsen, Magnus\"]\n\n\
+                    1. Nf3 d6 2. e4 c5 3. d4 0-1\n\n\
+                    [White \"Ding, Liren\"]\n[Black \"Nepomniachtchi, Ian\"]\n\n1. d4 Nf6 *\n";
+        let mut database = Database::new();
+        for pgn_game in pgn::parse_games_tagged(text) {
+            database.add_game(&pgn_game.unwrap()).unwrap();
+        }
+        assert_eq!(4, database.len());
+        // the same position twice in a game is indexed once
+        database.add_game(&pgn::parse_games("1. Nf3 Nf6 2. Ng1 Ng8 3. e4 *").remove(0).unwrap()).unwrap();
+        assert_eq!(1, database.occurrences(Game::new().hash()).iter().filter(|o| o.game == 4).count());
+
+        let after = |moves: &str| {
+            let mut game = Game::new();
+            for action in pgn::parse_games(moves).remove(0).unwrap().actions {
+                game.perform_action(action);
+            }
+            game
+        };
+        // the position after 1.e4 c5 2.Nf3 d6, reached in another order too
+        let sicilian = after("1. e4 c5 2. Nf3 d6");
+        let query = Query { position: Some(sicilian.hash()), ..Query::default() };
+        assert_eq!(vec![0, 1, 2], database.search(&query));
+        let stats = database.stats(&sicilian);
+        assert_eq!(
+            Results { games: 3, white_wins: 1, draws: 1, black_wins: 1 },
+            stats.results
+        );
+        assert_eq!(Some(0.5), stats.results.white_score());
+        let moves: Vec<(String, u32)> = stats
+            .moves
+            .iter()
+            .map(|(action, results)| (pgn::to_san(&sicilian, action), results.games))
+            .collect();
+        assert_eq!(vec![(String::from("d4"), 2), (String::from("Bb5+"), 1)], moves);
+        assert_eq!("3 games +1 =1 -1 (50.0%)", stats.results.to_string());
+
+        let white = Query { white: Some(String::from("carlsen")), ..Query::default() };
+        assert_eq!(vec![0], database.search(&white));
+        let player = Query { player: Some(String::from("Carlsen")), ..Query::default() };
+        assert_eq!(vec![0, 1, 2], database.search(&player));
+        let won = Query { player: Some(String::from("Nepo")), result: Some(String::from("0-1")), ..Query::default() };
+        assert_eq!(vec![2], database.search(&won));
+        let candidates = Query { event: Some(String::from("candidates")), ..Query::default() };
+        assert_eq!(vec![0], database.search(&candidates));
+        assert!(database.search(&Query { white: Some(String::from("nobody")), ..Query::default() }).is_empty());
+        assert!(database.stats(&after("1. a4")).moves.is_empty());
+
+        // written and read back the same, but for comments
+        let mut read = Database::from_bytes(&database.to_bytes()).unwrap();
+        assert_eq!((5, database.positions()), (read.len(), read.positions()));
+        let game = read.game(0).unwrap();
+        assert_eq!(Some("Carlsen, Magnus"), game.tag("White"));
+        assert_eq!(Some("B90"), game.tag("ECO"));
+        assert_eq!("1-0", game.result);
+        assert_eq!(10, game.actions.len());
+        assert!(game.to_pgn().unwrap().contains("5. Nc3 a6 1-0"));
+        assert_eq!(Some("1/2-1/2"), read.result(1));
+        assert_eq!(database.search(&player), read.search(&player));
+        assert!(read.game(5).is_err());
+        // games added to a database read back share its strings and index
+        let added = pgn::parse_games("[White \"Carlsen, Magnus\"]\n\n1. e4 c5 2. Nf3 d6 *").remove(0).unwrap();
+        assert_eq!(5, read.add_game(&added).unwrap());
+        assert_eq!(vec![0, 5], read.search(&Query { white: Some(String::from("Magnus")), ..Query::default() }));
+        assert_eq!(4, read.stats(&sicilian).results.games);
+        assert!(Database::from_bytes(b"CGDB").is_err());
+        assert!(Database::from_bytes(&database.to_bytes()[..100]).is_err());
+    }
+
     fn compare_pgn_and_game(pgn_filepath:&str){
         let mut game=game::Game::new();
         let (actions,gamestates)=pgn::read_pgn(pgn_filepath);
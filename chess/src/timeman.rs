@@ -0,0 +1,102 @@
+// Time management for games on a clock. Every move gets a soft and a hard
+// limit out of the time left: no new depth is started after the soft one,
+// stretched while the best move keeps changing or the score drops, and the
+// search is cut off at the hard one wherever it is. The move overhead is
+// taken off the clock first, the time lost every move to the gui and the
+// communication with it.
+use std::time::Duration;
+
+pub const DEFAULT_MOVE_OVERHEAD: u64 = 30;
+// moves the rest of the game is assumed to last without a time control
+const MOVES_LEFT: u64 = 30;
+// the most the soft limit stretches on unstable searches
+const MAX_SCALE: f64 = 3.0;
+
+// The clock of the side to move
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Clock {
+    pub time: Duration,
+    pub increment: Duration,
+    // moves until the next time control, none if the time is for the game
+    pub movestogo: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeBudget {
+    pub soft: Duration,
+    pub hard: Duration,
+}
+
+impl TimeBudget {
+    // The soft limit is an even share of the time left plus most of the
+    // increment, the hard one three times that, but never more than half
+    // the time left, or nearly all of it for the last move of a control
+    pub fn new(clock: &Clock, move_overhead: Duration) -> TimeBudget {
+        let available = clock.time.saturating_sub(move_overhead);
+        let moves = clock.movestogo.unwrap_or(MOVES_LEFT).max(1);
+        let share = available / moves as u32 + clock.increment * 3 / 4;
+        let most = if moves == 1 {
+            available * 9 / 10
+        } else {
+            available / 2
+        };
+        let minimum = Duration::from_millis(1);
+        let hard = (share * 3).min(most).max(minimum);
+        TimeBudget {
+            soft: share.min(hard).max(minimum),
+            hard,
+        }
+    }
+}
+
+// Decides after every completed depth whether to start the next one
+#[derive(Debug, Clone)]
+pub struct TimeManager {
+    budget: TimeBudget,
+    // recent changes of the best move, halved every depth
+    best_move_changes: f64,
+    // the best move and score of the previous depth
+    previous: Option<(u16, i32)>,
+}
+
+impl TimeManager {
+    pub fn new(budget: TimeBudget) -> TimeManager {
+        TimeManager {
+            budget,
+            best_move_changes: 0.0,
+            previous: None,
+        }
+    }
+
+    pub fn hard_limit(&self) -> Duration {
+        self.budget.hard
+    }
+
+    // Called with the best move (its key) and score of every completed
+    // depth, true when the search should end there
+    pub fn depth_done(&mut self, elapsed: Duration, best_move: u16, score: i32) -> bool {
+        self.best_move_changes /= 2.0;
+        let drop = match self.previous {
+            Some((previous_move, previous_score)) => {
+                if previous_move != best_move {
+                    self.best_move_changes += 1.0;
+                }
+                previous_score - score
+            }
+            None => 0,
+        };
+        self.previous = Some((best_move, score));
+        let scale = self.scale() * (1.0 + drop.clamp(0, 100) as f64 / 100.0);
+        elapsed
+            >= self
+                .budget
+                .soft
+                .mul_f64(scale.min(MAX_SCALE))
+                .min(self.budget.hard)
+    }
+
+    // 1 for a stable best move, up to 2 while it keeps changing
+    fn scale(&self) -> f64 {
+        1.0 + self.best_move_changes / 2.0
+    }
+}
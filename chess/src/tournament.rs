@@ -0,0 +1,864 @@
+// Matches between two configurations of the engine, for testing changes:
+// the games start from the positions of an openings file, every opening is
+// played twice with the colors swapped, and the result is an Elo difference
+// with its error margin and optionally a sequential probability ratio test.
+// Matches between any number of engines, built-in or external, play every
+// pair the same way and end in a crosstable.
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::engine::Engine;
+use crate::game::{Game, GameState, Outcome, Rank, Team};
+use crate::moves::{Action, ActionType};
+use crate::pgn;
+use crate::pgn::PgnGame;
+use crate::search::{SearchLimits, SearchResult};
+use crate::tablebase;
+use crate::timeman::Clock;
+use crate::uci_client::{GoOptions, UciClient};
+
+// A configuration of the engine, or an external uci engine started with
+// `command` (the program and its arguments): its uci options (e.g.
+// ("Hash", "16")) and the limits of every search, which a time control
+// shortens further
+#[derive(Debug, Clone, Default)]
+pub struct Player {
+    pub name: String,
+    pub command: Vec<String>,
+    pub options: Vec<(String, String)>,
+    pub limits: SearchLimits,
+}
+
+impl Player {
+    pub fn engine(&self) -> Result<Engine, String> {
+        let mut engine = Engine::default();
+        for (name, value) in self.options.iter() {
+            engine.set_option(name, value)?;
+        }
+        Ok(engine)
+    }
+
+    // The built-in engine without a command, the external one otherwise
+    pub fn start(&self) -> Result<Box<dyn Contestant + Send>, String> {
+        let (program, args) = match self.command.split_first() {
+            Some(command) => command,
+            None => return Ok(Box::new(self.engine()?)),
+        };
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let mut client = UciClient::spawn(program, &args)?;
+        for (name, value) in self.options.iter() {
+            client.set_option(name, value)?;
+        }
+        client.is_ready()?;
+        Ok(Box::new(client))
+    }
+}
+
+// Anything that plays moves in a match
+pub trait Contestant {
+    fn new_game(&mut self) -> Result<(), String>;
+    // The move for the side to move of `game`. An error loses the game.
+    fn play(&mut self, game: &Game, go: &GoOptions) -> Result<SearchResult, String>;
+}
+
+impl Contestant for Engine {
+    fn new_game(&mut self) -> Result<(), String> {
+        Engine::new_game(self);
+        Ok(())
+    }
+
+    // The engine manages its time on the clock of the side to move
+    fn play(&mut self, game: &Game, go: &GoOptions) -> Result<SearchResult, String> {
+        let side = if game.player == Team::White { 0 } else { 1 };
+        let mut limits = go.limits.clone();
+        limits.clock = go.time[side].map(|time| Clock {
+            time,
+            increment: go.increment[side].unwrap_or_default(),
+            movestogo: go.movestogo.map(|moves| moves as u64),
+        });
+        Ok(self.search(game, &limits))
+    }
+}
+
+impl Contestant for UciClient {
+    fn new_game(&mut self) -> Result<(), String> {
+        UciClient::new_game(self)
+    }
+
+    // Waits a second longer than the clock or the move time allows before
+    // giving up on the engine, and ten minutes without either
+    fn play(&mut self, game: &Game, go: &GoOptions) -> Result<SearchResult, String> {
+        let side = if game.player == Team::White { 0 } else { 1 };
+        let timeout = go.time[side]
+            .or(go.limits.movetime)
+            .map_or(Duration::from_secs(600), |time| {
+                time + Duration::from_secs(1)
+            });
+        self.go(game, go, timeout)
+    }
+}
+
+// `moves` moves in `time`, or the whole game without `moves`, with
+// `increment` added after every move
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TimeControl {
+    pub moves: Option<u32>,
+    pub time: Duration,
+    pub increment: Duration,
+}
+
+impl TimeControl {
+    // "40/60" (40 moves in 60 seconds), "10+0.1" (10 seconds and 0.1 seconds
+    // per move) or "60"
+    pub fn parse(text: &str) -> Result<TimeControl, String> {
+        let seconds = |text: &str| {
+            text.parse::<f64>()
+                .ok()
+                .filter(|seconds| *seconds >= 0.0)
+                .map(Duration::from_secs_f64)
+                .ok_or(format!("{} is not a time control", text))
+        };
+        let (moves, rest) = match text.split_once('/') {
+            Some((moves, rest)) => (
+                Some(
+                    moves
+                        .parse()
+                        .map_err(|_| format!("{} is not a time control", text))?,
+                ),
+                rest,
+            ),
+            None => (None, text),
+        };
+        let (time, increment) = match rest.split_once('+') {
+            Some((time, increment)) => (seconds(time)?, seconds(increment)?),
+            None => (seconds(rest)?, Duration::from_secs(0)),
+        };
+        Ok(TimeControl {
+            moves,
+            time,
+            increment,
+        })
+    }
+}
+
+// As in the pgn TimeControl tag
+impl fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(moves) = self.moves {
+            write!(f, "{}/", moves)?;
+        }
+        write!(f, "{}", self.time.as_secs_f64())?;
+        if self.increment > Duration::from_secs(0) {
+            write!(f, "+{}", self.increment.as_secs_f64())?;
+        }
+        Ok(())
+    }
+}
+
+// Games are adjudicated as draws once both engines scored within `score`
+// centipawns of equality for `plies` plies in a row, from move `move_number`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DrawAdjudication {
+    pub move_number: usize,
+    pub score: i32,
+    pub plies: usize,
+}
+
+// Games are adjudicated as a win once both engines scored at least `score`
+// centipawns for the same side for `plies` plies in a row
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ResignAdjudication {
+    pub score: i32,
+    pub plies: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TournamentOptions {
+    pub games: usize,
+    pub time_control: Option<TimeControl>,
+    pub draw: Option<DrawAdjudication>,
+    pub resign: Option<ResignAdjudication>,
+    // moves after which the game is a draw
+    pub max_moves: Option<usize>,
+    pub sprt: Option<Sprt>,
+    pub event: String,
+}
+
+// A start position, a fen or the standard position and the moves after it
+#[derive(Debug, Clone)]
+pub struct Opening {
+    pub fen: Option<String>,
+    pub actions: Vec<Action>,
+}
+
+impl Opening {
+    pub fn position(&self) -> Result<Game, String> {
+        let mut game = match &self.fen {
+            Some(fen) => Game::from_fen(fen)?,
+            None => Game::new(),
+        };
+        for action in self.actions.iter() {
+            game.perform_action(*action);
+        }
+        Ok(game)
+    }
+}
+
+// The openings of a pgn file (the main line of every game), or of a file
+// with a fen or epd position on every line
+pub fn read_openings(filepath: &str) -> Result<Vec<Opening>, String> {
+    let text =
+        fs::read_to_string(filepath).map_err(|e| format!("could not read {}: {}", filepath, e))?;
+    let openings = if filepath.to_lowercase().ends_with(".pgn") {
+        let mut openings = vec![];
+        for game in pgn::parse_games(&text) {
+            let game = game?;
+            openings.push(Opening {
+                fen: game.tag("FEN").map(String::from),
+                actions: game.actions,
+            });
+        }
+        openings
+    } else {
+        parse_positions(&text)?
+    };
+    if openings.is_empty() {
+        return Err(format!("no openings in {}", filepath));
+    }
+    Ok(openings)
+}
+
+// One fen or epd position per line, empty lines and lines starting with #
+// left out. Epd operations after the four position fields are ignored.
+pub fn parse_positions(text: &str) -> Result<Vec<Opening>, String> {
+    let mut openings = vec![];
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let clocks = fields.len() >= 6 && fields[4..6].iter().all(|f| f.parse::<u32>().is_ok());
+        let fen = if clocks {
+            fields[..6].join(" ")
+        } else {
+            fields[..fields.len().min(4)].join(" ")
+        };
+        Game::from_fen(&fen)?;
+        openings.push(Opening {
+            fen: Some(fen),
+            actions: vec![],
+        });
+    }
+    Ok(openings)
+}
+
+// Sequential probability ratio test of the hypotheses that the first player
+// is `elo0` (H0) or `elo1` (H1) Elo stronger than the second, with the false
+// positive rate `alpha` and false negative rate `beta`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SprtVerdict {
+    AcceptH0,
+    AcceptH1,
+    Continue,
+}
+
+impl Sprt {
+    pub fn new(elo0: f64, elo1: f64) -> Sprt {
+        Sprt {
+            elo0,
+            elo1,
+            alpha: 0.05,
+            beta: 0.05,
+        }
+    }
+
+    // The log likelihood ratio of the results, with the scores of the games
+    // taken as normally distributed. A count of zero is taken as half a game
+    // so one-sided results still move the ratio.
+    pub fn llr(&self, wins: usize, draws: usize, losses: usize) -> f64 {
+        if wins + draws + losses == 0 {
+            return 0.0;
+        }
+        let count = |games: usize| if games == 0 { 0.5 } else { games as f64 };
+        let games = count(wins) + count(draws) + count(losses);
+        let (w, d) = (count(wins) / games, count(draws) / games);
+        let score = w + d / 2.0;
+        let variance = (w + d / 4.0 - score * score) / games;
+        if variance <= 0.0 {
+            return 0.0;
+        }
+        let (score0, score1) = (elo_to_score(self.elo0), elo_to_score(self.elo1));
+        (score1 - score0) * (2.0 * score - score0 - score1) / (2.0 * variance)
+    }
+
+    // The llr below which H0 and above which H1 is accepted
+    pub fn bounds(&self) -> (f64, f64) {
+        (
+            (self.beta / (1.0 - self.alpha)).ln(),
+            ((1.0 - self.beta) / self.alpha).ln(),
+        )
+    }
+
+    pub fn verdict(&self, wins: usize, draws: usize, losses: usize) -> SprtVerdict {
+        let llr = self.llr(wins, draws, losses);
+        let (lower, upper) = self.bounds();
+        if llr <= lower {
+            SprtVerdict::AcceptH0
+        } else if llr >= upper {
+            SprtVerdict::AcceptH1
+        } else {
+            SprtVerdict::Continue
+        }
+    }
+}
+
+pub fn elo_to_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+pub fn score_to_elo(score: f64) -> f64 {
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+// The results of the first player
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Standings {
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+}
+
+impl Standings {
+    pub fn games(&self) -> usize {
+        self.wins + self.draws + self.losses
+    }
+
+    pub fn score(&self) -> f64 {
+        (self.wins as f64 + self.draws as f64 / 2.0) / self.games().max(1) as f64
+    }
+
+    // The Elo difference and the half width of its 95% confidence interval,
+    // `None` while every game was won or every game lost
+    pub fn elo(&self) -> Option<(f64, f64)> {
+        let games = self.games() as f64;
+        let score = self.score();
+        if self.games() == 0 || score <= 0.0 || score >= 1.0 {
+            return None;
+        }
+        let deviation = ((self.wins as f64 * (1.0 - score).powi(2)
+            + self.draws as f64 * (0.5 - score).powi(2)
+            + self.losses as f64 * score.powi(2))
+            / games)
+            .sqrt()
+            / games.sqrt();
+        let low = (score - 1.96 * deviation).max(f64::MIN_POSITIVE);
+        let high = (score + 1.96 * deviation).min(1.0 - f64::EPSILON);
+        Some((
+            score_to_elo(score),
+            (score_to_elo(high) - score_to_elo(low)) / 2.0,
+        ))
+    }
+}
+
+impl fmt::Display for Standings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "+{} ={} -{} ({:.1}%)",
+            self.wins,
+            self.draws,
+            self.losses,
+            self.score() * 100.0
+        )?;
+        if let Some((elo, error)) = self.elo() {
+            write!(f, " Elo {:+.1} +/- {:.1}", elo, error)?;
+        }
+        Ok(())
+    }
+}
+
+// A finished game: `white` and `black` are the indexes of the players
+#[derive(Debug, Clone)]
+pub struct GameRecord {
+    pub pgn: PgnGame,
+    pub white: usize,
+    pub black: usize,
+    pub outcome: Outcome,
+    pub termination: String,
+}
+
+pub struct Tournament {
+    pub players: [Player; 2],
+    pub openings: Vec<Opening>,
+    pub options: TournamentOptions,
+}
+
+impl Tournament {
+    pub fn new(
+        players: [Player; 2],
+        openings: Vec<Opening>,
+        options: TournamentOptions,
+    ) -> Tournament {
+        Tournament {
+            players,
+            openings,
+            options,
+        }
+    }
+
+    // Plays the games one after another, calling `on_game` after each. Stops
+    // early once the sprt accepts a hypothesis.
+    pub fn run<F: FnMut(&GameRecord, &Standings)>(
+        &self,
+        mut on_game: F,
+    ) -> Result<Standings, String> {
+        let mut contestants = [self.players[0].start()?, self.players[1].start()?];
+        let mut standings = Standings::default();
+        let openings = if self.openings.is_empty() {
+            vec![Opening {
+                fen: None,
+                actions: vec![],
+            }]
+        } else {
+            self.openings.clone()
+        };
+        for round in 0..self.options.games {
+            let opening = &openings[(round / 2) % openings.len()];
+            let white = round % 2;
+            let [first, second] = &mut contestants;
+            let record = play_game(
+                [&self.players[0], &self.players[1]],
+                [first.as_mut(), second.as_mut()],
+                white,
+                opening,
+                &self.options,
+                round + 1,
+            )?;
+            match record.outcome {
+                Outcome::Draw => standings.draws += 1,
+                Outcome::Win(team) if (team == Team::White) == (white == 0) => standings.wins += 1,
+                Outcome::Win(_) => standings.losses += 1,
+            }
+            on_game(&record, &standings);
+            if let Some(sprt) = self.options.sprt {
+                if sprt.verdict(standings.wins, standings.draws, standings.losses)
+                    != SprtVerdict::Continue
+                {
+                    break;
+                }
+            }
+        }
+        Ok(standings)
+    }
+}
+
+// The results of every player against every other, `results[i][j]` from
+// the view of player i
+#[derive(Debug, Clone, Default)]
+pub struct Crosstable {
+    pub names: Vec<String>,
+    pub results: Vec<Vec<Standings>>,
+}
+
+impl Crosstable {
+    pub fn new(names: Vec<String>) -> Crosstable {
+        let results = vec![vec![Standings::default(); names.len()]; names.len()];
+        Crosstable { names, results }
+    }
+
+    pub fn add(&mut self, record: &GameRecord) {
+        let (white, black) = (record.white, record.black);
+        match record.outcome {
+            Outcome::Draw => {
+                self.results[white][black].draws += 1;
+                self.results[black][white].draws += 1;
+            }
+            Outcome::Win(Team::White) => {
+                self.results[white][black].wins += 1;
+                self.results[black][white].losses += 1;
+            }
+            Outcome::Win(Team::Black) => {
+                self.results[white][black].losses += 1;
+                self.results[black][white].wins += 1;
+            }
+        }
+    }
+
+    pub fn points(&self, player: usize) -> f64 {
+        self.results[player]
+            .iter()
+            .map(|standings| standings.wins as f64 + standings.draws as f64 / 2.0)
+            .sum()
+    }
+
+    pub fn games(&self, player: usize) -> usize {
+        self.results[player].iter().map(Standings::games).sum()
+    }
+
+    // The indexes of the players, most points first
+    pub fn ranking(&self) -> Vec<usize> {
+        let mut ranking: Vec<usize> = (0..self.names.len()).collect();
+        ranking.sort_by(|a, b| self.points(*b).total_cmp(&self.points(*a)));
+        ranking
+    }
+}
+
+impl fmt::Display for Crosstable {
+    // One row per player by rank, with the points against every opponent
+    // in the order of the ranking
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ranking = self.ranking();
+        let width = self.names.iter().map(String::len).max().unwrap_or(0).max(4);
+        write!(f, "Rank {:width$} Points Games", "Name", width = width)?;
+        for rank in 1..=ranking.len() {
+            write!(f, " {:>5}", rank)?;
+        }
+        for (rank, &player) in ranking.iter().enumerate() {
+            write!(
+                f,
+                "\n{:>4} {:width$} {:>6.1} {:>5}",
+                rank + 1,
+                self.names[player],
+                self.points(player),
+                self.games(player),
+                width = width
+            )?;
+            for &opponent in ranking.iter() {
+                let standings = self.results[player][opponent];
+                if opponent == player {
+                    write!(f, " {:>5}", "*")?;
+                } else if standings.games() == 0 {
+                    write!(f, " {:>5}", "-")?;
+                } else {
+                    let points = standings.wins as f64 + standings.draws as f64 / 2.0;
+                    write!(f, " {:>5.1}", points)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Plays every player against every other: `options.games` games per pair
+// with every opening twice and the colors swapped, `concurrency` games at a
+// time. Each thread starts its own engines as it needs them. `on_game` is
+// called as the games finish, and the first error stops the match.
+pub fn run_match<F: FnMut(&GameRecord, &Crosstable)>(
+    players: &[Player],
+    openings: &[Opening],
+    options: &TournamentOptions,
+    concurrency: usize,
+    mut on_game: F,
+) -> Result<Crosstable, String> {
+    if players.len() < 2 {
+        return Err(String::from("a match needs at least two players"));
+    }
+    let openings = if openings.is_empty() {
+        vec![Opening {
+            fen: None,
+            actions: vec![],
+        }]
+    } else {
+        openings.to_vec()
+    };
+    // (round, white, black, opening) of every game
+    let mut jobs = VecDeque::new();
+    for round in 0..options.games {
+        for first in 0..players.len() {
+            for second in first + 1..players.len() {
+                let (white, black) = if round % 2 == 0 {
+                    (first, second)
+                } else {
+                    (second, first)
+                };
+                jobs.push_back((jobs.len() + 1, white, black, (round / 2) % openings.len()));
+            }
+        }
+    }
+    let threads = concurrency.clamp(1, jobs.len().max(1));
+    let jobs = Mutex::new(jobs);
+    let failed = AtomicBool::new(false);
+    let mut crosstable = Crosstable::new(players.iter().map(|p| p.name.clone()).collect());
+
+    thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel();
+        for _ in 0..threads {
+            let sender = sender.clone();
+            let (jobs, failed, openings) = (&jobs, &failed, &openings);
+            scope.spawn(move || {
+                let mut contestants: Vec<Option<Box<dyn Contestant + Send>>> =
+                    players.iter().map(|_| None).collect();
+                while !failed.load(Ordering::Relaxed) {
+                    let job = jobs.lock().ok().and_then(|mut jobs| jobs.pop_front());
+                    let (round, white, black, opening) = match job {
+                        Some(job) => job,
+                        None => break,
+                    };
+                    let result = play_pairing(
+                        players,
+                        &mut contestants,
+                        [white, black],
+                        &openings[opening],
+                        options,
+                        round,
+                    );
+                    if result.is_err() {
+                        failed.store(true, Ordering::Relaxed);
+                    }
+                    if sender.send(result).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+        for result in receiver {
+            let record = result?;
+            crosstable.add(&record);
+            on_game(&record, &crosstable);
+        }
+        Ok(crosstable)
+    })
+}
+
+// Plays one game of a match with the contestants of `seats` (white, black),
+// starting the ones not running yet
+fn play_pairing(
+    players: &[Player],
+    contestants: &mut [Option<Box<dyn Contestant + Send>>],
+    seats: [usize; 2],
+    opening: &Opening,
+    options: &TournamentOptions,
+    round: usize,
+) -> Result<GameRecord, String> {
+    let mut start = |index: usize| match contestants[index].take() {
+        Some(contestant) => Ok(contestant),
+        None => players[index].start(),
+    };
+    let mut white = start(seats[0])?;
+    let mut black = start(seats[1])?;
+    let record = play_game(
+        [&players[seats[0]], &players[seats[1]]],
+        [white.as_mut(), black.as_mut()],
+        0,
+        opening,
+        options,
+        round,
+    );
+    contestants[seats[0]] = Some(white);
+    contestants[seats[1]] = Some(black);
+    let mut record = record?;
+    record.white = seats[0];
+    record.black = seats[1];
+    Ok(record)
+}
+
+// Plays one game from `opening`, `white` being the index of the player with
+// white. A contestant that fails to answer or plays an illegal move loses.
+pub fn play_game(
+    players: [&Player; 2],
+    contestants: [&mut dyn Contestant; 2],
+    white: usize,
+    opening: &Opening,
+    options: &TournamentOptions,
+    round: usize,
+) -> Result<GameRecord, String> {
+    let mut contestants = contestants;
+    for contestant in contestants.iter_mut() {
+        contestant.new_game()?;
+    }
+    let mut game = opening.position()?;
+    let mut actions = opening.actions.clone();
+    let mut hashes = vec![game.hash()];
+    let mut clocks = [options.time_control.map(|tc| tc.time); 2];
+    // moves made by white and black, for the time control
+    let mut moves_made = [0; 2];
+    let mut draw_plies = 0;
+    // plies in a row with a winning score, positive when white wins
+    let mut resign_plies: i32 = 0;
+    let mut played = 0;
+
+    let (outcome, termination) = loop {
+        if let Some(end) = game_over(&mut game, &hashes) {
+            break end;
+        }
+        if options.max_moves.is_some_and(|moves| played >= moves * 2) {
+            break (Outcome::Draw, String::from("adjudication"));
+        }
+        let side = match game.player {
+            Team::White => 0,
+            Team::Black => 1,
+        };
+        let player = if side == 0 { white } else { 1 - white };
+        let go = GoOptions {
+            limits: players[player].limits.clone(),
+            time: clocks,
+            increment: [options.time_control.map(|tc| tc.increment); 2],
+            movestogo: options
+                .time_control
+                .and_then(|tc| tc.moves)
+                .map(|moves| moves - moves_made[side] % moves),
+        };
+
+        let start = Instant::now();
+        let result = contestants[player].play(&game, &go);
+        let elapsed = start.elapsed();
+        let loser = Outcome::Win(opponent(game.player));
+        let result = match result {
+            Ok(result) => result,
+            Err(message) if message.starts_with("illegal move") => {
+                break (loser, String::from("rules infraction"))
+            }
+            Err(_) => break (loser, String::from("abandoned")),
+        };
+        if let (Some(tc), Some(clock)) = (options.time_control, clocks[side]) {
+            if elapsed > clock {
+                break (loser, String::from("time forfeit"));
+            }
+            let mut clock = clock - elapsed + tc.increment;
+            moves_made[side] += 1;
+            if tc.moves.is_some_and(|moves| moves_made[side] % moves == 0) {
+                clock += tc.time;
+            }
+            clocks[side] = Some(clock);
+        }
+        let mut action = match result.best_move {
+            Some(action) => action,
+            None => break (loser, String::from("no move")),
+        };
+        if !game
+            .all_moves()
+            .iter()
+            .any(|legal| legal.key() == action.key())
+        {
+            break (loser, String::from("rules infraction"));
+        }
+        if action.action_type == ActionType::Promotion && action.promotion.is_none() {
+            action.promotion = Some(Rank::Queen);
+        }
+
+        let white_score = if side == 0 {
+            result.score
+        } else {
+            -result.score
+        };
+        if let Some(draw) = options.draw {
+            let move_number = actions.len() / 2 + 1;
+            if move_number >= draw.move_number && white_score.abs() <= draw.score {
+                draw_plies += 1;
+            } else {
+                draw_plies = 0;
+            }
+        }
+        if let Some(resign) = options.resign {
+            let sign = white_score.signum();
+            if white_score.abs() < resign.score {
+                resign_plies = 0;
+            } else if resign_plies.signum() == sign {
+                resign_plies += sign;
+            } else {
+                resign_plies = sign;
+            }
+        }
+
+        game.perform_action(action);
+        actions.push(action);
+        hashes.push(game.hash());
+        played += 1;
+
+        if options.draw.is_some_and(|draw| draw_plies >= draw.plies) {
+            break (Outcome::Draw, String::from("adjudication"));
+        }
+        if let Some(resign) = options.resign {
+            if resign_plies.unsigned_abs() as usize >= resign.plies {
+                let winner = if resign_plies > 0 {
+                    Team::White
+                } else {
+                    Team::Black
+                };
+                break (Outcome::Win(winner), String::from("adjudication"));
+            }
+        }
+    };
+
+    let names = if white == 0 { [0, 1] } else { [1, 0] };
+    let mut tags = vec![
+        (String::from("Event"), options.event.clone()),
+        (String::from("Site"), String::from("?")),
+        (String::from("Date"), String::from("????.??.??")),
+        (String::from("Round"), round.to_string()),
+        (String::from("White"), players[names[0]].name.clone()),
+        (String::from("Black"), players[names[1]].name.clone()),
+        (String::from("Result"), String::from(outcome.pgn_result())),
+    ];
+    if let Some(fen) = &opening.fen {
+        tags.push((String::from("SetUp"), String::from("1")));
+        tags.push((String::from("FEN"), fen.clone()));
+    }
+    if let Some(tc) = options.time_control {
+        tags.push((String::from("TimeControl"), tc.to_string()));
+    }
+    tags.push((String::from("Termination"), termination.clone()));
+    tags.push((String::from("PlyCount"), actions.len().to_string()));
+    Ok(GameRecord {
+        pgn: PgnGame {
+            tags,
+            actions,
+            annotations: vec![],
+            result: String::from(outcome.pgn_result()),
+        },
+        white,
+        black: 1 - white,
+        outcome,
+        termination,
+    })
+}
+
+// The result of a finished game: mate, stalemate, the fifty move rule,
+// threefold repetition, too little material to mate or a tablebase result
+fn game_over(game: &mut Game, hashes: &[u64]) -> Option<(Outcome, String)> {
+    let end = |outcome: Outcome| Some((outcome, String::from("normal")));
+    match game.get_game_state() {
+        GameState::Checkmate => return end(Outcome::Win(opponent(game.player))),
+        GameState::Stalemate | GameState::FiftyRule => return end(Outcome::Draw),
+        _ => {}
+    }
+    let current = hashes[hashes.len() - 1];
+    if hashes.iter().filter(|&&hash| hash == current).count() >= 3 || insufficient_material(game) {
+        return end(Outcome::Draw);
+    }
+    tablebase::adjudicate(game).map(|outcome| (outcome, String::from("adjudication")))
+}
+
+// Kings alone or with a single bishop or knight
+pub fn insufficient_material(game: &Game) -> bool {
+    let mut minors = 0;
+    for square in game.matrix.iter().flatten() {
+        match square.piece.map(|piece| piece.rank) {
+            None | Some(Rank::King) => {}
+            Some(Rank::Bishop) | Some(Rank::Knight) => minors += 1,
+            Some(_) => return false,
+        }
+    }
+    minors <= 1
+}
+
+fn opponent(team: Team) -> Team {
+    match team {
+        Team::White => Team::Black,
+        Team::Black => Team::White,
+    }
+}
@@ -0,0 +1,214 @@
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+use crate::moves::Action;
+
+pub const MATE: i32 = 32000;
+pub const MAX_PLY: i32 = 128;
+// scores beyond this are "mate in n" scores
+pub const MATE_BOUND: i32 = MATE - MAX_PLY;
+
+const BUCKET_SIZE: usize = 4;
+// every entry is two words: the key xor'ed with the data, and the data
+const WORDS_PER_ENTRY: usize = 2;
+// set in the data of every stored entry, since a real entry can pack to 0
+const USED: u64 = 1 << 56;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Entry {
+    pub depth: u8,
+    pub bound: Bound,
+    pub best_move: Option<u16>,
+    pub score: i32,
+    age: u8,
+}
+
+impl Entry {
+    pub fn is_best_move(&self, action: &Action) -> bool {
+        self.best_move == Some(action.key())
+    }
+
+    fn pack(&self) -> u64 {
+        let bound = match self.bound {
+            Bound::Exact => 0,
+            Bound::Lower => 1,
+            Bound::Upper => 2,
+        };
+        (self.score as i16 as u16 as u64)
+            | (self.best_move.unwrap_or(0) as u64) << 16
+            | (self.depth as u64) << 32
+            | bound << 40
+            | (self.age as u64) << 48
+            | USED
+    }
+
+    fn unpack(data: u64) -> Entry {
+        let best_move = (data >> 16) as u16;
+        Entry {
+            score: data as u16 as i16 as i32,
+            best_move: if best_move == 0 {
+                None
+            } else {
+                Some(best_move)
+            },
+            depth: (data >> 32) as u8,
+            bound: match (data >> 40) & 3 {
+                0 => Bound::Exact,
+                1 => Bound::Lower,
+                _ => Bound::Upper,
+            },
+            age: (data >> 48) as u8,
+        }
+    }
+}
+
+// Lockless hash table shared by all search threads. A torn write is
+// detected on probe since the stored key no longer matches.
+pub struct TranspositionTable {
+    words: Vec<AtomicU64>,
+    bucket_count: usize,
+    age: AtomicU8,
+}
+
+impl TranspositionTable {
+    pub fn new(size_mb: usize) -> TranspositionTable {
+        let entry_bytes = WORDS_PER_ENTRY * std::mem::size_of::<u64>();
+        let bucket_count = (size_mb.max(1) * 1024 * 1024 / (entry_bytes * BUCKET_SIZE)).max(1);
+        let words = (0..bucket_count * BUCKET_SIZE * WORDS_PER_ENTRY)
+            .map(|_| AtomicU64::new(0))
+            .collect();
+        TranspositionTable {
+            words,
+            bucket_count,
+            age: AtomicU8::new(0),
+        }
+    }
+
+    pub fn resize(&mut self, size_mb: usize) {
+        *self = TranspositionTable::new(size_mb);
+    }
+
+    pub fn clear(&self) {
+        for word in self.words.iter() {
+            word.store(0, Ordering::Relaxed);
+        }
+        self.age.store(0, Ordering::Relaxed);
+    }
+
+    // Called once per search so entries from earlier searches get replaced first
+    pub fn new_search(&self) {
+        self.age.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn probe(&self, hash: u64, ply: i32) -> Option<Entry> {
+        let bucket = self.bucket_start(hash);
+        for slot in 0..BUCKET_SIZE {
+            let index = (bucket + slot) * WORDS_PER_ENTRY;
+            let key = self.words[index].load(Ordering::Relaxed);
+            let data = self.words[index + 1].load(Ordering::Relaxed);
+            if data & USED != 0 && key ^ data == hash {
+                let mut entry = Entry::unpack(data);
+                entry.score = score_from_tt(entry.score, ply);
+                return Some(entry);
+            }
+        }
+        None
+    }
+
+    pub fn store(
+        &self,
+        hash: u64,
+        depth: i32,
+        bound: Bound,
+        best_move: Option<&Action>,
+        score: i32,
+        ply: i32,
+    ) {
+        let age = self.age.load(Ordering::Relaxed);
+        let bucket = self.bucket_start(hash);
+        let mut replace = bucket;
+        let mut replace_value = i32::MAX;
+
+        for slot in 0..BUCKET_SIZE {
+            let index = (bucket + slot) * WORDS_PER_ENTRY;
+            let data = self.words[index + 1].load(Ordering::Relaxed);
+            let key = self.words[index].load(Ordering::Relaxed) ^ data;
+            if data & USED == 0 || key == hash {
+                replace = bucket + slot;
+                break;
+            }
+            // prefer replacing shallow entries left over from old searches
+            let entry = Entry::unpack(data);
+            let value = entry.depth as i32 - 8 * age.wrapping_sub(entry.age) as i32;
+            if value < replace_value {
+                replace_value = value;
+                replace = bucket + slot;
+            }
+        }
+
+        let index = replace * WORDS_PER_ENTRY;
+        let old_data = self.words[index + 1].load(Ordering::Relaxed);
+        let old_key = self.words[index].load(Ordering::Relaxed) ^ old_data;
+        let mut best_move = best_move.map(|action| action.key());
+        if best_move.is_none() && old_data & USED != 0 && old_key == hash {
+            // keep the move from an earlier search of this position
+            best_move = Entry::unpack(old_data).best_move;
+        }
+
+        let entry = Entry {
+            depth: depth.max(0).min(u8::MAX as i32) as u8,
+            bound,
+            best_move,
+            score: score_to_tt(score, ply),
+            age,
+        };
+        let data = entry.pack();
+        self.words[index].store(hash ^ data, Ordering::Relaxed);
+        self.words[index + 1].store(data, Ordering::Relaxed);
+    }
+
+    // Permille of the table filled by the current search, as reported by uci
+    pub fn hashfull(&self) -> usize {
+        let age = self.age.load(Ordering::Relaxed);
+        let sample = (self.bucket_count * BUCKET_SIZE).min(1000);
+        let used = (0..sample)
+            .filter(|slot| {
+                let data = self.words[slot * WORDS_PER_ENTRY + 1].load(Ordering::Relaxed);
+                data & USED != 0 && Entry::unpack(data).age == age
+            })
+            .count();
+        used * 1000 / sample
+    }
+
+    fn bucket_start(&self, hash: u64) -> usize {
+        (((hash as u128) * (self.bucket_count as u128)) >> 64) as usize * BUCKET_SIZE
+    }
+}
+
+// Mate scores are stored relative to the position instead of the root,
+// so the same entry is correct wherever in the tree it is found
+pub fn score_to_tt(score: i32, ply: i32) -> i32 {
+    if score >= MATE_BOUND {
+        score + ply
+    } else if score <= -MATE_BOUND {
+        score - ply
+    } else {
+        score
+    }
+}
+
+pub fn score_from_tt(score: i32, ply: i32) -> i32 {
+    if score >= MATE_BOUND {
+        score - ply
+    } else if score <= -MATE_BOUND {
+        score + ply
+    } else {
+        score
+    }
+}
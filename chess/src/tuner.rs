@@ -0,0 +1,261 @@
+// Texel tuning of the evaluation weights: quiet positions of real games are
+// labelled with the game's result, and the weights are changed to predict
+// those results better. A score s predicts a result of 1 / (1 + 10^(-k s / 400))
+// for white, k being fitted to the starting weights first. As the evaluation
+// is a sum of weights times coefficients (`eval::features`), every position
+// is kept as its coefficients only.
+use std::thread;
+
+use crate::eval;
+use crate::eval::{Weights, WEIGHT_COUNT};
+use crate::game::{GameState, Rank};
+use crate::moves::ActionType;
+use crate::ordering;
+use crate::pgn;
+use crate::pgn::PgnGame;
+
+// A position's evaluation coefficients and the result of its game for white
+#[derive(Debug, Clone)]
+pub struct TuningPosition {
+    pub features: Vec<(u16, f32)>,
+    pub result: f32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Method {
+    // Adam steps along the gradient of the error
+    GradientDescent,
+    // every weight moved by one in both directions, the change kept if the
+    // error drops, until no change does
+    LocalSearch,
+}
+
+#[derive(Debug, Clone)]
+pub struct TunerOptions {
+    pub method: Method,
+    // steps of the gradient descent, passes over all weights of the local
+    // search
+    pub iterations: usize,
+    // the size of the gradient descent's steps, in centipawns
+    pub learning_rate: f64,
+    pub threads: usize,
+    // fitted to the starting weights if not given
+    pub k: Option<f64>,
+}
+
+impl Default for TunerOptions {
+    fn default() -> Self {
+        TunerOptions {
+            method: Method::GradientDescent,
+            iterations: 1000,
+            learning_rate: 1.0,
+            threads: 1,
+            k: None,
+        }
+    }
+}
+
+// The quiet positions of a game, after its first `skip_plies` plies: the
+// side to move isn't in check and has no capture winning material. Games
+// without a result give none.
+pub fn extract_positions(pgn_game: &PgnGame, skip_plies: usize) -> Vec<TuningPosition> {
+    let result = match pgn_game.white_score() {
+        Some(result) => result as f32,
+        None => return vec![],
+    };
+    let mut game = match pgn_game.start_position() {
+        Ok(game) => game,
+        Err(_) => return vec![],
+    };
+    let mut positions = vec![];
+    for (ply, action) in pgn_game.actions.iter().enumerate() {
+        if ply >= skip_plies && game.get_game_state() == GameState::Active {
+            let quiet = game.all_captures().iter().all(|capture| {
+                capture.action_type != ActionType::Promotion && ordering::see(&game, capture) <= 0
+            });
+            if quiet {
+                positions.push(TuningPosition {
+                    features: eval::features(&game)
+                        .into_iter()
+                        .map(|(index, coefficient)| (index as u16, coefficient as f32))
+                        .collect(),
+                    result,
+                });
+            }
+        }
+        game.perform_action(*action);
+    }
+    positions
+}
+
+// The positions of every game of the pgn files
+pub fn read_positions(
+    filepaths: &[String],
+    skip_plies: usize,
+) -> Result<Vec<TuningPosition>, String> {
+    let mut positions = vec![];
+    for filepath in filepaths.iter() {
+        for pgn_game in pgn::read_games(filepath)?.iter().flatten() {
+            positions.extend(extract_positions(pgn_game, skip_plies));
+        }
+    }
+    Ok(positions)
+}
+
+pub fn sigmoid(score: f64, k: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * score / 400.0))
+}
+
+fn score(position: &TuningPosition, weights: &[f64]) -> f64 {
+    position
+        .features
+        .iter()
+        .map(|&(index, coefficient)| weights[index as usize] * coefficient as f64)
+        .sum()
+}
+
+// Runs `work` on about equal parts of the positions and collects the
+// results in order
+fn parallel<T, F>(positions: &[TuningPosition], threads: usize, work: F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(&[TuningPosition]) -> T + Sync,
+{
+    let size = positions.len().div_ceil(threads.max(1)).max(1);
+    let work = &work;
+    thread::scope(|scope| {
+        let handles: Vec<_> = positions
+            .chunks(size)
+            .map(|chunk| scope.spawn(move || work(chunk)))
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("tuner thread panicked"))
+            .collect()
+    })
+}
+
+// The mean squared difference between the predicted and the real results
+pub fn error(positions: &[TuningPosition], weights: &[f64], k: f64, threads: usize) -> f64 {
+    if positions.is_empty() {
+        return 0.0;
+    }
+    let sums = parallel(positions, threads, |chunk| {
+        chunk
+            .iter()
+            .map(|position| (position.result as f64 - sigmoid(score(position, weights), k)).powi(2))
+            .sum::<f64>()
+    });
+    sums.iter().sum::<f64>() / positions.len() as f64
+}
+
+// The k with the smallest error for the weights, by golden section search
+pub fn optimal_k(positions: &[TuningPosition], weights: &[f64], threads: usize) -> f64 {
+    let ratio = (5f64.sqrt() - 1.0) / 2.0;
+    let (mut low, mut high) = (0.0, 4.0);
+    for _ in 0..40 {
+        let a = high - ratio * (high - low);
+        let b = low + ratio * (high - low);
+        if error(positions, weights, a, threads) < error(positions, weights, b, threads) {
+            high = b;
+        } else {
+            low = a;
+        }
+    }
+    (low + high) / 2.0
+}
+
+fn gradient(positions: &[TuningPosition], weights: &[f64], k: f64, threads: usize) -> Vec<f64> {
+    let parts = parallel(positions, threads, |chunk| {
+        let mut gradient = vec![0.0; WEIGHT_COUNT];
+        for position in chunk {
+            let predicted = sigmoid(score(position, weights), k);
+            let slope = (predicted - position.result as f64)
+                * predicted
+                * (1.0 - predicted)
+                * k
+                * 10f64.ln()
+                / 400.0;
+            for &(index, coefficient) in position.features.iter() {
+                gradient[index as usize] += slope * coefficient as f64;
+            }
+        }
+        gradient
+    });
+    let mut gradient = vec![0.0; WEIGHT_COUNT];
+    for part in parts {
+        for (sum, value) in gradient.iter_mut().zip(part) {
+            *sum += value;
+        }
+    }
+    let scale = 2.0 / positions.len().max(1) as f64;
+    gradient.iter_mut().for_each(|value| *value *= scale);
+    gradient
+}
+
+// Tunes the weights on the positions, calling `on_iteration` with every
+// iteration and its error. The king's value never changes, and weights no
+// position uses keep their value as well.
+pub fn tune<F: FnMut(usize, f64)>(
+    positions: &[TuningPosition],
+    start: &Weights,
+    options: &TunerOptions,
+    mut on_iteration: F,
+) -> Weights {
+    let mut tunable = vec![false; WEIGHT_COUNT];
+    for position in positions {
+        for &(index, _) in position.features.iter() {
+            tunable[index as usize] = true;
+        }
+    }
+    tunable[Rank::King.index()] = false;
+
+    let mut weights: Vec<f64> = start.to_vec().iter().map(|&w| w as f64).collect();
+    let threads = options.threads;
+    let k = options
+        .k
+        .unwrap_or_else(|| optimal_k(positions, &weights, threads));
+    match options.method {
+        Method::GradientDescent => {
+            let (beta1, beta2) = (0.9, 0.999);
+            let mut moment = vec![0.0; WEIGHT_COUNT];
+            let mut velocity = vec![0.0; WEIGHT_COUNT];
+            for iteration in 1..=options.iterations {
+                let gradient = gradient(positions, &weights, k, threads);
+                for i in (0..WEIGHT_COUNT).filter(|&i| tunable[i]) {
+                    moment[i] = beta1 * moment[i] + (1.0 - beta1) * gradient[i];
+                    velocity[i] = beta2 * velocity[i] + (1.0 - beta2) * gradient[i].powi(2);
+                    let moment = moment[i] / (1.0 - beta1.powi(iteration as i32));
+                    let velocity = velocity[i] / (1.0 - beta2.powi(iteration as i32));
+                    weights[i] -= options.learning_rate * moment / (velocity.sqrt() + 1e-8);
+                }
+                on_iteration(iteration, error(positions, &weights, k, threads));
+            }
+        }
+        Method::LocalSearch => {
+            let mut best = error(positions, &weights, k, threads);
+            for iteration in 1..=options.iterations {
+                let mut improved = false;
+                for i in (0..WEIGHT_COUNT).filter(|&i| tunable[i]) {
+                    for step in [1.0, -1.0].iter() {
+                        weights[i] += step;
+                        let error = error(positions, &weights, k, threads);
+                        if error < best {
+                            best = error;
+                            improved = true;
+                            break;
+                        }
+                        weights[i] -= step;
+                    }
+                }
+                on_iteration(iteration, best);
+                if !improved {
+                    break;
+                }
+            }
+        }
+    }
+
+    let values: Vec<i32> = weights.iter().map(|w| w.round() as i32).collect();
+    Weights::from_slice(&values).unwrap_or_else(|_| start.clone())
+}
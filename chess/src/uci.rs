@@ -0,0 +1,444 @@
+use std::io;
+use std::io::prelude::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::engine::Engine;
+use crate::game::{Game, Team};
+use crate::moves::Action;
+use crate::opening_book::{OpeningBook, Selection};
+use crate::search::{is_mate_score, AnalysisLine, SearchLimits, SearchResult};
+use crate::skill::{MAX_ELO, MAX_LEVEL, MIN_ELO};
+use crate::timeman::Clock;
+use crate::transposition::MATE;
+
+type Output = Arc<Mutex<Box<dyn Write + Send>>>;
+
+// Universal Chess Interface front-end. Searches run on a background thread
+// so "stop", "ponderhit" and "isready" are answered while searching.
+pub struct Uci {
+    engine: Arc<Mutex<Engine>>,
+    stop: Arc<AtomicBool>,
+    game: Game,
+    out: Output,
+    search_thread: Option<JoinHandle<()>>,
+    // set while pondering or searching "infinite", bestmove waits until it is cleared
+    hold: Arc<AtomicBool>,
+    // the engine's, set while pondering so the time limits wait for "ponderhit"
+    ponder: Arc<AtomicBool>,
+    book: Option<OpeningBook>,
+    own_book: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+struct GoCommand {
+    limits: SearchLimits,
+    time: [Option<u64>; 2],
+    increment: [Option<u64>; 2],
+    movestogo: Option<u64>,
+    infinite: bool,
+    ponder: bool,
+}
+
+impl Uci {
+    pub fn new(out: Box<dyn Write + Send>) -> Uci {
+        let engine = Engine::default();
+        Uci {
+            stop: engine.stop_handle(),
+            ponder: engine.ponder_handle(),
+            engine: Arc::new(Mutex::new(engine)),
+            game: Game::new(),
+            out: Arc::new(Mutex::new(out)),
+            search_thread: None,
+            hold: Arc::new(AtomicBool::new(false)),
+            book: None,
+            own_book: false,
+        }
+    }
+
+    // Handles one line of input, returns false once the engine should quit
+    pub fn handle(&mut self, line: &str) -> bool {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let command = match tokens.first() {
+            Some(command) => *command,
+            None => return true,
+        };
+        let result = match command {
+            "uci" => {
+                self.identify();
+                Ok(())
+            }
+            "isready" => {
+                send(&self.out, "readyok");
+                Ok(())
+            }
+            "ucinewgame" => {
+                self.stop_search();
+                self.engine.lock().unwrap().new_game();
+                self.game = Game::new();
+                Ok(())
+            }
+            "position" => self.position(&tokens[1..]),
+            "go" => self.go(&tokens[1..]),
+            "stop" => {
+                self.stop_search();
+                Ok(())
+            }
+            "ponderhit" => {
+                self.ponderhit();
+                Ok(())
+            }
+            "setoption" => self.set_option(&tokens[1..]),
+            "quit" => {
+                self.stop_search();
+                return false;
+            }
+            _ => Err(format!("unknown command {}", command)),
+        };
+        if let Err(message) = result {
+            send(&self.out, &format!("info string {}", message));
+        }
+        true
+    }
+
+    // Blocks until the running search (if any) has sent its bestmove
+    pub fn wait_for_search(&mut self) {
+        if let Some(handle) = self.search_thread.take() {
+            let _ = handle.join();
+        }
+    }
+
+    fn identify(&self) {
+        let options = self.engine.lock().unwrap().options.clone();
+        send(
+            &self.out,
+            &format!("id name chess {}", env!("CARGO_PKG_VERSION")),
+        );
+        send(
+            &self.out,
+            &format!("id author {}", env!("CARGO_PKG_AUTHORS")),
+        );
+        send(
+            &self.out,
+            &format!(
+                "option name Hash type spin default {} min 1 max 4096",
+                options.hash_mb
+            ),
+        );
+        send(
+            &self.out,
+            &format!(
+                "option name Threads type spin default {} min 1 max 256",
+                options.threads
+            ),
+        );
+        send(
+            &self.out,
+            &format!(
+                "option name MultiPV type spin default {} min 1 max 256",
+                options.multi_pv
+            ),
+        );
+        send(
+            &self.out,
+            &format!(
+                "option name Skill Level type spin default {} min 0 max {}",
+                options.skill_level, MAX_LEVEL
+            ),
+        );
+        send(
+            &self.out,
+            &format!(
+                "option name UCI_LimitStrength type check default {}",
+                options.limit_strength
+            ),
+        );
+        send(
+            &self.out,
+            &format!(
+                "option name UCI_Elo type spin default {} min {} max {}",
+                options.elo, MIN_ELO, MAX_ELO
+            ),
+        );
+        send(
+            &self.out,
+            &format!(
+                "option name Move Overhead type spin default {} min 0 max 5000",
+                options.move_overhead
+            ),
+        );
+        send(&self.out, "option name Clear Hash type button");
+        send(&self.out, "option name Ponder type check default false");
+        send(&self.out, "option name OwnBook type check default false");
+        send(&self.out, "option name BookFile type string default <empty>");
+        send(&self.out, "option name SyzygyPath type string default <empty>");
+        send(&self.out, "option name EvalFile type string default <empty>");
+        send(&self.out, "uciok");
+    }
+
+    fn position(&mut self, tokens: &[&str]) -> Result<(), String> {
+        let moves_index = tokens.iter().position(|&t| t == "moves");
+        let setup = &tokens[..moves_index.unwrap_or(tokens.len())];
+        let mut game = match setup.first() {
+            Some(&"startpos") => Game::new(),
+            Some(&"fen") => Game::from_fen(&setup[1..].join(" "))?,
+            _ => return Err(String::from("position needs startpos or fen")),
+        };
+        if let Some(index) = moves_index {
+            for text in tokens[index + 1..].iter() {
+                let action = game.action_from_uci(text)?;
+                game.perform_action(action);
+            }
+        }
+        self.stop_search();
+        self.game = game;
+        Ok(())
+    }
+
+    fn set_option(&mut self, tokens: &[&str]) -> Result<(), String> {
+        let name_index = tokens.iter().position(|&t| t == "name");
+        let value_index = tokens.iter().position(|&t| t == "value");
+        let name = match name_index {
+            Some(index) => tokens[index + 1..value_index.unwrap_or(tokens.len())].join(" "),
+            None => return Err(String::from("setoption needs a name")),
+        };
+        let value = match value_index {
+            Some(index) => tokens[index + 1..].join(" "),
+            None => String::new(),
+        };
+        self.stop_search();
+        let mut engine = self.engine.lock().unwrap();
+        match name.to_lowercase().as_str() {
+            "clear hash" => {
+                engine.new_game();
+                Ok(())
+            }
+            // pondering is started by the gui with "go ponder"
+            "ponder" => Ok(()),
+            "ownbook" => {
+                self.own_book = value == "true";
+                Ok(())
+            }
+            "bookfile" => {
+                self.book = match value.as_str() {
+                    "" | "<empty>" => None,
+                    path => Some(OpeningBook::open(path)?),
+                };
+                Ok(())
+            }
+            _ => engine.set_option(&name, &value),
+        }
+    }
+
+    fn go(&mut self, tokens: &[&str]) -> Result<(), String> {
+        let command = parse_go(tokens)?;
+        self.stop_search();
+
+        if !command.ponder && !command.infinite {
+            if let Some(action) = self.book_move() {
+                send(&self.out, &format!("bestmove {}", action.to_uci()));
+                return Ok(());
+            }
+        }
+
+        let limits = self.limits_for(&command);
+        let limits = if command.infinite {
+            SearchLimits {
+                movetime: None,
+                clock: None,
+                ..limits
+            }
+        } else {
+            limits
+        };
+
+        self.stop.store(false, Ordering::Relaxed);
+        self.hold
+            .store(command.ponder || command.infinite, Ordering::Relaxed);
+        self.ponder.store(command.ponder, Ordering::Relaxed);
+
+        let engine = Arc::clone(&self.engine);
+        let out = Arc::clone(&self.out);
+        let hold = Arc::clone(&self.hold);
+        let stop = Arc::clone(&self.stop);
+        let game = self.game.clone();
+        self.search_thread = Some(thread::spawn(move || {
+            let engine = engine.lock().unwrap();
+            let start = Instant::now();
+            let result = engine.search_with_info(&game, &limits, |result| {
+                let hashfull = engine.transposition_table().hashfull();
+                for (index, line) in result.lines.iter().enumerate() {
+                    send(
+                        &out,
+                        &info_line(result, index + 1, line, start.elapsed(), hashfull),
+                    );
+                }
+            });
+            // bestmove isn't allowed before the gui ends pondering or an infinite search
+            while hold.load(Ordering::Relaxed) && !stop.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(1));
+            }
+            stop.store(false, Ordering::Relaxed);
+            send(&out, &bestmove_line(&result));
+        }));
+        Ok(())
+    }
+
+    fn book_move(&self) -> Option<Action> {
+        if !self.own_book {
+            return None;
+        }
+        self.book
+            .as_ref()?
+            .select(&self.game, Selection::WeightedRandom)
+    }
+
+    // The clock of the side to move, the search allots its time out of it
+    fn limits_for(&self, command: &GoCommand) -> SearchLimits {
+        let mut limits = command.limits.clone();
+        let side = match self.game.player {
+            Team::White => 0,
+            Team::Black => 1,
+        };
+        if limits.movetime.is_none() {
+            if let Some(time) = command.time[side] {
+                limits.clock = Some(Clock {
+                    time: Duration::from_millis(time),
+                    increment: Duration::from_millis(command.increment[side].unwrap_or(0)),
+                    movestogo: command.movestogo,
+                });
+            }
+        }
+        limits
+    }
+
+    // The search keeps running, its clock starting now
+    fn ponderhit(&mut self) {
+        self.ponder.store(false, Ordering::Relaxed);
+        self.hold.store(false, Ordering::Relaxed);
+    }
+
+    fn stop_search(&mut self) {
+        if self.search_thread.is_some() {
+            self.hold.store(false, Ordering::Relaxed);
+            self.stop.store(true, Ordering::Relaxed);
+            self.wait_for_search();
+            self.stop.store(false, Ordering::Relaxed);
+        }
+    }
+}
+
+fn parse_go(tokens: &[&str]) -> Result<GoCommand, String> {
+    let mut command = GoCommand::default();
+    let mut i = 0;
+    while i < tokens.len() {
+        let value = || -> Result<u64, String> {
+            let text = tokens
+                .get(i + 1)
+                .ok_or(format!("{} needs a value", tokens[i]))?;
+            // clocks can go negative when the engine is late
+            let number: i64 = text
+                .parse()
+                .map_err(|_| format!("{} is not a number", text))?;
+            Ok(number.max(0) as u64)
+        };
+        match tokens[i] {
+            "infinite" => command.infinite = true,
+            "ponder" => command.ponder = true,
+            "depth" => command.limits.depth = Some(value()? as i32),
+            "nodes" => command.limits.nodes = Some(value()?),
+            "movetime" => command.limits.movetime = Some(Duration::from_millis(value()?)),
+            "wtime" => command.time[0] = Some(value()?),
+            "btime" => command.time[1] = Some(value()?),
+            "winc" => command.increment[0] = Some(value()?),
+            "binc" => command.increment[1] = Some(value()?),
+            "movestogo" => command.movestogo = Some(value()?),
+            _ => {
+                i += 1;
+                continue;
+            }
+        }
+        i += match tokens[i] {
+            "infinite" | "ponder" => 1,
+            _ => 2,
+        };
+    }
+    Ok(command)
+}
+
+pub fn score_to_uci(score: i32) -> String {
+    if is_mate_score(score) {
+        let moves = if score > 0 {
+            (MATE - score + 1) / 2
+        } else {
+            -(MATE + score) / 2
+        };
+        format!("mate {}", moves)
+    } else {
+        format!("cp {}", score)
+    }
+}
+
+fn pv_to_uci(pv: &[Action]) -> String {
+    pv.iter()
+        .map(|action| action.to_uci())
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+fn info_line(
+    result: &SearchResult,
+    multi_pv: usize,
+    line: &AnalysisLine,
+    elapsed: Duration,
+    hashfull: usize,
+) -> String {
+    let millis = elapsed.as_millis() as u64;
+    let nps = result.nodes * 1000 / millis.max(1);
+    format!(
+        "info depth {} multipv {} score {} nodes {} nps {} time {} hashfull {} pv {}",
+        result.depth,
+        multi_pv,
+        score_to_uci(line.score),
+        result.nodes,
+        nps,
+        millis,
+        hashfull,
+        pv_to_uci(&line.pv)
+    )
+}
+
+fn bestmove_line(result: &SearchResult) -> String {
+    match result.best_move {
+        Some(action) => match result.ponder_move() {
+            Some(ponder) => format!("bestmove {} ponder {}", action.to_uci(), ponder.to_uci()),
+            None => format!("bestmove {}", action.to_uci()),
+        },
+        None => String::from("bestmove 0000"),
+    }
+}
+
+fn send(out: &Output, line: &str) {
+    let mut out = out.lock().unwrap();
+    let _ = writeln!(out, "{}", line);
+    let _ = out.flush();
+}
+
+// Reads commands from stdin until "quit"
+pub fn run() {
+    let mut uci = Uci::new(Box::new(io::stdout()));
+    for line in io::stdin().lock().lines() {
+        match line {
+            Ok(line) => {
+                if !uci.handle(&line) {
+                    break;
+                }
+            }
+            Err(_) => break,
+        }
+    }
+    uci.handle("quit");
+}
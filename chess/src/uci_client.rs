@@ -0,0 +1,330 @@
+// Talks to another UCI engine running as a subprocess, to play against it or
+// use it for analysis. Its output is read by a thread so every wait for an
+// answer can time out.
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::game::Game;
+use crate::moves::Action;
+use crate::search::{AnalysisLine, SearchLimits, SearchResult};
+use crate::transposition::MATE;
+
+// Search limits and clock times of a go command
+#[derive(Debug, Clone, Default)]
+pub struct GoOptions {
+    pub limits: SearchLimits,
+    // time left and increment of white and black
+    pub time: [Option<Duration>; 2],
+    pub increment: [Option<Duration>; 2],
+    pub movestogo: Option<u32>,
+}
+
+pub struct UciClient {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+    // the name and author the engine gave in the handshake
+    pub name: String,
+    pub author: String,
+    // the option lines of the handshake without "option name"
+    pub options: Vec<String>,
+    // the longest wait for uciok and readyok
+    pub timeout: Duration,
+}
+
+impl UciClient {
+    // Starts the engine and waits for the end of the handshake
+    pub fn spawn(program: &str, args: &[&str]) -> Result<UciClient, String> {
+        UciClient::spawn_with_timeout(program, args, Duration::from_secs(10))
+    }
+
+    pub fn spawn_with_timeout(
+        program: &str,
+        args: &[&str],
+        timeout: Duration,
+    ) -> Result<UciClient, String> {
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("could not start {}: {}", program, e))?;
+        let stdin = child.stdin.take().ok_or("no stdin for the engine")?;
+        let stdout = child.stdout.take().ok_or("no stdout for the engine")?;
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                match line {
+                    Ok(line) => {
+                        if sender.send(line).is_err() {
+                            break;
+                        }
+                    }
+                    Err(_) => break,
+                }
+            }
+        });
+
+        let mut client = UciClient {
+            child,
+            stdin,
+            lines,
+            name: String::from(program),
+            author: String::new(),
+            options: vec![],
+            timeout,
+        };
+        client.send("uci")?;
+        let deadline = Instant::now() + timeout;
+        loop {
+            let line = client.read_line(deadline)?;
+            if line.trim() == "uciok" {
+                break;
+            } else if let Some(name) = line.strip_prefix("id name ") {
+                client.name = name.trim().to_string();
+            } else if let Some(author) = line.strip_prefix("id author ") {
+                client.author = author.trim().to_string();
+            } else if let Some(option) = line.strip_prefix("option name ") {
+                client.options.push(option.trim().to_string());
+            }
+        }
+        Ok(client)
+    }
+
+    pub fn send(&mut self, command: &str) -> Result<(), String> {
+        writeln!(self.stdin, "{}", command)
+            .and_then(|_| self.stdin.flush())
+            .map_err(|e| format!("could not write to {}: {}", self.name, e))
+    }
+
+    pub fn set_option(&mut self, name: &str, value: &str) -> Result<(), String> {
+        self.send(&format!("setoption name {} value {}", name, value))
+    }
+
+    // Waits until the engine answers isready
+    pub fn is_ready(&mut self) -> Result<(), String> {
+        self.send("isready")?;
+        let deadline = Instant::now() + self.timeout;
+        while self.read_line(deadline)?.trim() != "readyok" {}
+        Ok(())
+    }
+
+    pub fn new_game(&mut self) -> Result<(), String> {
+        self.send("ucinewgame")?;
+        self.is_ready()
+    }
+
+    // Searches the position of `game` and returns the bestmove with the
+    // last info the engine sent. Without a bestmove within `timeout` the
+    // engine is told to stop, and if it still doesn't answer it's an error.
+    // A bestmove that isn't legal is an error as well.
+    pub fn go(
+        &mut self,
+        game: &Game,
+        options: &GoOptions,
+        timeout: Duration,
+    ) -> Result<SearchResult, String> {
+        self.send(&position_command(game))?;
+        self.send(&go_command(options))?;
+        let mut result = SearchResult::default();
+        let mut deadline = Instant::now() + timeout;
+        let mut stopped = false;
+        loop {
+            let line = match self.read_line(deadline) {
+                Ok(line) => line,
+                Err(_) if !stopped => {
+                    self.send("stop")?;
+                    stopped = true;
+                    deadline = Instant::now() + Duration::from_secs(1);
+                    continue;
+                }
+                Err(message) => return Err(message),
+            };
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            match tokens.first() {
+                Some(&"info") => parse_info(game, &tokens[1..], &mut result),
+                Some(&"bestmove") => {
+                    result.best_move = match tokens.get(1) {
+                        None | Some(&"0000") | Some(&"(none)") => None,
+                        Some(text) => Some(
+                            game.clone()
+                                .action_from_uci(text)
+                                .map_err(|_| format!("illegal move {} by {}", text, self.name))?,
+                        ),
+                    };
+                    if let Some(best) = result.best_move {
+                        if result.pv.first().map(Action::key) != Some(best.key()) {
+                            result.pv = vec![best];
+                        }
+                    }
+                    return Ok(result);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn read_line(&mut self, deadline: Instant) -> Result<String, String> {
+        let wait = deadline.saturating_duration_since(Instant::now());
+        match self.lines.recv_timeout(wait) {
+            Ok(line) => Ok(line),
+            Err(RecvTimeoutError::Timeout) => Err(format!("{} didn't answer in time", self.name)),
+            Err(RecvTimeoutError::Disconnected) => Err(format!("{} exited", self.name)),
+        }
+    }
+}
+
+impl Drop for UciClient {
+    // Asks the engine to quit and kills it if it doesn't within a second
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while Instant::now() < deadline {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+// "position startpos moves ..." or "position fen ... moves ..." with the
+// moves of the game's history
+pub fn position_command(game: &Game) -> String {
+    let mut start = game.clone();
+    while start.undo().is_some() {}
+    let fen = start.to_fen();
+    let mut command = if fen == Game::new().to_fen() {
+        String::from("position startpos")
+    } else {
+        format!("position fen {}", fen)
+    };
+    if !game.history.is_empty() {
+        command.push_str(" moves");
+        for action in game.history.iter() {
+            command.push(' ');
+            command.push_str(&action.to_uci());
+        }
+    }
+    command
+}
+
+pub fn go_command(options: &GoOptions) -> String {
+    let mut command = String::from("go");
+    let limits = &options.limits;
+    let millis = |duration: Duration| duration.as_millis().to_string();
+    let fields = [
+        ("wtime", options.time[0].map(millis)),
+        ("btime", options.time[1].map(millis)),
+        ("winc", options.increment[0].map(millis)),
+        ("binc", options.increment[1].map(millis)),
+        ("movestogo", options.movestogo.map(|moves| moves.to_string())),
+        ("depth", limits.depth.map(|depth| depth.to_string())),
+        ("nodes", limits.nodes.map(|nodes| nodes.to_string())),
+        ("movetime", limits.movetime.map(millis)),
+    ];
+    let mut limited = false;
+    for (name, value) in fields.iter() {
+        if let Some(value) = value {
+            command.push_str(&format!(" {} {}", name, value));
+            limited = true;
+        }
+    }
+    if !limited {
+        command.push_str(" infinite");
+    }
+    command
+}
+
+// "cp 25" or "mate -3" as a score of the search
+pub fn score_from_uci(kind: &str, value: i32) -> Option<i32> {
+    match kind {
+        "cp" => Some(value),
+        "mate" if value > 0 => Some(MATE - (2 * value - 1)),
+        "mate" => Some(-MATE - 2 * value),
+        _ => None,
+    }
+}
+
+// Adds an info line to the result: depth, score, nodes and the pv (as far
+// as its moves are legal), each multipv line in `lines`
+fn parse_info(game: &Game, tokens: &[&str], result: &mut SearchResult) {
+    let mut multi_pv = 1;
+    let mut score: Option<i32> = None;
+    let mut pv: Option<Vec<Action>> = None;
+    let mut i = 0;
+    while i < tokens.len() {
+        let number = |offset: usize| tokens.get(i + offset).and_then(|t| t.parse::<i64>().ok());
+        match tokens[i] {
+            "depth" => {
+                if let Some(depth) = number(1) {
+                    result.depth = depth as i32;
+                }
+                i += 2;
+            }
+            "nodes" => {
+                if let Some(nodes) = number(1) {
+                    result.nodes = nodes.max(0) as u64;
+                }
+                i += 2;
+            }
+            "multipv" => {
+                multi_pv = number(1).unwrap_or(1).max(1) as usize;
+                i += 2;
+            }
+            "score" => {
+                if let (Some(kind), Some(value)) = (tokens.get(i + 1), number(2)) {
+                    score = score_from_uci(kind, value as i32);
+                }
+                // a following lowerbound or upperbound is skipped as unknown
+                i += 3;
+            }
+            "pv" => {
+                let mut position = game.clone();
+                let mut actions = vec![];
+                for text in tokens[i + 1..].iter() {
+                    match position.action_from_uci(text) {
+                        Ok(action) => {
+                            position.perform_action(action);
+                            actions.push(action);
+                        }
+                        Err(_) => break,
+                    }
+                }
+                pv = Some(actions);
+                break;
+            }
+            // the rest of the line is free text
+            "string" => break,
+            _ => i += 1,
+        }
+    }
+
+    let (score, pv) = match (score, pv) {
+        (Some(score), Some(pv)) if !pv.is_empty() => (score, pv),
+        _ => return,
+    };
+    if multi_pv == 1 {
+        result.score = score;
+        result.pv = pv.clone();
+        result.lines.clear();
+    }
+    let line = AnalysisLine {
+        action: pv[0],
+        score,
+        pv,
+    };
+    if multi_pv <= result.lines.len() {
+        result.lines[multi_pv - 1] = line;
+    } else {
+        result.lines.push(line);
+    }
+}
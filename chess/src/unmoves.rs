@@ -0,0 +1,571 @@
+// Retrograde move generation: the positions the current one can have been
+// reached from, for retro-analysis, proof games and tablebase generation.
+use crate::game::{self, Game, Piece, Rank, Team};
+use crate::moves::{Action, ActionType};
+
+// A predecessor of a position
+#[derive(Debug, Clone)]
+pub struct Unmove {
+    // the position before the last action, with the side that made it to move
+    pub game: Game,
+    // the legal action of `game` that leads to the current position
+    pub action: Action,
+}
+
+type Board = [[Option<Piece>; 8]; 8];
+
+// A predecessor that still has to pass the legality checks
+struct Candidate {
+    board: Board,
+    from: (isize, isize),
+    to: (isize, isize),
+    action_type: ActionType,
+    promotion: Option<Rank>,
+    // the castling right the predecessor needs besides the current ones
+    castling: Option<usize>,
+    en_passant: Option<(isize, isize)>,
+    // captures and pawn moves reset the halfmove clock
+    zeroing: bool,
+}
+
+const KING_STEPS: [(isize, isize); 8] = [
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+];
+const KNIGHT_STEPS: [(isize, isize); 8] = [
+    (1, 2),
+    (2, 1),
+    (2, -1),
+    (1, -2),
+    (-1, -2),
+    (-2, -1),
+    (-2, 1),
+    (-1, 2),
+];
+const STRAIGHT: [(isize, isize); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+const DIAGONAL: [(isize, isize); 4] = [(1, 1), (-1, 1), (-1, -1), (1, -1)];
+const UNCAPTURED: [Rank; 5] = [
+    Rank::Queen,
+    Rank::Rook,
+    Rank::Bishop,
+    Rank::Knight,
+    Rank::Pawn,
+];
+
+// Every legal predecessor of the position: the last action taken back with
+// each piece the side to move can have lost on its target square, promotions
+// turned back into pawns, en passant captures and castling. The predecessors
+// keep the current castling rights plus the one a castling used up, and come
+// with and without each right the last action gave up by moving a king or
+// rook from its square or taking a rook on its corner. Each also comes with
+// the en passant square of every pawn of the side to move that can have just
+// made a double step. With an en passant square in the position the last
+// action can only have been that double step.
+//
+// The halfmove clock of a predecessor is one less, or zero before a capture
+// or pawn move and after a double step; it isn't used to rule predecessors out, as most positions set
+// up from a fen have a made up clock.
+pub fn generate_unmoves(game: &Game) -> Vec<Unmove> {
+    let mover = opponent(game.player);
+    let mover_king = king_square(game, mover);
+    let mut probe = game.clone();
+    probe.player = mover;
+    if probe.check_square_attacked(mover_king) {
+        // the side that just moved left its king in check
+        return vec![];
+    }
+
+    let mut board: Board = [[None; 8]; 8];
+    for (x, column) in game.matrix.iter().enumerate() {
+        for (y, square) in column.iter().enumerate() {
+            board[x][y] = square.piece;
+        }
+    }
+    let rights = game.castling_rights();
+
+    let mut candidates: Vec<Candidate> = vec![];
+    if let Some(skipped) = game.en_passant_square() {
+        let forward = forward(mover);
+        let from = (skipped.0, skipped.1 - forward);
+        let to = (skipped.0, skipped.1 + forward);
+        if holds(&board, to, mover, Rank::Pawn)
+            && at(&board, skipped).is_none()
+            && at(&board, from).is_none()
+        {
+            let mut candidate = retraction(&board, from, to, None, ActionType::Regular);
+            candidate.zeroing = true;
+            candidates.push(candidate);
+        }
+    } else {
+        for x in 0..8 {
+            for y in 0..8 {
+                let piece = match board[x][y] {
+                    Some(piece) if piece.team == mover => piece,
+                    _ => continue,
+                };
+                let to = (x as isize, y as isize);
+                if keeps_castling_right(&rights, mover, to) {
+                    continue;
+                }
+                match piece.rank {
+                    Rank::Pawn => pawn_unmoves(&board, to, mover, &mut candidates),
+                    _ => {
+                        piece_unmoves(&board, to, piece, &mut candidates);
+                        if to.1 == last_row(mover) && piece.rank != Rank::King {
+                            promotion_unmoves(&board, to, piece, &mut candidates);
+                        }
+                        if piece.rank == Rank::King
+                            && rights[right_offset(mover)..][..2] == [false, false]
+                        {
+                            castling_unmoves(&board, to, mover, &mut candidates);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let fen = game.to_fen();
+    let fields: Vec<&str> = fen.split_whitespace().collect();
+    let halfmove: i32 = fields[4].parse().unwrap_or(0);
+    let mut fullmove: i32 = fields[5].parse().unwrap_or(1);
+    if mover == Team::Black {
+        fullmove = (fullmove - 1).max(1);
+    }
+
+    let mut unmoves: Vec<Unmove> = vec![];
+    for candidate in candidates {
+        for castling in castling_variants(&candidate, rights, mover) {
+            for en_passant in en_passant_variants(&candidate, mover) {
+                // a double step of the other side came just before
+                let clock = if candidate.zeroing || en_passant != candidate.en_passant {
+                    0
+                } else {
+                    (halfmove - 1).max(0)
+                };
+                let fen = to_fen(
+                    &candidate.board,
+                    mover,
+                    castling,
+                    en_passant,
+                    clock,
+                    fullmove,
+                );
+                if let Some(unmove) = legal_unmove(&fen, &candidate) {
+                    unmoves.push(unmove);
+                }
+            }
+        }
+    }
+    unmoves
+}
+
+// The castling rights the predecessor can have: the current ones and the one
+// a castling used up, with or without each right the action gave up by
+// moving the king or a rook off its square or taking a rook on its corner
+fn castling_variants(candidate: &Candidate, rights: [bool; 4], mover: Team) -> Vec<[bool; 4]> {
+    let mut required = rights;
+    if let Some(right) = candidate.castling {
+        required[right] = true;
+    }
+    let mut optional: Vec<usize> = vec![];
+    for (right, &kept) in required.iter().enumerate() {
+        let team = if right < 2 { Team::White } else { Team::Black };
+        let row = last_row(opponent(team));
+        let king = (4, row);
+        let corner = (if right % 2 == 0 { 7 } else { 0 }, row);
+        if kept
+            || !holds(&candidate.board, king, team, Rank::King)
+            || !holds(&candidate.board, corner, team, Rank::Rook)
+        {
+            continue;
+        }
+        let given_up = if team == mover {
+            candidate.from == king || candidate.from == corner
+        } else {
+            candidate.to == corner
+        };
+        if given_up {
+            optional.push(right);
+        }
+    }
+
+    let mut variants = vec![];
+    for subset in 0..1 << optional.len() {
+        let mut castling = required;
+        for (i, &right) in optional.iter().enumerate() {
+            castling[right] = subset & (1 << i) != 0;
+        }
+        variants.push(castling);
+    }
+    variants
+}
+
+// The en passant squares the predecessor can have: the one an en passant
+// capture needs, or else none or the square any pawn of the other side
+// skipped if its double step was the action before
+fn en_passant_variants(candidate: &Candidate, mover: Team) -> Vec<Option<(isize, isize)>> {
+    if candidate.en_passant.is_some() {
+        return vec![candidate.en_passant];
+    }
+    let other = opponent(mover);
+    let row = en_passant_row(mover);
+    let mut variants = vec![None];
+    for x in 0..8 {
+        let (origin, skipped, landed) = (
+            (x, row - forward(other)),
+            (x, row),
+            (x, row + forward(other)),
+        );
+        if holds(&candidate.board, landed, other, Rank::Pawn)
+            && at(&candidate.board, skipped).is_none()
+            && at(&candidate.board, origin).is_none()
+        {
+            variants.push(Some(skipped));
+        }
+    }
+    variants
+}
+
+// The predecessor if the side not to move isn't in check there and the
+// action is one of its legal moves
+fn legal_unmove(fen: &str, candidate: &Candidate) -> Option<Unmove> {
+    let mut game = Game::from_fen(fen).ok()?;
+    let mut probe = game.clone();
+    probe.player = opponent(game.player);
+    if probe.check_square_attacked(king_square(&probe, probe.player)) {
+        return None;
+    }
+    let mut action = game.all_moves().into_iter().find(|action| {
+        action.from.coordinate == candidate.from
+            && action.to.coordinate == candidate.to
+            && action.action_type == candidate.action_type
+    })?;
+    if candidate.promotion.is_some() {
+        action.promotion = candidate.promotion;
+    }
+    Some(Unmove { game, action })
+}
+
+fn pawn_unmoves(board: &Board, to: (isize, isize), mover: Team, candidates: &mut Vec<Candidate>) {
+    let forward = forward(mover);
+    let from_row = to.1 - forward;
+    // pawns never stand on their first row
+    if from_row == last_row(opponent(mover)) {
+        return;
+    }
+    let from = (to.0, from_row);
+    if at(board, from).is_none() {
+        let mut candidate = retraction(board, from, to, None, ActionType::Regular);
+        candidate.zeroing = true;
+        candidates.push(candidate);
+    }
+    for dx in [-1, 1].iter() {
+        let from = (to.0 + dx, from_row);
+        if !game::not_out_of_bounds(from.0, from.1) || at(board, from).is_some() {
+            continue;
+        }
+        for &rank in uncaptures(board, opponent(mover), to).iter() {
+            let mut candidate = retraction(board, from, to, Some(rank), ActionType::Regular);
+            candidate.zeroing = true;
+            candidates.push(candidate);
+        }
+        // en passant: the captured pawn stood next to `from` after its double
+        // step over `to`
+        let behind = (to.0, to.1 + forward);
+        let captured = (to.0, from_row);
+        if to.1 == en_passant_row(mover)
+            && at(board, behind).is_none()
+            && at(board, captured).is_none()
+            && can_add(board, opponent(mover), Rank::Pawn)
+        {
+            let mut candidate = retraction(board, from, to, None, ActionType::Enpassant);
+            candidate.board[captured.0 as usize][captured.1 as usize] = Some(pawn(opponent(mover)));
+            candidate.en_passant = Some(to);
+            candidate.zeroing = true;
+            candidates.push(candidate);
+        }
+    }
+}
+
+fn promotion_unmoves(
+    board: &Board,
+    to: (isize, isize),
+    piece: Piece,
+    candidates: &mut Vec<Candidate>,
+) {
+    if !can_add(board, piece.team, Rank::Pawn) {
+        return;
+    }
+    let from_row = to.1 - forward(piece.team);
+    for dx in [-1, 0, 1].iter() {
+        let from = (to.0 + dx, from_row);
+        if !game::not_out_of_bounds(from.0, from.1) || at(board, from).is_some() {
+            continue;
+        }
+        let captures: Vec<Option<Rank>> = if *dx == 0 {
+            vec![None]
+        } else {
+            uncaptures(board, opponent(piece.team), to)
+                .into_iter()
+                .map(Some)
+                .collect()
+        };
+        for captured in captures {
+            let mut candidate = retraction(board, from, to, captured, ActionType::Promotion);
+            candidate.board[from.0 as usize][from.1 as usize] = Some(pawn(piece.team));
+            candidate.promotion = Some(piece.rank);
+            candidate.zeroing = true;
+            candidates.push(candidate);
+        }
+    }
+}
+
+fn piece_unmoves(board: &Board, to: (isize, isize), piece: Piece, candidates: &mut Vec<Candidate>) {
+    let mut origins: Vec<(isize, isize)> = vec![];
+    let (directions, slides): (Vec<(isize, isize)>, bool) = match piece.rank {
+        Rank::King => (KING_STEPS.to_vec(), false),
+        Rank::Knight => (KNIGHT_STEPS.to_vec(), false),
+        Rank::Rook => (STRAIGHT.to_vec(), true),
+        Rank::Bishop => (DIAGONAL.to_vec(), true),
+        Rank::Queen => (KING_STEPS.to_vec(), true),
+        Rank::Pawn => return,
+    };
+    for (dx, dy) in directions {
+        let mut from = (to.0 + dx, to.1 + dy);
+        while game::not_out_of_bounds(from.0, from.1) && at(board, from).is_none() {
+            origins.push(from);
+            if !slides {
+                break;
+            }
+            from = (from.0 + dx, from.1 + dy);
+        }
+    }
+
+    let captures = uncaptures(board, opponent(piece.team), to);
+    for from in origins {
+        candidates.push(retraction(board, from, to, None, ActionType::Regular));
+        for &rank in captures.iter() {
+            let mut candidate = retraction(board, from, to, Some(rank), ActionType::Regular);
+            candidate.zeroing = true;
+            candidates.push(candidate);
+        }
+    }
+}
+
+fn castling_unmoves(
+    board: &Board,
+    to: (isize, isize),
+    mover: Team,
+    candidates: &mut Vec<Candidate>,
+) {
+    let row = last_row(opponent(mover));
+    let rook = Some(Piece {
+        team: mover,
+        rank: Rank::Rook,
+    });
+    // king square, rook square, rook corner, squares that had to be empty
+    let sides: [(isize, isize, isize, &[isize], usize); 2] =
+        [(6, 5, 7, &[], 0), (2, 3, 0, &[1], 1)];
+    for &(king, rook_column, corner, between, right) in sides.iter() {
+        if to != (king, row)
+            || !holds(board, (rook_column, row), mover, Rank::Rook)
+            || at(board, (4, row)).is_some()
+            || at(board, (corner, row)).is_some()
+            || between
+                .iter()
+                .any(|&column| at(board, (column, row)).is_some())
+        {
+            continue;
+        }
+        let mut candidate = retraction(board, (4, row), to, None, ActionType::Castling);
+        candidate.board[rook_column as usize][row as usize] = None;
+        candidate.board[corner as usize][row as usize] = rook;
+        candidate.castling = Some(right_offset(mover) + right);
+        candidates.push(candidate);
+    }
+}
+
+// The board with the piece on `to` moved back to `from`, and `captured` of
+// the other side put back on `to`
+fn retraction(
+    board: &Board,
+    from: (isize, isize),
+    to: (isize, isize),
+    captured: Option<Rank>,
+    action_type: ActionType,
+) -> Candidate {
+    let mut board = *board;
+    let piece = board[to.0 as usize][to.1 as usize].take().unwrap();
+    board[from.0 as usize][from.1 as usize] = Some(piece);
+    board[to.0 as usize][to.1 as usize] = captured.map(|rank| Piece {
+        team: opponent(piece.team),
+        rank,
+    });
+    Candidate {
+        board,
+        from,
+        to,
+        action_type,
+        promotion: None,
+        castling: None,
+        en_passant: None,
+        zeroing: false,
+    }
+}
+
+// The pieces of `team` that can have been captured on `square`
+fn uncaptures(board: &Board, team: Team, square: (isize, isize)) -> Vec<Rank> {
+    UNCAPTURED
+        .iter()
+        .copied()
+        .filter(|&rank| rank != Rank::Pawn || (square.1 != 0 && square.1 != 7))
+        .filter(|&rank| can_add(board, team, rank))
+        .collect()
+}
+
+// Whether `team` can have one more piece of `rank` on the board: at most
+// sixteen pieces and eight pawns, counting every piece beyond the starting
+// ones as a promoted pawn
+fn can_add(board: &Board, team: Team, rank: Rank) -> bool {
+    let mut counts = [0; 6];
+    for piece in board.iter().flatten().flatten() {
+        if piece.team == team {
+            counts[piece.rank.index()] += 1;
+        }
+    }
+    counts[rank.index()] += 1;
+    let starting = |rank: Rank| match rank {
+        Rank::Queen => 1,
+        Rank::Rook | Rank::Bishop | Rank::Knight => 2,
+        _ => 0,
+    };
+    let promoted: i32 = [Rank::Queen, Rank::Rook, Rank::Bishop, Rank::Knight]
+        .iter()
+        .map(|&rank| (counts[rank.index()] - starting(rank)).max(0))
+        .sum();
+    counts.iter().sum::<i32>() <= 16 && counts[Rank::Pawn.index()] + promoted <= 8
+}
+
+// Moving the king or a rook with a castling right would have lost the right
+fn keeps_castling_right(rights: &[bool; 4], team: Team, square: (isize, isize)) -> bool {
+    let offset = right_offset(team);
+    let row = last_row(opponent(team));
+    (square == (4, row) && (rights[offset] || rights[offset + 1]))
+        || (square == (7, row) && rights[offset])
+        || (square == (0, row) && rights[offset + 1])
+}
+
+fn to_fen(
+    board: &Board,
+    player: Team,
+    castling: [bool; 4],
+    en_passant: Option<(isize, isize)>,
+    halfmove: i32,
+    fullmove: i32,
+) -> String {
+    let mut rows: Vec<String> = vec![];
+    for y in (0..8).rev() {
+        let mut text = String::new();
+        let mut empty = 0;
+        for column in board.iter() {
+            match column[y] {
+                None => empty += 1,
+                Some(piece) => {
+                    if empty > 0 {
+                        text.push_str(&empty.to_string());
+                        empty = 0;
+                    }
+                    text.push(piece.fen_letter());
+                }
+            }
+        }
+        if empty > 0 {
+            text.push_str(&empty.to_string());
+        }
+        rows.push(text);
+    }
+    let mut rights: String = castling
+        .iter()
+        .zip(['K', 'Q', 'k', 'q'].iter())
+        .filter(|(right, _)| **right)
+        .map(|(_, letter)| *letter)
+        .collect();
+    if rights.is_empty() {
+        rights.push('-');
+    }
+    format!(
+        "{} {} {} {} {} {}",
+        rows.join("/"),
+        if player == Team::White { "w" } else { "b" },
+        rights,
+        en_passant.map_or(String::from("-"), game::coordinate_to_string),
+        halfmove,
+        fullmove
+    )
+}
+
+fn at(board: &Board, square: (isize, isize)) -> Option<Piece> {
+    board[square.0 as usize][square.1 as usize]
+}
+
+fn holds(board: &Board, square: (isize, isize), team: Team, rank: Rank) -> bool {
+    matches!(at(board, square), Some(piece) if piece.team == team && piece.rank == rank)
+}
+
+fn king_square(game: &Game, team: Team) -> game::Square {
+    match team {
+        Team::White => game.white_king_square,
+        Team::Black => game.black_king_square,
+    }
+}
+
+fn pawn(team: Team) -> Piece {
+    Piece {
+        team,
+        rank: Rank::Pawn,
+    }
+}
+
+fn opponent(team: Team) -> Team {
+    match team {
+        Team::White => Team::Black,
+        Team::Black => Team::White,
+    }
+}
+
+fn forward(team: Team) -> isize {
+    match team {
+        Team::White => 1,
+        Team::Black => -1,
+    }
+}
+
+// The row the pawns of `team` promote on
+fn last_row(team: Team) -> isize {
+    match team {
+        Team::White => 7,
+        Team::Black => 0,
+    }
+}
+
+// The row the pawns of `team` capture en passant on
+fn en_passant_row(team: Team) -> isize {
+    match team {
+        Team::White => 5,
+        Team::Black => 2,
+    }
+}
+
+fn right_offset(team: Team) -> usize {
+    match team {
+        Team::White => 0,
+        Team::Black => 2,
+    }
+}
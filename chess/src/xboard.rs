@@ -0,0 +1,351 @@
+use std::io;
+use std::io::prelude::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::engine::Engine;
+use crate::game::{Game, GameState, Team};
+use crate::moves::Action;
+use crate::search::{is_mate_score, SearchLimits, SearchResult};
+use crate::timeman::Clock;
+use crate::transposition::MATE;
+
+type Output = Arc<Mutex<Box<dyn Write + Send>>>;
+
+// Chess Engine Communication Protocol (xboard/winboard) front-end. Like the
+// uci front-end the engine thinks on a background thread, and plays its move
+// on the shared game when done.
+pub struct Xboard {
+    engine: Arc<Mutex<Engine>>,
+    stop: Arc<AtomicBool>,
+    // set when the move being thought about should not be played
+    discard: Arc<AtomicBool>,
+    game: Arc<Mutex<Game>>,
+    out: Output,
+    search_thread: Option<JoinHandle<()>>,
+    force: bool,
+    post: bool,
+    engine_team: Team,
+    // clock in centiseconds, as sent with "time"
+    time_left: Option<u64>,
+    moves_per_session: u64,
+    increment: Duration,
+    move_time: Option<Duration>,
+    max_depth: Option<i32>,
+}
+
+impl Xboard {
+    pub fn new(out: Box<dyn Write + Send>) -> Xboard {
+        let engine = Engine::default();
+        Xboard {
+            stop: engine.stop_handle(),
+            engine: Arc::new(Mutex::new(engine)),
+            discard: Arc::new(AtomicBool::new(false)),
+            game: Arc::new(Mutex::new(Game::new())),
+            out: Arc::new(Mutex::new(out)),
+            search_thread: None,
+            force: false,
+            post: false,
+            engine_team: Team::Black,
+            time_left: None,
+            moves_per_session: 0,
+            increment: Duration::from_secs(0),
+            move_time: None,
+            max_depth: None,
+        }
+    }
+
+    // Handles one line of input, returns false once the engine should quit
+    pub fn handle(&mut self, line: &str) -> bool {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let command = match tokens.first() {
+            Some(command) => *command,
+            None => return true,
+        };
+        let argument = tokens.get(1).copied().unwrap_or("");
+        let result = match command {
+            "xboard" | "accepted" | "rejected" | "random" | "hard" | "easy" | "computer" => Ok(()),
+            "protover" => {
+                send(
+                    &self.out,
+                    &format!(
+                        "feature ping=1 setboard=1 usermove=1 time=1 draw=0 sigint=0 sigterm=0 \
+                         reuse=1 analyze=0 colors=0 egt=\"syzygy\" myname=\"chess {}\" done=1",
+                        env!("CARGO_PKG_VERSION")
+                    ),
+                );
+                Ok(())
+            }
+            "new" => {
+                self.stop_search(true);
+                *self.game.lock().unwrap() = Game::new();
+                self.engine.lock().unwrap().new_game();
+                self.force = false;
+                self.engine_team = Team::Black;
+                self.max_depth = None;
+                self.move_time = None;
+                Ok(())
+            }
+            "force" | "result" => {
+                self.stop_search(true);
+                self.force = true;
+                Ok(())
+            }
+            "go" => {
+                self.stop_search(true);
+                self.force = false;
+                self.engine_team = self.game.lock().unwrap().player;
+                self.think();
+                Ok(())
+            }
+            "?" => {
+                self.stop_search(false);
+                Ok(())
+            }
+            "usermove" => self.user_move(argument),
+            "undo" => self.take_back(1),
+            "remove" => self.take_back(2),
+            "setboard" => {
+                self.stop_search(true);
+                match Game::from_fen(&tokens[1..].join(" ")) {
+                    Ok(game) => {
+                        *self.game.lock().unwrap() = game;
+                        Ok(())
+                    }
+                    Err(_) => {
+                        send(&self.out, "tellusererror Illegal position");
+                        Ok(())
+                    }
+                }
+            }
+            "egtpath" => match argument {
+                "syzygy" => self
+                    .engine
+                    .lock()
+                    .unwrap()
+                    .set_option("syzygypath", &tokens[2..].join(" ")),
+                _ => Err(format!("Error (unsupported tablebases): {}", argument)),
+            },
+            "time" => parse_number(argument).map(|time| self.time_left = Some(time)),
+            "otim" => parse_number(argument).map(|_| ()),
+            "level" => self.level(&tokens[1..]),
+            "st" => parse_number(argument)
+                .map(|seconds| self.move_time = Some(Duration::from_secs(seconds))),
+            "sd" => parse_number(argument).map(|depth| self.max_depth = Some(depth as i32)),
+            "post" => {
+                self.post = true;
+                Ok(())
+            }
+            "nopost" => {
+                self.post = false;
+                Ok(())
+            }
+            "ping" => {
+                send(&self.out, &format!("pong {}", argument));
+                Ok(())
+            }
+            "quit" => {
+                self.stop_search(true);
+                return false;
+            }
+            _ => Err(format!("Error (unknown command): {}", command)),
+        };
+        if let Err(message) = result {
+            send(&self.out, &message);
+        }
+        true
+    }
+
+    // Blocks until the engine has played the move it is thinking about
+    pub fn wait_for_search(&mut self) {
+        if let Some(handle) = self.search_thread.take() {
+            let _ = handle.join();
+        }
+    }
+
+    fn user_move(&mut self, text: &str) -> Result<(), String> {
+        self.stop_search(true);
+        {
+            let mut game = self.game.lock().unwrap();
+            let action = game
+                .action_from_uci(text)
+                .map_err(|_| format!("Illegal move: {}", text))?;
+            game.perform_action(action);
+        }
+        if !self.force {
+            self.think();
+        }
+        Ok(())
+    }
+
+    fn take_back(&mut self, count: usize) -> Result<(), String> {
+        self.stop_search(true);
+        let mut game = self.game.lock().unwrap();
+        if game.history.len() < count {
+            return Err(String::from("Error (no moves to take back): undo"));
+        }
+        for _ in 0..count {
+            game.undo();
+        }
+        Ok(())
+    }
+
+    // level MPS BASE INC, the base time as minutes or minutes:seconds
+    fn level(&mut self, tokens: &[&str]) -> Result<(), String> {
+        if tokens.len() < 3 {
+            return Err(String::from("Error (level needs three values): level"));
+        }
+        self.moves_per_session = parse_number(tokens[0])?;
+        let increment: f64 = tokens[2]
+            .parse()
+            .map_err(|_| format!("Error (bad increment): {}", tokens[2]))?;
+        self.increment = Duration::from_secs_f64(increment.max(0.0));
+        self.move_time = None;
+        Ok(())
+    }
+
+    fn limits(&self, game: &Game) -> SearchLimits {
+        let mut limits = SearchLimits {
+            depth: self.max_depth,
+            ..SearchLimits::default()
+        };
+        if let Some(move_time) = self.move_time {
+            limits.movetime = Some(move_time);
+        } else if let Some(centiseconds) = self.time_left {
+            let time = Duration::from_millis(centiseconds * 10);
+            let movestogo = if self.moves_per_session > 0 {
+                let played = (game.history.len() / 2) as u64;
+                Some((self.moves_per_session - played % self.moves_per_session).max(1))
+            } else {
+                None
+            };
+            limits.clock = Some(Clock {
+                time,
+                increment: self.increment,
+                movestogo,
+            });
+        }
+        limits
+    }
+
+    fn think(&mut self) {
+        let game = self.game.lock().unwrap().clone();
+        if game.player != self.engine_team {
+            return;
+        }
+        let mut finished = game.clone();
+        match finished.get_game_state() {
+            GameState::Checkmate | GameState::Stalemate | GameState::FiftyRule => return,
+            _ => {}
+        }
+
+        let limits = self.limits(&game);
+        self.stop.store(false, Ordering::Relaxed);
+        self.discard.store(false, Ordering::Relaxed);
+        let engine = Arc::clone(&self.engine);
+        let shared_game = Arc::clone(&self.game);
+        let out = Arc::clone(&self.out);
+        let discard = Arc::clone(&self.discard);
+        let post = self.post;
+        self.search_thread = Some(thread::spawn(move || {
+            let engine = engine.lock().unwrap();
+            let start = Instant::now();
+            let result = engine.search_with_info(&game, &limits, |result| {
+                if post {
+                    send(&out, &thinking_line(result, start.elapsed()));
+                }
+            });
+            if discard.load(Ordering::Relaxed) {
+                return;
+            }
+            if let Some(mut action) = result.best_move {
+                let mut shared_game = shared_game.lock().unwrap();
+                action.promotion = action.promotion.or(Some(crate::game::Rank::Queen));
+                shared_game.perform_action(action);
+                send(&out, &format!("move {}", action.to_uci()));
+                if let Some(result) = game_result(&mut shared_game) {
+                    send(&out, &result);
+                }
+            }
+        }));
+    }
+
+    fn stop_search(&mut self, discard: bool) {
+        if self.search_thread.is_some() {
+            self.discard.store(discard, Ordering::Relaxed);
+            self.stop.store(true, Ordering::Relaxed);
+            self.wait_for_search();
+            self.stop.store(false, Ordering::Relaxed);
+        }
+    }
+}
+
+fn parse_number(text: &str) -> Result<u64, String> {
+    // base times like 5:30 count as minutes
+    let text = text.split(':').next().unwrap_or("");
+    text.parse::<i64>()
+        .map(|number| number.max(0) as u64)
+        .map_err(|_| format!("Error (not a number): {}", text))
+}
+
+// Mates are reported as 100000 + moves, the convention most GUIs understand
+fn score_to_xboard(score: i32) -> i32 {
+    if is_mate_score(score) {
+        if score > 0 {
+            100000 + (MATE - score + 1) / 2
+        } else {
+            -100000 - (MATE + score) / 2
+        }
+    } else {
+        score
+    }
+}
+
+fn thinking_line(result: &SearchResult, elapsed: Duration) -> String {
+    let pv: Vec<String> = result.pv.iter().map(Action::to_uci).collect();
+    format!(
+        "{} {} {} {} {}",
+        result.depth,
+        score_to_xboard(result.score),
+        elapsed.as_millis() / 10,
+        result.nodes,
+        pv.join(" ")
+    )
+}
+
+fn game_result(game: &mut Game) -> Option<String> {
+    match game.get_game_state() {
+        GameState::Checkmate => Some(match game.player {
+            Team::White => String::from("0-1 {Black mates}"),
+            Team::Black => String::from("1-0 {White mates}"),
+        }),
+        GameState::Stalemate => Some(String::from("1/2-1/2 {Stalemate}")),
+        GameState::FiftyRule => Some(String::from("1/2-1/2 {Fifty move rule}")),
+        _ => None,
+    }
+}
+
+fn send(out: &Output, line: &str) {
+    let mut out = out.lock().unwrap();
+    let _ = writeln!(out, "{}", line);
+    let _ = out.flush();
+}
+
+// Reads commands from stdin until "quit"
+pub fn run() {
+    let mut xboard = Xboard::new(Box::new(io::stdout()));
+    for line in io::stdin().lock().lines() {
+        match line {
+            Ok(line) => {
+                if !xboard.handle(&line) {
+                    break;
+                }
+            }
+            Err(_) => break,
+        }
+    }
+    xboard.handle("quit");
+}